@@ -0,0 +1,88 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// The per sentence work of the dispatcher on a sample of a busy port:
+// splitting what is read into lines, looking at the raw sentences, decoding,
+// and handing a message to the endpoint queues. `ais-forwarder soak` measures
+// the whole path, throttling and sender threads included.
+use std::collections::VecDeque;
+use std::hint::black_box;
+use std::sync::Arc;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/nmea.rs"]
+mod nmea;
+
+// 2000 messages from 300 vessels, mostly positions, some static data and
+// binary messages, a fifth with a TAG block.
+const FEED: &[u8] = include_bytes!("busy-port.nmea");
+const ENDPOINTS: usize = 3;
+
+fn sentences() -> u64 {
+    nmea::lines(FEED).count() as u64
+}
+
+fn raw(c: &mut Criterion) {
+    let mut group = c.benchmark_group("raw");
+    group.throughput(Throughput::Elements(sentences()));
+    group.bench_function("lines", |b| b.iter(|| nmea::lines(black_box(FEED)).count()));
+    group.bench_function("header", |b| {
+        b.iter(|| {
+            nmea::lines(black_box(FEED))
+                .filter(|line| {
+                    nmea::checksum_ok(line)
+                        && nmea::fragment(line).is_some()
+                        && nmea::ais_header(line).is_some()
+                })
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(sentences()));
+    group.bench_function("parse", |b| {
+        b.iter(|| {
+            let mut parser = nmea_parser::NmeaParser::new();
+            nmea::lines(black_box(FEED))
+                .filter(|line| parser.parse_sentence(nmea::split_tag_block(line).1).is_ok())
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn fan_out(c: &mut Criterion) {
+    let lines: Vec<&str> = nmea::lines(FEED).collect();
+    let mut group = c.benchmark_group("fan_out");
+    group.throughput(Throughput::Elements(lines.len() as u64));
+    group.bench_function("shared", |b| {
+        b.iter(|| {
+            let mut queues: [VecDeque<Arc<[u8]>>; ENDPOINTS] = Default::default();
+            for line in &lines {
+                let data: Arc<[u8]> = line.as_bytes().into();
+                for queue in queues.iter_mut() {
+                    queue.push_back(data.clone());
+                }
+            }
+            queues
+        })
+    });
+    group.bench_function("copied", |b| {
+        b.iter(|| {
+            let mut queues: [VecDeque<Vec<u8>>; ENDPOINTS] = Default::default();
+            for line in &lines {
+                for queue in queues.iter_mut() {
+                    queue.push_back(line.as_bytes().to_vec());
+                }
+            }
+            queues
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, raw, decode, fan_out);
+criterion_main!(benches);
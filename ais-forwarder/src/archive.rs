@@ -0,0 +1,306 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Parquet archive output: the position reports that an [ais] endpoint is sent,
+// decoded into a Parquet file per day, so that DuckDB or pandas read them on
+// shore as they are:
+//
+//     Archive = parquet:///var/lib/ais-forwarder/archive
+//     SELECT mmsi, count(*) FROM 'archive/ais-2025-06-*.parquet' GROUP BY mmsi;
+//
+// Each row has the time the report was received, from its TAG block or else
+// when it was sent here, the MMSI, the message type, and the position, speed
+// and course where the report has them.
+//
+// Rows are written as a row group every FLUSH_INTERVAL or ROW_GROUP_ROWS, but
+// a Parquet file can only be read once its footer is written, when the day is
+// over or the forwarder stops. After a power cut the file of that day has no
+// footer; with ?rotate=hour a file is closed every hour and at most that hour
+// is lost. A forwarder started again on the same day starts a new file, with
+// -1, -2 and so on after the date.
+//
+// Parquet is not built in by default, as it adds megabytes to the binary that
+// routers do not have room for; build with --features parquet.
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use common::sink::Sink;
+use parquet::basic::Compression;
+use parquet::data_type::{DataType, DoubleType, Int32Type, Int64Type};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+use parquet::schema::parser::parse_message_type;
+use parquet::schema::types::Type;
+
+use crate::nmea;
+use crate::output::Options;
+use crate::schema::Report;
+
+const SCHEMA: &str = "message ais {
+    REQUIRED INT64 time (TIMESTAMP(MILLIS, true));
+    REQUIRED INT32 mmsi (INTEGER(32, false));
+    REQUIRED INT32 type (INTEGER(8, false));
+    OPTIONAL DOUBLE latitude;
+    OPTIONAL DOUBLE longitude;
+    OPTIONAL DOUBLE sog;
+    OPTIONAL DOUBLE cog;
+}";
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(600);
+const ROW_GROUP_ROWS: usize = 50_000;
+
+// The values of an optional column, and for each row whether it has one.
+struct Column<T> {
+    values: Vec<T>,
+    levels: Vec<i16>,
+}
+
+impl<T> Column<T> {
+    fn new() -> Self {
+        Column {
+            values: Vec::new(),
+            levels: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, value: Option<T>) {
+        self.levels.push(value.is_some() as i16);
+        self.values.extend(value);
+    }
+
+    fn clear(&mut self) {
+        self.values.clear();
+        self.levels.clear();
+    }
+}
+
+struct Rows {
+    time: Vec<i64>,
+    mmsi: Vec<i32>,
+    message_type: Vec<i32>,
+    latitude: Column<f64>,
+    longitude: Column<f64>,
+    sog: Column<f64>,
+    cog: Column<f64>,
+}
+
+impl Rows {
+    fn new() -> Self {
+        Rows {
+            time: Vec::new(),
+            mmsi: Vec::new(),
+            message_type: Vec::new(),
+            latitude: Column::new(),
+            longitude: Column::new(),
+            sog: Column::new(),
+            cog: Column::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.time.len()
+    }
+
+    fn clear(&mut self) {
+        self.time.clear();
+        self.mmsi.clear();
+        self.message_type.clear();
+        self.latitude.clear();
+        self.longitude.clear();
+        self.sog.clear();
+        self.cog.clear();
+    }
+
+    // A row for a position report, nothing for other messages.
+    fn add(&mut self, message: &[u8]) {
+        let lines: Vec<&str> = nmea::lines(message).collect();
+        let Some(report) = Report::decode(&lines)
+            .filter(|report| matches!(report.message_type, 1..=4 | 9 | 18 | 19 | 21))
+        else {
+            return;
+        };
+        let time = DateTime::parse_from_rfc3339(&report.time).map_or_else(
+            |_| Utc::now().timestamp_millis(),
+            |time| time.timestamp_millis(),
+        );
+        self.time.push(time);
+        self.mmsi.push(report.mmsi as i32);
+        self.message_type.push(report.message_type as i32);
+        self.latitude.push(report.latitude);
+        self.longitude.push(report.longitude);
+        self.sog.push(report.sog);
+        self.cog.push(report.cog);
+    }
+}
+
+pub struct ArchiveSink {
+    name: String,
+    directory: PathBuf,
+    hourly: bool,
+    schema: Arc<Type>,
+    // The file being written and the day or hour it is for
+    writer: Option<(String, SerializedFileWriter<File>)>,
+    rows: Rows,
+    flushed: Instant,
+    healthy: bool,
+}
+
+impl ArchiveSink {
+    pub fn create(name: &str, url: &str, _options: &Options) -> io::Result<Box<dyn Sink>> {
+        let rest = url.strip_prefix("parquet://").unwrap_or(url);
+        let (directory, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let mut hourly = false;
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            match (key, value) {
+                ("rotate", "day") => hourly = false,
+                ("rotate", "hour") => hourly = true,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{}: unknown option '{}={}'", url, key, value),
+                    ));
+                }
+            }
+        }
+        if directory.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: missing directory", url),
+            ));
+        }
+        let schema = parse_message_type(SCHEMA).map_err(io::Error::other)?;
+        Ok(Box::new(ArchiveSink {
+            name: name.to_string(),
+            directory: PathBuf::from(directory),
+            hourly,
+            schema: Arc::new(schema),
+            writer: None,
+            rows: Rows::new(),
+            flushed: Instant::now(),
+            healthy: true,
+        }))
+    }
+
+    fn period(&self) -> String {
+        let format = if self.hourly {
+            "%Y-%m-%dT%H"
+        } else {
+            "%Y-%m-%d"
+        };
+        Utc::now().format(format).to_string()
+    }
+
+    // A new file for the period, next to the ones of an earlier run.
+    fn open(&self, period: &str) -> io::Result<SerializedFileWriter<File>> {
+        fs::create_dir_all(&self.directory)?;
+        let mut path = self.directory.join(format!("ais-{}.parquet", period));
+        let mut run = 0;
+        while path.exists() {
+            run += 1;
+            path = self
+                .directory
+                .join(format!("ais-{}-{}.parquet", period, run));
+        }
+        let file = File::create(&path)?;
+        log::info!(endpoint = self.name.as_str(); "{}: Archiving to {}", self.name, path.display());
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::UNCOMPRESSED)
+            .build();
+        SerializedFileWriter::new(file, self.schema.clone(), Arc::new(properties))
+            .map_err(io::Error::other)
+    }
+
+    // Write the rows as a row group to the file for now, closing the file of
+    // an earlier day or hour first.
+    fn write_rows(&mut self) -> io::Result<()> {
+        let period = self.period();
+        if self
+            .writer
+            .as_ref()
+            .is_some_and(|(current, _)| *current != period)
+        {
+            self.finish()?;
+        }
+        self.flushed = Instant::now();
+        if self.rows.time.is_empty() {
+            return Ok(());
+        }
+        if self.writer.is_none() {
+            self.writer = Some((period.clone(), self.open(&period)?));
+        }
+        if let Some((_, writer)) = &mut self.writer {
+            let result = write_row_group(writer, &self.rows);
+            self.rows.clear();
+            result.map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+
+    // Write the footer, after which the file can be read.
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some((_, writer)) = self.writer.take() {
+            writer.close().map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_row_group(
+    writer: &mut SerializedFileWriter<File>,
+    rows: &Rows,
+) -> parquet::errors::Result<()> {
+    let mut group = writer.next_row_group()?;
+    write_column::<Int64Type>(&mut group, &rows.time, None)?;
+    write_column::<Int32Type>(&mut group, &rows.mmsi, None)?;
+    write_column::<Int32Type>(&mut group, &rows.message_type, None)?;
+    for column in [&rows.latitude, &rows.longitude, &rows.sog, &rows.cog] {
+        write_column::<DoubleType>(&mut group, &column.values, Some(&column.levels))?;
+    }
+    group.close()?;
+    Ok(())
+}
+
+fn write_column<T: DataType>(
+    group: &mut SerializedRowGroupWriter<'_, File>,
+    values: &[T::T],
+    levels: Option<&[i16]>,
+) -> parquet::errors::Result<()> {
+    let Some(mut column) = group.next_column()? else {
+        return Err(ParquetError::General(
+            "more columns than in the schema".to_string(),
+        ));
+    };
+    column.typed::<T>().write_batch(values, levels, None)?;
+    column.close()
+}
+
+impl Sink for ArchiveSink {
+    fn connect(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        self.rows.add(message);
+        let result =
+            if self.rows.len() >= ROW_GROUP_ROWS || self.flushed.elapsed() >= FLUSH_INTERVAL {
+                self.write_rows()
+            } else {
+                Ok(())
+            };
+        self.healthy = result.is_ok();
+        result
+    }
+
+    fn close(&mut self) {
+        if let Err(e) = self.write_rows().and_then(|()| self.finish()) {
+            log::warn!(endpoint = self.name.as_str(); "{}: {}", self.name, e);
+        }
+    }
+
+    fn healthy(&self) -> bool {
+        self.healthy
+    }
+}
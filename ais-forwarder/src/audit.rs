@@ -0,0 +1,51 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// An append-only record of every own-vessel position that actually left the
+// boat, so that users can check exactly what was shared with which service.
+// One line per successful send:
+//
+//   2025-06-01T12:00:00Z location/aprs 53.17500 5.41700 1234$GNRMC,...
+//
+// AIS messages carry the position in their encoded payload, so the decoded
+// position is written before the message itself.
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn new(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        log::info!("Logging own position disclosures to {}", path);
+        Ok(AuditLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(
+        &self,
+        section: &str,
+        endpoint: &str,
+        position: Option<(f64, f64)>,
+        message: &[u8],
+    ) {
+        let position = match position {
+            Some((latitude, longitude)) => format!("{:.5} {:.5}", latitude, longitude),
+            None => "- -".to_string(),
+        };
+        let line = format!(
+            "{} {}/{} {} {}\n",
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            section,
+            endpoint,
+            position,
+            String::from_utf8_lossy(message).trim_end()
+        );
+        // Written in one go, so that lines stay whole even if we are killed
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            log::warn!("Cannot write to audit log: {}", e);
+        }
+    }
+}
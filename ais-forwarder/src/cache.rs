@@ -20,7 +20,7 @@ impl Persistence {
             .cache_capacity(500_000)
             .path(&database_path)
             .open()
-            .expect(format!("Cannot open database {}", database_path.display()).as_str());
+            .unwrap_or_else(|_| panic!("Cannot open database {}", database_path.display()));
         let count = db.len();
 
         let this = Persistence { db, count };
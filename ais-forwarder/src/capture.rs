@@ -0,0 +1,160 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Record raw traffic to a file for debugging. Every chunk read from the
+// provider, and optionally every chunk sent to an endpoint, becomes one line:
+//
+//   2025-06-01T12:00:00.123456Z < provider !AIVDM,1,1,,A,...*24\r\n
+//   2025-06-01T12:00:00.124012Z > ais/marinetraffic !AIVDM,1,1,,A,...*24\r\n
+//
+// Bytes outside printable ASCII are escaped, so line endings and stray
+// control characters are visible exactly as they went over the wire. The
+// soak and replay modes read such a file back, with its timing. The record
+// mode writes the same format, with a sentence per line.
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::nmea;
+
+pub struct Capture {
+    writer: Mutex<BufWriter<File>>,
+    sent: bool,
+}
+
+impl Capture {
+    pub fn new(path: &str, sent: bool) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        log::info!("Capturing raw traffic to {}", path);
+        Ok(Capture {
+            writer: Mutex::new(BufWriter::new(file)),
+            sent,
+        })
+    }
+
+    pub fn received(&self, source: &str, data: &[u8]) {
+        self.record('<', source, data);
+    }
+
+    pub fn sent(&self, section: &str, endpoint: &str, data: &[u8]) {
+        if self.sent {
+            self.record('>', &format!("{}/{}", section, endpoint), data);
+        }
+    }
+
+    fn record(&self, direction: char, name: &str, data: &[u8]) {
+        let line = format!(
+            "{} {} {} {}\n",
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+            direction,
+            name,
+            escape(data)
+        );
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(e) = writer
+            .write_all(line.as_bytes())
+            .and_then(|_| writer.flush())
+        {
+            log::warn!("Cannot write to capture file: {}", e);
+        }
+    }
+}
+
+// A line of a capture file, as written by record.
+pub struct Record {
+    pub time: chrono::DateTime<chrono::Utc>,
+    pub received: bool,
+    pub data: Vec<u8>,
+}
+
+pub fn parse(line: &str) -> Option<Record> {
+    let mut fields = line.splitn(4, ' ');
+    let time = chrono::DateTime::parse_from_rfc3339(fields.next()?).ok()?;
+    let received = match fields.next()? {
+        "<" => true,
+        ">" => false,
+        _ => return None,
+    };
+    Some(Record {
+        time: time.with_timezone(&chrono::Utc),
+        received,
+        // The provider or endpoint name is only for people reading the file
+        data: unescape(fields.nth(1)?)?,
+    })
+}
+
+// The data received in a capture file, each chunk with when it was received
+// after the first. Lines that are not capture records are taken to be plain
+// sentences, timed by their TAG block if they have one.
+pub fn load(path: &str) -> io::Result<Vec<(Option<Duration>, Vec<u8>)>> {
+    let mut chunks = Vec::new();
+    let mut first = None;
+    let mut offset = |time: SystemTime| time.duration_since(*first.get_or_insert(time)).ok();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        match parse(&line) {
+            Some(record) if record.received => {
+                chunks.push((offset(record.time.into()), record.data));
+            }
+            Some(_) => {}
+            None if line.is_empty() => {}
+            None => chunks.push((
+                nmea::tag_timestamp(&line).and_then(&mut offset),
+                format!("{}\r\n", line).into_bytes(),
+            )),
+        }
+    }
+    Ok(chunks)
+}
+
+// Wait until a chunk received `offset` after the first is due, when played
+// back from `start` at `speed` times the pace it was received at. Zero plays
+// as fast as possible.
+pub fn pace(start: Instant, offset: Option<Duration>, speed: f64) {
+    if speed > 0.0
+        && let Some(offset) = offset
+    {
+        let due = start + offset.div_f64(speed);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+fn unescape(escaped: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(escaped.len());
+    let mut bytes = escaped.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            data.push(b);
+            continue;
+        }
+        match bytes.next()? {
+            b'r' => data.push(b'\r'),
+            b'n' => data.push(b'\n'),
+            b'\\' => data.push(b'\\'),
+            b'x' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                data.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            _ => return None,
+        }
+    }
+    Some(data)
+}
+
+fn escape(data: &[u8]) -> String {
+    let mut escaped = String::with_capacity(data.len());
+    for &b in data {
+        match b {
+            b'\r' => escaped.push_str("\\r"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(b as char),
+            _ => {
+                let _ = write!(escaped, "\\x{:02x}", b);
+            }
+        }
+    }
+    escaped
+}
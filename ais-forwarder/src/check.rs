@@ -0,0 +1,189 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Check mode: try every endpoint in the configuration, the providers and the
+// [ais] and [location] outputs, and print a table of what works. Optionally a
+// test sentence is sent to the outputs, a proprietary one that AIS services
+// ignore, which is the only way to know that an HTTP or UDP endpoint takes it.
+use std::collections::HashMap;
+use std::io;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use common::{NetworkEndpoint, Protocol};
+
+use crate::output::Registry;
+
+const TEST_SENTENCE: &str = "$PAISF,TEST*77\r\n";
+
+struct Check {
+    section: &'static str,
+    name: String,
+    url: String,
+    result: io::Result<String>,
+}
+
+// Returns whether all endpoints passed.
+pub fn run(
+    settings: &HashMap<String, HashMap<String, String>>,
+    outputs: &Registry,
+    connect_timeout: Duration,
+    send: bool,
+) -> bool {
+    let mut checks = Vec::new();
+    if let Some(provider) = settings.get("general").and_then(|g| g.get("provider")) {
+        checks.push(Check {
+            section: "provider",
+            name: "provider".to_string(),
+            url: provider.clone(),
+            result: check_provider(provider, connect_timeout),
+        });
+    }
+    let mut providers: Vec<_> = settings.get("providers").into_iter().flatten().collect();
+    providers.sort();
+    for (name, url) in providers {
+        checks.push(Check {
+            section: "providers",
+            name: name.clone(),
+            url: url.clone(),
+            result: check_provider(url, connect_timeout),
+        });
+    }
+    let mmsi = settings
+        .get("general")
+        .and_then(|general| general.get("mmsi"))
+        .map_or("", String::as_str);
+    for section in ["ais", "location"] {
+        let mut endpoints: Vec<_> = settings.get(section).into_iter().flatten().collect();
+        endpoints.sort();
+        // The location receiver files what it gets under the MMSI in front
+        let sentence = match section {
+            "location" => format!("{}{}", mmsi, TEST_SENTENCE),
+            _ => TEST_SENTENCE.to_string(),
+        };
+        for (name, url) in endpoints {
+            checks.push(Check {
+                section,
+                name: name.clone(),
+                url: url.clone(),
+                result: check_output(
+                    outputs,
+                    name,
+                    url,
+                    connect_timeout,
+                    send.then_some(&sentence),
+                ),
+            });
+        }
+    }
+
+    let name_width = checks
+        .iter()
+        .map(|c| c.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    let url_width = checks.iter().map(|c| c.url.len()).max().unwrap_or(0).max(3);
+    println!(
+        "{:<8}  {:<name_width$}  {:<url_width$}  RESULT",
+        "SECTION", "NAME", "URL"
+    );
+    for check in &checks {
+        let result = match &check.result {
+            Ok(detail) => format!("pass  {}", detail),
+            Err(e) => format!("FAIL  {}", e),
+        };
+        println!(
+            "{:<8}  {:<name_width$}  {:<url_width$}  {}",
+            check.section, check.name, check.url, result
+        );
+    }
+    checks.iter().all(|check| check.result.is_ok())
+}
+
+// A provider we connect to should accept a connection, for one that sends to
+// us we can only check that its port is free, which it is not while the
+// forwarder itself is running.
+fn check_provider(url: &str, timeout: Duration) -> io::Result<String> {
+    let mut endpoint: NetworkEndpoint = url.parse()?;
+    let addr = endpoint.resolve()?;
+    match endpoint.protocol {
+        Protocol::TCP => connect(addr.to_string().as_str(), timeout),
+        Protocol::TCPListen => TcpListener::bind(addr)
+            .map(|_| format!("can listen on {}", addr))
+            .map_err(|e| in_use(addr, e)),
+        Protocol::UDP | Protocol::UDPListen => UdpSocket::bind(addr)
+            .map(|_| format!("can listen on {}", addr))
+            .map_err(|e| in_use(addr, e)),
+    }
+}
+
+fn in_use(addr: std::net::SocketAddr, e: io::Error) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::AddrInUse => io::Error::new(
+            e.kind(),
+            format!("{} is in use, is ais-forwarder running?", addr),
+        ),
+        _ => io::Error::new(e.kind(), format!("{}: {}", addr, e)),
+    }
+}
+
+fn check_output(
+    outputs: &Registry,
+    name: &str,
+    url: &str,
+    timeout: Duration,
+    sentence: Option<&String>,
+) -> io::Result<String> {
+    let mut sink = outputs.create(name, url)?;
+    let start = Instant::now();
+    // An HTTP sink only connects when it posts
+    match url.split_once("://") {
+        Some(("http" | "https", rest)) if sentence.is_none() => {
+            let authority = rest.split(['/', '?']).next().unwrap_or(rest);
+            let authority = authority.rsplit_once('@').map_or(authority, |(_, a)| a);
+            let default_port = if url.starts_with("https") { 443 } else { 80 };
+            let address = match authority.rsplit_once(':') {
+                Some((_, port)) if port.parse::<u16>().is_ok() => authority.to_string(),
+                _ => format!("{}:{}", authority, default_port),
+            };
+            return connect(&address, timeout);
+        }
+        _ => sink.connect()?,
+    }
+    match sentence {
+        Some(sentence) => {
+            sink.send(sentence.as_bytes())?;
+            sink.flush()?;
+            Ok(format!(
+                "sent test sentence in {} ms",
+                start.elapsed().as_millis()
+            ))
+        }
+        None if url.starts_with("udp") || url.starts_with("lwe") => {
+            Ok("resolved, UDP is not acknowledged".to_string())
+        }
+        None => Ok(format!("connected in {} ms", start.elapsed().as_millis())),
+    }
+}
+
+fn connect(address: &str, timeout: Duration) -> io::Result<String> {
+    let addr = address
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::HostUnreachable,
+                format!("Cannot resolve {}", address),
+            )
+        })?;
+    let start = Instant::now();
+    TcpStream::connect_timeout(&addr, timeout)
+        .map(|_| {
+            format!(
+                "connected to {} in {} ms",
+                addr,
+                start.elapsed().as_millis()
+            )
+        })
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", addr, e)))
+}
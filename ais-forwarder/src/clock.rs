@@ -0,0 +1,111 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// The time the dispatcher throttles by and schedules location updates by.
+// The wall clock is right for a live feed. The message clock follows the
+// times carried in the data, the c: field of TAG blocks and the time of RMC
+// sentences, so that a recorded feed that is replayed faster or slower than
+// it was received is throttled as it was live, and a test gives the same
+// result on every run.
+//
+// The dispatcher keeps its times as Instants, so the message clock maps the
+// message times onto Instants from when it was created. It never goes back,
+// and a sentence without a time is taken to be at the latest time seen.
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::nmea;
+
+// A message time this far from the latest one is a new feed, or a source with
+// a wrong clock, and is not jumped to; the clock carries on from where it is.
+const MAX_STEP: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ClockSource {
+    #[default]
+    Wall,
+    Message,
+}
+
+impl FromStr for ClockSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wall" => Ok(ClockSource::Wall),
+            "message" => Ok(ClockSource::Message),
+            _ => Err(format!("'{}' is not wall or message", s)),
+        }
+    }
+}
+
+pub struct Clock {
+    source: ClockSource,
+    start: Instant,
+    // The message time that is `start`, and how far the messages are past it
+    epoch: Option<SystemTime>,
+    latest: Duration,
+}
+
+impl Clock {
+    pub fn new(source: ClockSource) -> Self {
+        Clock {
+            source,
+            start: Instant::now(),
+            epoch: None,
+            latest: Duration::ZERO,
+        }
+    }
+
+    // Follow the TAG block time of a sentence, if it has one.
+    pub fn observe_sentence(&mut self, line: &str) {
+        if self.source == ClockSource::Message {
+            self.observe(nmea::tag_timestamp(line));
+        }
+    }
+
+    // Follow the time of a message, such as that of an RMC.
+    pub fn observe(&mut self, time: Option<SystemTime>) {
+        if self.source != ClockSource::Message {
+            return;
+        }
+        let Some(time) = time else {
+            return;
+        };
+        match self.epoch.and_then(|epoch| time.duration_since(epoch).ok()) {
+            Some(since) if since.abs_diff(self.latest) <= MAX_STEP => {
+                self.latest = self.latest.max(since);
+            }
+            // The first time, or a jump
+            _ => self.epoch = time.checked_sub(self.latest),
+        }
+    }
+
+    pub fn now(&self) -> Instant {
+        match self.source {
+            ClockSource::Wall => Instant::now(),
+            ClockSource::Message => self.start + self.latest,
+        }
+    }
+
+    // The time of day that now() is, for aligning to the clock and for what
+    // we keep with a date, such as the logbook.
+    pub fn wall(&self) -> SystemTime {
+        match (self.source, self.epoch) {
+            (ClockSource::Message, Some(epoch)) => epoch + self.latest,
+            _ => SystemTime::now(),
+        }
+    }
+
+    // The next moment that is a whole multiple of interval seconds on the
+    // clock, for tidy update times. It is an Instant so that the wall clock
+    // being stepped by NTP or GPS, common on routers without an RTC, neither
+    // stalls nor rushes the schedule.
+    pub fn next_aligned(&self, interval: u64) -> Instant {
+        let interval = Duration::from_secs(interval.max(1));
+        let wall = self
+            .wall()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let into_period = wall.as_nanos() % interval.as_nanos();
+        self.now() + interval - Duration::from_nanos(into_period as u64)
+    }
+}
@@ -0,0 +1,181 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Runtime commands, one line of text each, as accepted on the control socket
+// and the HTTP API.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::location::LocationMessage;
+use crate::metrics::Metrics;
+use crate::notify::{Event, Notifier};
+
+const DUMP_TIMEOUT: Duration = Duration::from_secs(5);
+
+const HELP: &str = "\
+status             the status report
+pause <endpoint>   stop sending to an endpoint, either name or section/name
+resume <endpoint>  start sending to it again
+locate             send a location report with the next own position
+position           email our position to the email notification backends
+dump               list the location reports stored for resending
+reload             restart in place, rereading the configuration
+";
+
+// What the commands change, checked by the dispatcher and location thread.
+pub struct Controls {
+    paused: Mutex<BTreeSet<String>>,
+    // Endpoints paused by each geofence the own vessel is in
+    fenced: Mutex<BTreeMap<String, Vec<String>>>,
+    force_location: AtomicBool,
+}
+
+impl Controls {
+    pub fn new() -> Self {
+        Controls {
+            paused: Mutex::new(BTreeSet::new()),
+            fenced: Mutex::new(BTreeMap::new()),
+            force_location: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_paused(&self, section: &str, endpoint: &str) -> bool {
+        let paused = self.paused.lock().unwrap();
+        paused.contains(endpoint)
+            || paused.contains(&format!("{}/{}", section, endpoint))
+            || (section == "ais"
+                && self
+                    .fenced
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .any(|endpoints| endpoints.iter().any(|name| name == endpoint)))
+    }
+
+    // Geofences pause their endpoints apart from `pause`, so that `resume`
+    // does not undo them and leaving does not resume what was paused by hand.
+    pub fn fence_pause(&self, fence: &str, endpoints: &[String]) {
+        if !endpoints.is_empty() {
+            self.fenced
+                .lock()
+                .unwrap()
+                .insert(fence.to_string(), endpoints.to_vec());
+        }
+    }
+
+    pub fn fence_resume(&self, fence: &str) {
+        self.fenced.lock().unwrap().remove(fence);
+    }
+
+    // True once after `locate`.
+    pub fn take_force_location(&self) -> bool {
+        self.force_location.swap(false, Ordering::Relaxed)
+    }
+}
+
+pub enum Reply {
+    Text(String),
+    // Send "Reloading" and then call shutdown::reload()
+    #[cfg(unix)]
+    Reload,
+}
+
+#[derive(Clone)]
+pub struct Commands {
+    controls: Arc<Controls>,
+    metrics: Arc<Metrics>,
+    location_tx: SyncSender<LocationMessage>,
+    notifier: Notifier,
+}
+
+impl Commands {
+    pub fn new(
+        controls: Arc<Controls>,
+        metrics: Arc<Metrics>,
+        location_tx: SyncSender<LocationMessage>,
+        notifier: Notifier,
+    ) -> Self {
+        Commands {
+            controls,
+            metrics,
+            location_tx,
+            notifier,
+        }
+    }
+
+    pub fn execute(&self, command: &str) -> Reply {
+        let mut words = command.split_whitespace();
+        let reply = match (words.next().unwrap_or(""), words.next()) {
+            ("help", None) => HELP.to_string(),
+            ("stats" | "status", None) => self.status(),
+            ("pause", Some(endpoint)) => {
+                self.controls
+                    .paused
+                    .lock()
+                    .unwrap()
+                    .insert(endpoint.to_string());
+                log::info!("Paused {}", endpoint);
+                format!("Paused {}\n", endpoint)
+            }
+            ("resume", Some(endpoint)) => {
+                if self.controls.paused.lock().unwrap().remove(endpoint) {
+                    log::info!("Resumed {}", endpoint);
+                    format!("Resumed {}\n", endpoint)
+                } else {
+                    format!("{} is not paused\n", endpoint)
+                }
+            }
+            ("locate", None) => {
+                self.controls.force_location.store(true, Ordering::Relaxed);
+                "Sending a location report with the next own position\n".to_string()
+            }
+            ("position", None) => match self.metrics.own_position() {
+                Some((latitude, longitude)) => {
+                    self.notifier.notify(Event::Position {
+                        name: self.metrics.own_name(),
+                        latitude,
+                        longitude,
+                    });
+                    "Sending our position by email\n".to_string()
+                }
+                None => "No own position yet\n".to_string(),
+            },
+            ("dump", None) => self.dump(),
+            #[cfg(unix)]
+            ("reload", None) => return Reply::Reload,
+            #[cfg(not(unix))]
+            ("reload", None) => "reload is not supported on this platform\n".to_string(),
+            _ => format!("Unknown command '{}'\n", command.trim()),
+        };
+        Reply::Text(reply)
+    }
+
+    fn status(&self) -> String {
+        let mut status = self.metrics.render_status();
+        let paused = self.controls.paused.lock().unwrap();
+        if !paused.is_empty() {
+            let names: Vec<&str> = paused.iter().map(String::as_str).collect();
+            let _ = writeln!(status, "Paused: {}", names.join(", "));
+        }
+        for (fence, endpoints) in self.controls.fenced.lock().unwrap().iter() {
+            let _ = writeln!(status, "Paused inside {}: {}", fence, endpoints.join(", "));
+        }
+        status
+    }
+
+    fn dump(&self) -> String {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        if self
+            .location_tx
+            .send(LocationMessage::Dump(reply_tx))
+            .is_err()
+        {
+            return "Location thread is not running\n".to_string();
+        }
+        reply_rx
+            .recv_timeout(DUMP_TIMEOUT)
+            .unwrap_or_else(|_| "Location thread did not answer\n".to_string())
+    }
+}
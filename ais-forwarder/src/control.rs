@@ -0,0 +1,78 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// A unix socket on which the running daemon answers simple text commands,
+// one command per connection, see commands.rs. `ais-forwarder stats` and
+// `ais-forwarder control` are the client side.
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread::Builder;
+use std::time::Duration;
+
+use crate::commands::{Commands, Reply};
+
+pub fn start(path: &str, commands: Commands) -> io::Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // A socket left behind by an earlier run would make bind fail
+    if Path::new(path).exists() && UnixStream::connect(path).is_err() {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    log::info!("Control socket on {}", path);
+    Builder::new().name("control".to_string()).spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = handle(stream, &commands) {
+                        log::debug!("Control request failed: {}", e);
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Error accepting control connection: {}", e);
+                }
+            }
+        }
+    })?;
+    Ok(())
+}
+
+fn handle(stream: UnixStream, commands: &Commands) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut command = String::new();
+    BufReader::new(&stream).read_line(&mut command)?;
+    log::debug!("Control command: {}", command.trim());
+
+    let mut stream = &stream;
+    match commands.execute(&command) {
+        Reply::Text(reply) => {
+            stream.write_all(reply.as_bytes())?;
+            stream.flush()
+        }
+        Reply::Reload => {
+            stream.write_all(b"Reloading\n")?;
+            stream.flush()?;
+            crate::shutdown::reload();
+        }
+    }
+}
+
+// Send a command to the running daemon and return its reply.
+pub fn request(path: &str, command: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "Cannot connect to {}, is ais-forwarder running? {}",
+                path, e
+            ),
+        )
+    })?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    writeln!(stream, "{}", command)?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
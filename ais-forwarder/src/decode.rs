@@ -0,0 +1,80 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Decode mode: parse sentences given on the command line the way the
+// dispatcher would and print what comes out as JSON, to see why a sentence is
+// dropped or what a station extracts from it. A message in several fragments
+// takes all of them, in order.
+use std::io;
+
+use nmea_parser::{NmeaParser, ParsedMessage};
+use serde_json::{Map, Value, json};
+
+use crate::nmea::{self, ClassB, PayloadBits};
+
+pub fn run(sentences: &[String]) -> io::Result<()> {
+    let mut parser = NmeaParser::new();
+    let mut pending = false;
+    for sentence in sentences {
+        let line = sentence.trim();
+        let (tag_block, body) = nmea::split_tag_block(line);
+        if let Some(tag_block) = tag_block {
+            println!("Tag block: {}", tag_block);
+        }
+        match parser.parse_sentence(body) {
+            Ok(ParsedMessage::Incomplete) => pending = true,
+            Ok(message) => {
+                pending = false;
+                let mut json = serde_json::to_value(&message).map_err(io::Error::other)?;
+                // Next to the fields of the message, within its variant
+                if let Some(extended) = extended_class_b(body)
+                    && let Some(fields) = json
+                        .as_object_mut()
+                        .and_then(|variant| variant.values_mut().next())
+                        .and_then(Value::as_object_mut)
+                {
+                    fields.extend(extended);
+                }
+                let json = serde_json::to_string_pretty(&json).map_err(io::Error::other)?;
+                println!("{}", json);
+            }
+            Err(e) => {
+                let reason = if nmea::checksum_ok(body) {
+                    e.to_string()
+                } else {
+                    "bad checksum".to_string()
+                };
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Cannot parse '{}': {}", line, reason),
+                ));
+            }
+        }
+    }
+    if pending {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Incomplete message, the other fragments are missing",
+        ));
+    }
+    Ok(())
+}
+
+// The parser keeps only the position of an extended Class B report, type 19,
+// and drops the static data of the vessel that comes with it.
+fn extended_class_b(line: &str) -> Option<Map<String, Value>> {
+    if nmea::class_b(line)? != ClassB::Extended {
+        return None;
+    }
+    let bits = PayloadBits::new(nmea::ais_payload(line)?);
+    let mut fields = Map::new();
+    fields.insert("name".to_string(), json!(bits.text(143, 20)?));
+    fields.insert("ship_type".to_string(), json!(bits.unsigned(263, 8)?));
+    // Metres from the GPS antenna
+    fields.insert("to_bow".to_string(), json!(bits.unsigned(271, 9)?));
+    fields.insert("to_stern".to_string(), json!(bits.unsigned(280, 9)?));
+    fields.insert("to_port".to_string(), json!(bits.unsigned(289, 6)?));
+    fields.insert("to_starboard".to_string(), json!(bits.unsigned(295, 6)?));
+    fields.insert("epfd".to_string(), json!(bits.unsigned(301, 4)?));
+    fields.insert("dte_ready".to_string(), json!(bits.unsigned(306, 1)? == 0));
+    fields.insert("assigned".to_string(), json!(bits.unsigned(307, 1)? == 1));
+    Some(fields)
+}
@@ -0,0 +1,92 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// The sending end of a provider, for the modes that make up or play back
+// traffic for a forwarder. The address is the other end of the provider:
+// tcp-listen:// serves a tcp:// provider, tcp:// connects to a tcp-listen://
+// one and udp:// sends to a udp:// one.
+use std::io::{self, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use common::sink::Sink;
+use common::{NetworkEndpoint, Protocol};
+
+use crate::output::Registry;
+
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub enum Feed {
+    // Serving the providers that connect to us
+    Listen(TcpListener, Vec<TcpStream>),
+    Sink(Box<dyn Sink>),
+}
+
+impl Feed {
+    pub fn new(address: &str, outputs: &Registry) -> io::Result<Self> {
+        let mut endpoint: NetworkEndpoint = address.parse()?;
+        match endpoint.protocol {
+            Protocol::TCPListen => {
+                let listener = TcpListener::bind(endpoint.resolve()?)?;
+                listener.set_nonblocking(true)?;
+                Ok(Feed::Listen(listener, Vec::new()))
+            }
+            Protocol::TCP | Protocol::UDP => Ok(Feed::Sink(outputs.create("feed", address)?)),
+            Protocol::UDPListen => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "udp-listen cannot feed a provider, use udp:// to send to it",
+            )),
+        }
+    }
+
+    // A provider that connects to us is waited for, so that the start of a
+    // replay is not lost.
+    pub fn wait_for_provider(&mut self) {
+        if let Feed::Listen(listener, streams) = self {
+            log::info!("Waiting for the provider to connect");
+            while streams.is_empty() {
+                Self::accept(listener, streams);
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    fn accept(listener: &TcpListener, streams: &mut Vec<TcpStream>) {
+        while let Ok((stream, addr)) = listener.accept() {
+            log::info!("Provider connected from {}", addr);
+            // One that stops reading is dropped, not waited for
+            if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_ok() {
+                streams.push(stream);
+            }
+        }
+    }
+
+    pub fn send(&mut self, messages: &[Vec<u8>]) {
+        match self {
+            Feed::Listen(listener, streams) => {
+                Self::accept(listener, streams);
+                streams.retain_mut(|stream| {
+                    match messages
+                        .iter()
+                        .try_for_each(|message| stream.write_all(message))
+                    {
+                        Ok(()) => true,
+                        Err(e) => {
+                            log::info!("Provider disconnected: {}", e);
+                            false
+                        }
+                    }
+                });
+            }
+            Feed::Sink(sink) => {
+                let result = messages
+                    .iter()
+                    .try_for_each(|message| sink.send(message))
+                    .and_then(|()| sink.flush());
+                // The sink waits before connecting again
+                if let Err(e) = result {
+                    log::warn!("{}", e);
+                }
+            }
+        }
+    }
+}
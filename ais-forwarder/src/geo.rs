@@ -0,0 +1,126 @@
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+// Great circle distance in metres between two positions in degrees.
+pub fn distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let phi1 = lat1.to_radians();
+    let phi2 = lat2.to_radians();
+    let delta_phi = (lat2 - lat1).to_radians();
+    let delta_lambda = (lon2 - lon1).to_radians();
+
+    let a = (delta_phi / 2.0).sin().powi(2)
+        + phi1.cos() * phi2.cos() * (delta_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+// Initial great circle bearing in degrees from the first position to the
+// second.
+pub fn bearing(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let phi1 = lat1.to_radians();
+    let phi2 = lat2.to_radians();
+    let delta_lambda = (lon2 - lon1).to_radians();
+
+    let y = delta_lambda.sin() * phi2.cos();
+    let x = phi1.cos() * phi2.sin() - phi1.sin() * phi2.cos() * delta_lambda.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+// A position with speed over ground in knots and course over ground in degrees.
+#[derive(Clone, Copy, Debug)]
+pub struct Motion {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub sog: f64,
+    pub cog: f64,
+}
+
+impl Motion {
+    // East and north velocity in metres per second
+    fn velocity(&self) -> (f64, f64) {
+        let speed = self.sog * 1852.0 / 3600.0;
+        let course = self.cog.to_radians();
+        (speed * course.sin(), speed * course.cos())
+    }
+}
+
+// Closest point of approach of a target to us when both hold their course and
+// speed: the distance at that point in metres and the seconds until then,
+// negative when it has passed. Flat earth around us, which is close enough
+// for the few miles where this matters.
+pub fn cpa(own: &Motion, target: &Motion) -> (f64, f64) {
+    let metres_per_degree = EARTH_RADIUS_M.to_radians();
+    let x =
+        (target.longitude - own.longitude) * own.latitude.to_radians().cos() * metres_per_degree;
+    let y = (target.latitude - own.latitude) * metres_per_degree;
+    let (own_vx, own_vy) = own.velocity();
+    let (target_vx, target_vy) = target.velocity();
+    let (vx, vy) = (target_vx - own_vx, target_vy - own_vy);
+
+    let speed_squared = vx * vx + vy * vy;
+    if speed_squared < 1e-6 {
+        // Not moving relative to each other, the distance stays as it is
+        return ((x * x + y * y).sqrt(), 0.0);
+    }
+    let tcpa = -(x * vx + y * vy) / speed_squared;
+    let (cx, cy) = (x + vx * tcpa, y + vy * tcpa);
+    ((cx * cx + cy * cy).sqrt(), tcpa)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A nautical mile in degrees of latitude, on our sphere
+    const MILE: f64 = 1852.0 / 111_194.93;
+
+    fn motion(latitude: f64, longitude: f64, sog: f64, cog: f64) -> Motion {
+        Motion {
+            latitude,
+            longitude,
+            sog,
+            cog,
+        }
+    }
+
+    #[test]
+    fn distance_of_a_degree_of_latitude() {
+        assert!((distance(52.0, 5.0, 53.0, 5.0) - 111_195.0).abs() < 1.0);
+        assert_eq!(distance(53.0, 5.0, 53.0, 5.0), 0.0);
+    }
+
+    #[test]
+    fn head_on() {
+        let own = motion(53.0, 5.0, 10.0, 0.0);
+        let target = motion(53.0 + MILE, 5.0, 10.0, 180.0);
+        let (distance, tcpa) = cpa(&own, &target);
+        assert!(distance < 1.0);
+        // A mile at twenty knots closing speed
+        assert!((tcpa - 180.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn crossing_ahead() {
+        let own = motion(0.0, 0.0, 0.0, 0.0);
+        let target = motion(MILE / 2.0, -MILE, 10.0, 90.0);
+        let (distance, tcpa) = cpa(&own, &target);
+        assert!((distance - 926.0).abs() < 1.0);
+        assert!((tcpa - 360.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn already_passed() {
+        let own = motion(53.0, 5.0, 5.0, 0.0);
+        let target = motion(53.0 - MILE, 5.0, 10.0, 180.0);
+        let (distance, tcpa) = cpa(&own, &target);
+        assert!(tcpa < 0.0);
+        assert!(distance < 1.0);
+    }
+
+    #[test]
+    fn same_course_and_speed() {
+        let own = motion(53.0, 5.0, 8.0, 45.0);
+        let target = motion(53.0 + MILE, 5.0, 8.0, 45.0);
+        let (distance, tcpa) = cpa(&own, &target);
+        assert!((distance - 1852.0).abs() < 1.0);
+        assert_eq!(tcpa, 0.0);
+    }
+}
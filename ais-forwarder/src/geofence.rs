@@ -0,0 +1,137 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Named circles around a position, such as the home berth, a marina or an
+// anchorage, from the [geofences] section:
+//
+//     name = latitude, longitude, radius in metres[, endpoint ...]
+//
+// The own vessel entering or leaving one raises an event. The [ais] endpoints
+// listed are paused while the vessel is inside, so that a different set of
+// endpoints is fed in harbour than at sea.
+use std::collections::HashMap;
+use std::io;
+
+use crate::commands::Controls;
+use crate::geo;
+use crate::notify::Event;
+
+// Leaving takes getting this much further out than the radius, so that GPS
+// jitter on the edge does not flap in and out.
+const EXIT_MARGIN: f64 = 1.1;
+
+#[derive(Clone, Debug)]
+pub struct Geofence {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    radius: f64,
+    pause: Vec<String>,
+    // Unknown until the first own position
+    inside: Option<bool>,
+}
+
+impl Geofence {
+    fn parse(name: &str, value: &str) -> io::Result<Self> {
+        let invalid = |what: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} in geofence {}, should be latitude, longitude, radius[, endpoint ...]",
+                    what, name
+                ),
+            )
+        };
+        let mut fields = value.split(',').map(str::trim);
+        let mut number = |what: &str| {
+            fields
+                .next()
+                .and_then(|field| field.parse::<f64>().ok())
+                .ok_or_else(|| invalid(what))
+        };
+        let latitude = number("Invalid latitude")?;
+        let longitude = number("Invalid longitude")?;
+        let radius = number("Invalid radius")?;
+        if latitude.abs() > 90.0 || longitude.abs() > 180.0 {
+            return Err(invalid("Position out of range"));
+        }
+        if radius <= 0.0 {
+            return Err(invalid("Radius must be positive"));
+        }
+        Ok(Geofence {
+            name: name.to_string(),
+            latitude,
+            longitude,
+            radius,
+            pause: fields
+                .filter(|field| !field.is_empty())
+                .map(str::to_string)
+                .collect(),
+            inside: None,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // As of the last own position.
+    pub fn inside(&self) -> bool {
+        self.inside == Some(true)
+    }
+
+    // The endpoints paused while inside, for checking them against [ais].
+    pub fn pauses(&self) -> &[String] {
+        &self.pause
+    }
+
+    // Called with every own position. The first one only settles where we
+    // are, so a restart in harbour does not announce arriving there.
+    pub fn update(&mut self, latitude: f64, longitude: f64, controls: &Controls) -> Option<Event> {
+        let distance = geo::distance(self.latitude, self.longitude, latitude, longitude);
+        let inside = match self.inside {
+            Some(true) => distance <= self.radius * EXIT_MARGIN,
+            _ => distance <= self.radius,
+        };
+        let was_inside = self.inside.replace(inside);
+        if was_inside == Some(inside) {
+            return None;
+        }
+
+        if inside {
+            controls.fence_pause(&self.name, &self.pause);
+        } else {
+            controls.fence_resume(&self.name);
+        }
+        match was_inside {
+            None => {
+                if inside {
+                    log::info!("Inside geofence {}", self.name);
+                }
+                None
+            }
+            Some(_) => {
+                log::info!(
+                    "{} geofence {}",
+                    if inside { "Entered" } else { "Left" },
+                    self.name
+                );
+                Some(Event::Geofence {
+                    name: self.name.clone(),
+                    entered: inside,
+                    latitude,
+                    longitude,
+                })
+            }
+        }
+    }
+}
+
+// All geofences from the [geofences] section, in name order.
+pub fn from_config(section: Option<&HashMap<String, String>>) -> io::Result<Vec<Geofence>> {
+    let mut geofences = section
+        .into_iter()
+        .flatten()
+        .map(|(name, value)| Geofence::parse(name, value))
+        .collect::<io::Result<Vec<_>>>()?;
+    geofences.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(geofences)
+}
@@ -0,0 +1,174 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// The quality of our GPS fix, from the GGA and GSA sentences on the NMEA
+// stream. A GPS that is starting up reports positions before it has a proper
+// fix, and those can be miles off; a tracking service would show us there.
+// Location updates are held back while the last GGA or GSA says there is no
+// fix, and with [general] max_hdop while the horizontal dilution of precision
+// is higher than that. A GPS that sends neither is trusted as before, and so
+// is one that stopped sending them for FIX_EXPIRY.
+use std::time::{Duration, Instant};
+
+use crate::nmea;
+
+const FIX_EXPIRY: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+pub struct FixQuality {
+    // Whether there is a fix, and the HDOP, with when they were reported
+    fix: Option<(bool, Instant)>,
+    hdop: Option<(f64, Instant)>,
+    // Why location updates are held back, for logging when that changes
+    holding: Option<String>,
+}
+
+impl FixQuality {
+    // Take the fix quality from a GGA or GSA sentence, ignoring others.
+    pub fn observe(&mut self, line: &str) {
+        let sentence = nmea::split_tag_block(line).1;
+        if !sentence.starts_with('$') {
+            return;
+        }
+        let Some(formatter) = nmea::formatter(sentence) else {
+            return;
+        };
+        let Some(fields) = sentence.split('*').next() else {
+            return;
+        };
+        let fields: Vec<&str> = fields.split(',').collect();
+        let now = Instant::now();
+        // Fields that are empty are not known, and leave what we had
+        let (fix, hdop) = match formatter {
+            // $--GGA,time,lat,N/S,lon,E/W,<quality>,satellites,<hdop>,...
+            // Quality 0 is no fix, 6 is dead reckoning
+            "GGA" => (
+                fields
+                    .get(6)
+                    .and_then(|quality| quality.parse::<u8>().ok())
+                    .map(|quality| quality != 0 && quality != 6),
+                fields.get(8),
+            ),
+            // $--GSA,<A/M>,<1 no fix, 2 2D, 3 3D>,12 satellites,pdop,<hdop>,vdop
+            "GSA" => (
+                fields
+                    .get(2)
+                    .and_then(|mode| mode.parse::<u8>().ok())
+                    .map(|mode| mode >= 2),
+                fields.get(16),
+            ),
+            _ => return,
+        };
+        if let Some(fix) = fix {
+            self.fix = Some((fix, now));
+        }
+        if let Some(hdop) = hdop.and_then(|hdop| hdop.parse::<f64>().ok()) {
+            self.hdop = Some((hdop, now));
+        }
+    }
+
+    // Whether our position is good enough to report, logging when that
+    // changes.
+    pub fn acceptable(&mut self, max_hdop: Option<f64>) -> bool {
+        let holding = self.problem(max_hdop);
+        match (&self.holding, &holding) {
+            (None, Some(problem)) => {
+                log::warn!("Holding back location updates: {}", problem);
+            }
+            (Some(_), None) => log::info!("GPS fix is good again, sending location updates"),
+            _ => {}
+        }
+        let acceptable = holding.is_none();
+        self.holding = holding;
+        acceptable
+    }
+
+    fn problem(&self, max_hdop: Option<f64>) -> Option<String> {
+        let fresh = |when: &Instant| when.elapsed() < FIX_EXPIRY;
+        if let Some((false, when)) = &self.fix
+            && fresh(when)
+        {
+            return Some("the GPS has no fix".to_string());
+        }
+        if let (Some(max_hdop), Some((hdop, when))) = (max_hdop, &self.hdop)
+            && fresh(when)
+            && *hdop > max_hdop
+        {
+            return Some(format!("HDOP {:.1} is above {:.1}", hdop, max_hdop));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observed(sentences: &[&str]) -> FixQuality {
+        let mut quality = FixQuality::default();
+        for sentence in sentences {
+            quality.observe(&nmea::with_checksum(sentence));
+        }
+        quality
+    }
+
+    #[test]
+    fn gga_without_fix() {
+        for quality in ["0", "6"] {
+            let gga = format!(
+                "$GPGGA,123456.00,5310.50,N,00525.00,E,{},00,,,M,,M,,",
+                quality
+            );
+            assert!(!observed(&[&gga]).acceptable(None));
+        }
+    }
+
+    #[test]
+    fn gga_with_fix() {
+        let mut quality = observed(&["$GPGGA,123456.00,5310.50,N,00525.00,E,1,08,0.9,1.0,M,,M,,"]);
+        assert!(quality.acceptable(None));
+        assert!(quality.acceptable(Some(2.0)));
+        assert!(!quality.acceptable(Some(0.5)));
+    }
+
+    #[test]
+    fn empty_hdop_leaves_the_last_one() {
+        let mut quality = observed(&[
+            "$GPGGA,123456.00,5310.50,N,00525.00,E,1,08,6.0,1.0,M,,M,,",
+            "$GPGGA,123457.00,5310.50,N,00525.00,E,1,08,,1.0,M,,M,,",
+        ]);
+        assert!(!quality.acceptable(Some(5.0)));
+        assert!(quality.acceptable(None));
+    }
+
+    #[test]
+    fn gsa_mode_and_hdop() {
+        let mut quality = observed(&["$GPGSA,A,1,,,,,,,,,,,,,,,"]);
+        assert!(!quality.acceptable(None));
+        let mut quality = observed(&["$GNGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1"]);
+        assert!(quality.acceptable(Some(1.5)));
+        assert!(!quality.acceptable(Some(1.0)));
+    }
+
+    #[test]
+    fn other_sentences_are_ignored() {
+        let mut quality = observed(&[
+            "$GPRMC,123456.00,V,,,,,,,010625,,,N",
+            "!AIVDM,1,1,,A,15MgK45P3@G?fl0E`JbR0OwT0@MS,0",
+            "$GPGG",
+        ]);
+        assert!(quality.acceptable(Some(1.0)));
+    }
+
+    #[test]
+    fn non_ascii_does_not_panic() {
+        let mut quality = FixQuality::default();
+        for line in [
+            "$AB\u{e9}\u{e9}",
+            "$\u{e9}GA,0",
+            "$GPGG\u{e9},0",
+            "$\u{1f6a2}",
+        ] {
+            quality.observe(line);
+        }
+        assert!(quality.acceptable(None));
+    }
+}
@@ -0,0 +1,125 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Harbour mode: when the own vessel has not moved for hours, or is inside one
+// of the geofences listed, the vessels around us are forwarded less often and
+// only the nearer ones, to save data while berthed for weeks. Leaving the
+// berth ends it. From the [harbour] section:
+//
+//     after = 21600        seconds without moving, 6 hours when not given
+//     geofences = home     or a list, the names in [geofences]
+//     interval = 600       seconds between forwarded reports of each vessel
+//     range = 2            nautical miles, vessels further away are dropped
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::geo;
+use crate::geofence::Geofence;
+
+const DEFAULT_AFTER: u64 = 6 * 3600;
+const DEFAULT_INTERVAL: u64 = 600;
+// Staying within this many metres of where we stopped is not moving: GPS
+// jitter, swinging at anchor or lying to mooring lines.
+const STILL_RADIUS: f64 = 100.0;
+
+#[derive(Clone, Debug)]
+pub struct Harbour {
+    after: Duration,
+    geofences: Vec<String>,
+    interval: u64,
+    // Metres
+    range: Option<f64>,
+    // Where and since when we have not moved
+    still: Option<(f64, f64, Instant)>,
+    own_position: Option<(f64, f64)>,
+    active: bool,
+}
+
+impl Harbour {
+    pub fn from_config(section: &HashMap<String, String>) -> io::Result<Self> {
+        let number = |key: &str| -> io::Result<Option<f64>> {
+            section
+                .get(key)
+                .map(|value| {
+                    value.trim().parse::<f64>().map_err(|e| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Invalid {} '{}': {}", key, value, e),
+                        )
+                    })
+                })
+                .transpose()
+        };
+        Ok(Harbour {
+            after: Duration::from_secs(number("after")?.map_or(DEFAULT_AFTER, |s| s as u64)),
+            geofences: section
+                .get("geofences")
+                .into_iter()
+                .flat_map(|names| names.split(','))
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect(),
+            interval: number("interval")?.map_or(DEFAULT_INTERVAL, |s| s as u64),
+            range: number("range")?.map(|nm| nm * 1852.0),
+            still: None,
+            own_position: None,
+            active: false,
+        })
+    }
+
+    // The geofences it goes by, for checking them against [geofences].
+    pub fn geofences(&self) -> &[String] {
+        &self.geofences
+    }
+
+    // Seconds between forwarded reports of each vessel, 0 when not in
+    // harbour mode.
+    pub fn interval(&self) -> u64 {
+        if self.active { self.interval } else { 0 }
+    }
+
+    // Called with every own position, after the geofences are.
+    pub fn update(&mut self, latitude: f64, longitude: f64, now: Instant, geofences: &[Geofence]) {
+        self.own_position = Some((latitude, longitude));
+        let since = match self.still {
+            Some((still_latitude, still_longitude, since))
+                if geo::distance(still_latitude, still_longitude, latitude, longitude)
+                    <= STILL_RADIUS =>
+            {
+                since
+            }
+            _ => {
+                self.still = Some((latitude, longitude, now));
+                now
+            }
+        };
+        let fenced = geofences.iter().find(|geofence| {
+            geofence.inside() && self.geofences.iter().any(|name| name == geofence.name())
+        });
+        let active = fenced.is_some() || now.duration_since(since) >= self.after;
+        if active == self.active {
+            return;
+        }
+        self.active = active;
+        match (active, fenced) {
+            (true, Some(geofence)) => {
+                log::info!("Harbour mode: inside geofence {}", geofence.name());
+            }
+            (true, None) => log::info!(
+                "Harbour mode: not moved for {} hours",
+                now.duration_since(since).as_secs() / 3600
+            ),
+            (false, _) => log::info!("Harbour mode ended, under way"),
+        }
+    }
+
+    // Whether a vessel at this position is forwarded.
+    pub fn in_range(&self, latitude: f64, longitude: f64) -> bool {
+        match (self.active, self.range, self.own_position) {
+            (true, Some(range), Some((own_latitude, own_longitude))) => {
+                geo::distance(own_latitude, own_longitude, latitude, longitude) <= range
+            }
+            _ => true,
+        }
+    }
+}
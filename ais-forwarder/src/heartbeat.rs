@@ -0,0 +1,59 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Periodically tell a monitoring service that this station is alive. The URL
+// can be a healthchecks.io style ping URL, which alerts the owner when the
+// pings stop, or any endpoint that accepts a JSON POST.
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use std::thread::Builder;
+use std::time::Duration;
+
+use crate::metrics::Metrics;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn start(
+    section: &HashMap<String, String>,
+    mmsi: u32,
+    metrics: Arc<Metrics>,
+) -> io::Result<()> {
+    let url = match section.get("url") {
+        Some(url) => url.clone(),
+        None => return Ok(()),
+    };
+    let interval = match section.get("interval").map(|v| v.parse::<u64>()) {
+        None => 300,
+        Some(Ok(interval)) => interval,
+        Some(Err(e)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid interval in [heartbeat] section: {}", e),
+            ));
+        }
+    };
+    let interval = Duration::from_secs(interval.max(10));
+    let station = section
+        .get("station")
+        .cloned()
+        .unwrap_or_else(|| mmsi.to_string());
+
+    log::info!("Sending heartbeat to {} every {}s", url, interval.as_secs());
+    Builder::new()
+        .name("heartbeat".to_string())
+        .spawn(move || {
+            loop {
+                let mut body = metrics.summary_json();
+                body["station"] = serde_json::Value::String(station.clone());
+                match ureq::post(&url)
+                    .timeout(HTTP_TIMEOUT)
+                    .set("Content-Type", "application/json")
+                    .send_string(&body.to_string())
+                {
+                    Ok(_) => log::debug!("Heartbeat sent to {}", url),
+                    Err(e) => log::warn!("Cannot send heartbeat to {}: {}", url, e),
+                }
+                std::thread::sleep(interval);
+            }
+        })?;
+    Ok(())
+}
@@ -0,0 +1,220 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::Builder;
+use std::time::Duration;
+
+use crate::commands::{Commands, Reply};
+use crate::logbook::Logbook;
+use crate::metrics::Metrics;
+use crate::targets::Targets;
+
+// The control commands that only look, and may be sent with GET. The others
+// change something and need POST.
+const READ_ONLY_COMMANDS: [&str; 4] = ["help", "status", "stats", "dump"];
+const COMMANDS: [&str; 4] = ["pause", "resume", "locate", "reload"];
+
+// The control commands under /api/<command>[/<argument>], only served when a
+// token is configured and the request carries it as a bearer token.
+pub struct Api {
+    pub token: String,
+    pub commands: Commands,
+}
+
+// A deliberately tiny HTTP/1.1 server for the status and metrics pages.
+// Requests are handled one at a time, which is plenty for a scraper and a
+// browser now and then.
+pub fn start(
+    listen: SocketAddr,
+    metrics: Arc<Metrics>,
+    targets: Arc<Targets>,
+    logbook: Arc<Logbook>,
+    api: Option<Api>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    log::info!("Serving status on http://{}", listen);
+    Builder::new().name("http".to_string()).spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = handle(stream, &metrics, &targets, &logbook, api.as_ref()) {
+                        log::debug!("HTTP request failed: {}", e);
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Error accepting HTTP connection: {}", e);
+                }
+            }
+        }
+    })?;
+    Ok(())
+}
+
+fn handle(
+    stream: TcpStream,
+    metrics: &Metrics,
+    targets: &Targets,
+    logbook: &Logbook,
+    api: Option<&Api>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut authorization = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("authorization")
+        {
+            authorization = Some(value.trim().to_string());
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+    log::debug!("HTTP {} {}", method, path);
+
+    let mut reload = false;
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            metrics.render_prometheus(),
+        ),
+        ("GET", "/") | ("GET", "/status") => (
+            "200 OK",
+            "text/plain; charset=utf-8",
+            metrics.render_status(),
+        ),
+        ("GET", "/weather") => (
+            "200 OK",
+            "application/json",
+            metrics.weather_json().to_string(),
+        ),
+        ("GET", "/targets.geojson") => (
+            "200 OK",
+            "application/geo+json",
+            targets.geojson().to_string(),
+        ),
+        ("GET", "/vessels") => (
+            "200 OK",
+            "text/html; charset=utf-8",
+            targets.html(metrics.own_position(), &metrics.voyage()),
+        ),
+        ("GET", path) if path.starts_with("/track/") => track_request(targets, path),
+        ("GET", "/logbook") => ("200 OK", "application/json", logbook.json().to_string()),
+        ("GET", "/logbook.csv") => ("200 OK", "text/csv; charset=utf-8", logbook.csv()),
+        ("GET", "/talkers") => (
+            "200 OK",
+            "text/plain; charset=utf-8",
+            metrics.render_talkers(100),
+        ),
+        (_, path) if api.is_some() && path.starts_with("/api/") => {
+            let (status, body, then_reload) =
+                api_request(api.unwrap(), method, path, authorization.as_deref());
+            reload = then_reload;
+            (status, "text/plain; charset=utf-8", body)
+        }
+        _ => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+    };
+
+    // Web maps on other sites may load the targets
+    let cors = if path == "/targets.geojson" || path.starts_with("/track/") {
+        "Access-Control-Allow-Origin: *\r\n"
+    } else {
+        ""
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        cors,
+        body
+    )?;
+    stream.flush()?;
+    if reload {
+        #[cfg(unix)]
+        crate::shutdown::reload();
+    }
+    Ok(())
+}
+
+// GET /track/<mmsi>?hours=<hours>, the last day when hours is not given.
+fn track_request(targets: &Targets, path: &str) -> (&'static str, &'static str, String) {
+    let request = &path["/track/".len()..];
+    let (mmsi, query) = request.split_once('?').unwrap_or((request, ""));
+    let hours = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "hours")
+        .map(|(_, hours)| hours.parse::<u32>());
+    let (Ok(mmsi), Ok(hours)) = (mmsi.parse::<u32>(), hours.unwrap_or(Ok(24))) else {
+        return (
+            "400 Bad Request",
+            "text/plain",
+            "Expected /track/<mmsi>?hours=<hours>\n".to_string(),
+        );
+    };
+    match targets.track(mmsi, hours) {
+        Some(track) => ("200 OK", "application/geo+json", track.to_string()),
+        None => (
+            "404 Not Found",
+            "text/plain",
+            format!("No track of MMSI {}\n", mmsi),
+        ),
+    }
+}
+
+// Status, body and whether to reload once the reply has been sent.
+fn api_request(
+    api: &Api,
+    method: &str,
+    path: &str,
+    authorization: Option<&str>,
+) -> (&'static str, String, bool) {
+    if !authorized(authorization, &api.token) {
+        log::warn!("Unauthorized HTTP API request {} {}", method, path);
+        return ("401 Unauthorized", "Unauthorized\n".to_string(), false);
+    }
+    let request = &path["/api/".len()..];
+    let (name, argument) = request.split_once('/').unwrap_or((request, ""));
+    if !READ_ONLY_COMMANDS.contains(&name) && !COMMANDS.contains(&name) {
+        return ("404 Not Found", "Not found\n".to_string(), false);
+    }
+    if method != "POST" && !(method == "GET" && READ_ONLY_COMMANDS.contains(&name)) {
+        return (
+            "405 Method Not Allowed",
+            "Method not allowed\n".to_string(),
+            false,
+        );
+    }
+    match api.commands.execute(&format!("{} {}", name, argument)) {
+        Reply::Text(text) => ("200 OK", text, false),
+        #[cfg(unix)]
+        Reply::Reload => ("200 OK", "Reloading\n".to_string(), true),
+    }
+}
+
+// Compare the bearer token without giving away how much of it was right.
+fn authorized(authorization: Option<&str>, token: &str) -> bool {
+    let Some(given) = authorization.and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
@@ -0,0 +1,268 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Iridium output: our own position as Short Burst Data, through a RockBLOCK or
+// other Iridium 9602/9603 modem on a serial port, for tracking where there is
+// no other link at all. Meant for [location]:
+//
+//     rockblock = iridium:///dev/ttyUSB0?interval=1800
+//
+// A position is taken every `interval` seconds, 1800 when not given, in the
+// 17 byte frame of lora.rs, and queued. Each SBD session sends all that is
+// queued in one message, frame after frame, up to MAX_FRAMES; the oldest are
+// dropped when more wait. A session that fails, because the antenna does not
+// see the sky, is tried again after RETRY_MIN, doubling up to the interval.
+// As a session is costly, sending only happens when the location thread
+// sends us something, never on a timer of our own.
+//
+// Every session also checks the mailbox: a message from shore is logged, and
+// when more are waiting another session picks them up. With ?mailbox=seconds
+// the mailbox is checked that often as well when nothing is queued. Sessions
+// take up to a minute, during which the location thread waits.
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
+
+use common::sink::Sink;
+
+use crate::lora;
+use crate::modem::Modem;
+use crate::output::Options;
+
+const DEFAULT_INTERVAL: u64 = 1800;
+// An SBD message from us is at most 340 bytes
+const MAX_FRAMES: usize = 20;
+const RETRY_MIN: Duration = Duration::from_secs(60);
+// Sessions to fetch the messages that are waiting for us in a row
+const MAX_MAILBOX_SESSIONS: usize = 3;
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+const SESSION_TIMEOUT: Duration = Duration::from_secs(90);
+
+// What +SBDIX answers, after a session.
+struct Session {
+    // 0 to 4 when our message went out
+    mo_status: u32,
+    // 1 when a message for us came in
+    mt_status: u32,
+    // Messages still waiting for us
+    mt_queued: u32,
+}
+
+impl Session {
+    // +SBDIX: <MO status>, <MOMSN>, <MT status>, <MTMSN>, <MT length>, <MT queued>
+    fn parse(answer: &str) -> Option<Session> {
+        let (_, rest) = answer.split_once("+SBDIX:")?;
+        let fields: Vec<u32> = rest
+            .lines()
+            .next()?
+            .split(',')
+            .map(|field| field.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        match fields[..] {
+            [mo_status, _, mt_status, _, _, mt_queued] => Some(Session {
+                mo_status,
+                mt_status,
+                mt_queued,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub struct IridiumSink {
+    name: String,
+    device: String,
+    interval: Duration,
+    mailbox: Option<Duration>,
+    modem: Option<Modem>,
+    queue: VecDeque<Vec<u8>>,
+    // When the last position was queued, and when a session is due
+    queued: Option<Instant>,
+    next_session: Instant,
+    retry: Duration,
+    last_session: Instant,
+    healthy: bool,
+}
+
+impl IridiumSink {
+    pub fn create(name: &str, url: &str, _options: &Options) -> io::Result<Box<dyn Sink>> {
+        let rest = url.strip_prefix("iridium://").unwrap_or(url);
+        let (device, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let mut interval = DEFAULT_INTERVAL;
+        let mut mailbox = None;
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            let seconds = value
+                .parse::<u64>()
+                .map_err(|e| invalid(format!("{}: invalid {}: {}", url, key, e)))?;
+            match key {
+                "interval" => interval = seconds,
+                "mailbox" => mailbox = Some(Duration::from_secs(seconds)),
+                _ => {
+                    return Err(invalid(format!(
+                        "{}: unknown option '{}={}'",
+                        url, key, value
+                    )));
+                }
+            }
+        }
+        if device.is_empty() {
+            return Err(invalid(format!("{}: missing device", url)));
+        }
+        let now = Instant::now();
+        Ok(Box::new(IridiumSink {
+            name: name.to_string(),
+            device: device.to_string(),
+            interval: Duration::from_secs(interval),
+            mailbox,
+            modem: None,
+            queue: VecDeque::new(),
+            queued: None,
+            next_session: now,
+            retry: RETRY_MIN,
+            last_session: now,
+            healthy: true,
+        }))
+    }
+
+    // Whether a session is due: for what is queued once a retry is allowed,
+    // or to check the mailbox.
+    fn due(&self) -> bool {
+        let now = Instant::now();
+        if !self.queue.is_empty() {
+            return now >= self.next_session;
+        }
+        self.mailbox
+            .is_some_and(|mailbox| now.duration_since(self.last_session) >= mailbox)
+    }
+
+    // Send what is queued, if anything, and fetch what waits for us.
+    fn session(&mut self) -> io::Result<()> {
+        self.connect()?;
+        let Some(modem) = self.modem.as_mut() else {
+            return Ok(());
+        };
+        self.last_session = Instant::now();
+        let message: Vec<u8> = self.queue.iter().flatten().copied().collect();
+        if message.is_empty() {
+            modem.command("AT+SBDD0", "OK", COMMAND_TIMEOUT)?;
+        } else {
+            write_message(modem, &message)?;
+        }
+        for _ in 0..MAX_MAILBOX_SESSIONS {
+            let answer = modem.command("AT+SBDIX", "OK", SESSION_TIMEOUT)?;
+            let session = Session::parse(&answer).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: unexpected answer '{}'", self.device, answer.trim()),
+                )
+            })?;
+            if session.mo_status > 4 {
+                return Err(io::Error::other(format!(
+                    "{}: session failed with status {}",
+                    self.device, session.mo_status
+                )));
+            }
+            if !self.queue.is_empty() {
+                log::info!(endpoint = self.name.as_str(); "{}: Sent {} positions", self.name, self.queue.len());
+                self.queue.clear();
+                modem.command("AT+SBDD0", "OK", COMMAND_TIMEOUT)?;
+            }
+            if session.mt_status == 1 {
+                let answer = modem.command("AT+SBDRT", "OK", COMMAND_TIMEOUT)?;
+                let text = answer
+                    .split_once("+SBDRT:")
+                    .map_or("", |(_, text)| text.trim().trim_end_matches("OK").trim());
+                log::info!(endpoint = self.name.as_str(); "{}: Message from shore: {}", self.name, text);
+            }
+            if session.mt_queued == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Put the message in the modem's outgoing buffer, followed by the sum of its
+// bytes as a checksum.
+fn write_message(modem: &mut Modem, message: &[u8]) -> io::Result<()> {
+    modem.command(
+        &format!("AT+SBDWB={}", message.len()),
+        "READY",
+        COMMAND_TIMEOUT,
+    )?;
+    let checksum = message
+        .iter()
+        .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    modem.write(message)?;
+    modem.write(&checksum.to_be_bytes())?;
+    // 0 when taken, 1 timeout, 2 bad checksum, 3 bad size
+    let answer = modem.expect("OK", COMMAND_TIMEOUT)?;
+    match answer.split_whitespace().next() {
+        Some("0") => Ok(()),
+        status => Err(io::Error::other(format!(
+            "message not taken, status {}",
+            status.unwrap_or("none")
+        ))),
+    }
+}
+
+impl Sink for IridiumSink {
+    fn connect(&mut self) -> io::Result<()> {
+        if self.modem.is_none() {
+            let mut modem = Modem::open(&self.device)?;
+            // No echo, and no flow control, as a RockBLOCK has only three wires
+            modem.command("ATE0", "OK", COMMAND_TIMEOUT)?;
+            modem.command("AT&K0", "OK", COMMAND_TIMEOUT)?;
+            log::info!(endpoint = self.name.as_str(); "{}: Iridium modem on {}", self.name, self.device);
+            self.modem = Some(modem);
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        if let Some(frame) = lora::frame(message)
+            && self
+                .queued
+                .is_none_or(|queued| queued.elapsed() >= self.interval)
+        {
+            self.queued = Some(Instant::now());
+            self.queue.push_back(frame);
+            if self.queue.len() > MAX_FRAMES {
+                self.queue.pop_front();
+            }
+        }
+        Ok(())
+    }
+
+    // Queued positions are kept by us rather than by the location thread, so
+    // a failed session is not an error to it; it shows in healthy.
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.due() {
+            return Ok(());
+        }
+        match self.session() {
+            Ok(()) => {
+                self.healthy = true;
+                self.retry = RETRY_MIN;
+            }
+            Err(e) => {
+                log::warn!(endpoint = self.name.as_str(); "{}: {}, trying again in {}s", self.name, e, self.retry.as_secs());
+                self.healthy = false;
+                self.modem = None;
+                self.next_session = Instant::now() + self.retry;
+                self.retry = (self.retry * 2).min(self.interval.max(RETRY_MIN));
+            }
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        if !self.queue.is_empty() {
+            log::warn!(endpoint = self.name.as_str(); "{}: {} positions not sent", self.name, self.queue.len());
+        }
+        self.modem = None;
+    }
+
+    fn healthy(&self) -> bool {
+        self.healthy
+    }
+}
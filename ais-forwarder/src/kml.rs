@@ -0,0 +1,164 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// KML mode: turn the own positions in the audit log into a track that Google
+// Earth can open and play back with its time slider. The audit log is the
+// only place where the forwarder keeps where we have been, so audit_log must
+// be set for there to be a track.
+//
+// A name ending in .kmz gets the KML zipped, as Google Earth likes it for
+// sharing; the zip is stored, not compressed, which it reads just the same.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+
+struct Point {
+    time: DateTime<Utc>,
+    latitude: f64,
+    longitude: f64,
+}
+
+pub fn run(log: &str, output: &str, hours: Option<u64>) -> io::Result<()> {
+    let file = File::open(log).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", log, e)))?;
+    let since = hours.map(|hours| Utc::now() - Duration::hours(hours as i64));
+    let mut points = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Some(point) = parse(&line?)
+            && since.is_none_or(|since| point.time >= since)
+        {
+            points.push(point);
+        }
+    }
+    points.sort_by_key(|point| point.time);
+    // Each send to each endpoint has a line, the track needs each position once
+    points.dedup_by(|b, a| {
+        a.time == b.time || (a.latitude == b.latitude && a.longitude == b.longitude)
+    });
+    if points.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{}: no own positions found", log),
+        ));
+    }
+
+    let document = document(&points);
+    let mut file = File::create(output)?;
+    if output.to_ascii_lowercase().ends_with(".kmz") {
+        file.write_all(&zip("doc.kml", document.as_bytes()))?;
+    } else {
+        file.write_all(document.as_bytes())?;
+    }
+    println!(
+        "Wrote {} positions from {} to {} to {}",
+        points.len(),
+        points[0].time.format("%Y-%m-%d %H:%M"),
+        points[points.len() - 1].time.format("%Y-%m-%d %H:%M"),
+        output
+    );
+    Ok(())
+}
+
+// 2025-06-01T12:00:00Z location/aprs 53.17500 5.41700 1234$GNRMC,...
+fn parse(line: &str) -> Option<Point> {
+    let mut fields = line.split_whitespace();
+    let time = DateTime::parse_from_rfc3339(fields.next()?).ok()?;
+    let _endpoint = fields.next()?;
+    let latitude = fields.next()?.parse().ok()?;
+    let longitude = fields.next()?.parse().ok()?;
+    Some(Point {
+        time: time.with_timezone(&Utc),
+        latitude,
+        longitude,
+    })
+}
+
+fn document(points: &[Point]) -> String {
+    let mut kml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<kml xmlns=\"http://www.opengis.net/kml/2.2\" xmlns:gx=\"http://www.google.com/kml/ext/2.2\">\n",
+        "<Document>\n",
+        "<name>Own track</name>\n",
+        "<Style id=\"track\"><LineStyle><color>ff0000ff</color><width>3</width></LineStyle></Style>\n",
+        "<Placemark>\n",
+        "<name>Own track</name>\n",
+        "<styleUrl>#track</styleUrl>\n",
+        "<gx:Track>\n",
+        "<altitudeMode>clampToGround</altitudeMode>\n",
+    ));
+    for point in points {
+        kml.push_str(&format!(
+            "<when>{}</when>\n",
+            point.time.format("%Y-%m-%dT%H:%M:%SZ")
+        ));
+    }
+    for point in points {
+        kml.push_str(&format!(
+            "<gx:coord>{:.5} {:.5} 0</gx:coord>\n",
+            point.longitude, point.latitude
+        ));
+    }
+    kml.push_str("</gx:Track>\n</Placemark>\n</Document>\n</kml>\n");
+    kml
+}
+
+// A zip archive with one stored file.
+fn zip(name: &str, data: &[u8]) -> Vec<u8> {
+    let now = Utc::now();
+    let time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+    let date = ((((now.year() - 1980).max(0) as u32) << 9) | (now.month() << 5) | now.day()) as u16;
+    let crc = crc32(data);
+    let size = data.len() as u32;
+    let name = name.as_bytes();
+
+    // Version needed 2.0, no flags, stored, then the same in both headers
+    let mut common = Vec::new();
+    common.extend_from_slice(&20u16.to_le_bytes());
+    common.extend_from_slice(&0u16.to_le_bytes());
+    common.extend_from_slice(&0u16.to_le_bytes());
+    common.extend_from_slice(&time.to_le_bytes());
+    common.extend_from_slice(&date.to_le_bytes());
+    common.extend_from_slice(&crc.to_le_bytes());
+    common.extend_from_slice(&size.to_le_bytes());
+    common.extend_from_slice(&size.to_le_bytes());
+    common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    common.extend_from_slice(&0u16.to_le_bytes());
+
+    let mut zip = Vec::new();
+    zip.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+    zip.extend_from_slice(&common);
+    zip.extend_from_slice(name);
+    zip.extend_from_slice(data);
+
+    let directory = zip.len() as u32;
+    zip.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+    zip.extend_from_slice(&20u16.to_le_bytes());
+    zip.extend_from_slice(&common);
+    // Comment length, disk, attributes and the offset of the local header
+    zip.extend_from_slice(&[0; 10]);
+    zip.extend_from_slice(&0u32.to_le_bytes());
+    zip.extend_from_slice(name);
+    let directory_size = zip.len() as u32 - directory;
+
+    zip.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    zip.extend_from_slice(&[0; 4]);
+    zip.extend_from_slice(&1u16.to_le_bytes());
+    zip.extend_from_slice(&1u16.to_le_bytes());
+    zip.extend_from_slice(&directory_size.to_le_bytes());
+    zip.extend_from_slice(&directory.to_le_bytes());
+    zip.extend_from_slice(&0u16.to_le_bytes());
+    zip
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
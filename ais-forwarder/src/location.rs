@@ -1,52 +1,181 @@
 /// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
 use nmea_parser::ParsedMessage;
+use nmea_parser::ais::VesselStaticData;
 use std::collections::HashMap;
 use std::io;
-use std::sync::mpsc::Receiver;
-use std::time::Duration;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::{Duration, Instant};
 
+use common::sink::Sink;
+
+use crate::Shared;
+use crate::audit::AuditLog;
 use crate::cache::Persistence;
-use crate::{NetworkEndpoint, send_message};
+use crate::capture::Capture;
+use crate::commands::Controls;
+use crate::metrics::{DropReason, Metrics};
+use crate::sms::{self, Sms};
+
+// Updates waiting for this thread, unless [queues] location says otherwise.
+// One comes every location_interval at most, so this is plenty.
+pub const QUEUE_SIZE: usize = 100;
+
+// Wait before the loop is started again after it panicked, doubling up to the
+// maximum when it keeps doing so.
+const MIN_RESTART: Duration = Duration::from_secs(1);
+const MAX_RESTART: Duration = Duration::from_secs(60);
+
+pub enum LocationMessage {
+    Position(ParsedMessage),
+    // Static data of our own vessel, AIS type 5 or 24
+    Static(ParsedMessage),
+    // Store what is pending and stop, then acknowledge
+    Shutdown(Sender<()>),
+    // Reply with the stored messages, one per line
+    Dump(Sender<String>),
+}
 
 pub fn work_thread(
-    rx: std::sync::mpsc::Receiver<ParsedMessage>,
-    location: HashMap<String, NetworkEndpoint>,
+    rx: Receiver<LocationMessage>,
+    location: HashMap<String, Box<dyn Sink>>,
     mmsi: u32,
     cache_dir: &str,
+    shared: Shared,
 ) {
     let persistence = Persistence::new(cache_dir);
+    let mut location = Location::new(location, persistence, mmsi, shared);
+
+    // A panic while handling one update does not stop our position from being
+    // reported: the loop is started again on the same channel, so the
+    // dispatcher, the commands and the shutdown handler keep reaching us.
+    // Stored updates are resent when it starts.
+    let mut delay = MIN_RESTART;
+    loop {
+        let started = Instant::now();
+        match std::panic::catch_unwind(AssertUnwindSafe(|| location.location_loop(&rx))) {
+            Ok(_) => return,
+            Err(_) => {
+                if started.elapsed() > MAX_RESTART {
+                    delay = MIN_RESTART;
+                }
+                log::error!("Location thread failed, restarting in {}s", delay.as_secs());
+                std::thread::sleep(delay);
+                delay = (delay * 2).min(MAX_RESTART);
+            }
+        }
+    }
+}
+
+// Our name, call sign and dimensions, as our transponder sends them, so that
+// the location server can label us without being told. Type 24 sends them in
+// two parts, so they are collected.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OwnStatic {
+    pub name: Option<String>,
+    pub call_sign: Option<String>,
+    // Metres from the GPS antenna
+    pub to_bow: Option<u16>,
+    pub to_stern: Option<u16>,
+    pub to_port: Option<u16>,
+    pub to_starboard: Option<u16>,
+}
+
+impl OwnStatic {
+    // Take what a static data message has, true when anything changed.
+    fn update(&mut self, data: &VesselStaticData) -> bool {
+        let before = self.clone();
+        let text = |value: &Option<String>| value.clone().filter(|value| !value.is_empty());
+        self.name = text(&data.name).or(self.name.take());
+        self.call_sign = text(&data.call_sign).or(self.call_sign.take());
+        self.to_bow = data.dimension_to_bow.or(self.to_bow);
+        self.to_stern = data.dimension_to_stern.or(self.to_stern);
+        self.to_port = data.dimension_to_port.or(self.to_port);
+        self.to_starboard = data.dimension_to_starboard.or(self.to_starboard);
+        *self != before
+    }
 
-    let _ = Location::new(location, persistence, mmsi).location_loop(&rx);
+    pub fn length(&self) -> Option<u16> {
+        Some(self.to_bow? + self.to_stern?)
+    }
+
+    pub fn beam(&self) -> Option<u16> {
+        Some(self.to_port? + self.to_starboard?)
+    }
+
+    // For the status page, such as "HOLLANDIA (PD1234), 12 x 4 m".
+    pub fn describe(&self) -> String {
+        let mut text = self.name.clone().unwrap_or_else(|| "unnamed".to_string());
+        if let Some(call_sign) = &self.call_sign {
+            text.push_str(&format!(" ({})", call_sign));
+        }
+        if let (Some(length), Some(beam)) = (self.length(), self.beam()) {
+            text.push_str(&format!(", {} x {} m", length, beam));
+        }
+        text
+    }
+
+    // An IEC 61162-1 SSD, ship static data, without a checksum like the RMC we
+    // send.
+    fn ssd(&self) -> String {
+        let number = |value: Option<u16>| value.map(|v| v.to_string()).unwrap_or_default();
+        format!(
+            "$AISSD,{},{},{},{},{},{},,AI",
+            self.call_sign.as_deref().unwrap_or(""),
+            self.name.as_deref().unwrap_or(""),
+            number(self.to_bow),
+            number(self.to_stern),
+            number(self.to_port),
+            number(self.to_starboard),
+        )
+    }
 }
 
 struct Location {
-    location: HashMap<String, NetworkEndpoint>,
+    location: HashMap<String, Box<dyn Sink>>,
     persistence: Persistence,
     mmsi: u32,
+    own_static: OwnStatic,
+    // Send the static data with the next position
+    static_pending: bool,
     prev_latitude: Option<f64>,
     prev_longitude: Option<f64>,
     doubtful_latitude: Option<f64>,
     doubtful_longitude: Option<f64>,
+    metrics: Arc<Metrics>,
+    capture: Option<Arc<Capture>>,
+    audit: Option<Arc<AuditLog>>,
+    controls: Arc<Controls>,
+    sms: Option<Sms>,
 }
 
 impl Location {
     fn new(
-        location: HashMap<String, NetworkEndpoint>,
+        location: HashMap<String, Box<dyn Sink>>,
         persistence: Persistence,
         mmsi: u32,
+        shared: Shared,
     ) -> Self {
         Self {
             location,
             persistence,
             mmsi,
+            own_static: OwnStatic::default(),
+            static_pending: false,
             prev_latitude: None,
             prev_longitude: None,
             doubtful_latitude: None,
             doubtful_longitude: None,
+            metrics: shared.metrics,
+            capture: shared.capture,
+            audit: shared.audit,
+            controls: shared.controls,
+            sms: shared.sms,
         }
     }
 
-    fn location_loop(&mut self, rx: &Receiver<ParsedMessage>) -> io::Result<()> {
+    fn location_loop(&mut self, rx: &Receiver<LocationMessage>) -> io::Result<()> {
         const MESSAGE_TIMEOUT: Duration = Duration::from_secs(360);
 
         log::info!(
@@ -58,14 +187,41 @@ impl Location {
         let mut first = true;
 
         loop {
+            self.metrics.record_progress("location");
+            self.metrics.set_location_stored(self.persistence.count());
             match rx.recv_timeout(MESSAGE_TIMEOUT) {
-                Ok(message) => {
+                Ok(LocationMessage::Shutdown(ack)) => {
+                    self.persistence.flush();
+                    for sink in self.location.values_mut() {
+                        sink.close();
+                    }
+                    log::info!(
+                        "Location thread stopped with {} messages stored",
+                        self.persistence.count()
+                    );
+                    let _ = ack.send(());
+                    return Ok(());
+                }
+                Ok(LocationMessage::Dump(reply)) => {
+                    let _ = reply.send(self.dump());
+                }
+                Ok(LocationMessage::Static(ParsedMessage::VesselStaticData(data))) => {
+                    if self.own_static.update(&data) {
+                        log::info!("Own vessel is {}", self.own_static.describe());
+                        self.metrics.record_own_static(&self.own_static);
+                        self.static_pending = true;
+                    }
+                }
+                Ok(LocationMessage::Static(_)) => {}
+                Ok(LocationMessage::Position(message)) => {
+                    self.metrics.location_dequeued();
                     log::debug!("Received message: {:?}", message);
                     if !connection_ok {
                         first = true;
                         connection_ok = self.resend_messages().is_ok();
                     }
                     connection_ok = self.parse_message(&message, connection_ok).is_ok();
+                    self.sms_fallback(&message, connection_ok);
                     if first {
                         log::info!(
                             "Location thread sent first message, connection ok: {}",
@@ -84,16 +240,56 @@ impl Location {
                     }
                     std::sync::mpsc::RecvTimeoutError::Disconnected => {
                         log::error!("Receiver disconnected");
-                        return Err(io::Error::new(
-                            io::ErrorKind::Other,
-                            "Receiver disconnected",
-                        ));
+                        return Err(io::Error::other("Receiver disconnected"));
                     }
                 },
             }
         }
     }
 
+    // Our position by SMS, when the location endpoints have been failing for
+    // long enough.
+    fn sms_fallback(&mut self, message: &ParsedMessage, connection_ok: bool) {
+        let Some(sms) = self.sms.as_mut() else {
+            return;
+        };
+        let (Some(latitude), Some(longitude)) = (self.prev_latitude, self.prev_longitude) else {
+            return;
+        };
+        if !sms.due(connection_ok) {
+            return;
+        }
+        let (sog, cog, time) = match message {
+            ParsedMessage::VesselDynamicData(data) => (data.sog_knots, data.cog, None),
+            ParsedMessage::Rmc(rmc) => (rmc.sog_knots, rmc.bearing, rmc.timestamp),
+            _ => (None, None, None),
+        };
+        let who = match &self.own_static.name {
+            Some(name) => format!("{} {}", name, self.mmsi),
+            None => self.mmsi.to_string(),
+        };
+        let text = sms::text(
+            &who,
+            latitude,
+            longitude,
+            sog,
+            cog,
+            time.unwrap_or_else(chrono::Utc::now),
+        );
+        sms.send(&text);
+    }
+
+    fn dump(&self) -> String {
+        let mut dump = format!("{} stored messages\n", self.persistence.count());
+        for (key, value) in self.persistence.iter().flatten() {
+            dump.push_str(&String::from_utf8_lossy(&key));
+            dump.push(' ');
+            dump.push_str(String::from_utf8_lossy(&value).trim_end());
+            dump.push('\n');
+        }
+        dump
+    }
+
     fn resend_messages(&mut self) -> io::Result<()> {
         let resend_count = self.persistence.count();
         if resend_count == 0 {
@@ -106,11 +302,31 @@ impl Location {
                 Ok((key, value)) => {
                     let key = &key.to_vec();
                     let value = &value.to_vec();
-                    let skey = String::from_utf8_lossy(&key);
-                    let svalue = String::from_utf8_lossy(&value);
+                    let skey = String::from_utf8_lossy(key);
+                    let svalue = String::from_utf8_lossy(value);
                     log::debug!("Resending message: {}: {}", skey, svalue);
-                    for (key, address) in self.location.iter_mut() {
-                        send_message(value, key, address)?;
+                    for (key, sink) in self.location.iter_mut() {
+                        if self.controls.is_paused("location", key) {
+                            continue;
+                        }
+                        let start = Instant::now();
+                        // Flushed right away, a stored message is only removed once sent
+                        let result = sink.send(value).and_then(|_| sink.flush());
+                        self.metrics.record_send(
+                            "location",
+                            key,
+                            value.len(),
+                            start.elapsed(),
+                            &result,
+                        );
+                        self.metrics.record_health("location", key, sink.healthy());
+                        result?;
+                        if let Some(capture) = &self.capture {
+                            capture.sent("location", key, value);
+                        }
+                        if let Some(audit) = &self.audit {
+                            audit.record("location", key, None, value);
+                        }
                     }
                     self.persistence.remove(key);
                     self.persistence.flush();
@@ -140,30 +356,30 @@ impl Location {
             log::warn!("Invalid position: latitude and longitude are too close to zero");
             return false;
         }
-        if let Some(prev_latitude) = self.prev_latitude {
-            if (latitude - prev_latitude).abs() >= 2.00 {
-                if let Some(doubtful_latitude) = self.doubtful_latitude {
-                    if (latitude - doubtful_latitude).abs() >= 2.00 {
-                        log::warn!("Doubtful position: latitude change is too big");
-                        return false;
-                    }
-                } else {
-                    log::warn!("Invalid position: latitude change is too big");
+        if let Some(prev_latitude) = self.prev_latitude
+            && (latitude - prev_latitude).abs() >= 2.00
+        {
+            if let Some(doubtful_latitude) = self.doubtful_latitude {
+                if (latitude - doubtful_latitude).abs() >= 2.00 {
+                    log::warn!("Doubtful position: latitude change is too big");
                     return false;
                 }
+            } else {
+                log::warn!("Invalid position: latitude change is too big");
+                return false;
             }
         }
-        if let Some(prev_longitude) = self.prev_longitude {
-            if (longitude - prev_longitude).abs() >= 2.00 {
-                if let Some(doubtful_longitude) = self.doubtful_longitude {
-                    if (longitude - doubtful_longitude).abs() >= 2.00 {
-                        log::warn!("Doubtful position: longitude change is too big");
-                        return false;
-                    }
-                } else {
-                    log::warn!("Invalid position: longitude change is too big");
+        if let Some(prev_longitude) = self.prev_longitude
+            && (longitude - prev_longitude).abs() >= 2.00
+        {
+            if let Some(doubtful_longitude) = self.doubtful_longitude {
+                if (longitude - doubtful_longitude).abs() >= 2.00 {
+                    log::warn!("Doubtful position: longitude change is too big");
                     return false;
                 }
+            } else {
+                log::warn!("Invalid position: longitude change is too big");
+                return false;
             }
         }
 
@@ -229,17 +445,63 @@ impl Location {
             }
         };
 
+        // Our static data goes along with the first position after it changed
+        let nmea_message = if self.static_pending {
+            self.static_pending = false;
+            format!("{}{}\r\n{}", self.mmsi, self.own_static.ssd(), nmea_message)
+        } else {
+            nmea_message
+        };
+
+        // The GPS fix time tells us how long the position took to get here
+        let source_time = match message {
+            ParsedMessage::Rmc(message) => message.timestamp,
+            _ => None,
+        };
         let nmea_bytes = nmea_message.as_bytes();
-        for (key, address) in self.location.iter_mut() {
+        for (key, sink) in self.location.iter_mut() {
+            if self.controls.is_paused("location", key) {
+                self.metrics.record_dropped(Some(key), DropReason::Paused);
+                continue;
+            }
             let db_key = format!("{}-{}", now, key);
             if !connection_ok {
-                log::debug!("Storing message: {}: {}", key, nmea_message);
+                log::debug!(endpoint = key.as_str(); "Storing message: {}: {}", key, nmea_message);
                 self.persistence.store(db_key.as_bytes(), nmea_bytes);
                 self.persistence.flush();
             } else {
-                log::debug!("Sending message: {}: {}", key, nmea_message);
-                if let Err(e) = send_message(&nmea_bytes, key, address) {
-                    log::error!("Error sending location message to {}: {}", key, e);
+                log::debug!(endpoint = key.as_str(); "Sending message: {}: {}", key, nmea_message);
+                let start = Instant::now();
+                let result = sink.send(nmea_bytes).and_then(|_| sink.flush());
+                self.metrics.record_send(
+                    "location",
+                    key,
+                    nmea_bytes.len(),
+                    start.elapsed(),
+                    &result,
+                );
+                self.metrics.record_health("location", key, sink.healthy());
+                if result.is_ok() {
+                    if let Some(age) =
+                        source_time.and_then(|time| (chrono::Utc::now() - time).to_std().ok())
+                    {
+                        self.metrics.record_age("location", key, age);
+                    }
+                    if let Some(capture) = &self.capture {
+                        capture.sent("location", key, nmea_bytes);
+                    }
+                    if let Some(audit) = &self.audit {
+                        let position = self.prev_latitude.zip(self.prev_longitude);
+                        audit.record("location", key, position, nmea_bytes);
+                    }
+                }
+                if let Err(e) = result {
+                    log::error!(
+                        endpoint = key.as_str();
+                        "Error sending location message to {}: {}",
+                        key,
+                        e
+                    );
                     self.persistence.store(db_key.as_bytes(), nmea_bytes);
                     self.persistence.flush();
                 }
@@ -0,0 +1,269 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// The automatic logbook: our trips from departure to arrival, kept in the
+// cache directory and served by the [http] status server as /logbook, JSON,
+// and /logbook.csv for a spreadsheet.
+//
+// A trip starts when we leave the spot where we lay still, more than
+// STILL_RADIUS from it, and ends once we have been still again for
+// ARRIVE_AFTER; the arrival is when we got there. The ports are the names of
+// the [geofences] we were inside at either end, or left out. Trips shorter
+// than MIN_DISTANCE, such as moving to the fuel berth, are not logged.
+//
+// Each trip is a line of JSON in logbook.jsonl. The trip under way is in
+// logbook-trip.json, saved every SAVE_INTERVAL, so that it carries on after
+// a restart or a power cut at sea.
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::geo;
+
+// The same as for harbour mode: GPS jitter, swinging at anchor or lying to
+// mooring lines is not moving.
+const STILL_RADIUS: f64 = 100.0;
+const ARRIVE_AFTER: chrono::Duration = chrono::Duration::minutes(30);
+// Nautical miles
+const MIN_DISTANCE: f64 = 0.5;
+const SAMPLE_INTERVAL: chrono::Duration = chrono::Duration::seconds(60);
+// Metres between two samples that are GPS jitter rather than distance sailed
+const NOISE: f64 = 20.0;
+const SAVE_INTERVAL: chrono::Duration = chrono::Duration::minutes(15);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Place {
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Trip {
+    // RFC 3339, UTC
+    pub departure: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arrival: Option<String>,
+    pub from: Place,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<Place>,
+    // Nautical miles
+    pub distance: f64,
+}
+
+// Where we lie still: since when, when we were last seen there, and the port
+// we are in.
+struct Still {
+    since: DateTime<Utc>,
+    last: DateTime<Utc>,
+    latitude: f64,
+    longitude: f64,
+    port: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    trips: Vec<Trip>,
+    trip: Option<Trip>,
+    still: Option<Still>,
+    sample: Option<(DateTime<Utc>, f64, f64)>,
+    saved: Option<DateTime<Utc>>,
+}
+
+pub struct Logbook {
+    path: PathBuf,
+    trip_path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl Logbook {
+    // The trips logged so far, and the trip under way, from the cache.
+    pub fn open(cache_dir: &str) -> Self {
+        let path = Path::new(cache_dir).join("logbook.jsonl");
+        let trip_path = Path::new(cache_dir).join("logbook-trip.json");
+        let mut state = State::default();
+        if let Ok(contents) = fs::read_to_string(&path) {
+            for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+                match serde_json::from_str(line) {
+                    Ok(trip) => state.trips.push(trip),
+                    Err(e) => log::warn!("Skipping logbook entry in {}: {}", path.display(), e),
+                }
+            }
+        }
+        if let Ok(contents) = fs::read_to_string(&trip_path) {
+            match serde_json::from_str::<Trip>(&contents) {
+                Ok(trip) => {
+                    log::info!("Trip under way since {}", trip.departure);
+                    state.trip = Some(trip);
+                }
+                Err(e) => log::warn!("Ignoring {}: {}", trip_path.display(), e),
+            }
+        }
+        Logbook {
+            path,
+            trip_path,
+            state: Mutex::new(state),
+        }
+    }
+
+    // Called with every own position and the geofence we are in, if any.
+    pub fn update(&self, now: DateTime<Utc>, latitude: f64, longitude: f64, port: Option<&str>) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        match state.sample {
+            Some((time, _, _)) if now - time < SAMPLE_INTERVAL => {}
+            sample => {
+                if let Some((_, last_latitude, last_longitude)) = sample
+                    && let Some(trip) = state.trip.as_mut()
+                {
+                    let step = geo::distance(last_latitude, last_longitude, latitude, longitude);
+                    if step > NOISE {
+                        trip.distance += step / 1852.0;
+                    }
+                }
+                state.sample = Some((now, latitude, longitude));
+            }
+        }
+
+        match state.still.as_mut() {
+            Some(still)
+                if geo::distance(still.latitude, still.longitude, latitude, longitude)
+                    <= STILL_RADIUS =>
+            {
+                still.last = now;
+                if now - still.since >= ARRIVE_AFTER
+                    && let Some(mut trip) = state.trip.take()
+                {
+                    trip.arrival = Some(time_text(still.since));
+                    trip.to = Some(Place {
+                        latitude: still.latitude,
+                        longitude: still.longitude,
+                        port: port.map(str::to_string).or_else(|| still.port.clone()),
+                    });
+                    self.arrive(trip, &mut state.trips);
+                }
+            }
+            still => {
+                if state.trip.is_none()
+                    && let Some(still) = still
+                {
+                    let trip = Trip {
+                        departure: time_text(still.last),
+                        arrival: None,
+                        from: Place {
+                            latitude: still.latitude,
+                            longitude: still.longitude,
+                            port: still.port.clone(),
+                        },
+                        to: None,
+                        distance: 0.0,
+                    };
+                    log::info!(
+                        "Departed {} at {}",
+                        trip.from.port.as_deref().unwrap_or("anchorage"),
+                        trip.departure
+                    );
+                    state.trip = Some(trip);
+                    state.saved = None;
+                }
+                state.still = Some(Still {
+                    since: now,
+                    last: now,
+                    latitude,
+                    longitude,
+                    port: port.map(str::to_string),
+                });
+            }
+        }
+
+        if let Some(trip) = &state.trip
+            && state.saved.is_none_or(|saved| now - saved >= SAVE_INTERVAL)
+        {
+            state.saved = Some(now);
+            if let Err(e) = save(&self.trip_path, trip) {
+                log::warn!("Cannot save {}: {}", self.trip_path.display(), e);
+            }
+        }
+    }
+
+    fn arrive(&self, trip: Trip, trips: &mut Vec<Trip>) {
+        let _ = fs::remove_file(&self.trip_path);
+        if trip.distance < MIN_DISTANCE {
+            log::info!("Moved {:.1} nm, not logged as a trip", trip.distance);
+            return;
+        }
+        log::info!(
+            "Arrived in {} after {:.1} nm",
+            trip.to
+                .as_ref()
+                .and_then(|to| to.port.as_deref())
+                .unwrap_or("anchorage"),
+            trip.distance
+        );
+        if let Err(e) = append(&self.path, &trip) {
+            log::error!("Cannot write logbook {}: {}", self.path.display(), e);
+        }
+        trips.push(trip);
+    }
+
+    // All trips, the one under way last, without an arrival.
+    pub fn json(&self) -> Value {
+        let state = self.state.lock().unwrap();
+        json!(state.trips.iter().chain(&state.trip).collect::<Vec<_>>())
+    }
+
+    pub fn csv(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut csv = String::from(
+            "departure,arrival,from,to,from_latitude,from_longitude,to_latitude,to_longitude,distance_nm\n",
+        );
+        for trip in state.trips.iter().chain(&state.trip) {
+            let to = trip.to.as_ref();
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{:.5},{:.5},{},{},{:.1}",
+                trip.departure,
+                trip.arrival.as_deref().unwrap_or(""),
+                csv_field(trip.from.port.as_deref().unwrap_or("")),
+                csv_field(to.and_then(|to| to.port.as_deref()).unwrap_or("")),
+                trip.from.latitude,
+                trip.from.longitude,
+                to.map_or(String::new(), |to| format!("{:.5}", to.latitude)),
+                to.map_or(String::new(), |to| format!("{:.5}", to.longitude)),
+                trip.distance
+            );
+        }
+        csv
+    }
+}
+
+fn time_text(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+// Quoted when it has a comma or a quote, as geofence names may.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn append(path: &Path, trip: &Trip) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(trip)?)
+}
+
+// Written to a temporary file first, so a power cut leaves the old one.
+fn save(path: &Path, trip: &Trip) -> io::Result<()> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, serde_json::to_string(trip)?)?;
+    fs::rename(&temporary, path)
+}
@@ -0,0 +1,437 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Log backends. By default everything goes to stderr via env_logger, in a
+// container it may have to be stdout instead, but
+// under procd or systemd it is nicer to talk to syslog or journald directly
+// so that records keep their priority. A Windows service has no stderr at
+// all and logs to the Event Log instead.
+use env_logger::Env;
+use log::kv::{Key, Value, VisitSource};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::UdpSocket;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::Cli;
+
+#[cfg(unix)]
+const SYSLOG_SOCKET: &str = "/dev/log";
+#[cfg(unix)]
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+pub const APP_NAME: &str = "ais-forwarder";
+const FACILITY_DAEMON: u8 = 3;
+const RATE_LIMIT_PERIOD: Duration = Duration::from_secs(60);
+// Forget about messages that were last seen this many windows ago
+const MAX_TRACKED_MESSAGES: usize = 1000;
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
+pub enum LogTarget {
+    Stderr,
+    Stdout,
+    Syslog,
+    #[cfg(unix)]
+    Journald,
+    #[cfg(windows)]
+    Eventlog,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+enum SyslogSocket {
+    #[cfg(unix)]
+    Local(UnixDatagram),
+    Remote(UdpSocket),
+}
+
+enum Backend {
+    // env_logger, writing to stderr or stdout
+    Console,
+    Syslog {
+        socket: SyslogSocket,
+        hostname: String,
+    },
+    #[cfg(unix)]
+    Journald(UnixDatagram),
+    #[cfg(windows)]
+    Eventlog(crate::windows::EventLog),
+}
+
+struct Logger {
+    filter: env_logger::Logger,
+    backend: Backend,
+    format: LogFormat,
+    throttle: Mutex<Throttle>,
+}
+
+// Warnings and errors tend to repeat for as long as an endpoint is down, and
+// on a router every line ends up in flash. Identical messages are therefore
+// only logged once per window, and every module gets a limited number of
+// warnings per minute.
+struct Throttle {
+    window: Duration,
+    rate_limit: u32,
+    messages: HashMap<String, Repeats>,
+    sources: HashMap<String, Budget>,
+}
+
+struct Repeats {
+    since: Instant,
+    count: u32,
+}
+
+struct Budget {
+    since: Instant,
+    count: u32,
+    suppressed: u32,
+}
+
+pub fn init(cli: &Cli) {
+    let level: LevelFilter = cli.verbose.log_level_filter();
+    let target = &cli.log_target;
+    let format = &cli.log_format;
+    let syslog_server = cli.syslog_server.as_deref();
+
+    let mut builder = env_logger::Builder::from_env(Env::default());
+    builder.filter_level(level);
+    if *target == LogTarget::Stdout {
+        builder.target(env_logger::Target::Stdout);
+    }
+    match format {
+        LogFormat::Text => {
+            // When running as a procd daemon, the PWD environment variable is not set
+            // which can be used to shorten the logging records that already contain the timestamp.
+            if std::env::var("PWD").is_err() {
+                builder.format_timestamp(None);
+            }
+            // The endpoint and MMSI fields are already part of the text messages.
+            builder.format_key_values(|_, _| Ok(()));
+        }
+        LogFormat::Json => {
+            builder.format(|buf, record| writeln!(buf, "{}", format_json(record)));
+        }
+    }
+
+    let backend = match open_backend(target, syslog_server) {
+        Ok(backend) => backend,
+        Err(e) => {
+            eprintln!("Cannot open {:?} log target, using stderr: {}", target, e);
+            Backend::Console
+        }
+    };
+
+    let filter = builder.build();
+    let max_level = filter.filter();
+    let logger = Logger {
+        filter,
+        backend,
+        format: format.clone(),
+        throttle: Mutex::new(Throttle {
+            window: Duration::from_secs(cli.log_repeat_window),
+            rate_limit: cli.log_rate_limit,
+            messages: HashMap::new(),
+            sources: HashMap::new(),
+        }),
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+fn open_backend(target: &LogTarget, syslog_server: Option<&str>) -> io::Result<Backend> {
+    match target {
+        LogTarget::Stderr | LogTarget::Stdout => Ok(Backend::Console),
+        LogTarget::Syslog => {
+            let socket = match syslog_server {
+                Some(server) => {
+                    let socket = UdpSocket::bind("0.0.0.0:0")?;
+                    socket.connect(server)?;
+                    SyslogSocket::Remote(socket)
+                }
+                #[cfg(unix)]
+                None => {
+                    let socket = UnixDatagram::unbound()?;
+                    socket.connect(SYSLOG_SOCKET)?;
+                    SyslogSocket::Local(socket)
+                }
+                #[cfg(not(unix))]
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "There is no local syslog, use --syslog-server",
+                    ));
+                }
+            };
+            Ok(Backend::Syslog {
+                socket,
+                hostname: hostname(),
+            })
+        }
+        #[cfg(unix)]
+        LogTarget::Journald => {
+            let socket = UnixDatagram::unbound()?;
+            socket.connect(JOURNALD_SOCKET)?;
+            Ok(Backend::Journald(socket))
+        }
+        #[cfg(windows)]
+        LogTarget::Eventlog => Ok(Backend::Eventlog(crate::windows::EventLog::open(APP_NAME)?)),
+    }
+}
+
+pub fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|s| s.trim().to_string())
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+// Map log levels to syslog severities, which journald uses as well.
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.filter.matches(record) {
+            return;
+        }
+        if record.level() > Level::Warn {
+            self.write(record);
+            return;
+        }
+
+        let message = record.args().to_string();
+        let (notices, allowed) = self
+            .throttle
+            .lock()
+            .unwrap()
+            .check(record.target(), &message);
+        for notice in notices {
+            self.write(
+                &Record::builder()
+                    .args(format_args!("{}", notice))
+                    .level(record.level())
+                    .target(record.target())
+                    .module_path(record.module_path())
+                    .build(),
+            );
+        }
+        if allowed {
+            self.write(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.filter.flush();
+    }
+}
+
+impl Throttle {
+    // Returns notices about earlier suppressed messages that should be logged
+    // first, and whether this message should be logged at all.
+    fn check(&mut self, source: &str, message: &str) -> (Vec<String>, bool) {
+        let now = Instant::now();
+        let mut notices = Vec::new();
+
+        if !self.window.is_zero() {
+            if let Some(repeats) = self.messages.get_mut(message) {
+                if now.duration_since(repeats.since) < self.window {
+                    repeats.count += 1;
+                    return (notices, false);
+                }
+                if repeats.count > 0 {
+                    notices.push(format!(
+                        "Last message repeated {} times in {}s: {}",
+                        repeats.count,
+                        now.duration_since(repeats.since).as_secs(),
+                        message
+                    ));
+                }
+                repeats.since = now;
+                repeats.count = 0;
+            } else {
+                if self.messages.len() >= MAX_TRACKED_MESSAGES {
+                    let window = self.window;
+                    self.messages
+                        .retain(|_, repeats| now.duration_since(repeats.since) < window);
+                }
+                self.messages.insert(
+                    message.to_string(),
+                    Repeats {
+                        since: now,
+                        count: 0,
+                    },
+                );
+            }
+        }
+
+        if self.rate_limit > 0 {
+            let budget = self.sources.entry(source.to_string()).or_insert(Budget {
+                since: now,
+                count: 0,
+                suppressed: 0,
+            });
+            if now.duration_since(budget.since) >= RATE_LIMIT_PERIOD {
+                if budget.suppressed > 0 {
+                    notices.push(format!(
+                        "{} messages from {} suppressed in the last {}s",
+                        budget.suppressed,
+                        source,
+                        now.duration_since(budget.since).as_secs()
+                    ));
+                }
+                budget.since = now;
+                budget.count = 0;
+                budget.suppressed = 0;
+            }
+            if budget.count >= self.rate_limit {
+                budget.suppressed += 1;
+                return (notices, false);
+            }
+            budget.count += 1;
+        }
+        (notices, true)
+    }
+}
+
+impl Logger {
+    fn write(&self, record: &Record) {
+        // There is nowhere to report a failure to log, so errors are dropped.
+        let _ = match &self.backend {
+            Backend::Console => {
+                self.filter.log(record);
+                Ok(0)
+            }
+            Backend::Syslog { socket, hostname } => {
+                let message = format_syslog(record, hostname, &self.format);
+                match socket {
+                    #[cfg(unix)]
+                    SyslogSocket::Local(socket) => socket.send(message.as_bytes()),
+                    SyslogSocket::Remote(socket) => socket.send(message.as_bytes()),
+                }
+            }
+            #[cfg(unix)]
+            Backend::Journald(socket) => socket.send(&format_journald(record)),
+            #[cfg(windows)]
+            Backend::Eventlog(event_log) => event_log
+                .report(record.level(), &record.args().to_string())
+                .map(|()| 0),
+        };
+    }
+}
+
+// RFC 5424: <PRI>VERSION TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG
+fn format_syslog(record: &Record, hostname: &str, format: &LogFormat) -> String {
+    let message = match format {
+        LogFormat::Text => record.args().to_string(),
+        LogFormat::Json => format_json(record),
+    };
+    format!(
+        "<{}>1 {} {} {} {} - - {}",
+        FACILITY_DAEMON * 8 + severity(record.level()),
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+        hostname,
+        APP_NAME,
+        std::process::id(),
+        message
+    )
+}
+
+// One JSON object per record, with the structured key-values (endpoint, mmsi)
+// as top level fields so that log shippers can index them.
+fn format_json(record: &Record) -> String {
+    let mut object = serde_json::Map::new();
+    object.insert(
+        "timestamp".to_string(),
+        chrono::Utc::now()
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+            .into(),
+    );
+    object.insert("level".to_string(), record.level().as_str().into());
+    object.insert(
+        "module".to_string(),
+        record.module_path().unwrap_or(record.target()).into(),
+    );
+    object.insert("message".to_string(), record.args().to_string().into());
+    let _ = record.key_values().visit(&mut JsonFields(&mut object));
+    serde_json::Value::Object(object).to_string()
+}
+
+struct JsonFields<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let value = match value.to_u64() {
+            Some(number) => number.into(),
+            None => value.to_string().into(),
+        };
+        self.0.insert(key.as_str().to_string(), value);
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+struct JournaldFields<'a>(&'a mut Vec<u8>);
+
+#[cfg(unix)]
+impl<'kvs> VisitSource<'kvs> for JournaldFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        append_journald_field(self.0, &key.as_str().to_uppercase(), &value.to_string());
+        Ok(())
+    }
+}
+
+// The journald native protocol: one datagram with KEY=value lines. Values that
+// contain a newline must use the binary form KEY\n<u64 le length><value>\n.
+#[cfg(unix)]
+fn format_journald(record: &Record) -> Vec<u8> {
+    let mut datagram = Vec::with_capacity(256);
+    append_journald_field(
+        &mut datagram,
+        "PRIORITY",
+        &severity(record.level()).to_string(),
+    );
+    append_journald_field(&mut datagram, "SYSLOG_IDENTIFIER", APP_NAME);
+    append_journald_field(&mut datagram, "TARGET", record.target());
+    if let Some(file) = record.file() {
+        append_journald_field(&mut datagram, "CODE_FILE", file);
+    }
+    if let Some(line) = record.line() {
+        append_journald_field(&mut datagram, "CODE_LINE", &line.to_string());
+    }
+    let _ = record
+        .key_values()
+        .visit(&mut JournaldFields(&mut datagram));
+    append_journald_field(&mut datagram, "MESSAGE", &record.args().to_string());
+    datagram
+}
+
+#[cfg(unix)]
+fn append_journald_field(datagram: &mut Vec<u8>, key: &str, value: &str) {
+    datagram.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        datagram.push(b'\n');
+        datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        datagram.push(b'=');
+    }
+    datagram.extend_from_slice(value.as_bytes());
+    datagram.push(b'\n');
+}
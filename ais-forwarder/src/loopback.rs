@@ -0,0 +1,357 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Loopback harness: a Dispatcher wired to a fake provider and fake endpoints
+// in the process, so that whole paths through the forwarder, from sentences
+// read to messages sent, are covered by cargo test. The tests wait on threads
+// and clocks, so they are only built with the loopback feature:
+//
+//     cargo test -p ais-forwarder --features loopback
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::Builder;
+use std::time::{Duration, Instant};
+
+use common::sink::Sink;
+
+use crate::clock::ClockSource;
+use crate::commands::{Commands, Reply};
+use crate::location::{self, LocationMessage};
+use crate::nmea::{self, PayloadWriter};
+use crate::reader::{self, Input, Reader};
+use crate::sender::{DropPolicy, Endpoint};
+use crate::{Dispatcher, Shared};
+
+// How long to wait for something that should happen, and for something that
+// should not.
+const TIMEOUT: Duration = Duration::from_secs(5);
+const SETTLE: Duration = Duration::from_millis(300);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub struct Options {
+    pub endpoints: usize,
+    // As in config.ini, in seconds
+    pub interval: u64,
+    pub location_interval: u64,
+    pub passthrough: bool,
+    pub clock: ClockSource,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            endpoints: 2,
+            interval: 10,
+            location_interval: 1,
+            passthrough: false,
+            clock: ClockSource::Wall,
+        }
+    }
+}
+
+// An endpoint that keeps the sentences it is sent, and fails while it is down.
+#[derive(Clone, Default)]
+pub struct FakeSink {
+    sent: Arc<Mutex<Vec<String>>>,
+    down: Arc<AtomicBool>,
+    healthy: bool,
+}
+
+impl FakeSink {
+    pub fn sent(&self) -> Vec<String> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    pub fn set_down(&self, down: bool) {
+        self.down.store(down, Ordering::Relaxed);
+    }
+
+    fn check(&mut self) -> io::Result<()> {
+        self.healthy = !self.down.load(Ordering::Relaxed);
+        if !self.healthy {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "fake endpoint is down",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Sink for FakeSink {
+    fn connect(&mut self) -> io::Result<()> {
+        self.check()
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        self.check()?;
+        let mut sent = self.sent.lock().unwrap();
+        sent.extend(nmea::lines(message).map(str::to_string));
+        Ok(())
+    }
+
+    fn close(&mut self) {}
+
+    fn healthy(&self) -> bool {
+        self.healthy
+    }
+}
+
+pub struct Loopback {
+    feed: SyncSender<Input>,
+    pub endpoints: Vec<FakeSink>,
+    locations: Receiver<LocationMessage>,
+    commands: Commands,
+}
+
+impl Loopback {
+    // Start a dispatcher with endpoints fake0, fake1, ... It stops when the
+    // Loopback is dropped, like it does when the provider reader stops.
+    pub fn start(options: &Options) -> io::Result<Self> {
+        let mut shared = Shared::standalone(options.passthrough)?;
+        shared.clock = options.clock;
+        let endpoints: Vec<FakeSink> = (0..options.endpoints)
+            .map(|_| FakeSink::default())
+            .collect();
+        let ais = endpoints
+            .iter()
+            .enumerate()
+            .map(|(i, sink)| {
+                Endpoint::start(
+                    &format!("fake{}", i),
+                    Box::new(sink.clone()),
+                    DropPolicy::default(),
+                    &shared,
+                )
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let (feed, input) = std::sync::mpsc::sync_channel(reader::QUEUE_SIZE);
+        let (tx, locations) = std::sync::mpsc::sync_channel(location::QUEUE_SIZE);
+        let commands = Commands::new(
+            shared.controls.clone(),
+            shared.metrics.clone(),
+            tx.clone(),
+            shared.notifier.clone(),
+        );
+        let reader = Reader::fed(input);
+        let mut dispatcher = Dispatcher::new(
+            reader,
+            ais,
+            tx,
+            options.interval,
+            options.location_interval,
+            86400,
+            &shared,
+        );
+        Builder::new()
+            .name("loopback dispatcher".to_string())
+            .spawn(move || {
+                let _ = dispatcher.work();
+            })?;
+        Ok(Loopback {
+            feed,
+            endpoints,
+            locations,
+            commands,
+        })
+    }
+
+    // Hand sentences to the dispatcher as one chunk from the provider.
+    pub fn feed<S: AsRef<str>>(&self, sentences: &[S]) {
+        let chunk = nmea::group(sentences);
+        self.feed
+            .send(Input::Chunk(0, chunk))
+            .expect("dispatcher stopped");
+    }
+
+    // Run a control command, as on the control socket.
+    pub fn command(&self, command: &str) -> String {
+        match self.commands.execute(command) {
+            Reply::Text(text) => text,
+            #[cfg(unix)]
+            Reply::Reload => "Reloading\n".to_string(),
+        }
+    }
+
+    // The sentences an endpoint was sent, once it has `count` of them. Then
+    // wait a little longer, so that a test also sees the ones too many.
+    pub fn wait_for(&self, endpoint: usize, count: usize) -> Vec<String> {
+        let sink = &self.endpoints[endpoint];
+        let start = Instant::now();
+        while sink.sent().len() < count && start.elapsed() < TIMEOUT {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        std::thread::sleep(SETTLE);
+        sink.sent()
+    }
+
+    // The own positions that reached the location thread so far.
+    pub fn locations(&self) -> usize {
+        self.locations
+            .try_iter()
+            .filter(|message| matches!(message, LocationMessage::Position(_)))
+            .count()
+    }
+}
+
+// A class A position report.
+pub fn position_report(mmsi: u32, latitude: f64, longitude: f64) -> String {
+    let mut w = PayloadWriter::default();
+    w.unsigned(1, 6)
+        .unsigned(0, 2)
+        .unsigned(mmsi as u64, 30)
+        .unsigned(0, 4)
+        .signed(-128, 8)
+        .unsigned(60, 10)
+        .unsigned(0, 1)
+        .signed((longitude * 600_000.0).round() as i64, 28)
+        .signed((latitude * 600_000.0).round() as i64, 27)
+        .unsigned(450, 12)
+        .unsigned(45, 9)
+        .unsigned(0, 6)
+        .unsigned(0, 6)
+        .unsigned(0, 19);
+    let (payload, fill) = w.finish();
+    nmea::ais_sentences("AIVDM", &payload, fill, 0).remove(0)
+}
+
+// A sentence with a TAG block saying it was received at `time`, in seconds
+// since 1970.
+pub fn tagged(time: u64, sentence: &str) -> String {
+    format!(
+        "{}\\{}",
+        nmea::with_checksum(&format!("\\c:{}", time)),
+        sentence
+    )
+}
+
+// An RMC of our own GPS, in the north east quadrant.
+pub fn rmc(latitude: f64, longitude: f64) -> String {
+    let now = chrono::Utc::now();
+    nmea::with_checksum(&format!(
+        "$GPRMC,{},A,{:02}{:07.4},N,{:03}{:07.4},E,6.0,45.0,{},,,A",
+        now.format("%H%M%S.00"),
+        latitude.trunc(),
+        latitude.fract() * 60.0,
+        longitude.trunc(),
+        longitude.fract() * 60.0,
+        now.format("%d%m%y"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwards_to_every_endpoint() {
+        let loopback = Loopback::start(&Options::default()).unwrap();
+        let sentence = position_report(244_100_001, 53.175, 5.417);
+        loopback.feed(&[&sentence]);
+        for endpoint in 0..2 {
+            assert_eq!(loopback.wait_for(endpoint, 1), vec![sentence.clone()]);
+        }
+    }
+
+    #[test]
+    fn throttles_each_vessel() {
+        let loopback = Loopback::start(&Options::default()).unwrap();
+        let first = position_report(244_100_001, 53.175, 5.417);
+        let again = position_report(244_100_001, 53.176, 5.418);
+        let other = position_report(244_100_002, 53.180, 5.420);
+        loopback.feed(&[&first, &again, &other]);
+        assert_eq!(loopback.wait_for(0, 2), vec![first, other]);
+    }
+
+    #[test]
+    fn throttles_by_message_time() {
+        let options = Options {
+            clock: ClockSource::Message,
+            ..Options::default()
+        };
+        let loopback = Loopback::start(&options).unwrap();
+        // Received over 25 seconds, fed at once
+        let reports: Vec<String> = [0, 5, 12, 20, 25]
+            .iter()
+            .map(|seconds| {
+                tagged(
+                    1_750_000_000 + seconds,
+                    &position_report(244_100_001, 53.175, 5.417),
+                )
+            })
+            .collect();
+        loopback.feed(&reports);
+        let sent = loopback.wait_for(0, 3);
+        assert_eq!(
+            sent,
+            vec![reports[0].clone(), reports[2].clone(), reports[4].clone()]
+        );
+    }
+
+    #[test]
+    fn passthrough_does_not_throttle() {
+        let options = Options {
+            passthrough: true,
+            ..Options::default()
+        };
+        let loopback = Loopback::start(&options).unwrap();
+        let first = position_report(244_100_001, 53.175, 5.417);
+        let again = position_report(244_100_001, 53.176, 5.418);
+        loopback.feed(&[&first, &again]);
+        assert_eq!(loopback.wait_for(0, 2), vec![first, again]);
+    }
+
+    #[test]
+    fn paused_endpoint_gets_nothing() {
+        let loopback = Loopback::start(&Options::default()).unwrap();
+        loopback.command("pause fake1");
+        let sentence = position_report(244_100_001, 53.175, 5.417);
+        loopback.feed(&[&sentence]);
+        assert_eq!(loopback.wait_for(0, 1).len(), 1);
+        assert!(loopback.endpoints[1].sent().is_empty());
+
+        loopback.command("resume fake1");
+        let other = position_report(244_100_002, 53.180, 5.420);
+        loopback.feed(&[&other]);
+        assert_eq!(loopback.wait_for(1, 1), vec![other]);
+    }
+
+    #[test]
+    fn endpoint_recovers() {
+        let loopback = Loopback::start(&Options::default()).unwrap();
+        loopback.endpoints[0].set_down(true);
+        let lost = position_report(244_100_001, 53.175, 5.417);
+        loopback.feed(&[&lost]);
+        // The other endpoint is not held up
+        assert_eq!(loopback.wait_for(1, 1), vec![lost]);
+        assert!(loopback.endpoints[0].sent().is_empty());
+
+        loopback.endpoints[0].set_down(false);
+        let sentence = position_report(244_100_002, 53.180, 5.420);
+        loopback.feed(&[&sentence]);
+        assert_eq!(loopback.wait_for(0, 1), vec![sentence]);
+    }
+
+    #[test]
+    fn own_position_once_per_location_interval() {
+        let loopback = Loopback::start(&Options::default()).unwrap();
+        // Moving a few hundred metres every 100 ms for 2.5 s, with a
+        // location_interval of 1 s
+        let start = Instant::now();
+        let mut latitude = 53.175;
+        while start.elapsed() < Duration::from_millis(2500) {
+            loopback.feed(&[rmc(latitude, 5.417)]);
+            latitude += 0.002;
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        std::thread::sleep(SETTLE);
+        let locations = loopback.locations();
+        assert!(
+            (1..=3).contains(&locations),
+            "{} location updates",
+            locations
+        );
+        // Our own GPS is not forwarded as AIS
+        assert!(loopback.endpoints[0].sent().is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// LoRa output: our own position as a small binary frame, sent by a LoRa module
+// on a serial port, either straight to other LoRa stations or into a LoRaWAN
+// network such as The Things Network:
+//
+//     club = lora:///dev/ttyUSB0?interval=300
+//     ttn = lorawan:///dev/ttyUSB0?port=2&interval=300
+//
+// The module talks the RUI3 AT commands of the RAK3172 and its kin, and is set
+// up beforehand: the frequency plan and P2P mode for lora://, the keys and
+// joining for lorawan://. The own positions come from what the endpoint is
+// sent: the RMC of a [location] endpoint, or our !AIVDO in [ais]. Everything
+// else is dropped, and a position is sent every `interval` seconds at most,
+// 300 when not given, to stay within the duty cycle and fair use limits.
+//
+// The frame is 17 bytes, big endian:
+//
+//     0      1, the version of the frame
+//     1..5   MMSI
+//     5..9   latitude, signed, 1/10000 minute as in AIS
+//     9..13  longitude, the same
+//     13..15 speed over ground, 0.1 knot, 0xffff when not known
+//     15..17 course over ground, 0.1 degree, 0xffff when not known
+use std::io;
+use std::time::{Duration, Instant};
+
+use common::sink::Sink;
+
+use crate::modem::Modem;
+use crate::nmea;
+use crate::output::Options;
+use crate::schema::Report;
+
+const FRAME_VERSION: u8 = 1;
+const DEFAULT_INTERVAL: u64 = 300;
+const DEFAULT_PORT: u8 = 2;
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Position {
+    mmsi: u32,
+    latitude: f64,
+    longitude: f64,
+    sog: Option<f64>,
+    cog: Option<f64>,
+}
+
+impl Position {
+    // Our position in what an endpoint is sent, if there is one.
+    fn find(message: &[u8]) -> Option<Position> {
+        nmea::lines(message).find_map(|line| {
+            if nmea::is_own_vessel(line) {
+                Self::from_vdo(line)
+            } else {
+                Self::from_rmc(line)
+            }
+        })
+    }
+
+    fn from_vdo(line: &str) -> Option<Position> {
+        let report = Report::decode(&[line])?;
+        let (latitude, longitude) = report.position()?;
+        Some(Position {
+            mmsi: report.mmsi,
+            latitude,
+            longitude,
+            sog: report.sog,
+            cog: report.cog,
+        })
+    }
+
+    // As the location thread sends it, with our MMSI in front:
+    // 244123456$GNRMC,123456,A,5310.50000,N,525.02000,E,6.2,245.0,010625,,,A
+    fn from_rmc(line: &str) -> Option<Position> {
+        let (mmsi, sentence) = line.split_at(line.find('$')?);
+        let mut fields = sentence.split(',');
+        if !fields.next()?.ends_with("RMC") {
+            return None;
+        }
+        let _time = fields.next()?;
+        if fields.next()? != "A" {
+            return None;
+        }
+        let latitude = degrees(fields.next()?, fields.next()?)?;
+        let longitude = degrees(fields.next()?, fields.next()?)?;
+        let number = |field: Option<&str>| field.and_then(|field| field.parse::<f64>().ok());
+        Some(Position {
+            mmsi: mmsi.parse().unwrap_or(0),
+            latitude,
+            longitude,
+            sog: number(fields.next()),
+            cog: number(fields.next()),
+        })
+    }
+
+    fn frame(&self) -> Vec<u8> {
+        let tenths = |value: Option<f64>, max: f64| {
+            value
+                .filter(|value| (0.0..max).contains(value))
+                .map_or(0xffff, |value| (value * 10.0).round() as u16)
+        };
+        let mut frame = Vec::with_capacity(17);
+        frame.push(FRAME_VERSION);
+        frame.extend_from_slice(&self.mmsi.to_be_bytes());
+        frame.extend_from_slice(&((self.latitude * 600_000.0).round() as i32).to_be_bytes());
+        frame.extend_from_slice(&((self.longitude * 600_000.0).round() as i32).to_be_bytes());
+        frame.extend_from_slice(&tenths(self.sog, 6553.5).to_be_bytes());
+        frame.extend_from_slice(&tenths(self.cog, 360.0).to_be_bytes());
+        frame
+    }
+}
+
+// The frame for our position in what an endpoint is sent, if there is one.
+// Iridium (iridium.rs) sends the same.
+pub fn frame(message: &[u8]) -> Option<Vec<u8>> {
+    Position::find(message).map(|position| position.frame())
+}
+
+// Degrees from NMEA degrees and minutes, dddmm.mmmm, and the hemisphere.
+// Leading zeros are not needed, the location thread leaves them out.
+fn degrees(value: &str, hemisphere: &str) -> Option<f64> {
+    let value = value.parse::<f64>().ok()?;
+    let whole = (value / 100.0).trunc();
+    let degrees = whole + (value - whole * 100.0) / 60.0;
+    match hemisphere {
+        "N" | "E" => Some(degrees),
+        "S" | "W" => Some(-degrees),
+        _ => None,
+    }
+}
+
+pub struct LoraSink {
+    name: String,
+    device: String,
+    // The LoRaWAN port, None for peer to peer
+    port: Option<u8>,
+    interval: Duration,
+    modem: Option<Modem>,
+    last_sent: Option<Instant>,
+    healthy: bool,
+}
+
+impl LoraSink {
+    pub fn create(name: &str, url: &str, _options: &Options) -> io::Result<Box<dyn Sink>> {
+        let (scheme, rest) = url.split_once("://").unwrap_or(("lora", url));
+        let (device, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let mut port = (scheme == "lorawan").then_some(DEFAULT_PORT);
+        let mut interval = DEFAULT_INTERVAL;
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            match key {
+                "interval" => {
+                    interval = value
+                        .parse()
+                        .map_err(|e| invalid(format!("{}: invalid interval: {}", url, e)))?;
+                }
+                // 1 to 223 are for applications
+                "port" if port.is_some() => {
+                    port = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|port| (1..=223).contains(port))
+                            .ok_or_else(|| invalid(format!("{}: invalid port '{}'", url, value)))?,
+                    );
+                }
+                _ => {
+                    return Err(invalid(format!(
+                        "{}: unknown option '{}={}'",
+                        url, key, value
+                    )));
+                }
+            }
+        }
+        if device.is_empty() {
+            return Err(invalid(format!("{}: missing device", url)));
+        }
+        Ok(Box::new(LoraSink {
+            name: name.to_string(),
+            device: device.to_string(),
+            port,
+            interval: Duration::from_secs(interval),
+            modem: None,
+            last_sent: None,
+            healthy: true,
+        }))
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.connect()?;
+        let hex: String = frame.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let command = match self.port {
+            Some(port) => format!("AT+SEND={}:{}", port, hex),
+            None => format!("AT+PSEND={}", hex),
+        };
+        if let Some(modem) = self.modem.as_mut() {
+            modem.command(&command, "OK", COMMAND_TIMEOUT)?;
+        }
+        Ok(())
+    }
+}
+
+impl Sink for LoraSink {
+    fn connect(&mut self) -> io::Result<()> {
+        if self.modem.is_none() {
+            let mut modem = Modem::open(&self.device)?;
+            modem.command("AT", "OK", COMMAND_TIMEOUT)?;
+            log::info!(endpoint = self.name.as_str(); "{}: LoRa module on {}", self.name, self.device);
+            self.modem = Some(modem);
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        let Some(frame) = frame(message) else {
+            return Ok(());
+        };
+        if self
+            .last_sent
+            .is_some_and(|sent| sent.elapsed() < self.interval)
+        {
+            return Ok(());
+        }
+        let result = self.transmit(&frame);
+        self.healthy = result.is_ok();
+        match result {
+            Ok(()) => self.last_sent = Some(Instant::now()),
+            // Opened again for the next position
+            Err(_) => self.modem = None,
+        }
+        result
+    }
+
+    fn close(&mut self) {
+        self.modem = None;
+    }
+
+    fn healthy(&self) -> bool {
+        self.healthy
+    }
+}
@@ -0,0 +1,102 @@
+// Estimate packet loss on UDP providers. Datagrams carry no sequence number,
+// but multi-sentence AIS messages do: each sentence says how many fragments
+// the group has, which one it is and which sequential message id the group
+// uses. Missing fragments and skipped group ids tell us something was lost.
+
+const SEQUENCE_IDS: u8 = 10;
+
+struct Group {
+    total: u32,
+    next: u32,
+    sequence_id: Option<u8>,
+}
+
+#[derive(Default)]
+pub struct LossDetector {
+    group: Option<Group>,
+    last_sequence_id: Option<u8>,
+    pub fragments_expected: u64,
+    pub fragments_missing: u64,
+    pub sequence_gaps: u64,
+}
+
+impl LossDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, line: &str) {
+        let mut fields = crate::nmea::split_tag_block(line).1.split(',');
+        let address = fields.next().unwrap_or("");
+        if !address.starts_with('!') || !(address.ends_with("VDM") || address.ends_with("VDO")) {
+            return;
+        }
+        let (total, number) = match (
+            fields.next().and_then(|f| f.parse::<u32>().ok()),
+            fields.next().and_then(|f| f.parse::<u32>().ok()),
+        ) {
+            (Some(total), Some(number)) if number >= 1 && number <= total => (total, number),
+            _ => return,
+        };
+        let sequence_id = fields
+            .next()
+            .and_then(|f| f.parse::<u8>().ok())
+            .filter(|id| *id < SEQUENCE_IDS);
+
+        if number == 1 {
+            self.close_group();
+            self.fragments_expected += total as u64;
+            if total > 1 {
+                self.check_sequence_id(sequence_id);
+                self.group = Some(Group {
+                    total,
+                    next: 2,
+                    sequence_id,
+                });
+            }
+            return;
+        }
+
+        match self.group.as_mut() {
+            Some(group) if group.sequence_id == sequence_id && number >= group.next => {
+                self.fragments_missing += (number - group.next) as u64;
+                group.next = number + 1;
+                if group.next > group.total {
+                    self.group = None;
+                }
+            }
+            _ => {
+                // A fragment of a group whose start we never saw
+                self.close_group();
+                self.fragments_expected += total as u64;
+                self.fragments_missing += (number - 1) as u64;
+                self.check_sequence_id(sequence_id);
+                if number < total {
+                    self.group = Some(Group {
+                        total,
+                        next: number + 1,
+                        sequence_id,
+                    });
+                }
+            }
+        }
+    }
+
+    fn close_group(&mut self) {
+        if let Some(group) = self.group.take() {
+            self.fragments_missing += (group.total + 1 - group.next) as u64;
+        }
+    }
+
+    // Sequential message ids count 0..9 for each new multi-sentence group, so
+    // skipped ids point to whole groups that were lost.
+    fn check_sequence_id(&mut self, sequence_id: Option<u8>) {
+        if let Some(sequence_id) = sequence_id {
+            if let Some(last) = self.last_sequence_id {
+                let gap = (sequence_id + SEQUENCE_IDS - last - 1) % SEQUENCE_IDS;
+                self.sequence_gaps += gap as u64;
+            }
+            self.last_sequence_id = Some(sequence_id);
+        }
+    }
+}
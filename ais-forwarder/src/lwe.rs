@@ -0,0 +1,164 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// IEC 61162-450 output, the "lightweight Ethernet" (LWE) that bridge equipment
+// and voyage data recorders take NMEA from: UDP datagrams, usually multicast,
+// that start with "UdPbC" and carry each sentence behind a TAG block with the
+// source (s:) and a line count (n:), and a group code (g:) that ties the
+// sentences of a multi-sentence message together.
+//
+//     lwe://239.192.0.2:60002?source=AI0001
+//
+// AIS belongs in the target data group, TGTD, the address above. The source
+// is the system function ID that the equipment knows us by, two letters and
+// four digits; AI0001 when not given.
+use std::io;
+use std::net::UdpSocket;
+
+use common::NetworkEndpoint;
+use common::sink::Sink;
+
+use crate::nmea;
+use crate::output::Options;
+
+const HEADER: &[u8] = b"UdPbC\0";
+const DEFAULT_SOURCE: &str = "AI0001";
+// Line counts go from 1 to 999, group codes from 1 to 99.
+const MAX_LINE_COUNT: u32 = 999;
+const MAX_GROUP: u32 = 99;
+
+pub struct LweSink {
+    name: String,
+    endpoint: NetworkEndpoint,
+    source: String,
+    socket: Option<UdpSocket>,
+    line_count: u32,
+    group: u32,
+    healthy: bool,
+}
+
+impl LweSink {
+    pub fn create(name: &str, url: &str, options: &Options) -> io::Result<Box<dyn Sink>> {
+        let rest = url.strip_prefix("lwe://").unwrap_or(url);
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let mut source = DEFAULT_SOURCE.to_string();
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            match key {
+                "source" => source = value.to_string(),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{}: unknown option '{}'", url, key),
+                    ));
+                }
+            }
+        }
+        let bytes = source.as_bytes();
+        if bytes.len() != 6
+            || !bytes[..2].iter().all(u8::is_ascii_alphabetic)
+            || !bytes[2..].iter().all(u8::is_ascii_digit)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: source should be two letters and four digits", url),
+            ));
+        }
+        let mut endpoint: NetworkEndpoint = format!("udp://{}", address).parse()?;
+        endpoint.dns_ttl = options.dns_ttl;
+        Ok(Box::new(LweSink {
+            name: name.to_string(),
+            endpoint,
+            source,
+            socket: None,
+            line_count: 0,
+            group: 0,
+            healthy: true,
+        }))
+    }
+
+    fn try_connect(&mut self) -> io::Result<()> {
+        let addr = self.endpoint.resolve()?;
+        if self.socket.is_none() {
+            let socket = UdpSocket::bind("0.0.0.0:0")?;
+            // Multicast stays on the ship's network
+            socket.set_multicast_ttl_v4(1)?;
+            self.socket = Some(socket);
+        }
+        if let Some(socket) = &self.socket
+            && socket.peer_addr().ok() != Some(addr)
+        {
+            socket.connect(addr)?;
+            log::info!(endpoint = self.name.as_str(); "{}: Sending IEC 61162-450 to {}", self.name, self.endpoint);
+        }
+        Ok(())
+    }
+
+    // One datagram for the sentences of a message. A TAG block that came
+    // with them is replaced, but the time it has is kept.
+    fn datagram(&mut self, message: &[u8]) -> Vec<u8> {
+        let lines: Vec<&str> = nmea::lines(message).collect();
+        if lines.len() > 1 {
+            self.group = self.group % MAX_GROUP + 1;
+        }
+        let mut datagram = HEADER.to_vec();
+        for (i, line) in lines.iter().enumerate() {
+            let (tag_block, sentence) = nmea::split_tag_block(line);
+            self.line_count = self.line_count % MAX_LINE_COUNT + 1;
+            let mut fields = Vec::new();
+            if lines.len() > 1 {
+                fields.push(format!("g:{}-{}-{}", i + 1, lines.len(), self.group));
+            }
+            fields.push(format!("s:{}", self.source));
+            fields.push(format!("n:{}", self.line_count));
+            if let Some(time) = tag_block
+                .and_then(|tag_block| tag_block.split('*').next())
+                .and_then(|fields| fields.split(',').find(|field| field.starts_with("c:")))
+            {
+                fields.push(time.to_string());
+            }
+            let fields = fields.join(",");
+            let sum = fields.bytes().fold(0u8, |sum, b| sum ^ b);
+            datagram.extend_from_slice(format!("\\{}*{:02X}\\", fields, sum).as_bytes());
+            datagram.extend_from_slice(sentence.as_bytes());
+            datagram.extend_from_slice(b"\r\n");
+        }
+        datagram
+    }
+
+    fn try_send(&mut self, message: &[u8]) -> io::Result<()> {
+        self.try_connect()?;
+        let datagram = self.datagram(message);
+        if let Some(socket) = &self.socket {
+            socket.send(&datagram).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    format!("send_message lwe {} ({}): {}", self.name, self.endpoint, e),
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl Sink for LweSink {
+    fn connect(&mut self) -> io::Result<()> {
+        let result = self.try_connect();
+        self.healthy = result.is_ok();
+        result
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        let result = self.try_send(message);
+        self.healthy = result.is_ok();
+        if result.is_err() {
+            self.endpoint.forget_address();
+        }
+        result
+    }
+
+    fn close(&mut self) {
+        self.socket = None;
+    }
+
+    fn healthy(&self) -> bool {
+        self.healthy
+    }
+}
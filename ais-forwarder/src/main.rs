@@ -1,45 +1,494 @@
 use clap::Parser;
 use config::Config;
-use env_logger::Env;
 use nmea_parser::ParsedMessage;
 use std::collections::HashMap;
-use std::net::UdpSocket;
-use std::ops::Add;
+use std::net::{SocketAddr, UdpSocket};
 use std::path::PathBuf;
 use std::process::exit;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
 use std::thread::Builder;
 use std::time::{Duration, Instant, SystemTime};
 use std::{io, path};
 
 use common::NetworkEndpoint;
-use common::Protocol;
-use common::buffer::BufReaderDirectWriter;
-use common::send_message_tcp;
-use common::send_message_udp;
 
+#[cfg(feature = "parquet")]
+mod archive;
+mod audit;
 mod cache;
+mod capture;
+mod check;
+mod clock;
+mod commands;
+#[cfg(unix)]
+mod control;
+mod decode;
+mod email;
+mod feed;
+mod geo;
+mod geofence;
+mod gps;
+mod harbour;
+mod heartbeat;
+mod http;
+mod iridium;
+mod kml;
 mod location;
+mod logbook;
+mod logging;
+#[cfg(all(test, feature = "loopback"))]
+mod loopback;
+mod lora;
+mod loss;
+mod lwe;
+mod mdns;
+mod memory;
+mod meteo;
+mod metrics;
+mod modem;
+mod mqtt;
+mod nmea;
+mod notify;
+mod output;
+mod pipeline;
+mod portmap;
+mod priority;
+#[cfg(unix)]
+mod privileges;
+mod reader;
+mod record;
+mod repeater;
+mod replay;
+mod resources;
+mod satellite;
+mod schema;
+mod sender;
+mod shutdown;
+mod simulate;
+mod smoothing;
+mod sms;
+mod soak;
+mod standby;
+#[cfg(target_os = "linux")]
+mod systemd;
+mod targets;
+mod telemetry;
+mod uci;
+mod voyage;
+mod watchdog;
+#[cfg(windows)]
+mod windows;
+
+use audit::AuditLog;
+use capture::Capture;
+use clock::{Clock, ClockSource};
+use commands::{Commands, Controls};
+use geofence::Geofence;
+use gps::FixQuality;
+use harbour::Harbour;
+use location::LocationMessage;
+use logbook::Logbook;
+use loss::LossDetector;
+use memory::MemoryGuard;
+use metrics::{DropReason, Metrics};
+use nmea::{ClassB, DistressDevice};
+use notify::{AnchorWatch, CollisionWatch, DensityWatch, Event, Notifier};
+use pipeline::Pipeline;
+use reader::{Input, Reader};
+use repeater::GpsRepeater;
+use satellite::Profile;
+use sender::{DropPolicy, Endpoint, Outgoing};
+use smoothing::PositionFilter;
+use sms::Sms;
+use standby::Standby;
+use targets::Targets;
+use telemetry::{Reading, Telemetry};
+
+// Exit codes, so that a supervisor can tell a broken configuration, which a
+// restart will not fix, from a failure at runtime.
+const EXIT_FAILURE: i32 = 1;
+const EXIT_CONFIG: i32 = 78; // EX_CONFIG from sysexits.h
+
+// The AIS message types that are decoded, for throttling and the location;
+// all others are dropped, or forwarded as is with passthrough.
+const DECODED_TYPES: [u8; 11] = [1, 2, 3, 4, 5, 9, 18, 19, 21, 24, 27];
+const STATIC_TYPES: [u8; 2] = [5, 24];
+// Addressed and broadcast binary messages, such as area weather, forwarded as
+// they are without throttling.
+const BINARY_TYPES: [u8; 2] = [6, 8];
+
+// What to do with an AIS message without decoding it.
+#[derive(Clone, Copy)]
+enum FastPath {
+    Drop,
+    Forward { static_data: bool },
+}
+
+// The interval throttle remembers when each vessel was last sent. Vessels
+// not sent for a whole interval are forgotten every minute, which changes
+// nothing as their next message is sent anyway, and there is a hard cap for
+// a busy coastal station.
+const LAST_SENT_CLEANUP: Duration = Duration::from_secs(60);
+const MAX_LAST_SENT: usize = 20_000;
+
+// Aids to navigation report every three minutes, and do not move; a virtual
+// buoy or racing mark needs no more than that, unless [general] aton_interval
+// says otherwise.
+const ATON_INTERVAL: u64 = 180;
+// Base stations report every 10 seconds from the same place, downstream only
+// needs to know that they are there.
+const BASE_STATION_INTERVAL: u64 = 60;
+
+// A clock that differs more than this from the UTC that base stations report
+// is warned about, when [general] clock_check is on. Their reports may take a
+// few seconds to reach us.
+const CLOCK_TOLERANCE: Duration = Duration::from_secs(30);
+
+// A vessel swinging at anchor turns at a few degrees a minute, in a gust
+// perhaps twenty. Turning faster than this, in degrees per minute, is under way
+// when [general] moving_speed is set.
+const MOVING_RATE_OF_TURN: f64 = 30.0;
+
+// A message has at most 9 sentences, sent right after each other. A group
+// that is not complete within the timeout, or when the next one starts, is
+// dropped.
+const MAX_FRAGMENTS: usize = 9;
+const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The parser keeps the sentences of multi-sentence messages until the rest
+// arrives, which for corrupted traffic it never does. It is replaced by a new
+// one this often, at a moment that no message of ours is half way.
+const PARSER_RESET_INTERVAL: Duration = Duration::from_secs(600);
+
+// Some transponders and multiplexers pass on our own reports both as !AIVDO
+// and as a received !AIVDM, which an aggregator counts twice. The VDM of our
+// MMSI is dropped while VDOs come in; a Class A or B at anchor reports every
+// three minutes, so after twice that without one the VDM is all we have.
+const OWN_VDO_TIMEOUT: Duration = Duration::from_secs(360);
+
+// A multi-sentence message being put together, from one provider.
+#[derive(Default)]
+struct Assembly {
+    fragments: Vec<String>,
+    started: Option<Instant>,
+    // One that is handled without decoding
+    raw_fragments: Vec<String>,
+    fast_path: Option<FastPath>,
+}
 
 struct LastSent {
     vessel_dynamic_data: Instant,
     vessel_static_data: Instant,
+    // Only for the MMSIs of aids to navigation and base stations, which have
+    // their own intervals
+    aid_to_navigation: Option<Instant>,
+    base_station: Option<Instant>,
+}
+
+impl LastSent {
+    fn newest(&self) -> Instant {
+        let newest = self.vessel_dynamic_data.max(self.vessel_static_data);
+        self.aid_to_navigation
+            .into_iter()
+            .chain(self.base_station)
+            .fold(newest, Instant::max)
+    }
 }
 
 struct Dispatcher {
-    provider: NetworkEndpoint,
-    ais: HashMap<String, NetworkEndpoint>,
-    location_tx: Sender<ParsedMessage>,
+    reader: Reader,
+    ais: Vec<Endpoint>,
+    location_tx: SyncSender<LocationMessage>,
     interval: u64,
+    // For Class B CS units
+    class_b_interval: u64,
+    aton_interval: u64,
+    base_station_interval: u64,
+    clock_check: bool,
+    // Whether the clock was off at the last base station report
+    clock_off: bool,
+    meteo: bool,
+    telemetry: Option<Telemetry>,
+    dsc_endpoints: Vec<String>,
+    gps_repeater: Option<GpsRepeater>,
+    // Put in the TAG block of everything forwarded
+    station: Option<String>,
+    targets: Option<Arc<Targets>>,
+    logbook: Option<Arc<Logbook>>,
     location_interval: u64,
     location_anchor_interval: u64,
-    nmea_parser: nmea_parser::NmeaParser,
+    moving_speed: Option<f64>,
+    // From GGA and GSA, to hold back location updates without a good fix
+    gps_fix: FixQuality,
+    max_hdop: Option<f64>,
+    position_filter: Option<PositionFilter>,
+    own_mmsi: Option<u32>,
+    // The MMSI in our last !AIVDO and when it came
+    own_vdo: Option<(u32, Instant)>,
+    // Throttling and location updates go by this
+    clock: Clock,
+    // One for each provider, as their sentences come mixed
+    nmea_parsers: Vec<nmea_parser::NmeaParser>,
+    parser_reset: Instant,
+    // The provider of the chunk being handled, its index in reader.names
+    provider: usize,
+    // Put the name of the provider in the TAG block instead of the station
+    tag_provider: bool,
     last_sent: HashMap<u32, LastSent>,
+    last_sent_cleanup: Instant,
     last_sent_location: SystemTime,
+    metrics: Arc<Metrics>,
+    notifier: Notifier,
+    anchor_watch: Option<AnchorWatch>,
+    collision_watch: Option<CollisionWatch>,
+    density_watch: Option<DensityWatch>,
+    geofences: Vec<Geofence>,
+    harbour: Option<Harbour>,
+    loss_detector: Option<LossDetector>,
+    capture: Option<Arc<Capture>>,
+    busy: Arc<Mutex<()>>,
+    ready_file: Option<String>,
+    // Whether each provider is delivering data, by index
+    delivering: Vec<bool>,
+    memory_guard: Option<MemoryGuard>,
+    controls: Arc<Controls>,
+    standby: Option<Arc<Standby>>,
+    passthrough: bool,
+}
+
+// How many items each internal queue holds, from the [queues] section. More
+// rides out longer bursts and outages, at the cost of memory.
+#[derive(Clone, Copy)]
+struct Capacities {
+    provider: usize,
+    endpoint: usize,
+    location: usize,
+}
+
+// State the Dispatcher shares with the other threads.
+#[derive(Clone)]
+struct Shared {
+    metrics: Arc<Metrics>,
+    notifier: Notifier,
+    anchor_drift: Option<f64>,
+    // CPA in nautical miles and TCPA for the collision watch
+    collision: Option<(f64, Duration)>,
+    // Moving targets and the range in nautical miles for the density watch
+    density: Option<(usize, f64)>,
+    geofences: Vec<Geofence>,
+    harbour: Option<Harbour>,
+    // Our position by SMS when the location endpoints are unreachable
+    sms: Option<Sms>,
+    capture: Option<Arc<Capture>>,
+    audit: Option<Arc<AuditLog>>,
+    // Held while a chunk from the provider is being handled, see shutdown.rs
+    busy: Arc<Mutex<()>>,
+    ready_file: Option<String>,
+    memory_limit: Option<u64>,
+    controls: Arc<Controls>,
+    standby: Option<Arc<Standby>>,
+    // Forward other vessels as received, without decoding or throttling
+    passthrough: bool,
+    // Our MMSI, when configured, to find our own static data
+    own_mmsi: Option<u32>,
+    // Seconds between forwarded reports of each Class B CS unit, when not the
+    // interval of all vessels
+    class_b_interval: Option<u64>,
+    // Seconds between forwarded reports of each aid to navigation
+    aton_interval: u64,
+    // Seconds between forwarded reports of each base station
+    base_station_interval: u64,
+    // Compare our clock with the UTC in base station reports
+    clock_check: bool,
+    // Decode met/hydro binary broadcasts for the status outputs
+    meteo: bool,
+    // The engine and tank sentences to sample for the status outputs
+    telemetry: Option<Telemetry>,
+    // Knots above which we are under way by the reported speed, rather than
+    // by how far the position moved
+    moving_speed: Option<f64>,
+    // Hold back location updates while the GPS reports a higher HDOP
+    max_hdop: Option<f64>,
+    // Leave out jumps of our own position and smooth it
+    smooth_position: bool,
+    // Throttle by the wall clock or by the times in the data
+    clock: ClockSource,
+    // The [ais] endpoints that DSC sentences are passed on to
+    dsc_endpoints: Vec<String>,
+    // The [ais] endpoints that our own position is repeated to as RMC and GGA
+    gps_endpoints: Vec<String>,
+    // Identifies this station to aggregators and in the metrics
+    station: Option<String>,
+    // Put the name of the provider in the TAG block instead of the station
+    tag_provider: bool,
+    // The targets around us, kept for the [http] status server
+    targets: Option<Arc<Targets>>,
+    // Our trips, in the cache directory
+    logbook: Option<Arc<Logbook>>,
+    capacities: Capacities,
+    // Niceness of the provider reader, dispatcher and AIS sender threads
+    io_nice: Option<i32>,
+}
+
+impl Shared {
+    // For the modes that run a Dispatcher on input of their own, without a
+    // configuration: soak and the loopback tests.
+    fn standalone(passthrough: bool) -> io::Result<Self> {
+        let metrics = Arc::new(Metrics::new());
+        Ok(Shared {
+            notifier: Notifier::new(None, metrics.clone())?,
+            metrics,
+            anchor_drift: None,
+            collision: None,
+            density: None,
+            geofences: Vec::new(),
+            harbour: None,
+            sms: None,
+            capture: None,
+            audit: None,
+            busy: Arc::new(Mutex::new(())),
+            ready_file: None,
+            memory_limit: None,
+            controls: Arc::new(Controls::new()),
+            standby: None,
+            passthrough,
+            own_mmsi: None,
+            class_b_interval: None,
+            aton_interval: ATON_INTERVAL,
+            base_station_interval: BASE_STATION_INTERVAL,
+            clock_check: false,
+            meteo: false,
+            telemetry: None,
+            moving_speed: None,
+            max_hdop: None,
+            smooth_position: false,
+            clock: ClockSource::Wall,
+            dsc_endpoints: Vec::new(),
+            gps_endpoints: Vec::new(),
+            station: None,
+            tag_provider: false,
+            targets: None,
+            logbook: None,
+            capacities: Capacities {
+                provider: reader::QUEUE_SIZE,
+                endpoint: sender::QUEUE_SIZE,
+                location: location::QUEUE_SIZE,
+            },
+            io_nice: None,
+        })
+    }
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Print the status of the running ais-forwarder
+    #[cfg(unix)]
+    Stats,
+    /// Send a command to the running ais-forwarder, `help` lists them
+    #[cfg(unix)]
+    Control {
+        #[clap(required = true, num_args = 1..)]
+        command: Vec<String>,
+    },
+    /// Replay a capture through the dispatcher and report the sentences per second
+    Soak {
+        /// Capture file, made with --capture, or a file of NMEA sentences
+        capture: String,
+        /// Replay at this multiple of the recorded speed, 0 is as fast as possible
+        #[clap(long, default_value_t = 0.0)]
+        speed: f64,
+        /// Number of AIS endpoints to fan out to, they discard everything
+        #[clap(long, default_value_t = 3)]
+        endpoints: usize,
+        /// Seconds between updates for each vessel, as in config.ini
+        #[clap(long, default_value_t = 10)]
+        interval: u64,
+        /// Forward without decoding, as in config.ini
+        #[clap(long)]
+        passthrough: bool,
+        /// Throttle by the wall clock or by the times in the capture, wall or message
+        #[clap(long, default_value = "wall")]
+        clock: ClockSource,
+    },
+    /// Record the provider, each sentence with the time it was received
+    Record {
+        /// File to write, in the format of --capture
+        file: String,
+        /// Provider to record instead of the one in config.ini
+        #[clap(long)]
+        provider: Option<String>,
+        /// Stop after this many seconds
+        #[clap(long)]
+        duration: Option<u64>,
+    },
+    /// Play a capture into the provider of a forwarder as it was received
+    Replay {
+        /// Capture file, made with record or --capture, or a file of NMEA sentences
+        capture: String,
+        /// tcp-listen://host:port for a tcp:// provider, tcp://host:port for a
+        /// tcp-listen:// provider or udp://host:port for a udp:// provider
+        address: String,
+        /// Replay at this multiple of the recorded speed, 0 is as fast as possible
+        #[clap(long, default_value_t = 1.0)]
+        speed: f64,
+    },
+    /// Generate AIS and GPS sentences of a made up scene, to feed a provider
+    Simulate {
+        /// tcp-listen://host:port for a tcp:// provider, tcp://host:port for a
+        /// tcp-listen:// provider or udp://host:port for a udp:// provider
+        address: String,
+        /// Number of other vessels
+        #[clap(long, default_value_t = 20)]
+        targets: usize,
+        /// Centre of the scene
+        #[clap(long, default_value_t = 53.175, allow_negative_numbers = true)]
+        latitude: f64,
+        #[clap(long, default_value_t = 5.417, allow_negative_numbers = true)]
+        longitude: f64,
+        /// MMSI of our own vessel
+        #[clap(long, default_value_t = 244_000_000)]
+        mmsi: u32,
+        /// The same seed gives the same scene
+        #[clap(long, default_value_t = 1)]
+        seed: u64,
+    },
+    /// Parse sentences as the forwarder would and print the fields as JSON
+    Decode {
+        /// NMEA sentences, all fragments of a multi-sentence message in order
+        #[clap(required = true, num_args = 1..)]
+        sentences: Vec<String>,
+    },
+    /// Write the own track in the audit log as KML, or as KMZ for Google Earth
+    Kml {
+        /// Output file, zipped when the name ends in .kmz
+        output: String,
+        /// Audit log to read, by default audit_log in the configuration
+        #[clap(long)]
+        log: Option<String>,
+        /// Only the last this many hours
+        #[clap(long)]
+        hours: Option<u64>,
+    },
+    /// Try to reach the provider and every endpoint in the configuration
+    TestEndpoints {
+        /// Also send a test sentence to the [ais] and [location] endpoints
+        #[clap(long)]
+        send: bool,
+    },
+    /// Install, remove or run as a Windows service
+    #[cfg(windows)]
+    Service {
+        #[clap(subcommand)]
+        action: windows::ServiceAction,
+    },
 }
 
 #[derive(Parser, Clone, Debug)]
 pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     #[clap(flatten)]
     pub verbose: clap_verbosity_flag::Verbosity<clap_verbosity_flag::InfoLevel>,
 
@@ -49,70 +498,312 @@ pub struct Cli {
     #[clap(long, default_value = "config")]
     pub config: String,
 
+    /// UCI configuration --
+    /// Read the configuration from an OpenWrt UCI file instead of --config, by default
+    /// /etc/config/ais-forwarder. The sections and options are the same as in config.ini.
+    #[clap(long, num_args = 0..=1, default_missing_value = "/etc/config/ais-forwarder")]
+    pub uci: Option<String>,
+
     /// Cache directory --
     /// This must be a directory that is writable by the user running the program.
     /// If the directory does not exist, it will be created.
-    #[clap(long, default_value = "/usr/local/var/cache/ais-forwarder")]
+    #[clap(long, default_value_t = default_cache_dir())]
     pub cache_dir: String,
+
+    /// PID file --
+    /// Write the process id to this file, it is removed again on a clean shutdown.
+    #[clap(long)]
+    pub pidfile: Option<String>,
+
+    #[cfg(unix)]
+    /// User --
+    /// Switch to this user after startup, when started as root to bind low ports.
+    #[clap(long)]
+    pub user: Option<String>,
+
+    #[cfg(unix)]
+    /// Group --
+    /// Switch to this group after startup. Defaults to the primary group of --user.
+    #[clap(long)]
+    pub group: Option<String>,
+
+    #[cfg(unix)]
+    /// Control socket --
+    /// Unix socket on which the running daemon answers `stats` requests.
+    /// Defaults to control.sock in the cache directory.
+    #[clap(long)]
+    pub control_socket: Option<String>,
+
+    /// Log target --
+    /// Where log records are sent: stderr, stdout, syslog (RFC 5424), journald or, on Windows,
+    /// eventlog.
+    #[clap(long, value_enum, default_value = "stderr")]
+    pub log_target: logging::LogTarget,
+
+    /// Log format --
+    /// Either human readable text or one JSON object per record, with timestamp, level,
+    /// module, endpoint and mmsi fields.
+    #[clap(long, value_enum, default_value = "text")]
+    pub log_format: logging::LogFormat,
+
+    /// Syslog server --
+    /// Send syslog records over UDP to this host:port instead of the local /dev/log socket.
+    #[clap(long)]
+    pub syslog_server: Option<String>,
+
+    /// Log repeat window --
+    /// Identical warnings and errors are logged once per this many seconds, with a count of
+    /// how often they were repeated. 0 logs every occurrence.
+    #[clap(long, default_value_t = 600)]
+    pub log_repeat_window: u64,
+
+    /// Log rate limit --
+    /// Maximum number of warnings and errors per minute from a single module. 0 is unlimited.
+    #[clap(long, default_value_t = 30)]
+    pub log_rate_limit: u32,
+
+    /// Capture file --
+    /// Append all raw data received from the provider to this file, with a timestamp per chunk.
+    #[clap(long)]
+    pub capture: Option<String>,
+
+    /// Capture sent data --
+    /// Also record all data sent to the AIS and location endpoints in the capture file.
+    #[clap(long, requires = "capture")]
+    pub capture_sent: bool,
+
+    /// Ready file --
+    /// Created once data arrives from a provider and removed while all providers are failing,
+    /// for container readiness probes.
+    #[clap(long)]
+    pub ready_file: Option<String>,
 }
 
 fn main() {
     let cli = Cli::parse();
-    let log_level = cli.verbose.log_level_filter();
-    let mut logger = env_logger::Builder::from_env(Env::default());
-    logger.filter_level(log_level);
-    // When running as a procd daemon, the PWD environment variable is not set
-    // which can be used to shorten the logging records that already contain the timestamp.
-    if std::env::var("PWD").is_err() {
-        logger.format_timestamp(None);
-    }
-    logger.init();
+    logging::init(&cli);
 
-    let mut config_path = PathBuf::from(cli.config);
-    if config_path.is_relative() {
-        config_path = get_config_dir().join(config_path);
+    match &cli.command {
+        #[cfg(unix)]
+        Some(Command::Stats) => match control::request(&control_socket_path(&cli), "stats") {
+            Ok(reply) => {
+                print!("{}", reply);
+                exit(0);
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                exit(EXIT_FAILURE);
+            }
+        },
+        #[cfg(unix)]
+        Some(Command::Control { command }) => {
+            match control::request(&control_socket_path(&cli), &command.join(" ")) {
+                Ok(reply) => {
+                    print!("{}", reply);
+                    exit(0);
+                }
+                Err(e) => {
+                    log::error!("{}", e);
+                    exit(EXIT_FAILURE);
+                }
+            }
+        }
+        Some(Command::Soak {
+            capture,
+            speed,
+            endpoints,
+            interval,
+            passthrough,
+            clock,
+        }) => {
+            let options = soak::Options {
+                speed: *speed,
+                endpoints: *endpoints,
+                interval: *interval,
+                passthrough: *passthrough,
+                clock: *clock,
+            };
+            match soak::run(capture, &options) {
+                Ok(()) => exit(0),
+                Err(e) => {
+                    log::error!("{}", e);
+                    exit(EXIT_FAILURE);
+                }
+            }
+        }
+        Some(Command::Record {
+            file,
+            provider,
+            duration,
+        }) => {
+            let provider = match provider {
+                Some(provider) => provider.clone(),
+                None => match load_settings(&cli)
+                    .get("general")
+                    .and_then(|general| general.get("provider"))
+                {
+                    Some(provider) => provider.clone(),
+                    None => {
+                        log::error!("Missing provider in config.ini");
+                        exit(EXIT_CONFIG);
+                    }
+                },
+            };
+            let provider = match provider.parse::<NetworkEndpoint>() {
+                Ok(provider) => provider,
+                Err(e) => {
+                    log::error!("Invalid provider {}: {}", provider, e);
+                    exit(EXIT_CONFIG);
+                }
+            };
+            match record::run(provider, file, duration.map(Duration::from_secs)) {
+                Ok(()) => exit(0),
+                Err(e) => {
+                    log::error!("{}", e);
+                    exit(EXIT_FAILURE);
+                }
+            }
+        }
+        Some(Command::Replay {
+            capture,
+            address,
+            speed,
+        }) => {
+            let outputs = output::Registry::new(output_options(&HashMap::new()));
+            match replay::run(capture, address, *speed, &outputs) {
+                Ok(()) => exit(0),
+                Err(e) => {
+                    log::error!("{}", e);
+                    exit(EXIT_FAILURE);
+                }
+            }
+        }
+        Some(Command::Simulate {
+            address,
+            targets,
+            latitude,
+            longitude,
+            mmsi,
+            seed,
+        }) => {
+            let options = simulate::Options {
+                targets: *targets,
+                latitude: *latitude,
+                longitude: *longitude,
+                mmsi: *mmsi,
+                seed: *seed,
+            };
+            let outputs = output::Registry::new(output_options(&HashMap::new()));
+            if let Err(e) = simulate::run(address, &options, &outputs) {
+                log::error!("{}", e);
+                exit(EXIT_FAILURE);
+            }
+        }
+        Some(Command::Decode { sentences }) => match decode::run(sentences) {
+            Ok(()) => exit(0),
+            Err(e) => {
+                log::error!("{}", e);
+                exit(EXIT_FAILURE);
+            }
+        },
+        Some(Command::Kml { output, log, hours }) => {
+            let log = match log {
+                Some(log) => log.clone(),
+                None => match load_settings(&cli)
+                    .get("general")
+                    .and_then(|general| general.get("audit_log"))
+                {
+                    Some(log) => log.clone(),
+                    None => {
+                        log::error!("Missing audit_log in config.ini, there is no own track");
+                        exit(EXIT_CONFIG);
+                    }
+                },
+            };
+            match kml::run(&log, output, *hours) {
+                Ok(()) => exit(0),
+                Err(e) => {
+                    log::error!("{}", e);
+                    exit(EXIT_FAILURE);
+                }
+            }
+        }
+        Some(Command::TestEndpoints { send }) => {
+            let settings = load_settings(&cli);
+            let general = settings.get("general").cloned().unwrap_or_default();
+            let options = output_options(&general);
+            let connect_timeout = options.connect_timeout;
+            let outputs = output::Registry::new(options);
+            if check::run(&settings, &outputs, connect_timeout, *send) {
+                exit(0);
+            }
+            exit(EXIT_FAILURE);
+        }
+        #[cfg(windows)]
+        Some(Command::Service { action }) => {
+            if let Err(e) = windows::service(action) {
+                log::error!("{}", e);
+                exit(EXIT_FAILURE);
+            }
+        }
+        None => run(cli),
     }
-    let config_path = config_path
-        .to_str()
-        .expect("Cannot convert config path to string");
-    log::info!("Loading config from {}", config_path);
+}
 
-    let settings = match Config::builder()
-        .add_source(config::File::with_name(config_path))
-        .build()
-    {
-        Ok(config) => config,
-        Err(e) => {
-            log::error!("Error loading {}: {}", config_path, e);
-            exit(1);
-        }
-    };
+#[cfg(unix)]
+fn control_socket_path(cli: &Cli) -> String {
+    cli.control_socket
+        .clone()
+        .unwrap_or_else(|| format!("{}/control.sock", cli.cache_dir))
+}
 
-    let settings = match settings.try_deserialize::<HashMap<String, HashMap<String, String>>>() {
-        Ok(config) => config,
+// The daemon itself, it never returns.
+pub fn run(cli: Cli) -> ! {
+    // Two instances would report the vessel twice to every service
+    let _lock = match lock_instance(&cli.cache_dir) {
+        Ok(lock) => lock,
         Err(e) => {
-            log::error!("Invalid format in {}: {}", config_path, e);
-            exit(1);
+            log::error!("{}", e);
+            exit(EXIT_FAILURE);
         }
     };
+    let mut remove_on_exit = Vec::new();
+    #[cfg(unix)]
+    let control_socket = control_socket_path(&cli);
+    #[cfg(unix)]
+    remove_on_exit.push(control_socket.clone());
+    if let Some(pidfile) = &cli.pidfile {
+        if let Err(e) = std::fs::write(pidfile, format!("{}\n", std::process::id())) {
+            log::error!("Cannot write pidfile {}: {}", pidfile, e);
+            exit(EXIT_FAILURE);
+        }
+        remove_on_exit.push(pidfile.clone());
+    }
+    if let Some(ready_file) = &cli.ready_file {
+        // Left behind by an unclean exit, we are not ready yet
+        let _ = std::fs::remove_file(ready_file);
+        remove_on_exit.push(ready_file.clone());
+    }
+
+    let settings = load_settings(&cli);
     log::info!("Settings: {:?}", settings);
 
     let general = match settings.get("general") {
         Some(internal) => internal,
         None => {
             log::error!("Missing [internal] section in config.ini");
-            exit(1);
+            exit(EXIT_CONFIG);
         }
     };
     let mmsi = match general.get("mmsi").map(|v| v.parse::<u32>()) {
         None => {
             log::error!("Missing MMSI in config.ini");
-            exit(1);
+            exit(EXIT_CONFIG);
         }
         Some(Ok(interval)) => interval,
         Some(Err(e)) => {
             log::error!("Invalid MMSI in config.ini: {}", e);
-            exit(1);
+            exit(EXIT_CONFIG);
         }
     };
     let interval = match general.get("interval").map(|v| v.parse::<u64>()) {
@@ -120,7 +811,7 @@ fn main() {
         Some(Ok(interval)) => interval,
         Some(Err(e)) => {
             log::error!("Invalid interval in config.ini: {}", e);
-            exit(1);
+            exit(EXIT_CONFIG);
         }
     };
     let location_interval = match general.get("location_interval").map(|v| v.parse::<u64>()) {
@@ -128,7 +819,7 @@ fn main() {
         Some(Ok(interval)) => interval,
         Some(Err(e)) => {
             log::error!("Invalid location_interval in config.ini: {}", e);
-            exit(1);
+            exit(EXIT_CONFIG);
         }
     };
     let location_anchor_interval = match general
@@ -139,129 +830,763 @@ fn main() {
         Some(Ok(interval)) => interval,
         Some(Err(e)) => {
             log::error!("Invalid location_anchor_interval in config.ini: {}", e);
-            exit(1);
+            exit(EXIT_CONFIG);
+        }
+    };
+
+    // In MB, the resident size above which caches are dropped
+    let memory_limit = parse_option::<u64>(Some(general), "general", "memory_limit");
+    let passthrough =
+        parse_option::<bool>(Some(general), "general", "passthrough").unwrap_or(false);
+    let class_b_interval = parse_option::<u64>(Some(general), "general", "class_b_interval");
+    let aton_interval =
+        parse_option::<u64>(Some(general), "general", "aton_interval").unwrap_or(ATON_INTERVAL);
+    let base_station_interval =
+        parse_option::<u64>(Some(general), "general", "base_station_interval")
+            .unwrap_or(BASE_STATION_INTERVAL);
+    let clock_check =
+        parse_option::<bool>(Some(general), "general", "clock_check").unwrap_or(false);
+    let meteo = parse_option::<bool>(Some(general), "general", "meteo").unwrap_or(false);
+    let moving_speed = parse_option::<f64>(Some(general), "general", "moving_speed");
+    let max_hdop = parse_option::<f64>(Some(general), "general", "max_hdop");
+    let smooth_position =
+        parse_option::<bool>(Some(general), "general", "smooth_position").unwrap_or(false);
+    let mdns = parse_option::<bool>(Some(general), "general", "mdns").unwrap_or(true);
+    let port_mapping = parse_option::<portmap::Method>(Some(general), "general", "port_mapping")
+        .unwrap_or_default();
+    let clock = parse_option::<ClockSource>(Some(general), "general", "clock").unwrap_or_default();
+    // A comma separated list of [ais] endpoint names
+    let endpoint_list = |key: &str| -> Vec<String> {
+        let names: Vec<String> = general
+            .get(key)
+            .map(|names| {
+                names
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        if let Some(name) = names.iter().find(|name| {
+            !settings
+                .get("ais")
+                .is_some_and(|ais| ais.contains_key(*name))
+        }) {
+            log::error!("Invalid {} in config.ini: no [ais] endpoint {}", key, name);
+            exit(EXIT_CONFIG);
+        }
+        names
+    };
+    let dsc_endpoints = endpoint_list("dsc_endpoints");
+    let gps_endpoints = endpoint_list("gps_endpoints");
+    let station = general.get("station").cloned();
+    if let Some(station) = &station
+        && !nmea::valid_source(station)
+    {
+        log::error!(
+            "Invalid station in config.ini: '{}' should be up to 15 letters, digits, - or _",
+            station
+        );
+        exit(EXIT_CONFIG);
+    }
+    let tag_provider =
+        parse_option::<bool>(Some(general), "general", "tag_provider").unwrap_or(false);
+    let mut nice = [None, None];
+    for (value, key) in nice.iter_mut().zip(["nice", "io_nice"]) {
+        *value = match general.get(key).map(|v| v.parse::<i32>()) {
+            None => None,
+            Some(Ok(n)) if (priority::MIN_NICE..=priority::MAX_NICE).contains(&n) => Some(n),
+            Some(Ok(_)) => {
+                log::error!(
+                    "Invalid {} in config.ini: must be between {} and {}",
+                    key,
+                    priority::MIN_NICE,
+                    priority::MAX_NICE
+                );
+                exit(EXIT_CONFIG);
+            }
+            Some(Err(e)) => {
+                log::error!("Invalid {} in config.ini: {}", key, e);
+                exit(EXIT_CONFIG);
+            }
+        };
+    }
+    let [nice, io_nice] = nice;
+    // Before any thread is started, they inherit it
+    if let Some(nice) = nice
+        && let Err(e) = priority::set_process(nice)
+    {
+        log::warn!("Cannot set nice {}: {}", nice, e);
+    }
+    let queues = settings.get("queues");
+    let capacities = Capacities {
+        provider: parse_option::<usize>(queues, "queues", "provider")
+            .unwrap_or(reader::QUEUE_SIZE)
+            .max(1),
+        endpoint: parse_option::<usize>(queues, "queues", "endpoint")
+            .unwrap_or(sender::QUEUE_SIZE)
+            .max(1),
+        location: parse_option::<usize>(queues, "queues", "location")
+            .unwrap_or(location::QUEUE_SIZE)
+            .max(1),
+    };
+
+    let capture = match cli
+        .capture
+        .as_deref()
+        .map(|path| Capture::new(path, cli.capture_sent))
+    {
+        None => None,
+        Some(Ok(capture)) => Some(Arc::new(capture)),
+        Some(Err(e)) => {
+            log::error!("Cannot open capture file: {}", e);
+            exit(EXIT_FAILURE);
+        }
+    };
+
+    // Only written when configured, some users want to know what they share
+    let audit = match general.get("audit_log").map(|path| AuditLog::new(path)) {
+        None => None,
+        Some(Ok(audit)) => Some(Arc::new(audit)),
+        Some(Err(e)) => {
+            log::error!("Cannot open audit_log in config.ini: {}", e);
+            exit(EXIT_CONFIG);
+        }
+    };
+
+    let metrics = Arc::new(Metrics::new());
+    if let Some(station) = &station {
+        metrics.set_station(station);
+    }
+    if let Err(e) = resources::start(metrics.clone()) {
+        log::error!("Cannot start resource sampling: {}", e);
+        exit(EXIT_FAILURE);
+    }
+    // Seconds without progress from the provider or location thread before we restart
+    match parse_option::<u64>(Some(general), "general", "watchdog").unwrap_or(900) {
+        0 => {}
+        timeout if timeout < watchdog::MIN_TIMEOUT.as_secs() => {
+            log::error!(
+                "Invalid watchdog in config.ini: must be 0 or at least {}",
+                watchdog::MIN_TIMEOUT.as_secs()
+            );
+            exit(EXIT_CONFIG);
+        }
+        timeout => {
+            if let Err(e) = watchdog::start(Duration::from_secs(timeout), metrics.clone()) {
+                log::error!("Cannot start watchdog: {}", e);
+                exit(EXIT_FAILURE);
+            }
+        }
+    }
+    // Location updates go from the dispatcher to the location thread, which
+    // also answers the dump command.
+    let (tx, rx) = std::sync::mpsc::sync_channel::<LocationMessage>(capacities.location);
+    let controls = Arc::new(Controls::new());
+    let notifier = match Notifier::new(settings.get("notify"), metrics.clone()) {
+        Ok(notifier) => notifier,
+        Err(e) => {
+            log::error!("Invalid [notify] section in config.ini: {}", e);
+            exit(EXIT_CONFIG);
         }
     };
+    let commands = Commands::new(
+        controls.clone(),
+        metrics.clone(),
+        tx.clone(),
+        notifier.clone(),
+    );
 
-    let (tx, rx) = std::sync::mpsc::channel::<ParsedMessage>();
+    let logbook = Arc::new(Logbook::open(&cli.cache_dir));
+    let mut targets = None;
+    if let Some(http) = settings.get("http") {
+        match http.get("listen").map(|v| v.parse::<SocketAddr>()) {
+            None => {}
+            Some(Ok(listen)) => {
+                // The control commands are only served with a token, and an
+                // empty one would let anyone in
+                if http
+                    .get("token")
+                    .is_some_and(|token| token.trim().is_empty())
+                {
+                    log::error!("Empty [http] token in config.ini");
+                    exit(EXIT_CONFIG);
+                }
+                let api = http.get("token").map(|token| http::Api {
+                    token: token.clone(),
+                    commands: commands.clone(),
+                });
+                let served = Arc::new(Targets::new());
+                targets = Some(served.clone());
+                if let Err(e) = http::start(listen, metrics.clone(), served, logbook.clone(), api) {
+                    log::error!("Cannot serve status on {}: {}", listen, e);
+                    exit(EXIT_FAILURE);
+                }
+            }
+            Some(Err(e)) => {
+                log::error!("Invalid [http] listen address in config.ini: {}", e);
+                exit(EXIT_CONFIG);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    if let Err(e) = control::start(&control_socket, commands) {
+        log::error!("Cannot create control socket {}: {}", control_socket, e);
+        exit(EXIT_FAILURE);
+    }
+
+    let alerts_section = settings.get("alerts");
+    let alerts = notify::Alerts {
+        provider_down: parse_option::<u64>(alerts_section, "alerts", "provider_down")
+            .map(|minutes| Duration::from_secs(minutes * 60)),
+        endpoint_failures: parse_option(alerts_section, "alerts", "endpoint_failures"),
+        anchor_drift: parse_option(alerts_section, "alerts", "anchor_drift"),
+        cpa: parse_option(alerts_section, "alerts", "cpa"),
+        tcpa: parse_option::<u64>(alerts_section, "alerts", "tcpa")
+            .map(|minutes| Duration::from_secs(minutes * 60))
+            .unwrap_or(notify::TCPA),
+        density: parse_option(alerts_section, "alerts", "density"),
+        density_range: parse_option(alerts_section, "alerts", "density_range")
+            .unwrap_or(notify::DENSITY_RANGE),
+    };
+    let geofences = match geofence::from_config(settings.get("geofences")) {
+        Ok(geofences) => geofences,
+        Err(e) => {
+            log::error!("Invalid [geofences] section in config.ini: {}", e);
+            exit(EXIT_CONFIG);
+        }
+    };
+    if let Some(name) = geofences
+        .iter()
+        .flat_map(|geofence| geofence.pauses())
+        .find(|name| {
+            !settings
+                .get("ais")
+                .is_some_and(|ais| ais.contains_key(*name))
+        })
+    {
+        log::error!(
+            "Invalid [geofences] section in config.ini: no [ais] endpoint {}",
+            name
+        );
+        exit(EXIT_CONFIG);
+    }
+    let harbour = match settings.get("harbour").map(Harbour::from_config) {
+        None => None,
+        Some(Ok(harbour)) => Some(harbour),
+        Some(Err(e)) => {
+            log::error!("Invalid [harbour] section in config.ini: {}", e);
+            exit(EXIT_CONFIG);
+        }
+    };
+    if let Some(name) = harbour
+        .iter()
+        .flat_map(|harbour| harbour.geofences())
+        .find(|name| {
+            !geofences
+                .iter()
+                .any(|geofence| geofence.name() == name.as_str())
+        })
+    {
+        log::error!(
+            "Invalid [harbour] section in config.ini: no geofence {}",
+            name
+        );
+        exit(EXIT_CONFIG);
+    }
+    // The demo config has an empty [sms] section
+    let sms = match settings
+        .get("sms")
+        .filter(|section| !section.is_empty())
+        .map(Sms::from_config)
+    {
+        None => None,
+        Some(Ok(sms)) => Some(sms),
+        Some(Err(e)) => {
+            log::error!("Invalid [sms] section in config.ini: {}", e);
+            exit(EXIT_CONFIG);
+        }
+    };
+    // The demo config has an empty [telemetry] section
+    let telemetry = match settings
+        .get("telemetry")
+        .filter(|section| !section.is_empty())
+        .map(Telemetry::from_config)
+    {
+        None => None,
+        Some(Ok(telemetry)) => Some(telemetry),
+        Some(Err(e)) => {
+            log::error!("Invalid [telemetry] section in config.ini: {}", e);
+            exit(EXIT_CONFIG);
+        }
+    };
+    if let Err(e) = notify::start_watcher(&alerts, metrics.clone(), notifier.clone()) {
+        log::error!("Cannot start alerts watcher: {}", e);
+        exit(EXIT_FAILURE);
+    }
+
+    // The demo config has an empty [mqtt] section, only a server enables it
+    if let Some(section) = settings.get("mqtt")
+        && section.contains_key("server")
+        && let Err(e) = mqtt::start(section, mmsi, metrics.clone())
+    {
+        log::error!("Invalid [mqtt] section in config.ini: {}", e);
+        exit(EXIT_CONFIG);
+    }
+
+    if let Some(section) = settings.get("heartbeat")
+        && let Err(e) = heartbeat::start(section, mmsi, metrics.clone())
+    {
+        log::error!("Invalid [heartbeat] section in config.ini: {}", e);
+        exit(EXIT_CONFIG);
+    }
+
+    let standby = match settings.get("standby") {
+        None => None,
+        Some(section) => match standby::start(section, metrics.clone()) {
+            Ok(standby) => standby,
+            Err(e) => {
+                log::error!("Invalid [standby] section in config.ini: {}", e);
+                exit(EXIT_CONFIG);
+            }
+        },
+    };
+
+    // The one in [general] and those in [providers], by name
+    let mut providers: Vec<(String, NetworkEndpoint)> = Vec::new();
+    let named = settings
+        .get("providers")
+        .into_iter()
+        .flatten()
+        .map(|(name, url)| (name.as_str(), url));
+    for (name, url) in general
+        .get("provider")
+        .map(|url| (reader::DEFAULT_NAME, url))
+        .into_iter()
+        .chain(named)
+    {
+        if !nmea::valid_source(name) {
+            log::error!(
+                "Invalid provider name in config.ini: '{}' should be up to 15 letters, digits, - or _",
+                name
+            );
+            exit(EXIT_CONFIG);
+        }
+        if providers.iter().any(|(other, _)| other == name) {
+            log::error!("Provider {} in config.ini is there twice", name);
+            exit(EXIT_CONFIG);
+        }
+        match url.parse::<NetworkEndpoint>() {
+            Ok(provider) => providers.push((name.to_string(), provider)),
+            Err(e) => {
+                log::error!("Invalid provider {} in config.ini: {}", name, e);
+                exit(EXIT_CONFIG);
+            }
+        }
+    }
+    if providers.is_empty() {
+        log::error!("Missing provider in config.ini");
+        exit(EXIT_CONFIG);
+    }
+    // The one in [general] first, then the others by name
+    providers.sort_by_key(|(name, _)| (name != reader::DEFAULT_NAME, name.clone()));
+    let mut options = output_options(general);
+    for (name, provider) in providers.iter_mut() {
+        provider.dns_ttl = options.dns_ttl;
+        // Bind now, the port may need privileges that we are about to drop
+        match provider.bind() {
+            Ok(()) => {}
+            // DNS may not be up yet at boot, the dispatcher keeps trying
+            Err(e) if e.kind() == io::ErrorKind::HostUnreachable => {
+                log::warn!(provider = name.as_str(); "{}, will retry", e);
+            }
+            Err(e) => {
+                log::error!("Cannot listen on {}: {}", provider, e);
+                exit(EXIT_FAILURE);
+            }
+        }
+    }
+    // Bound now as well, for a source port that needs privileges
+    let udp_socket = match general.get("udp_source").map(|v| v.parse::<SocketAddr>()) {
+        None => None,
+        Some(Ok(source)) => match UdpSocket::bind(source) {
+            Ok(socket) => {
+                log::info!("Sending to UDP outputs from {}", source);
+                Some(Arc::new(socket))
+            }
+            Err(e) => {
+                log::error!("Cannot bind udp_source {}: {}", source, e);
+                exit(EXIT_FAILURE);
+            }
+        },
+        Some(Err(e)) => {
+            log::error!("Invalid udp_source in config.ini: {}", e);
+            exit(EXIT_CONFIG);
+        }
+    };
+    #[cfg(unix)]
+    if let Err(e) = privileges::drop_privileges(
+        cli.user.as_deref(),
+        cli.group.as_deref(),
+        &[
+            &cli.cache_dir,
+            &format!("{}/ais-forwarder.lock", cli.cache_dir),
+        ],
+    ) {
+        log::error!("Cannot drop privileges: {}", e);
+        exit(EXIT_FAILURE);
+    }
+
+    options.udp_socket = udp_socket;
+    let outputs = output::Registry::new(options);
     let location = match settings.get("location") {
         Some(location) => location,
         None => {
             log::error!("Missing [location] section in config.ini");
-            exit(1);
+            exit(EXIT_CONFIG);
         }
     }
-    .into_iter()
+    .iter()
     .map(|(key, value)| {
-        let address = value
-            .parse::<NetworkEndpoint>()
-            .map_err(|e| {
-                log::error!("Invalid address '{}' in config.ini: {}", value, e);
-                exit(1);
-            })
-            .unwrap();
-        (key.clone(), address)
+        let sink = outputs.create(key, value).unwrap_or_else(|e| {
+            log::error!("Invalid address '{}' in config.ini: {}", value, e);
+            exit(EXIT_CONFIG);
+        });
+        (key.clone(), sink)
     })
     .collect();
+    let shared = Shared {
+        metrics: metrics.clone(),
+        notifier,
+        anchor_drift: alerts.anchor_drift,
+        collision: alerts.cpa.map(|cpa| (cpa, alerts.tcpa)),
+        density: alerts
+            .density
+            .map(|density| (density, alerts.density_range)),
+        geofences,
+        harbour,
+        sms,
+        capture,
+        audit,
+        busy: Arc::new(Mutex::new(())),
+        ready_file: cli.ready_file.clone(),
+        memory_limit,
+        controls,
+        standby,
+        passthrough,
+        own_mmsi: (mmsi != 0).then_some(mmsi),
+        class_b_interval,
+        aton_interval,
+        base_station_interval,
+        clock_check,
+        meteo,
+        telemetry,
+        moving_speed,
+        max_hdop,
+        smooth_position,
+        clock,
+        dsc_endpoints,
+        gps_endpoints,
+        station,
+        tag_provider,
+        targets,
+        logbook: Some(logbook),
+        capacities,
+        io_nice,
+    };
+    let location_shared = shared.clone();
     Builder::new()
         .name("location".to_string())
         .spawn(move || {
-            location::work_thread(rx, location, mmsi, cli.cache_dir.as_str());
+            location::work_thread(rx, location, mmsi, cli.cache_dir.as_str(), location_shared);
         })
         .unwrap();
+    if let Err(e) = shutdown::start(shared.busy.clone(), tx.clone(), remove_on_exit) {
+        log::error!("Cannot install signal handlers: {}", e);
+        exit(EXIT_FAILURE);
+    }
 
-    loop {
-        let provider = match general
-            .get("provider")
-            .map(|v| v.parse::<NetworkEndpoint>())
-        {
-            None => {
-                log::error!("Missing provider in config.ini");
-                exit(1);
-            }
-            Some(Ok(provider)) => provider,
-            Some(Err(e)) => {
-                log::error!("Invalid interval in config.ini: {}", e);
-                exit(1);
-            }
-        };
+    #[cfg(target_os = "linux")]
+    if let Err(e) = systemd::start(metrics.clone()) {
+        log::error!("Cannot notify systemd: {}", e);
+        exit(EXIT_FAILURE);
+    }
 
-        let ais = match settings.get("ais") {
-            Some(ais) => ais,
-            None => {
-                log::error!("Missing [ais] section in config.ini");
-                exit(1);
-            }
-        };
-        let ais = ais
-            .into_iter()
-            .map(|(key, value)| {
-                let address = value
-                    .parse::<NetworkEndpoint>()
-                    .map_err(|e| {
-                        log::error!("Invalid address '{}' in config.ini: {}", value, e);
-                        exit(1);
-                    })
-                    .unwrap();
-                (key.clone(), address)
+    let ais = match settings.get("ais") {
+        Some(ais) => ais,
+        None => {
+            log::error!("Missing [ais] section in config.ini");
+            exit(EXIT_CONFIG);
+        }
+    };
+    // Feeds that we serve are advertised on the boat's network
+    let services: Vec<mdns::Service> = ais
+        .iter()
+        .filter_map(|(key, value)| {
+            let (transport, address) = if let Some(address) = value.strip_prefix("tcp-listen://") {
+                (mdns::Transport::Tcp, address)
+            } else {
+                (mdns::Transport::Udp, value.strip_prefix("udp-listen://")?)
+            };
+            let address = address.split('?').next().unwrap_or(address);
+            let port = address.rsplit(':').next()?.parse().ok()?;
+            Some(mdns::Service {
+                instance: key.clone(),
+                transport,
+                port,
             })
-            .collect();
+        })
+        .collect();
+    // and the TCP ones can be reached from shore through the router
+    let mappings = services
+        .iter()
+        .filter(|service| service.transport == mdns::Transport::Tcp)
+        .map(|service| portmap::Mapping {
+            name: service.instance.clone(),
+            port: service.port,
+        })
+        .collect();
+    if let Err(e) = portmap::start(port_mapping, mappings) {
+        log::warn!("Cannot map ports on the router: {}", e);
+    }
+    if mdns
+        && !services.is_empty()
+        && let Err(e) = mdns::start(services)
+    {
+        log::warn!("Cannot advertise with mDNS: {}", e);
+    }
+    // What to drop when an endpoint cannot keep up, per endpoint or as default
+    let drop_policy = settings.get("drop_policy");
+    let profile = settings.get("profile");
+    let pipeline = settings.get("pipeline");
+    let ais = ais
+        .iter()
+        .map(|(key, value)| {
+            let sink = outputs.create(key, value).unwrap_or_else(|e| {
+                log::error!("Invalid address '{}' in config.ini: {}", value, e);
+                exit(EXIT_CONFIG);
+            });
+            let sink = match profile
+                .and_then(|section| section.get(key).or_else(|| section.get("default")))
+                .map(|v| v.parse::<Profile>())
+            {
+                None => sink,
+                Some(Ok(profile)) => profile.wrap(key, sink),
+                Some(Err(e)) => {
+                    log::error!("Invalid [profile] for {} in config.ini: {}", key, e);
+                    exit(EXIT_CONFIG);
+                }
+            };
+            let pipeline = match pipeline
+                .and_then(|section| section.get(key).or_else(|| section.get("default")))
+                .map(|v| v.parse::<Pipeline>())
+            {
+                None => None,
+                Some(Ok(pipeline)) => Some(pipeline),
+                Some(Err(e)) => {
+                    log::error!("Invalid [pipeline] for {} in config.ini: {}", key, e);
+                    exit(EXIT_CONFIG);
+                }
+            };
+            let sink = match &pipeline {
+                Some(pipeline) => pipeline.wrap(key, clock, sink),
+                None => sink,
+            };
+            let from = pipeline.as_ref().and_then(Pipeline::providers);
+            if let Some(name) = from
+                .iter()
+                .flatten()
+                .find(|name| !providers.iter().any(|(provider, _)| provider == *name))
+            {
+                log::error!(
+                    "Invalid [pipeline] for {} in config.ini: no provider {}",
+                    key,
+                    name
+                );
+                exit(EXIT_CONFIG);
+            }
+            let policy = match drop_policy
+                .and_then(|section| section.get(key).or_else(|| section.get("default")))
+                .map(|v| v.parse::<DropPolicy>())
+            {
+                None => DropPolicy::default(),
+                Some(Ok(policy)) => policy,
+                Some(Err(e)) => {
+                    log::error!("Invalid [drop_policy] for {} in config.ini: {}", key, e);
+                    exit(EXIT_CONFIG);
+                }
+            };
+            Endpoint::start(key, sink, policy, &shared)
+                .unwrap_or_else(|e| {
+                    log::error!("Cannot start sender for {}: {}", key, e);
+                    exit(EXIT_FAILURE);
+                })
+                .only_from(from)
+        })
+        .collect();
 
-        let mut dispatcher = Dispatcher::new(
-            provider,
-            ais,
-            tx.clone(),
-            interval,
-            location_interval,
-            location_anchor_interval,
-        );
-        if let Err(e) = dispatcher.work() {
-            log::error!("{}", e);
-            std::thread::sleep(Duration::from_secs(1));
+    // The dispatcher and its endpoints live as long as we do, the reader
+    // keeps trying a failing provider.
+    let reader = match reader::start(providers, capacities.provider, io_nice, metrics.clone()) {
+        Ok(reader) => reader,
+        Err(e) => {
+            log::error!("Cannot start reading from the provider: {}", e);
+            exit(EXIT_FAILURE);
         }
+    };
+    let mut dispatcher = Dispatcher::new(
+        reader,
+        ais,
+        tx.clone(),
+        interval,
+        location_interval,
+        location_anchor_interval,
+        &shared,
+    );
+    priority::io_thread("dispatcher", io_nice);
+    if let Err(e) = dispatcher.work() {
+        log::error!("{}", e);
     }
+    exit(EXIT_FAILURE);
 }
 
 impl Dispatcher {
     fn new(
-        provider: NetworkEndpoint,
-        ais: HashMap<String, NetworkEndpoint>,
-        location_tx: Sender<ParsedMessage>,
+        reader: Reader,
+        ais: Vec<Endpoint>,
+        location_tx: SyncSender<LocationMessage>,
         interval: u64,
         location_interval: u64,
         location_anchor_interval: u64,
+        shared: &Shared,
     ) -> Self {
+        let loss_detector = reader.lossy.then(LossDetector::new);
+        let nmea_parsers = reader
+            .names
+            .iter()
+            .map(|_| nmea_parser::NmeaParser::new())
+            .collect();
         Dispatcher {
-            provider,
+            reader,
             ais,
             location_tx,
             interval,
+            class_b_interval: shared.class_b_interval.unwrap_or(interval),
+            aton_interval: shared.aton_interval,
+            base_station_interval: shared.base_station_interval,
+            clock_check: shared.clock_check,
+            clock_off: false,
+            meteo: shared.meteo,
+            telemetry: shared.telemetry.clone(),
+            dsc_endpoints: shared.dsc_endpoints.clone(),
+            gps_repeater: (!shared.gps_endpoints.is_empty())
+                .then(|| GpsRepeater::new(shared.gps_endpoints.clone())),
+            station: shared.station.clone(),
+            targets: shared.targets.clone(),
+            logbook: shared.logbook.clone(),
             location_interval,
             location_anchor_interval,
-            nmea_parser: nmea_parser::NmeaParser::new(),
+            moving_speed: shared.moving_speed,
+            gps_fix: FixQuality::default(),
+            max_hdop: shared.max_hdop,
+            position_filter: shared.smooth_position.then(PositionFilter::default),
+            own_mmsi: shared.own_mmsi,
+            own_vdo: None,
+            clock: Clock::new(shared.clock),
+            nmea_parsers,
+            parser_reset: Instant::now(),
+            provider: 0,
+            tag_provider: shared.tag_provider,
             last_sent: HashMap::new(),
+            last_sent_cleanup: Instant::now(),
             last_sent_location: SystemTime::now() - Duration::from_secs(location_interval),
+            metrics: shared.metrics.clone(),
+            notifier: shared.notifier.clone(),
+            anchor_watch: shared.anchor_drift.map(AnchorWatch::new),
+            collision_watch: shared
+                .collision
+                .map(|(cpa, tcpa)| CollisionWatch::new(cpa, tcpa)),
+            density_watch: shared
+                .density
+                .map(|(threshold, range)| DensityWatch::new(threshold, range)),
+            geofences: shared.geofences.clone(),
+            harbour: shared.harbour.clone(),
+            loss_detector,
+            capture: shared.capture.clone(),
+            busy: shared.busy.clone(),
+            ready_file: shared.ready_file.clone(),
+            delivering: Vec::new(),
+            memory_guard: shared
+                .memory_limit
+                .map(|limit| MemoryGuard::new(limit * 1024 * 1024)),
+            controls: shared.controls.clone(),
+            standby: shared.standby.clone(),
+            passthrough: shared.passthrough,
         }
     }
 
-    fn next_location_system_time(&self, now: &SystemTime) -> SystemTime {
-        let next_instant = now.add(Duration::from_secs(self.location_interval));
-        let next_instant_secs = next_instant
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap() // Since this is now plus the interval, this should always be valid
-            .as_secs();
-        let next_instant_secs = next_instant_secs - (next_instant_secs % self.location_interval);
-        SystemTime::UNIX_EPOCH + Duration::from_secs(next_instant_secs)
+    // The name of the provider of the chunk being handled.
+    fn provider_name(&self) -> &str {
+        &self.reader.names[self.provider]
+    }
+
+    // False while the peer of a hot standby pair is doing the forwarding.
+    fn is_active(&self) -> bool {
+        self.standby
+            .as_ref()
+            .is_none_or(|standby| standby.is_active())
+    }
+
+    // Forget everything that the live feed will tell us again soon enough.
+    fn shed_memory(&mut self, resident: u64) {
+        log::warn!(
+            "Using {} MB, over the memory_limit; dropping {} vessels from the cache",
+            resident / 1024 / 1024,
+            self.last_sent.len()
+        );
+        let evicted = self.last_sent.len();
+        self.last_sent = HashMap::new();
+        self.metrics.record_throttle(0, evicted);
+        self.reset_parser();
+        self.metrics.shed_memory();
+        if let Some(targets) = &self.targets {
+            targets.clear();
+        }
+    }
+
+    // Drop the sentences the parser holds of incomplete messages.
+    fn reset_parser(&mut self) {
+        for parser in self.nmea_parsers.iter_mut() {
+            *parser = nmea_parser::NmeaParser::new();
+        }
+        self.parser_reset = Instant::now();
     }
-    fn next_location_anchor_system_time(&self, now: &SystemTime) -> SystemTime {
-        let next_instant = now.add(Duration::from_secs(self.location_anchor_interval));
-        let next_instant_secs = next_instant
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap() // Since this is now plus the interval, this should always be valid
-            .as_secs();
-        let next_instant_secs =
-            next_instant_secs - (next_instant_secs % self.location_anchor_interval);
-        SystemTime::UNIX_EPOCH + Duration::from_secs(next_instant_secs)
+
+    // Create the --ready-file when a provider starts delivering data, and
+    // remove it when none of them does any more.
+    fn set_delivering(&mut self, provider: usize, delivering: bool) {
+        let was_ready = self.delivering.contains(&true);
+        if self.delivering.len() <= provider {
+            self.delivering.resize(provider + 1, false);
+        }
+        self.delivering[provider] = delivering;
+        let ready = self.delivering.contains(&true);
+        if ready == was_ready {
+            return;
+        }
+        let Some(path) = &self.ready_file else {
+            return;
+        };
+        let result = if ready {
+            std::fs::write(path, b"")
+        } else {
+            std::fs::remove_file(path)
+        };
+        if let Err(e) = result {
+            log::warn!("Cannot update ready file {}: {}", path, e);
+        }
     }
 
     // Send AIS messages to the AIS endpoints and handle location updates.
@@ -273,111 +1598,754 @@ impl Dispatcher {
     fn work(&mut self) -> io::Result<()> {
         const RMC_MESSAGE_TIMEOUT: Duration = Duration::from_secs(30);
 
-        let mut fragments = Vec::new();
-        let mut last_seen_rmc_message = SystemTime::UNIX_EPOCH;
+        // Each provider has messages of its own under way
+        let mut assemblies: Vec<Assembly> = self
+            .reader
+            .names
+            .iter()
+            .map(|_| Assembly::default())
+            .collect();
+        let mut last_seen_rmc_message: Option<Instant> = None;
         let mut prev_lat = 0.0;
         let mut prev_long = 0.0;
-        let now = SystemTime::now();
-        let mut next_location_ts = self.next_location_system_time(&now);
-        let mut next_location_anchor_ts = self.next_location_anchor_system_time(&now);
+        let mut next_location_ts = self.clock.next_aligned(self.location_interval);
+        let mut next_location_anchor_ts = self.clock.next_aligned(self.location_anchor_interval);
 
         loop {
             log::trace!("Waiting for message from provider");
-            let message = self.provider.read_to_string()?;
-            log::trace!("Received message: {}", message);
+            let (provider, message) = match self.reader.input.recv() {
+                Ok(Input::Chunk(provider, message)) => (provider, message),
+                Ok(Input::Failed(provider)) => {
+                    self.set_delivering(provider, false);
+                    continue;
+                }
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::BrokenPipe,
+                        "Provider reader stopped",
+                    ));
+                }
+            };
+            let message = message.as_slice();
+            self.provider = provider;
+            log::trace!(
+                provider = self.provider_name();
+                "Received message from {}: {}",
+                self.provider_name(),
+                String::from_utf8_lossy(message)
+            );
+            // Released at the end of this iteration, before the next read
+            let busy = self.busy.clone();
+            let _busy = busy.lock().unwrap();
+            self.set_delivering(provider, true);
+            self.metrics.record_progress("provider");
+            if let Some(resident) = self.memory_guard.as_mut().and_then(|g| g.exceeded()) {
+                self.shed_memory(resident);
+            }
+            if self.last_sent_cleanup.elapsed() >= LAST_SENT_CLEANUP {
+                self.evict_last_sent();
+            }
+            if assemblies
+                .iter()
+                .all(|assembly| assembly.fragments.is_empty())
+                && self.parser_reset.elapsed() >= PARSER_RESET_INTERVAL
+            {
+                log::debug!("Resetting the NMEA parser");
+                self.reset_parser();
+            }
+            if let Some(capture) = &self.capture {
+                capture.received(&self.reader.names[provider], message);
+            }
+            let assembly = &mut assemblies[provider];
 
-            for line in message.lines() {
+            for line in nmea::lines(message) {
                 log::trace!("Received line: {}", line);
-                match self.nmea_parser.parse_sentence(line) {
+                self.clock.observe_sentence(line);
+                self.gps_fix.observe(line);
+                if let Some(detector) = self.loss_detector.as_mut() {
+                    detector.observe(line);
+                    self.metrics.record_loss(detector);
+                }
+                let fragment = nmea::fragment(line);
+                if !assembly.fragments.is_empty()
+                    && (fragment.is_some_and(|(_, number)| number == 1)
+                        || assembly.fragments.len() >= MAX_FRAGMENTS
+                        || assembly
+                            .started
+                            .is_some_and(|started| started.elapsed() > FRAGMENT_TIMEOUT))
+                {
+                    log::debug!(
+                        provider = self.provider_name();
+                        "Dropping {} sentences of an incomplete message from {}",
+                        assembly.fragments.len(),
+                        self.provider_name()
+                    );
+                    self.metrics.record_dropped(None, DropReason::Incomplete);
+                    assembly.fragments.clear();
+                }
+                if let Some((total, number)) = fragment {
+                    if number == 1 {
+                        if assembly.fast_path.is_some() && !assembly.raw_fragments.is_empty() {
+                            self.metrics.record_dropped(None, DropReason::Incomplete);
+                        }
+                        assembly.fast_path = self.fast_path(line);
+                        assembly.raw_fragments.clear();
+                    }
+                    if let Some(action) = assembly.fast_path {
+                        if total == 1 {
+                            self.handle_raw(action, &[line]);
+                        } else {
+                            assembly.raw_fragments.push(line.to_string());
+                            if number == total {
+                                self.handle_raw(action, &assembly.raw_fragments);
+                            }
+                        }
+                        if number == total {
+                            assembly.fast_path = None;
+                        }
+                        continue;
+                    }
+                }
+                if nmea::is_dsc(line) {
+                    self.handle_dsc(line);
+                    continue;
+                }
+                if let Some(readings) = self
+                    .telemetry
+                    .as_ref()
+                    .and_then(|telemetry| telemetry.readings(line))
+                {
+                    self.handle_telemetry(line, readings);
+                    continue;
+                }
+                // The parser does not know about TAG blocks, we forward them as is
+                match self.nmea_parsers[provider].parse_sentence(nmea::split_tag_block(line).1) {
                     Ok(parsed_message) => {
                         if parsed_message == ParsedMessage::Incomplete {
-                            fragments.push(line.to_string());
+                            if assembly.fragments.is_empty() {
+                                assembly.started = Some(Instant::now());
+                            }
+                            assembly.fragments.push(line.to_string());
                             continue;
                         }
                         log::debug!("Parsed message: {:?}", parsed_message);
-                        let now = SystemTime::now();
+                        if let ParsedMessage::Rmc(data) = &parsed_message {
+                            self.clock.observe(data.timestamp.map(SystemTime::from));
+                        }
+                        let now = self.clock.now();
+                        let first_line = assembly
+                            .fragments
+                            .first()
+                            .map(String::as_str)
+                            .unwrap_or(line);
+                        let message_type = nmea::sentence_type(first_line);
+                        let mmsi = nmea::mmsi(first_line);
+                        let source_time = nmea::tag_timestamp(first_line);
+                        let distress = mmsi.and_then(DistressDevice::from_mmsi);
+                        let nav_status = nmea::nav_status(first_line);
+                        let class_b = nmea::class_b(first_line);
+                        self.metrics.record_received(&message_type, mmsi);
+                        if nmea::is_own_vessel(first_line) {
+                            self.own_vdo = mmsi.map(|mmsi| (mmsi, Instant::now()));
+                        } else if mmsi.is_some_and(|mmsi| self.mirrors_own_vdo(mmsi)) {
+                            log::trace!("Dropping our own report received as VDM");
+                            self.metrics.record_dropped(None, DropReason::Duplicate);
+                            assembly.fragments.clear();
+                            continue;
+                        }
+                        if let Some(targets) = &self.targets
+                            && !nmea::is_own_vessel(first_line)
+                            && mmsi.is_some_and(|mmsi| self.own_mmsi != Some(mmsi))
+                        {
+                            let lines: Vec<&str> = assembly
+                                .fragments
+                                .iter()
+                                .map(String::as_str)
+                                .chain([line])
+                                .collect();
+                            targets.update(&lines);
+                        }
 
                         if let (Some(own_vessel), lat, long) = match &parsed_message {
                             ParsedMessage::VesselDynamicData(data) => (
                                 Some(
-                                    last_seen_rmc_message + RMC_MESSAGE_TIMEOUT > now
+                                    last_seen_rmc_message
+                                        .is_some_and(|seen| seen + RMC_MESSAGE_TIMEOUT > now)
                                         && data.own_vessel,
                                 ),
                                 data.latitude,
                                 data.longitude,
                             ),
-                            ParsedMessage::VesselStaticData(_data) => (Some(false), None, None),
+                            ParsedMessage::VesselStaticData(data) => {
+                                if data.own_vessel || self.own_mmsi == Some(data.mmsi) {
+                                    self.own_static(&parsed_message);
+                                }
+                                (Some(false), None, None)
+                            }
+                            // Search and rescue aircraft, positioned and throttled like vessels
+                            ParsedMessage::StandardSarAircraftPositionReport(data) => {
+                                (Some(false), data.latitude, data.longitude)
+                            }
+                            ParsedMessage::AidToNavigationReport(data) => {
+                                (Some(false), data.latitude, data.longitude)
+                            }
+                            ParsedMessage::BaseStationReport(data) => {
+                                if let Some(timestamp) = data.timestamp {
+                                    self.check_clock(data.mmsi, timestamp);
+                                }
+                                (Some(false), data.latitude, data.longitude)
+                            }
                             ParsedMessage::Rmc(data) => {
-                                last_seen_rmc_message = now;
+                                last_seen_rmc_message = Some(now);
                                 (Some(true), data.latitude, data.longitude)
                             }
-                            _ => (None, None, None),
+                            _ => {
+                                self.metrics
+                                    .record_dropped(None, DropReason::UnsupportedType);
+                                (None, None, None)
+                            }
                         } {
-                            fragments.push(line.to_string());
+                            assembly.fragments.push(line.to_string());
                             // Ignore messages with no position or at (0, 0) coordinates
                             if let (Some(lat), Some(long)) = (lat, long) {
                                 log::trace!("Parsed position: lat: {}, long: {}", lat, long);
                                 if lat != 0.0 || long != 0.0 {
-                                    if self.check_last_sent(&parsed_message) {
-                                        self.broadcast_ais(
-                                            &parsed_message,
-                                            fragments.join("").as_bytes(),
-                                        )?;
+                                    if let (Some(device), Some(mmsi)) = (distress, mmsi) {
+                                        self.check_distress(device, mmsi, nav_status, lat, long);
+                                    }
+                                    if let Some(watch) = self.collision_watch.as_mut()
+                                        && let Some(motion) = motion(&parsed_message, lat, long)
+                                    {
+                                        if own_vessel {
+                                            watch.update_own(motion);
+                                        } else if let (
+                                            ParsedMessage::VesselDynamicData(data),
+                                            Some(mmsi),
+                                        ) = (&parsed_message, mmsi)
+                                            && !data.own_vessel
+                                            && let Some(event) = watch.check(mmsi, &motion)
+                                        {
+                                            self.notifier.notify(event);
+                                        }
+                                    }
+                                    if let Some(watch) = self.density_watch.as_mut() {
+                                        if own_vessel {
+                                            watch.update_own(lat, long);
+                                        } else if let (
+                                            ParsedMessage::VesselDynamicData(data),
+                                            Some(mmsi),
+                                        ) = (&parsed_message, mmsi)
+                                            && !data.own_vessel
+                                            && let Some(event) =
+                                                watch.check(mmsi, lat, long, data.sog_knots)
+                                        {
+                                            self.notifier.notify(event);
+                                        }
                                     }
-                                    if own_vessel {
+                                    // In harbour only the vessels nearby are forwarded
+                                    let in_range = own_vessel
+                                        || self
+                                            .harbour
+                                            .as_ref()
+                                            .is_none_or(|harbour| harbour.in_range(lat, long));
+                                    // Someone in distress is never held back
+                                    if distress.is_some()
+                                        || (in_range
+                                            && self.check_last_sent(&parsed_message, class_b))
+                                    {
+                                        log::debug!(
+                                            provider = self.provider_name();
+                                            "Broadcasting message from {}: {:?}",
+                                            self.provider_name(),
+                                            parsed_message
+                                        );
+                                        self.broadcast_ais(Outgoing {
+                                            data: nmea::group(&assembly.fragments).into(),
+                                            source_time,
+                                            own_position: match &parsed_message {
+                                                ParsedMessage::VesselDynamicData(data)
+                                                    if data.own_vessel =>
+                                                {
+                                                    Some(data.latitude.zip(data.longitude))
+                                                }
+                                                _ => None,
+                                            },
+                                            static_data: matches!(
+                                                parsed_message,
+                                                ParsedMessage::VesselStaticData(_)
+                                            ),
+                                        });
+                                        self.metrics.record_forwarded(&message_type, mmsi);
+                                    } else if let ParsedMessage::Rmc(_) = parsed_message {
+                                        // Our own GPS, only used for location updates
+                                        self.metrics
+                                            .record_dropped(None, DropReason::UnsupportedType);
+                                    } else if !in_range {
+                                        self.metrics.record_dropped(None, DropReason::Harbour);
+                                    } else {
+                                        self.metrics.record_dropped(None, DropReason::Throttled);
+                                    }
+                                    // What the filter leaves out is still forwarded
+                                    // as AIS, as received
+                                    if own_vessel
+                                        && let Some((lat, long)) = self.smooth_own(lat, long)
+                                    {
+                                        let sog = match &parsed_message {
+                                            ParsedMessage::VesselDynamicData(data) => {
+                                                data.sog_knots
+                                            }
+                                            ParsedMessage::Rmc(data) => data.sog_knots,
+                                            _ => None,
+                                        };
+                                        self.metrics.record_own_position(lat, long, sog);
+                                        self.repeat_gps(&parsed_message, lat, long);
+                                        if let Some(event) = self
+                                            .anchor_watch
+                                            .as_mut()
+                                            .and_then(|watch| watch.update(lat, long))
+                                        {
+                                            self.notifier.notify(event);
+                                        }
+                                        for geofence in self.geofences.iter_mut() {
+                                            if let Some(event) =
+                                                geofence.update(lat, long, &self.controls)
+                                            {
+                                                self.notifier.notify(event);
+                                            }
+                                        }
+                                        if let Some(harbour) = self.harbour.as_mut() {
+                                            harbour.update(lat, long, now, &self.geofences);
+                                        }
+                                        if let Some(logbook) = &self.logbook {
+                                            let port = self
+                                                .geofences
+                                                .iter()
+                                                .find(|geofence| geofence.inside())
+                                                .map(Geofence::name);
+                                            logbook.update(
+                                                self.clock.wall().into(),
+                                                lat,
+                                                long,
+                                                port,
+                                            );
+                                        }
                                         log::trace!(
                                             "Compare last sent location: {:?} interval {:?} anchor {:?}",
                                             now,
                                             next_location_ts,
                                             next_location_anchor_ts,
                                         );
-                                        if now >= next_location_anchor_ts
-                                            || (now >= next_location_ts
-                                                && is_moving(lat, long, prev_lat, prev_long))
+                                        let moving = self
+                                            .moving_speed
+                                            .and_then(|speed| {
+                                                reported_moving(&parsed_message, speed)
+                                            })
+                                            .unwrap_or_else(|| {
+                                                is_moving(lat, long, prev_lat, prev_long)
+                                            });
+                                        // On a standby station the active one reports our position
+                                        if self.is_active()
+                                            && self.gps_fix.acceptable(self.max_hdop)
+                                            && (self.controls.take_force_location()
+                                                || now >= next_location_anchor_ts
+                                                || (now >= next_location_ts && moving))
                                         {
                                             prev_lat = lat;
                                             prev_long = long;
-                                            self.last_sent_location = now;
-                                            self.location_tx.send(parsed_message).unwrap();
-                                            next_location_ts = self.next_location_system_time(&now);
-                                            next_location_anchor_ts =
-                                                self.next_location_anchor_system_time(&now);
+                                            self.last_sent_location = SystemTime::now();
+                                            match self.location_tx.try_send(
+                                                LocationMessage::Position(with_position(
+                                                    parsed_message,
+                                                    lat,
+                                                    long,
+                                                )),
+                                            ) {
+                                                Ok(()) => self.metrics.location_queued(),
+                                                Err(TrySendError::Full(_)) => {
+                                                    self.metrics.record_dropped(
+                                                        None,
+                                                        DropReason::LocationBehind,
+                                                    );
+                                                }
+                                                // Only when it is shutting down, or its restart
+                                                // loop itself failed; AIS keeps flowing.
+                                                Err(TrySendError::Disconnected(_)) => {
+                                                    log::error!(
+                                                        "Location thread stopped, dropping position update"
+                                                    );
+                                                    self.metrics.record_dropped(
+                                                        None,
+                                                        DropReason::LocationStopped,
+                                                    );
+                                                }
+                                            }
+                                            next_location_ts =
+                                                self.clock.next_aligned(self.location_interval);
+                                            next_location_anchor_ts = self
+                                                .clock
+                                                .next_aligned(self.location_anchor_interval);
                                         }
                                     }
+                                } else {
+                                    self.metrics.record_dropped(None, DropReason::NoPosition);
                                 }
+                            } else {
+                                self.metrics.record_dropped(None, DropReason::NoPosition);
                             }
-                            fragments.clear();
                         }
+                        assembly.fragments.clear();
                     }
-                    Err(_e) => {
-                        fragments.clear();
+                    Err(e) => {
+                        log::debug!(
+                            provider = self.provider_name();
+                            "Cannot parse '{}' from {}: {}",
+                            line,
+                            self.provider_name(),
+                            e
+                        );
+                        let reason = if nmea::checksum_ok(line) {
+                            DropReason::ParseError
+                        } else {
+                            DropReason::Checksum
+                        };
+                        self.metrics.record_dropped(None, reason);
+                        assembly.fragments.clear();
                     }
                 }
             }
         }
     }
 
-    fn broadcast_ais(&mut self, message: &ParsedMessage, nmea_message: &[u8]) -> io::Result<()> {
-        log::debug!("Broadcasting message: {:?} / {:?}", message, nmea_message);
-        for (key, address) in self.ais.iter_mut() {
-            send_message(&nmea_message, key, address)?;
+    // Pass our static data on to the location thread, which sends it with our
+    // position. It comes every few minutes, so one that does not fit is not
+    // missed for long.
+    fn own_static(&self, message: &ParsedMessage) {
+        if self
+            .location_tx
+            .try_send(LocationMessage::Static(message.clone()))
+            .is_err()
+        {
+            log::debug!("Location thread busy, own static data not passed on");
+        }
+    }
+
+    // Our own position after the smoothing filter, None when it is left out.
+    fn smooth_own(&mut self, latitude: f64, longitude: f64) -> Option<(f64, f64)> {
+        match self.position_filter.as_mut() {
+            Some(filter) => filter.update(self.clock.now(), latitude, longitude),
+            None => Some((latitude, longitude)),
+        }
+    }
+
+    // Our own position as RMC and GGA to the gps_endpoints, see repeater.rs.
+    fn repeat_gps(&mut self, message: &ParsedMessage, latitude: f64, longitude: f64) {
+        let (sog, cog) = match message {
+            ParsedMessage::VesselDynamicData(data) => (data.sog_knots, data.cog),
+            ParsedMessage::Rmc(data) => (data.sog_knots, data.bearing),
+            _ => (None, None),
+        };
+        if !self
+            .gps_repeater
+            .as_mut()
+            .is_some_and(|repeater| repeater.due(Instant::now()))
+        {
+            return;
+        }
+        let fix = self.gps_fix.acceptable(self.max_hdop);
+        let data = repeater::sentences(chrono::Utc::now(), fix, latitude, longitude, sog, cog);
+        let outgoing = Outgoing {
+            data: data.into(),
+            source_time: None,
+            own_position: None,
+            static_data: false,
+        };
+        self.broadcast_to(outgoing, |name| {
+            self.gps_repeater
+                .as_ref()
+                .is_some_and(|repeater| repeater.wants(name))
+        });
+    }
+
+    // Whether a VDM of this MMSI is our own report, also sent as VDO.
+    fn mirrors_own_vdo(&self, mmsi: u32) -> bool {
+        self.own_vdo
+            .is_some_and(|(own, seen)| own == mmsi && seen.elapsed() < OWN_VDO_TIMEOUT)
+    }
+
+    // Decoding is the most expensive part of handling a sentence. It is
+    // skipped for messages from other vessels when nothing needs the decoded
+    // fields: types that are never forwarded, and with passthrough everything
+    // but distress devices, which raise an alert. Our own vessel is always
+    // decoded.
+    fn fast_path(&self, line: &str) -> Option<FastPath> {
+        if nmea::is_own_vessel(line) {
+            return None;
+        }
+        let (message_type, mmsi) = nmea::ais_header(line)?;
+        if self.own_mmsi == Some(mmsi) || self.own_vdo.is_some_and(|(own, _)| own == mmsi) {
+            return None;
+        }
+        if BINARY_TYPES.contains(&message_type) {
+            return Some(FastPath::Forward { static_data: false });
+        }
+        if !DECODED_TYPES.contains(&message_type) {
+            // Such as the SART ACTIVE safety broadcast of a distress device
+            return Some(
+                if self.passthrough || DistressDevice::from_mmsi(mmsi).is_some() {
+                    FastPath::Forward { static_data: false }
+                } else {
+                    FastPath::Drop
+                },
+            );
+        }
+        if self.passthrough && DistressDevice::from_mmsi(mmsi).is_none() {
+            return Some(FastPath::Forward {
+                static_data: STATIC_TYPES.contains(&message_type),
+            });
+        }
+        None
+    }
+
+    // Handle the sentences of an AIS message as decided by fast_path.
+    fn handle_raw<S: AsRef<str>>(&mut self, fast_path: FastPath, lines: &[S]) {
+        let first_line = lines[0].as_ref();
+        let message_type = nmea::sentence_type(first_line);
+        let mmsi = nmea::mmsi(first_line);
+        self.metrics.record_received(&message_type, mmsi);
+        if !lines.iter().all(|line| nmea::checksum_ok(line.as_ref())) {
+            self.metrics.record_dropped(None, DropReason::Checksum);
+            return;
+        }
+        if let Some(targets) = &self.targets {
+            targets.update(lines);
+        }
+        match fast_path {
+            FastPath::Drop => {
+                self.metrics
+                    .record_dropped(None, DropReason::UnsupportedType);
+            }
+            FastPath::Forward { static_data } => {
+                log::debug!(
+                    provider = self.provider_name();
+                    "Passing through {} from {:?} from {}",
+                    message_type,
+                    mmsi,
+                    self.provider_name()
+                );
+                self.broadcast_ais(Outgoing {
+                    data: nmea::group(lines).into(),
+                    source_time: nmea::tag_timestamp(first_line),
+                    own_position: None,
+                    static_data,
+                });
+                self.metrics.record_forwarded(&message_type, mmsi);
+                if self.meteo
+                    && let Some(mmsi) = mmsi
+                    && let Some(report) = meteo::decode(lines)
+                {
+                    log::debug!(mmsi = mmsi; "Weather from MMSI {}: {}", mmsi, report);
+                    self.metrics.record_weather(mmsi, report);
+                }
+            }
+        }
+    }
+
+    // DSC calls that a VHF radio puts on the same stream. They only go to the
+    // dsc_endpoints, as AIS aggregators do not want them, and a distress
+    // alert is notified.
+    fn handle_dsc(&mut self, line: &str) {
+        let sentence_type = nmea::sentence_type(line);
+        self.metrics.record_received(&sentence_type, None);
+        if !nmea::checksum_ok(line) {
+            self.metrics.record_dropped(None, DropReason::Checksum);
+            return;
+        }
+        if let Some(distress) = nmea::dsc_distress(line) {
+            self.notifier.notify(Event::DscDistress {
+                mmsi: distress.mmsi,
+                position: distress.position,
+            });
         }
-        Ok(())
+        if self.dsc_endpoints.is_empty() {
+            self.metrics
+                .record_dropped(None, DropReason::UnsupportedType);
+            return;
+        }
+        let outgoing = Outgoing {
+            data: nmea::group(&[line]).into(),
+            source_time: nmea::tag_timestamp(line),
+            own_position: None,
+            static_data: false,
+        };
+        self.broadcast_to(outgoing, |name| {
+            self.dsc_endpoints.iter().any(|dsc| dsc == name)
+        });
+        self.metrics.record_forwarded(&sentence_type, None);
+    }
+
+    // Engine and tank readings, only kept for the status outputs.
+    fn handle_telemetry(&self, line: &str, readings: Vec<Reading>) {
+        self.metrics
+            .record_received(&nmea::sentence_type(line), None);
+        if !nmea::checksum_ok(line) {
+            self.metrics.record_dropped(None, DropReason::Checksum);
+            return;
+        }
+        for reading in readings {
+            log::trace!(
+                "Telemetry {} {} {}",
+                reading.name,
+                reading.value,
+                reading.unit
+            );
+            self.metrics.record_telemetry(reading);
+        }
+    }
+
+    fn broadcast_ais(&mut self, outgoing: Outgoing) {
+        self.broadcast_to(outgoing, |_| true);
     }
 
-    fn check_last_sent(&mut self, message: &ParsedMessage) -> bool {
+    // Send to the AIS endpoints whose name is wanted.
+    fn broadcast_to(&self, outgoing: Outgoing, wanted: impl Fn(&str) -> bool) {
+        if !self.is_active() {
+            self.metrics.record_dropped(None, DropReason::Standby);
+            return;
+        }
+        let source = if self.tag_provider {
+            Some(self.provider_name())
+        } else {
+            self.station.as_deref()
+        };
+        let outgoing = match source {
+            Some(source) => Outgoing {
+                data: nmea::with_source(&outgoing.data, source).into(),
+                ..outgoing
+            },
+            None => outgoing,
+        };
+        // Each endpoint sends from its own thread, one being down or slow
+        // is no reason to hold up the others or the provider.
+        for endpoint in self
+            .ais
+            .iter()
+            .filter(|endpoint| wanted(endpoint.name()) && endpoint.wants(self.provider_name()))
+        {
+            if self.controls.is_paused("ais", endpoint.name()) {
+                self.metrics
+                    .record_dropped(Some(endpoint.name()), DropReason::Paused);
+                continue;
+            }
+            endpoint.send(outgoing.clone());
+        }
+    }
+
+    // AIS SARTs, MOB devices and EPIRBs use MMSI 970xxyyyy, 972xxyyyy and
+    // 974xxyyyy. One that is being tested is forwarded but raises no alert.
+    fn check_distress(
+        &self,
+        device: DistressDevice,
+        mmsi: u32,
+        nav_status: Option<u8>,
+        latitude: f64,
+        longitude: f64,
+    ) {
+        if nav_status == Some(nmea::NAV_STATUS_DISTRESS_TEST) {
+            log::debug!(mmsi = mmsi; "AIS {} {} is being tested", device.name(), mmsi);
+            return;
+        }
+        self.notifier.notify(Event::Distress {
+            device,
+            mmsi,
+            latitude,
+            longitude,
+        });
+    }
+
+    // Forget the vessels that were not sent for a whole interval, and the aids
+    // to navigation and base stations not sent for a whole interval of theirs.
+    fn evict_last_sent(&mut self) {
+        let interval = Duration::from_secs(self.vessel_interval());
+        let aton_interval = Duration::from_secs(self.aton_interval);
+        let base_station_interval = Duration::from_secs(self.base_station_interval);
+        let now = self.clock.now();
+        let before = self.last_sent.len();
+        self.last_sent.retain(|_, last_sent| {
+            now.duration_since(last_sent.newest()) < interval
+                || last_sent
+                    .aid_to_navigation
+                    .is_some_and(|sent| now.duration_since(sent) < aton_interval)
+                || last_sent
+                    .base_station
+                    .is_some_and(|sent| now.duration_since(sent) < base_station_interval)
+        });
+        let evicted = before - self.last_sent.len();
+        if evicted > 0 {
+            log::debug!("Forgot {} vessels not sent in the last interval", evicted);
+        }
+        self.metrics.record_throttle(self.last_sent.len(), evicted);
+        self.last_sent_cleanup = Instant::now();
+    }
+
+    fn last_sent_entry(&mut self, mmsi: u32, now: Instant) -> &mut LastSent {
+        if self.last_sent.len() >= MAX_LAST_SENT && !self.last_sent.contains_key(&mmsi) {
+            self.evict_last_sent();
+            // Still full of vessels sent within the interval, then the one sent
+            // longest ago makes room.
+            if self.last_sent.len() >= MAX_LAST_SENT
+                && let Some(oldest) = self
+                    .last_sent
+                    .iter()
+                    .min_by_key(|(_, last_sent)| last_sent.newest())
+                    .map(|(mmsi, _)| *mmsi)
+            {
+                self.last_sent.remove(&oldest);
+                self.metrics.record_throttle(self.last_sent.len(), 1);
+            }
+        }
+        let elapsed = now - Duration::from_secs(self.vessel_interval());
+        self.last_sent.entry(mmsi).or_insert(LastSent {
+            vessel_dynamic_data: elapsed,
+            vessel_static_data: elapsed,
+            aid_to_navigation: None,
+            base_station: None,
+        })
+    }
+
+    // The longest that a vessel may go between forwarded reports.
+    fn vessel_interval(&self) -> u64 {
+        self.interval
+            .max(self.class_b_interval)
+            .max(self.harbour_interval())
+    }
+
+    // The interval in harbour mode, 0 when not in it.
+    fn harbour_interval(&self) -> u64 {
+        self.harbour.as_ref().map_or(0, Harbour::interval)
+    }
+
+    // `class_b` is the kind of Class B unit of a position report, see nmea.rs.
+    fn check_last_sent(&mut self, message: &ParsedMessage, class_b: Option<ClassB>) -> bool {
+        let harbour_interval = self.harbour_interval();
+        let interval = self.interval.max(harbour_interval);
         match message {
             ParsedMessage::VesselDynamicData(data) => {
-                let now = Instant::now();
-                let elapsed = now - Duration::from_secs(self.interval);
-                let last_sent = self.last_sent.entry(data.mmsi).or_insert(LastSent {
-                    vessel_dynamic_data: elapsed,
-                    vessel_static_data: elapsed,
-                });
+                let now = self.clock.now();
+                // SO units report like class A and are throttled like it
+                let dynamic_interval = match class_b {
+                    Some(ClassB::CarrierSense) => self.class_b_interval.max(harbour_interval),
+                    _ => interval,
+                };
+                let last_sent = self.last_sent_entry(data.mmsi, now);
                 let elapsed_secs = now.duration_since(last_sent.vessel_dynamic_data).as_secs();
-                if elapsed_secs >= self.interval {
+                // An extended report carries the static data as well, and is not
+                // held back when that is due
+                let static_due = class_b == Some(ClassB::Extended)
+                    && now.duration_since(last_sent.vessel_static_data).as_secs() >= interval;
+                if elapsed_secs >= dynamic_interval || static_due {
                     last_sent.vessel_dynamic_data = now;
                     log::debug!(
+                        mmsi = data.mmsi;
                         "Sending dynamic data for MMSI {} as we last sent it {} seconds ago",
                         data.mmsi,
                         elapsed_secs
@@ -385,22 +2353,20 @@ impl Dispatcher {
                     return true;
                 }
                 log::debug!(
+                    mmsi = data.mmsi;
                     "Skipping dynamic data for MMSI {} as we last sent it {} seconds ago",
                     data.mmsi,
                     elapsed_secs
                 );
             }
             ParsedMessage::VesselStaticData(data) => {
-                let now = Instant::now();
-                let elapsed = now - Duration::from_secs(self.interval);
-                let last_sent = self.last_sent.entry(data.mmsi).or_insert(LastSent {
-                    vessel_dynamic_data: elapsed,
-                    vessel_static_data: elapsed,
-                });
+                let now = self.clock.now();
+                let last_sent = self.last_sent_entry(data.mmsi, now);
                 let elapsed_secs = now.duration_since(last_sent.vessel_static_data).as_secs();
-                if elapsed_secs >= self.interval {
+                if elapsed_secs >= interval {
                     last_sent.vessel_static_data = now;
                     log::debug!(
+                        mmsi = data.mmsi;
                         "Sending static data for MMSI {} as we last sent it {} seconds ago",
                         data.mmsi,
                         elapsed_secs
@@ -408,17 +2374,144 @@ impl Dispatcher {
                     return true;
                 }
                 log::debug!(
+                    mmsi = data.mmsi;
                     "Skipping static data for MMSI {} as we last sent it {} seconds ago",
                     data.mmsi,
                     elapsed_secs
                 );
             }
+            ParsedMessage::StandardSarAircraftPositionReport(data) => {
+                let now = self.clock.now();
+                let last_sent = self.last_sent_entry(data.mmsi, now);
+                let elapsed_secs = now.duration_since(last_sent.vessel_dynamic_data).as_secs();
+                if elapsed_secs >= interval {
+                    last_sent.vessel_dynamic_data = now;
+                    log::debug!(
+                        mmsi = data.mmsi;
+                        "Sending SAR aircraft position for MMSI {} as we last sent it {} seconds ago",
+                        data.mmsi,
+                        elapsed_secs
+                    );
+                    return true;
+                }
+                log::debug!(
+                    mmsi = data.mmsi;
+                    "Skipping SAR aircraft position for MMSI {} as we last sent it {} seconds ago",
+                    data.mmsi,
+                    elapsed_secs
+                );
+            }
+            ParsedMessage::AidToNavigationReport(data) => {
+                let now = self.clock.now();
+                let aton_interval = self.aton_interval.max(harbour_interval);
+                let last_sent = self.last_sent_entry(data.mmsi, now);
+                if fixed_station_due(&mut last_sent.aid_to_navigation, now, aton_interval) {
+                    log::debug!(
+                        mmsi = data.mmsi;
+                        "Sending aid to navigation report for MMSI {} '{}'",
+                        data.mmsi,
+                        data.name
+                    );
+                    return true;
+                }
+                log::debug!(
+                    mmsi = data.mmsi;
+                    "Skipping aid to navigation report for MMSI {}",
+                    data.mmsi
+                );
+            }
+            ParsedMessage::BaseStationReport(data) => {
+                let now = self.clock.now();
+                let base_station_interval = self.base_station_interval.max(harbour_interval);
+                let last_sent = self.last_sent_entry(data.mmsi, now);
+                if fixed_station_due(&mut last_sent.base_station, now, base_station_interval) {
+                    log::debug!(
+                        mmsi = data.mmsi;
+                        "Sending base station report for MMSI {}",
+                        data.mmsi
+                    );
+                    return true;
+                }
+                log::debug!(
+                    mmsi = data.mmsi;
+                    "Skipping base station report for MMSI {}",
+                    data.mmsi
+                );
+            }
             _ => {
                 log::debug!("Ignoring message: {:?}", message);
             }
         }
+        false
+    }
+
+    // Compare our clock with the UTC a base station reports, for routers
+    // without a real time clock that did not get the time from NTP or GPS.
+    // Warned about once each time it goes wrong.
+    fn check_clock(&mut self, mmsi: u32, station_time: chrono::DateTime<chrono::Utc>) {
+        if !self.clock_check {
+            return;
+        }
+        let offset = (chrono::Utc::now() - station_time).as_seconds_f64();
+        self.metrics.record_clock_offset(mmsi, offset);
+        let off = offset.abs() > CLOCK_TOLERANCE.as_secs_f64();
+        if off && !self.clock_off {
+            log::warn!(
+                mmsi = mmsi;
+                "Our clock is {:.0}s {} the UTC of base station MMSI {}",
+                offset.abs(),
+                if offset > 0.0 { "ahead of" } else { "behind" },
+                mmsi
+            );
+        } else if !off && self.clock_off {
+            log::info!(mmsi = mmsi; "Our clock agrees with base station MMSI {} again", mmsi);
+        }
+        self.clock_off = off;
+    }
+}
+
+// Whether the report of a fixed station, an aid to navigation or a base
+// station, is due `interval` seconds after the last one was sent; if so it is
+// marked as sent now.
+fn fixed_station_due(sent: &mut Option<Instant>, now: Instant, interval: u64) -> bool {
+    if sent.is_some_and(|sent| now.duration_since(sent).as_secs() < interval) {
         return false;
     }
+    *sent = Some(now);
+    true
+}
+
+// Course and speed of a vessel or our own GPS for the collision watch. The
+// course may be missing when hardly moving, then it does not matter.
+fn motion(message: &ParsedMessage, latitude: f64, longitude: f64) -> Option<geo::Motion> {
+    let (sog, cog) = match message {
+        ParsedMessage::VesselDynamicData(data) => (data.sog_knots?, data.cog),
+        ParsedMessage::Rmc(data) => (data.sog_knots?, data.bearing),
+        _ => return None,
+    };
+    let cog = cog.or((sog < 0.5).then_some(0.0))?;
+    Some(geo::Motion {
+        latitude,
+        longitude,
+        sog,
+        cog,
+    })
+}
+
+// Our own report with the position that we use, the smoothed one.
+fn with_position(mut message: ParsedMessage, latitude: f64, longitude: f64) -> ParsedMessage {
+    match &mut message {
+        ParsedMessage::VesselDynamicData(data) => {
+            data.latitude = Some(latitude);
+            data.longitude = Some(longitude);
+        }
+        ParsedMessage::Rmc(data) => {
+            data.latitude = Some(latitude);
+            data.longitude = Some(longitude);
+        }
+        _ => {}
+    }
+    message
 }
 
 fn is_moving(lat: f64, long: f64, prev_lat: f64, prev_long: f64) -> bool {
@@ -428,78 +2521,160 @@ fn is_moving(lat: f64, long: f64, prev_lat: f64, prev_long: f64) -> bool {
     lat_diff > 0.001 || long_diff > 0.001
 }
 
-fn send_message(
-    nmea_message: &[u8],
-    key: &String,
-    address: &mut NetworkEndpoint,
-) -> io::Result<()> {
-    match address.protocol {
-        Protocol::TCP => {
-            address.tcp_stream.retain(|writer| {
-                if writer.peer_addr().is_err() {
-                    log::warn!("Removing disconnected TCP stream");
-                    false
-                } else {
-                    true
+// Whether our own GPS or transponder says we are under way: faster than
+// moving_speed, or turning faster than swinging at anchor does. None when it
+// does not report a speed, then the position decides.
+fn reported_moving(message: &ParsedMessage, moving_speed: f64) -> Option<bool> {
+    let (sog, rot) = match message {
+        ParsedMessage::VesselDynamicData(data) => (data.sog_knots, data.rot),
+        ParsedMessage::Rmc(data) => (data.sog_knots, None),
+        _ => return None,
+    };
+    if rot.is_some_and(|rot| rot.abs() >= MOVING_RATE_OF_TURN) {
+        return Some(true);
+    }
+    sog.map(|sog| sog > moving_speed)
+}
+
+// Parse an optional setting, exiting when it is present but invalid.
+fn parse_option<T>(
+    section: Option<&HashMap<String, String>>,
+    section_name: &str,
+    key: &str,
+) -> Option<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match section
+        .and_then(|section| section.get(key))
+        .map(|v| v.parse::<T>())
+    {
+        None => None,
+        Some(Ok(value)) => Some(value),
+        Some(Err(e)) => {
+            log::error!(
+                "Invalid {} in [{}] section of config.ini: {}",
+                key,
+                section_name,
+                e
+            );
+            exit(EXIT_CONFIG);
+        }
+    }
+}
+
+// Lock a file in the cache directory for as long as the returned file is open.
+fn lock_instance(cache_dir: &str) -> io::Result<std::fs::File> {
+    std::fs::create_dir_all(cache_dir)?;
+    let path = path::Path::new(cache_dir).join("ais-forwarder.lock");
+    let file = std::fs::File::create(&path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(std::fs::TryLockError::WouldBlock) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "Another ais-forwarder is already running with cache directory {}",
+                cache_dir
+            ),
+        )),
+        Err(std::fs::TryLockError::Error(e)) => Err(e),
+    }
+}
+
+fn default_cache_dir() -> String {
+    #[cfg(windows)]
+    return windows::program_data()
+        .join("cache")
+        .to_string_lossy()
+        .into_owned();
+    #[cfg(not(windows))]
+    return "/usr/local/var/cache/ais-forwarder".to_string();
+}
+
+// Load config.ini (or .toml, .json, .yaml), exiting when that fails.
+fn load_settings(cli: &Cli) -> HashMap<String, HashMap<String, String>> {
+    match &cli.uci {
+        Some(path) => {
+            log::info!("Loading UCI config from {}", path);
+            match uci::load(path) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    log::error!("Error loading {}", e);
+                    exit(EXIT_CONFIG);
                 }
-            });
+            }
+        }
+        None => load_config(&cli.config),
+    }
+}
+
+// The [general] settings for the outputs, sending from their own sockets.
+fn output_options(general: &HashMap<String, String>) -> output::Options {
+    output::Options {
+        connect_timeout: Duration::from_secs(
+            parse_option::<u64>(Some(general), "general", "connect_timeout")
+                .unwrap_or(10)
+                .max(1),
+        ),
+        write_timeout: Duration::from_secs(
+            parse_option::<u64>(Some(general), "general", "write_timeout")
+                .unwrap_or(10)
+                .max(1),
+        ),
+        udp_socket: None,
+        dns_ttl: Duration::from_secs(
+            parse_option::<u64>(Some(general), "general", "dns_ttl")
+                .unwrap_or(common::DNS_TTL.as_secs())
+                .max(1),
+        ),
+    }
+}
 
-            if address.tcp_stream.len() == 0 {
-                let stream = std::net::TcpStream::connect(address.addr).map_err(|e| {
-                    std::io::Error::new(
-                        std::io::ErrorKind::ConnectionRefused,
-                        format!("{} ({}): {}", key, address.addr, e),
-                    )
-                })?;
-
-                // Set the stream to use keepalive
-                let sock_ref = socket2::SockRef::from(&stream);
-                let mut ka = socket2::TcpKeepalive::new();
-                ka = ka.with_time(Duration::from_secs(30));
-                ka = ka.with_interval(Duration::from_secs(30));
-                sock_ref.set_tcp_keepalive(&ka)?;
-
-                log::info!("{}: Connected to {}", key, address);
-                let writer = BufReaderDirectWriter::new(stream);
-                address.tcp_stream.push(writer);
-            }
-            if let Some(tcp_stream) = address.tcp_stream.get_mut(0) {
-                send_message_tcp(tcp_stream, nmea_message).map_err(|e| {
-                    address.tcp_stream.clear();
-                    std::io::Error::new(
-                        std::io::ErrorKind::ConnectionRefused,
-                        format!("send_message tcp {} ({}): {}", key, address.addr, e),
-                    )
-                })?;
-                log::debug!("{}: Sent message to {}", key, address);
-            }
-        }
-        Protocol::UDP => {
-            if address.udp_socket.is_none() {
-                let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| {
-                    std::io::Error::new(
-                        std::io::ErrorKind::ConnectionRefused,
-                        format!("{} ({}): {}", key, address.addr, e),
-                    )
-                })?;
-                UdpSocket::connect(&socket, address.addr)?;
-                log::info!("{}: Connected to {}", key, address);
-                address.udp_socket = Some(socket);
-            }
-            if let Some(udp_socket) = address.udp_socket.as_mut() {
-                send_message_udp(udp_socket, nmea_message).map_err(|e| {
-                    std::io::Error::new(
-                        std::io::ErrorKind::ConnectionRefused,
-                        format!("send_message udp {} ({}): {}", key, address.addr, e),
-                    )
-                })?;
-            }
-        }
-        Protocol::TCPListen | Protocol::UDPListen => {}
-    }
-    Ok(())
+fn load_config(config: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut config_path = PathBuf::from(config);
+    if config_path.is_relative() {
+        config_path = get_config_dir().join(config_path);
+    }
+    let config_path = config_path
+        .to_str()
+        .expect("Cannot convert config path to string");
+    log::info!("Loading config from {}", config_path);
+
+    let settings = match Config::builder()
+        .add_source(config::File::with_name(config_path))
+        .build()
+    {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("Error loading {}: {}", config_path, e);
+            exit(EXIT_CONFIG);
+        }
+    };
+
+    match settings.try_deserialize::<HashMap<String, HashMap<String, String>>>() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("Invalid format in {}: {}", config_path, e);
+            exit(EXIT_CONFIG);
+        }
+    }
+}
+
+#[cfg(windows)]
+fn get_config_dir() -> PathBuf {
+    let path = windows::program_data();
+    if !path.exists() {
+        log::error!(
+            "No {} config directory found and no config file argument provided",
+            path.display()
+        );
+        exit(EXIT_CONFIG);
+    }
+    path
 }
 
+#[cfg(not(windows))]
 fn get_config_dir() -> PathBuf {
     let path = if path::Path::new("/etc/ais-forwarder").exists() {
         "/etc/ais-forwarder"
@@ -509,7 +2684,7 @@ fn get_config_dir() -> PathBuf {
         log::error!(
             "No /etc/ais-forwarder or /usr/local/etc/ais-forwarder config directory found and no config file argument provided"
         );
-        exit(1);
+        exit(EXIT_CONFIG);
     };
     let path = path::Path::new(path);
     path.to_path_buf()
@@ -0,0 +1,410 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Advertise the feeds that we serve with multicast DNS, so that plotters and
+// apps on the boat's network find them as _nmea-0183._tcp (or ._udp) without
+// being told an address and port. Each tcp-listen:// and udp-listen://
+// output is a service instance named after its key in [ais].
+//
+// Only what DNS-SD needs is implemented here: the services are announced
+// when we start, and queries for the service types, the instances and our
+// host name are answered. Names in queries may be compressed, ours are not.
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::thread::Builder;
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::logging;
+
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const PORT: u16 = 5353;
+const SERVICE: &str = "_nmea-0183";
+const SERVICES: [&str; 3] = ["_services", "_dns-sd", "_udp"];
+// RFC 6762 section 10: host records live shorter than the others
+const HOST_TTL: u32 = 120;
+const OTHER_TTL: u32 = 4500;
+// Announced twice, a second apart (RFC 6762 section 8.3)
+const ANNOUNCE_DELAY: Duration = Duration::from_secs(1);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+// In questions the top bit of the class asks for a unicast reply, in answers
+// it tells caches to replace what they have for the name
+const CLASS_TOP_BIT: u16 = 0x8000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+impl Transport {
+    fn label(&self) -> &'static str {
+        match self {
+            Transport::Tcp => "_tcp",
+            Transport::Udp => "_udp",
+        }
+    }
+}
+
+pub struct Service {
+    pub instance: String,
+    pub transport: Transport,
+    pub port: u16,
+}
+
+impl Service {
+    fn service_type(&self) -> Vec<String> {
+        name(&[SERVICE, self.transport.label()])
+    }
+
+    fn instance_name(&self) -> Vec<String> {
+        let mut name = vec![self.instance.clone()];
+        name.extend(self.service_type());
+        name
+    }
+}
+
+// A name as its labels, with "local" at the end.
+fn name(labels: &[&str]) -> Vec<String> {
+    labels
+        .iter()
+        .map(|label| label.to_string())
+        .chain(std::iter::once("local".to_string()))
+        .collect()
+}
+
+fn same_name(a: &[String], b: &[String]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_ignore_ascii_case(b))
+}
+
+#[derive(Clone, PartialEq)]
+enum Record {
+    Ptr(Vec<String>, Vec<String>),
+    Srv(Vec<String>, u16, Vec<String>),
+    Txt(Vec<String>, Vec<String>),
+    A(Vec<String>, Ipv4Addr),
+}
+
+impl Record {
+    fn name(&self) -> &[String] {
+        match self {
+            Record::Ptr(name, _)
+            | Record::Srv(name, _, _)
+            | Record::Txt(name, _)
+            | Record::A(name, _) => name,
+        }
+    }
+
+    fn record_type(&self) -> u16 {
+        match self {
+            Record::Ptr(..) => TYPE_PTR,
+            Record::Srv(..) => TYPE_SRV,
+            Record::Txt(..) => TYPE_TXT,
+            Record::A(..) => TYPE_A,
+        }
+    }
+
+    fn write(&self, packet: &mut Vec<u8>) {
+        write_name(packet, self.name());
+        packet.extend_from_slice(&self.record_type().to_be_bytes());
+        // PTRs are shared between responders, the others are ours alone
+        let (class, ttl) = match self {
+            Record::Ptr(..) => (CLASS_IN, OTHER_TTL),
+            Record::Txt(..) => (CLASS_IN | CLASS_TOP_BIT, OTHER_TTL),
+            Record::Srv(..) | Record::A(..) => (CLASS_IN | CLASS_TOP_BIT, HOST_TTL),
+        };
+        packet.extend_from_slice(&class.to_be_bytes());
+        packet.extend_from_slice(&ttl.to_be_bytes());
+        let mut data = Vec::new();
+        match self {
+            Record::Ptr(_, target) => write_name(&mut data, target),
+            Record::Srv(_, port, host) => {
+                // Priority and weight
+                data.extend_from_slice(&[0, 0, 0, 0]);
+                data.extend_from_slice(&port.to_be_bytes());
+                write_name(&mut data, host);
+            }
+            Record::Txt(_, entries) => {
+                for entry in entries {
+                    let entry = &entry.as_bytes()[..entry.len().min(255)];
+                    data.push(entry.len() as u8);
+                    data.extend_from_slice(entry);
+                }
+            }
+            Record::A(_, address) => data.extend_from_slice(&address.octets()),
+        }
+        packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&data);
+    }
+}
+
+fn write_name(packet: &mut Vec<u8>, name: &[String]) {
+    for label in name {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label);
+    }
+    packet.push(0);
+}
+
+// Read a name at offset, following compression pointers. Returns the name and
+// the offset after it where it is in the packet.
+fn read_name(packet: &[u8], mut offset: usize) -> Option<(Vec<String>, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // A pointer loop would otherwise never end
+    for _ in 0..128 {
+        let len = *packet.get(offset)? as usize;
+        match len {
+            0 => return Some((labels, end.unwrap_or(offset + 1))),
+            0xC0.. => {
+                let pointer = ((len & 0x3F) << 8) | *packet.get(offset + 1)? as usize;
+                end.get_or_insert(offset + 2);
+                offset = pointer;
+            }
+            1..=63 => {
+                let label = packet.get(offset + 1..offset + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).to_string());
+                offset += 1 + len;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+// The questions of a query: name, type and whether a unicast reply is asked.
+fn questions(packet: &[u8]) -> Vec<(Vec<String>, u16, bool)> {
+    let mut questions = Vec::new();
+    // Only queries, without the truncated bit that says more will follow
+    if packet.len() < 12 || packet[2] & 0x80 != 0 || packet[2] & 0x02 != 0 {
+        return questions;
+    }
+    let count = u16::from_be_bytes([packet[4], packet[5]]);
+    let mut offset = 12;
+    for _ in 0..count {
+        let Some((name, next)) = read_name(packet, offset) else {
+            break;
+        };
+        let Some(fields) = packet.get(next..next + 4) else {
+            break;
+        };
+        let record_type = u16::from_be_bytes([fields[0], fields[1]]);
+        let class = u16::from_be_bytes([fields[2], fields[3]]);
+        questions.push((name, record_type, class & CLASS_TOP_BIT != 0));
+        offset = next + 4;
+    }
+    questions
+}
+
+// An authoritative answer. A reply to a legacy resolver repeats the id and
+// question of its query, multicast ones have neither.
+fn response(
+    query: Option<(u16, &[String], u16)>,
+    answers: &[Record],
+    additional: &[Record],
+) -> Vec<u8> {
+    let id = query.map_or(0, |(id, _, _)| id);
+    let mut packet = id.to_be_bytes().to_vec();
+    packet.extend_from_slice(&[0x84, 0, 0, query.is_some() as u8]);
+    packet.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+    packet.extend_from_slice(&[0, 0]);
+    packet.extend_from_slice(&(additional.len() as u16).to_be_bytes());
+    if let Some((_, name, record_type)) = query {
+        write_name(&mut packet, name);
+        packet.extend_from_slice(&record_type.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    }
+    for record in answers.iter().chain(additional) {
+        record.write(&mut packet);
+    }
+    packet
+}
+
+struct Responder {
+    services: Vec<Service>,
+    host: Vec<String>,
+    address: Ipv4Addr,
+}
+
+impl Responder {
+    fn host_record(&self) -> Record {
+        Record::A(self.host.clone(), self.address)
+    }
+
+    fn instance_records(&self, service: &Service) -> [Record; 2] {
+        let instance = service.instance_name();
+        [
+            Record::Srv(instance.clone(), service.port, self.host.clone()),
+            Record::Txt(instance, vec!["txtvers=1".to_string()]),
+        ]
+    }
+
+    fn service_types(&self) -> Vec<Vec<String>> {
+        let mut types: Vec<Vec<String>> = Vec::new();
+        for service in &self.services {
+            let service_type = service.service_type();
+            if !types.contains(&service_type) {
+                types.push(service_type);
+            }
+        }
+        types
+    }
+
+    // Everything, for announcing.
+    fn all(&self) -> Vec<Record> {
+        let mut records: Vec<Record> = self
+            .services
+            .iter()
+            .map(|service| Record::Ptr(service.service_type(), service.instance_name()))
+            .collect();
+        for service in &self.services {
+            records.extend(self.instance_records(service));
+        }
+        records.push(self.host_record());
+        records
+    }
+
+    // The records that answer a question, and those that the asker will want
+    // next.
+    fn answer(&self, asked: &[String], record_type: u16) -> (Vec<Record>, Vec<Record>) {
+        let mut answers = Vec::new();
+        let mut additional = Vec::new();
+        let mut host = false;
+        if same_name(asked, &name(&SERVICES)) {
+            for service_type in self.service_types() {
+                answers.push(Record::Ptr(asked.to_vec(), service_type));
+            }
+        }
+        for service in &self.services {
+            if same_name(asked, &service.service_type()) {
+                answers.push(Record::Ptr(service.service_type(), service.instance_name()));
+                additional.extend(self.instance_records(service));
+                host = true;
+            } else if same_name(asked, &service.instance_name()) {
+                answers.extend(self.instance_records(service));
+                host = true;
+            }
+        }
+        if same_name(asked, &self.host) {
+            answers.push(self.host_record());
+        } else if host {
+            additional.push(self.host_record());
+        }
+        answers.retain(|record| record_type == TYPE_ANY || record.record_type() == record_type);
+        additional.retain(|record| !answers.contains(record));
+        (answers, additional)
+    }
+
+    fn work(&self, socket: &UdpSocket) {
+        let group = SocketAddr::V4(SocketAddrV4::new(GROUP, PORT));
+        for _ in 0..2 {
+            if let Err(e) = socket.send_to(&response(None, &self.all(), &[]), group) {
+                log::warn!("mDNS: Cannot announce: {}", e);
+            }
+            std::thread::sleep(ANNOUNCE_DELAY);
+        }
+        let mut buffer = [0u8; 9000];
+        loop {
+            let (len, from) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) => {
+                    log::warn!("mDNS: {}", e);
+                    std::thread::sleep(ANNOUNCE_DELAY);
+                    continue;
+                }
+            };
+            let query = &buffer[..len];
+            for (name, record_type, unicast) in questions(query) {
+                let (answers, additional) = self.answer(&name, record_type);
+                if answers.is_empty() {
+                    continue;
+                }
+                // Legacy resolvers ask from another port and only hear a
+                // direct reply
+                let (packet, to) = if from.port() != PORT {
+                    let id = u16::from_be_bytes([query[0], query[1]]);
+                    let question = Some((id, name.as_slice(), record_type));
+                    (response(question, &answers, &additional), from)
+                } else if unicast {
+                    (response(None, &answers, &additional), from)
+                } else {
+                    (response(None, &answers, &additional), group)
+                };
+                if let Err(e) = socket.send_to(&packet, to) {
+                    log::debug!("mDNS: Cannot answer {}: {}", from, e);
+                }
+            }
+        }
+    }
+}
+
+// Our host name as a single .local label.
+fn host_name() -> Vec<String> {
+    let host = logging::hostname();
+    let host: String = host
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    match host.trim_matches('-') {
+        "" => name(&["ais-forwarder"]),
+        host => name(&[host]),
+    }
+}
+
+// The address that others on the network reach us on: the one that a route
+// to the mDNS group leaves from. Nothing is sent.
+fn local_address() -> io::Result<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect((GROUP, PORT))?;
+    match socket.local_addr()? {
+        SocketAddr::V4(addr) if !addr.ip().is_unspecified() => Ok(*addr.ip()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            "no IPv4 address for multicast",
+        )),
+    }
+}
+
+// Other responders on the host, such as avahi, share the port.
+fn bind() -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT)).into())?;
+    let socket: UdpSocket = socket.into();
+    socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_ttl_v4(255)?;
+    Ok(socket)
+}
+
+pub fn start(services: Vec<Service>) -> io::Result<()> {
+    let address = local_address()?;
+    let socket = bind()?;
+    let responder = Responder {
+        services,
+        host: host_name(),
+        address,
+    };
+    for service in &responder.services {
+        log::info!(
+            "mDNS: Advertising '{}' as {}.{} on {}:{}",
+            service.instance,
+            SERVICE,
+            service.transport.label(),
+            address,
+            service.port
+        );
+    }
+    Builder::new()
+        .name("mdns".to_string())
+        .spawn(move || responder.work(&socket))?;
+    Ok(())
+}
@@ -0,0 +1,48 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Keep an eye on our own memory use. On a 64 MB router the OOM killer picks
+// the biggest process, and a busy AIS feed with thousands of vessels makes
+// that us. Above the configured limit the dispatcher drops what it can
+// rebuild from the live feed, which is better than being killed.
+use std::time::{Duration, Instant};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct MemoryGuard {
+    limit: u64,
+    last_check: Instant,
+}
+
+impl MemoryGuard {
+    // Limit in bytes
+    pub fn new(limit: u64) -> Self {
+        MemoryGuard {
+            limit,
+            last_check: Instant::now(),
+        }
+    }
+
+    // The resident size when it is over the limit. Only looks every
+    // CHECK_INTERVAL, so this is cheap enough to call for every message.
+    pub fn exceeded(&mut self) -> Option<u64> {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return None;
+        }
+        self.last_check = Instant::now();
+        resident().filter(|rss| *rss > self.limit)
+    }
+}
+
+// Resident set size of this process in bytes, where the platform tells us.
+#[cfg(target_os = "linux")]
+pub fn resident() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    // SAFETY: sysconf has no preconditions
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * u64::try_from(page_size).ok()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn resident() -> Option<u64> {
+    None
+}
@@ -0,0 +1,144 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Decode the IMO meteorological and hydrographic binary broadcast (type 8,
+// DAC 1, FI 31, from IMO SN.1/Circ.289) that base stations and weather buoys
+// send, into JSON for the status outputs. Values that the station does not
+// have are left out.
+use serde_json::{Map, Value, json};
+
+use crate::nmea::{self, PayloadBits};
+
+const MESSAGE_TYPE: u64 = 8;
+const DAC: u64 = 1;
+const FI: u64 = 31;
+const BITS: usize = 350;
+
+// Decode the sentences of a message, None when it is not a met/hydro report.
+pub fn decode<S: AsRef<str>>(lines: &[S]) -> Option<Value> {
+    let payload: String = lines
+        .iter()
+        .map(|line| nmea::ais_payload(line.as_ref()))
+        .collect::<Option<_>>()?;
+    let bits = PayloadBits::new(&payload);
+    if bits.unsigned(0, 6)? != MESSAGE_TYPE
+        || bits.unsigned(40, 10)? != DAC
+        || bits.unsigned(50, 6)? != FI
+        || bits.len() < BITS
+    {
+        return None;
+    }
+
+    let mut report = Map::new();
+    let mut field = |name: &str, value: Option<f64>| {
+        if let Some(value) = value {
+            report.insert(name.to_string(), json!(value));
+        }
+    };
+    // Each is a raw value, the value that means not available, and its unit
+    let unsigned = |start, len, na: u64, per_unit: f64| {
+        bits.unsigned(start, len)
+            .filter(|&raw| raw != na)
+            .map(|raw| raw as f64 / per_unit)
+    };
+    let signed = |start, len, na: i64, per_unit: f64| {
+        bits.signed(start, len)
+            .filter(|&raw| raw != na)
+            .map(|raw| raw as f64 / per_unit)
+    };
+
+    // In 1/1000 minutes
+    field(
+        "longitude",
+        signed(56, 25, 181 * 60_000, 60_000.0).filter(|lon| lon.abs() <= 180.0),
+    );
+    field(
+        "latitude",
+        signed(81, 24, 91 * 60_000, 60_000.0).filter(|lat| lat.abs() <= 90.0),
+    );
+    field("day", unsigned(106, 5, 0, 1.0));
+    field("hour", unsigned(111, 5, 24, 1.0));
+    field("minute", unsigned(116, 6, 60, 1.0));
+    // Knots and degrees
+    field("wind_speed", unsigned(122, 7, 127, 1.0));
+    field("wind_gust", unsigned(129, 7, 127, 1.0));
+    field(
+        "wind_direction",
+        unsigned(136, 9, 360, 1.0).filter(|&d| d < 360.0),
+    );
+    field(
+        "wind_gust_direction",
+        unsigned(145, 9, 360, 1.0).filter(|&d| d < 360.0),
+    );
+    // Degrees Celsius and percent
+    field("air_temperature", signed(154, 11, -1024, 10.0));
+    field(
+        "relative_humidity",
+        unsigned(165, 7, 101, 1.0).filter(|&h| h <= 100.0),
+    );
+    field("dew_point", signed(172, 10, 501, 10.0));
+    // hPa, from 799 for 0
+    field(
+        "air_pressure",
+        unsigned(182, 9, 511, 1.0)
+            .filter(|&p| p <= 402.0)
+            .map(|p| p + 799.0),
+    );
+    field("air_pressure_tendency", unsigned(191, 2, 3, 1.0));
+    // Nautical miles
+    field("visibility", unsigned(194, 7, 127, 10.0));
+    // Metres, from -10 for 0
+    field(
+        "water_level",
+        unsigned(201, 12, 4001, 100.0)
+            .filter(|&l| l <= 40.0)
+            .map(|l| l - 10.0),
+    );
+    field("water_level_trend", unsigned(213, 2, 3, 1.0));
+    // Knots and degrees, at the surface
+    field(
+        "current_speed",
+        unsigned(215, 8, 255, 10.0).filter(|&s| s <= 25.0),
+    );
+    field(
+        "current_direction",
+        unsigned(223, 9, 360, 1.0).filter(|&d| d < 360.0),
+    );
+    // Metres, seconds and degrees
+    field(
+        "wave_height",
+        unsigned(276, 8, 255, 10.0).filter(|&h| h <= 25.0),
+    );
+    field(
+        "wave_period",
+        unsigned(284, 6, 63, 1.0).filter(|&p| p <= 60.0),
+    );
+    field(
+        "wave_direction",
+        unsigned(290, 9, 360, 1.0).filter(|&d| d < 360.0),
+    );
+    field(
+        "swell_height",
+        unsigned(299, 8, 255, 10.0).filter(|&h| h <= 25.0),
+    );
+    field(
+        "swell_period",
+        unsigned(307, 6, 63, 1.0).filter(|&p| p <= 60.0),
+    );
+    field(
+        "swell_direction",
+        unsigned(313, 9, 360, 1.0).filter(|&d| d < 360.0),
+    );
+    // Beaufort
+    field(
+        "sea_state",
+        unsigned(322, 4, 13, 1.0).filter(|&b| b <= 12.0),
+    );
+    field("water_temperature", signed(326, 10, 501, 10.0));
+    field("precipitation_type", unsigned(336, 3, 7, 1.0));
+    // Parts per thousand
+    field(
+        "salinity",
+        unsigned(339, 9, 510, 10.0).filter(|&s| s <= 50.0),
+    );
+    field("ice", unsigned(348, 2, 3, 1.0));
+    Some(Value::Object(report))
+}
@@ -0,0 +1,1036 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::location::OwnStatic;
+use crate::loss::LossDetector;
+use crate::resources::{Usage, state_name};
+use crate::telemetry::Reading;
+use crate::voyage::Voyage;
+
+// Counters for a single endpoint, keyed by config section and endpoint name.
+#[derive(Default)]
+struct EndpointStats {
+    messages_sent: u64,
+    bytes_sent: u64,
+    errors: u64,
+    consecutive_failures: u64,
+    latency_total: Duration,
+    latency_max: Duration,
+    last_error: Option<String>,
+    // Age of the data when it was sent, for messages with a source timestamp
+    age_count: u64,
+    age_total: Duration,
+    age_max: Duration,
+    // As reported by the sink after the last send
+    healthy: bool,
+}
+
+// Received and forwarded counts, per MMSI and per message type.
+#[derive(Default, Clone, Copy)]
+struct TrafficCount {
+    received: u64,
+    forwarded: u64,
+}
+
+// The counts of a message type, and its rates per minute over the last
+// whole RATE_WINDOW, so that one can see which types fill a slow link.
+struct TypeTraffic {
+    total: TrafficCount,
+    window: TrafficCount,
+    window_start: Instant,
+    rates: Option<(f64, f64)>,
+}
+
+impl TypeTraffic {
+    fn new() -> Self {
+        TypeTraffic {
+            total: TrafficCount::default(),
+            window: TrafficCount::default(),
+            window_start: Instant::now(),
+            rates: None,
+        }
+    }
+
+    fn window_rates(&self) -> (f64, f64) {
+        let minutes = self.window_start.elapsed().max(RATE_WINDOW).as_secs_f64() / 60.0;
+        (
+            self.window.received as f64 / minutes,
+            self.window.forwarded as f64 / minutes,
+        )
+    }
+
+    // Start a new window when this one is over.
+    fn roll(&mut self) -> &mut Self {
+        if self.window_start.elapsed() >= RATE_WINDOW {
+            self.rates = Some(self.window_rates());
+            self.window = TrafficCount::default();
+            self.window_start = Instant::now();
+        }
+        self
+    }
+
+    // Received and forwarded per minute. Until the first window is over it
+    // is what was counted so far, and a window that should have ended but
+    // has not seen a message since is taken as it is.
+    fn rates(&self) -> (f64, f64) {
+        match self.rates {
+            Some(rates) if self.window_start.elapsed() < RATE_WINDOW => rates,
+            _ => self.window_rates(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Traffic {
+    by_type: BTreeMap<String, TypeTraffic>,
+    by_mmsi: HashMap<u32, TrafficCount>,
+}
+
+// Why a message was not forwarded.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DropReason {
+    Throttled,
+    NoPosition,
+    UnsupportedType,
+    ParseError,
+    Checksum,
+    Paused,
+    Standby,
+    QueueFull,
+    Incomplete,
+    DispatcherBehind,
+    LocationBehind,
+    LocationStopped,
+    Harbour,
+    Duplicate,
+}
+
+impl DropReason {
+    fn name(&self) -> &'static str {
+        match self {
+            DropReason::Throttled => "throttled",
+            DropReason::NoPosition => "no_position",
+            DropReason::UnsupportedType => "unsupported_type",
+            DropReason::ParseError => "parse_error",
+            DropReason::Checksum => "checksum",
+            DropReason::Paused => "paused",
+            DropReason::Standby => "standby",
+            DropReason::QueueFull => "queue_full",
+            DropReason::Incomplete => "incomplete",
+            DropReason::DispatcherBehind => "dispatcher_behind",
+            DropReason::LocationBehind => "location_behind",
+            DropReason::LocationStopped => "location_stopped",
+            DropReason::Harbour => "harbour",
+            DropReason::Duplicate => "duplicate",
+        }
+    }
+}
+
+// Message rates per type are over this long.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+// Weather stations that are remembered, a coastal station hears a few.
+const MAX_WEATHER_STATIONS: usize = 100;
+
+// Telemetry readings not heard for this long have gone, like the RPM of an
+// engine that was stopped.
+const TELEMETRY_EXPIRY: Duration = Duration::from_secs(300);
+
+// Drops that happen before the message is fanned out count for all endpoints.
+const ALL_ENDPOINTS: &str = "*";
+
+// Name and value accessor of a per message type counter, and of its rates.
+type TrafficCounter = (&'static str, fn(&TrafficCount) -> u64);
+type RateGauge = (&'static str, fn((f64, f64)) -> f64);
+
+// Once this many vessels have been seen, the ones heard only once are forgotten.
+const MAX_TALKERS: usize = 5000;
+
+// Name, Prometheus type and value accessor of a per endpoint metric.
+type MetricFamily = (&'static str, &'static str, fn(&EndpointStats) -> String);
+
+pub struct Metrics {
+    started: Instant,
+    // [general] station, labelling everything we report
+    station: OnceLock<String>,
+    provider_messages: AtomicU64,
+    provider_last_message: Mutex<Instant>,
+    // By provider name, with [providers] there are several
+    provider_state: Mutex<BTreeMap<String, String>>,
+    messages_by_provider: Mutex<BTreeMap<String, u64>>,
+    standby_state: Mutex<Option<String>>,
+    location_queued: AtomicU64,
+    location_stored: AtomicU64,
+    own_position: Mutex<Option<(f64, f64, Instant)>>,
+    own_static: Mutex<Option<OwnStatic>>,
+    voyage: Mutex<Voyage>,
+    endpoints: Mutex<BTreeMap<(String, String), EndpointStats>>,
+    traffic: Mutex<Traffic>,
+    loss: Mutex<Option<(u64, u64, u64)>>,
+    dropped: Mutex<BTreeMap<(String, DropReason), u64>>,
+    progress: Mutex<BTreeMap<&'static str, Instant>>,
+    resources: Mutex<Option<Usage>>,
+    throttled_vessels: AtomicU64,
+    throttle_evictions: AtomicU64,
+    // Seconds our clock is ahead of the last base station, with its MMSI
+    clock_offset: Mutex<Option<(u32, f64)>>,
+    // The last met/hydro report of each station
+    weather: Mutex<BTreeMap<u32, (serde_json::Value, Instant)>>,
+    // The last engine and tank readings, by name
+    telemetry: Mutex<BTreeMap<String, (f64, String, Instant)>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            started: Instant::now(),
+            station: OnceLock::new(),
+            provider_messages: AtomicU64::new(0),
+            provider_last_message: Mutex::new(Instant::now()),
+            provider_state: Mutex::new(BTreeMap::new()),
+            messages_by_provider: Mutex::new(BTreeMap::new()),
+            standby_state: Mutex::new(None),
+            location_queued: AtomicU64::new(0),
+            location_stored: AtomicU64::new(0),
+            own_position: Mutex::new(None),
+            own_static: Mutex::new(None),
+            voyage: Mutex::new(Voyage::default()),
+            endpoints: Mutex::new(BTreeMap::new()),
+            traffic: Mutex::new(Traffic::default()),
+            loss: Mutex::new(None),
+            dropped: Mutex::new(BTreeMap::new()),
+            progress: Mutex::new(BTreeMap::new()),
+            resources: Mutex::new(None),
+            throttled_vessels: AtomicU64::new(0),
+            throttle_evictions: AtomicU64::new(0),
+            clock_offset: Mutex::new(None),
+            weather: Mutex::new(BTreeMap::new()),
+            telemetry: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn set_station(&self, station: &str) {
+        let _ = self.station.set(station.to_string());
+    }
+
+    pub fn record_loss(&self, detector: &LossDetector) {
+        *self.loss.lock().unwrap() = Some((
+            detector.fragments_expected,
+            detector.fragments_missing,
+            detector.sequence_gaps,
+        ));
+    }
+
+    pub fn record_received(&self, message_type: &str, mmsi: Option<u32>) {
+        let mut traffic = self.traffic.lock().unwrap();
+        let counts = traffic
+            .by_type
+            .entry(message_type.to_string())
+            .or_insert_with(TypeTraffic::new)
+            .roll();
+        counts.total.received += 1;
+        counts.window.received += 1;
+        if let Some(mmsi) = mmsi {
+            if traffic.by_mmsi.len() >= MAX_TALKERS && !traffic.by_mmsi.contains_key(&mmsi) {
+                traffic.by_mmsi.retain(|_, count| count.received > 1);
+            }
+            traffic.by_mmsi.entry(mmsi).or_default().received += 1;
+        }
+    }
+
+    pub fn record_forwarded(&self, message_type: &str, mmsi: Option<u32>) {
+        let mut traffic = self.traffic.lock().unwrap();
+        let counts = traffic
+            .by_type
+            .entry(message_type.to_string())
+            .or_insert_with(TypeTraffic::new)
+            .roll();
+        counts.total.forwarded += 1;
+        counts.window.forwarded += 1;
+        if let Some(mmsi) = mmsi {
+            traffic.by_mmsi.entry(mmsi).or_default().forwarded += 1;
+        }
+    }
+
+    // Count a message that was not forwarded. Endpoint is None when it was
+    // dropped before being offered to any endpoint.
+    pub fn record_dropped(&self, endpoint: Option<&str>, reason: DropReason) {
+        let endpoint = endpoint.unwrap_or(ALL_ENDPOINTS).to_string();
+        *self
+            .dropped
+            .lock()
+            .unwrap()
+            .entry((endpoint, reason))
+            .or_default() += 1;
+    }
+
+    // Forget the per vessel counts when memory is short.
+    pub fn shed_memory(&self) {
+        self.traffic.lock().unwrap().by_mmsi = HashMap::new();
+    }
+
+    // The vessels we hear most, and the traffic per message type.
+    pub fn render_talkers(&self, count: usize) -> String {
+        let traffic = self.traffic.lock().unwrap();
+        let mut talkers: Vec<(&u32, &TrafficCount)> = traffic.by_mmsi.iter().collect();
+        talkers.sort_by_key(|(_, counts)| std::cmp::Reverse(counts.received));
+
+        let mut report = String::new();
+        let _ = writeln!(
+            report,
+            "Top talkers ({} vessels)\n{:<12} {:>10} {:>10}",
+            traffic.by_mmsi.len(),
+            "MMSI",
+            "Received",
+            "Forwarded"
+        );
+        for (mmsi, counts) in talkers.iter().take(count) {
+            let _ = writeln!(
+                report,
+                "{:<12} {:>10} {:>10}",
+                mmsi, counts.received, counts.forwarded
+            );
+        }
+        let _ = writeln!(
+            report,
+            "\n{:<12} {:>10} {:>10} {:>10} {:>10}",
+            "Type", "Received", "Forwarded", "Recv/min", "Fwd/min"
+        );
+        for (message_type, counts) in traffic.by_type.iter() {
+            let (received, forwarded) = counts.rates();
+            let _ = writeln!(
+                report,
+                "{:<12} {:>10} {:>10} {:>10.1} {:>10.1}",
+                message_type, counts.total.received, counts.total.forwarded, received, forwarded
+            );
+        }
+        report
+    }
+
+    pub fn record_provider_message(&self, provider: &str) {
+        self.provider_messages.fetch_add(1, Ordering::Relaxed);
+        *self.provider_last_message.lock().unwrap() = Instant::now();
+        *self
+            .messages_by_provider
+            .lock()
+            .unwrap()
+            .entry(provider.to_string())
+            .or_default() += 1;
+    }
+
+    // Long running threads call this every time around their loop, see
+    // watchdog.rs.
+    pub fn record_progress(&self, thread: &'static str) {
+        self.progress.lock().unwrap().insert(thread, Instant::now());
+    }
+
+    // The thread that has not reported progress for longest, if that is more
+    // than timeout.
+    pub fn stalled(&self, timeout: Duration) -> Option<(&'static str, Duration)> {
+        self.progress
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(thread, when)| (*thread, when.elapsed()))
+            .filter(|(_, idle)| *idle > timeout)
+            .max_by_key(|(_, idle)| *idle)
+    }
+
+    pub fn provider_messages(&self) -> u64 {
+        self.provider_messages.load(Ordering::Relaxed)
+    }
+
+    // Such as "reading from tcp://127.0.0.1:2599", with the name of each
+    // provider in front when there are several.
+    pub fn provider_state(&self) -> String {
+        let states = self.provider_state.lock().unwrap();
+        match states.len() {
+            0 => "starting".to_string(),
+            1 => states.values().next().cloned().unwrap_or_default(),
+            _ => states
+                .iter()
+                .map(|(provider, state)| format!("{} {}", provider, state))
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+
+    pub fn set_provider_state(&self, provider: &str, state: String) {
+        self.provider_state
+            .lock()
+            .unwrap()
+            .insert(provider.to_string(), state);
+    }
+
+    pub fn record_resources(&self, usage: Usage) {
+        *self.resources.lock().unwrap() = Some(usage);
+    }
+
+    // Only set when running as one of a hot standby pair.
+    pub fn set_standby_state(&self, state: Option<String>) {
+        *self.standby_state.lock().unwrap() = state;
+    }
+
+    // Location updates travel from the dispatcher to the location thread over
+    // a channel, and are stored on disk while the location endpoints are down.
+    pub fn location_queued(&self) {
+        self.location_queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn location_dequeued(&self) {
+        self.location_queued.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn set_location_stored(&self, count: usize) {
+        self.location_stored.store(count as u64, Ordering::Relaxed);
+    }
+
+    // The vessels the interval throttle remembers, and how many it forgot.
+    pub fn record_throttle(&self, vessels: usize, evicted: usize) {
+        self.throttled_vessels
+            .store(vessels as u64, Ordering::Relaxed);
+        self.throttle_evictions
+            .fetch_add(evicted as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_clock_offset(&self, mmsi: u32, offset: f64) {
+        *self.clock_offset.lock().unwrap() = Some((mmsi, offset));
+    }
+
+    pub fn record_weather(&self, mmsi: u32, report: serde_json::Value) {
+        let mut weather = self.weather.lock().unwrap();
+        if weather.len() >= MAX_WEATHER_STATIONS
+            && !weather.contains_key(&mmsi)
+            && let Some(oldest) = weather
+                .iter()
+                .min_by_key(|(_, (_, when))| *when)
+                .map(|(mmsi, _)| *mmsi)
+        {
+            weather.remove(&oldest);
+        }
+        weather.insert(mmsi, (report, Instant::now()));
+    }
+
+    // The last met/hydro report of each station, by MMSI, with its age.
+    pub fn weather_json(&self) -> serde_json::Value {
+        let weather = self.weather.lock().unwrap();
+        let mut stations = serde_json::Map::new();
+        for (mmsi, (report, when)) in weather.iter() {
+            let mut report = report.clone();
+            report["age"] = when.elapsed().as_secs().into();
+            stations.insert(mmsi.to_string(), report);
+        }
+        serde_json::Value::Object(stations)
+    }
+
+    pub fn record_telemetry(&self, reading: Reading) {
+        let mut telemetry = self.telemetry.lock().unwrap();
+        telemetry.retain(|_, (_, _, when)| when.elapsed() < TELEMETRY_EXPIRY);
+        telemetry.insert(reading.name, (reading.value, reading.unit, Instant::now()));
+    }
+
+    // The current telemetry readings, by name, with their unit and age.
+    pub fn telemetry_json(&self) -> serde_json::Value {
+        let telemetry = self.telemetry.lock().unwrap();
+        let mut readings = serde_json::Map::new();
+        for (name, (value, unit, when)) in telemetry.iter() {
+            if when.elapsed() < TELEMETRY_EXPIRY {
+                readings.insert(
+                    name.clone(),
+                    serde_json::json!({
+                        "value": value,
+                        "unit": unit,
+                        "age": when.elapsed().as_secs(),
+                    }),
+                );
+            }
+        }
+        serde_json::Value::Object(readings)
+    }
+
+    // Our position, and the speed over ground in knots when it came with one.
+    pub fn record_own_position(&self, latitude: f64, longitude: f64, sog: Option<f64>) {
+        *self.own_position.lock().unwrap() = Some((latitude, longitude, Instant::now()));
+        self.voyage
+            .lock()
+            .unwrap()
+            .update(chrono::Utc::now(), latitude, longitude, sog);
+    }
+
+    // The statistics of today and yesterday, as far as we have them.
+    pub fn voyage(&self) -> Vec<(&'static str, String)> {
+        let voyage = self.voyage.lock().unwrap();
+        [("Today", voyage.today()), ("Yesterday", voyage.yesterday())]
+            .into_iter()
+            .filter_map(|(day, stats)| stats.map(|stats| (day, stats.describe())))
+            .collect()
+    }
+
+    // Where we are, for ranges and bearings to other vessels.
+    pub fn own_position(&self) -> Option<(f64, f64)> {
+        self.own_position
+            .lock()
+            .unwrap()
+            .map(|(latitude, longitude, _)| (latitude, longitude))
+    }
+
+    // What people know us by, for reports to them: the name of our vessel,
+    // else the station.
+    pub fn own_name(&self) -> Option<String> {
+        self.own_static
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|own_static| own_static.name.clone())
+            .or_else(|| self.station.get().cloned())
+    }
+
+    pub fn record_own_static(&self, own_static: &OwnStatic) {
+        *self.own_static.lock().unwrap() = Some(own_static.clone());
+    }
+
+    // How long ago the provider sent us anything, or how long we have been
+    // running if it never did.
+    pub fn provider_idle(&self) -> Duration {
+        self.provider_last_message.lock().unwrap().elapsed()
+    }
+
+    pub fn consecutive_failures(&self) -> Vec<(String, u64)> {
+        let endpoints = self.endpoints.lock().unwrap();
+        endpoints
+            .iter()
+            .map(|((section, endpoint), stats)| {
+                (
+                    format!("{}/{}", section, endpoint),
+                    stats.consecutive_failures,
+                )
+            })
+            .collect()
+    }
+
+    // Record the outcome of a single send to an endpoint.
+    pub fn record_send(
+        &self,
+        section: &str,
+        endpoint: &str,
+        bytes: usize,
+        latency: Duration,
+        result: &io::Result<()>,
+    ) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let stats = endpoints
+            .entry((section.to_string(), endpoint.to_string()))
+            .or_default();
+        match result {
+            Ok(()) => {
+                stats.messages_sent += 1;
+                stats.bytes_sent += bytes as u64;
+                stats.consecutive_failures = 0;
+                stats.latency_total += latency;
+                stats.latency_max = stats.latency_max.max(latency);
+            }
+            Err(e) => {
+                stats.errors += 1;
+                stats.consecutive_failures += 1;
+                stats.last_error = Some(e.to_string());
+            }
+        }
+    }
+
+    pub fn record_health(&self, section: &str, endpoint: &str, healthy: bool) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        endpoints
+            .entry((section.to_string(), endpoint.to_string()))
+            .or_default()
+            .healthy = healthy;
+    }
+
+    // Record how old a message was, according to its source timestamp, when
+    // it was sent to an endpoint.
+    pub fn record_age(&self, section: &str, endpoint: &str, age: Duration) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let stats = endpoints
+            .entry((section.to_string(), endpoint.to_string()))
+            .or_default();
+        stats.age_count += 1;
+        stats.age_total += age;
+        stats.age_max = stats.age_max.max(age);
+    }
+
+    pub fn render_status(&self) -> String {
+        let mut status = String::new();
+        let _ = writeln!(
+            status,
+            "ais-forwarder up {}",
+            format_duration(self.started.elapsed())
+        );
+        if let Some(station) = self.station.get() {
+            let _ = writeln!(status, "Station {}", station);
+        }
+        let _ = writeln!(
+            status,
+            "Provider {}, {} messages, last one {} ago",
+            self.provider_state(),
+            self.provider_messages.load(Ordering::Relaxed),
+            format_duration(self.provider_idle())
+        );
+        let by_provider = self
+            .messages_by_provider
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(provider, messages)| format!("{} {}", provider, messages))
+            .collect::<Vec<_>>();
+        if by_provider.len() > 1 {
+            let _ = writeln!(status, "Messages by provider: {}", by_provider.join(", "));
+        }
+        if let Some(state) = self.standby_state.lock().unwrap().as_ref() {
+            let _ = writeln!(status, "Standby {}", state);
+        }
+        let _ = writeln!(
+            status,
+            "Location updates {} queued, {} stored for resending",
+            self.location_queued.load(Ordering::Relaxed),
+            self.location_stored.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            status,
+            "Throttling {} vessels, {} forgotten",
+            self.throttled_vessels.load(Ordering::Relaxed),
+            self.throttle_evictions.load(Ordering::Relaxed)
+        );
+        if let Some(usage) = self.resources.lock().unwrap().as_ref() {
+            let threads: Vec<String> = usage
+                .threads
+                .iter()
+                .map(|(state, count)| format!("{} {}", count, state_name(*state)))
+                .collect();
+            let _ = writeln!(
+                status,
+                "Resources CPU {:.1}% ({:.1}s in total), {:.1} MB resident, {} open files, threads {}",
+                usage.cpu_percent,
+                usage.cpu_seconds,
+                usage.resident as f64 / 1024.0 / 1024.0,
+                usage.open_files,
+                threads.join(", ")
+            );
+        }
+        if let Some((expected, missing, gaps)) = *self.loss.lock().unwrap() {
+            let _ = writeln!(
+                status,
+                "Provider loss estimate {:.2}% ({} of {} fragments missing, {} skipped groups)",
+                ratio(missing, expected) * 100.0,
+                missing,
+                expected,
+                gaps
+            );
+        }
+        let weather = self.weather.lock().unwrap().len();
+        if weather > 0 {
+            let _ = writeln!(status, "Weather reports from {} stations", weather);
+        }
+        let telemetry: Vec<String> = self
+            .telemetry
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, (_, _, when))| when.elapsed() < TELEMETRY_EXPIRY)
+            .map(|(name, (value, unit, _))| format!("{} {} {}", name, value, unit))
+            .collect();
+        if !telemetry.is_empty() {
+            let _ = writeln!(status, "Telemetry {}", telemetry.join(", "));
+        }
+        if let Some((mmsi, offset)) = *self.clock_offset.lock().unwrap() {
+            let _ = writeln!(
+                status,
+                "Clock {:+.1}s from base station MMSI {}",
+                offset, mmsi
+            );
+        }
+        if let Some((latitude, longitude, when)) = *self.own_position.lock().unwrap() {
+            let _ = writeln!(
+                status,
+                "Own position {:.5}, {:.5} {} ago",
+                latitude,
+                longitude,
+                format_duration(when.elapsed())
+            );
+        }
+        if let Some(own_static) = self.own_static.lock().unwrap().as_ref() {
+            let _ = writeln!(status, "Own vessel {}", own_static.describe());
+        }
+        for (day, stats) in self.voyage() {
+            let _ = writeln!(status, "Voyage {}: {}", day.to_lowercase(), stats);
+        }
+        let _ = writeln!(
+            status,
+            "\n{:<32} {:>10} {:>12} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10}  Last error",
+            "Endpoint",
+            "Sent",
+            "Bytes",
+            "Errors",
+            "Failing",
+            "Avg ms",
+            "Max ms",
+            "Avg age",
+            "Max age"
+        );
+        let endpoints = self.endpoints.lock().unwrap();
+        for ((section, endpoint), stats) in endpoints.iter() {
+            let _ = writeln!(
+                status,
+                "{:<32} {:>10} {:>12} {:>8} {:>8} {:>10.1} {:>10.1} {:>10.1} {:>10.1}  {}",
+                format!("{}/{}", section, endpoint),
+                stats.messages_sent,
+                stats.bytes_sent,
+                stats.errors,
+                stats.consecutive_failures,
+                average_ms(stats.latency_total, stats.messages_sent),
+                stats.latency_max.as_secs_f64() * 1000.0,
+                average_ms(stats.age_total, stats.age_count) / 1000.0,
+                stats.age_max.as_secs_f64(),
+                stats.last_error.as_deref().unwrap_or("")
+            );
+        }
+        drop(endpoints);
+        let dropped = self.dropped.lock().unwrap();
+        if !dropped.is_empty() {
+            let _ = writeln!(
+                status,
+                "\n{:<32} {:<18} {:>10}",
+                "Dropped for", "Reason", "Messages"
+            );
+            for ((endpoint, reason), count) in dropped.iter() {
+                let _ = writeln!(
+                    status,
+                    "{:<32} {:<18} {:>10}",
+                    endpoint,
+                    reason.name(),
+                    count
+                );
+            }
+        }
+        drop(dropped);
+        let _ = writeln!(status, "\n{}", self.render_talkers(10));
+        status
+    }
+
+    // A compact summary for machine consumers such as the MQTT state topic.
+    pub fn summary_json(&self) -> serde_json::Value {
+        let mut endpoints = serde_json::Map::new();
+        for ((section, endpoint), stats) in self.endpoints.lock().unwrap().iter() {
+            endpoints.insert(
+                format!("{}/{}", section, endpoint),
+                serde_json::json!({
+                    "sent": stats.messages_sent,
+                    "bytes": stats.bytes_sent,
+                    "errors": stats.errors,
+                    "consecutive_failures": stats.consecutive_failures,
+                    "healthy": stats.healthy,
+                }),
+            );
+        }
+        let position = match *self.own_position.lock().unwrap() {
+            Some((latitude, longitude, when)) => serde_json::json!({
+                "latitude": latitude,
+                "longitude": longitude,
+                "age": when.elapsed().as_secs(),
+            }),
+            None => serde_json::Value::Null,
+        };
+        let vessel = match self.own_static.lock().unwrap().as_ref() {
+            Some(own_static) => serde_json::json!({
+                "name": own_static.name,
+                "call_sign": own_static.call_sign,
+                "length": own_static.length(),
+                "beam": own_static.beam(),
+            }),
+            None => serde_json::Value::Null,
+        };
+        let resources = match self.resources.lock().unwrap().as_ref() {
+            Some(usage) => serde_json::json!({
+                "cpu_percent": usage.cpu_percent,
+                "resident": usage.resident,
+                "open_files": usage.open_files,
+            }),
+            None => serde_json::Value::Null,
+        };
+        let mut types = serde_json::Map::new();
+        for (message_type, counts) in self.traffic.lock().unwrap().by_type.iter() {
+            let (received, forwarded) = counts.rates();
+            types.insert(
+                message_type.clone(),
+                serde_json::json!({
+                    "received": counts.total.received,
+                    "forwarded": counts.total.forwarded,
+                    "received_per_minute": received,
+                    "forwarded_per_minute": forwarded,
+                }),
+            );
+        }
+        serde_json::json!({
+            "station": self.station.get(),
+            "uptime": self.started.elapsed().as_secs(),
+            "provider_messages": self.provider_messages.load(Ordering::Relaxed),
+            "messages_by_provider": *self.messages_by_provider.lock().unwrap(),
+            "provider_idle": self.provider_idle().as_secs(),
+            "throttled_vessels": self.throttled_vessels.load(Ordering::Relaxed),
+            "clock_offset": self.clock_offset.lock().unwrap().map(|(_, offset)| offset),
+            "endpoints": endpoints,
+            "types": types,
+            "position": position,
+            "vessel": vessel,
+            "weather": self.weather_json(),
+            "telemetry": self.telemetry_json(),
+            "resources": resources,
+        })
+    }
+
+    // Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE ais_forwarder_uptime_seconds gauge");
+        let _ = writeln!(
+            out,
+            "ais_forwarder_uptime_seconds {}",
+            self.started.elapsed().as_secs()
+        );
+        let _ = writeln!(out, "# TYPE ais_forwarder_provider_messages_total counter");
+        let _ = writeln!(
+            out,
+            "ais_forwarder_provider_messages_total {}",
+            self.provider_messages.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "# TYPE ais_forwarder_messages_by_provider_total counter"
+        );
+        for (provider, messages) in self.messages_by_provider.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "ais_forwarder_messages_by_provider_total{{provider=\"{}\"}} {}",
+                escape_label(provider),
+                messages
+            );
+        }
+        if let Some((expected, missing, gaps)) = *self.loss.lock().unwrap() {
+            let _ = writeln!(
+                out,
+                "# TYPE ais_forwarder_provider_fragments_expected_total counter"
+            );
+            let _ = writeln!(
+                out,
+                "ais_forwarder_provider_fragments_expected_total {}",
+                expected
+            );
+            let _ = writeln!(
+                out,
+                "# TYPE ais_forwarder_provider_fragments_missing_total counter"
+            );
+            let _ = writeln!(
+                out,
+                "ais_forwarder_provider_fragments_missing_total {}",
+                missing
+            );
+            let _ = writeln!(
+                out,
+                "# TYPE ais_forwarder_provider_sequence_gaps_total counter"
+            );
+            let _ = writeln!(out, "ais_forwarder_provider_sequence_gaps_total {}", gaps);
+            let _ = writeln!(out, "# TYPE ais_forwarder_provider_loss_ratio gauge");
+            let _ = writeln!(
+                out,
+                "ais_forwarder_provider_loss_ratio {}",
+                ratio(missing, expected)
+            );
+        }
+        if let Some(usage) = self.resources.lock().unwrap().as_ref() {
+            let _ = writeln!(out, "# TYPE process_cpu_seconds_total counter");
+            let _ = writeln!(out, "process_cpu_seconds_total {}", usage.cpu_seconds);
+            let _ = writeln!(out, "# TYPE process_resident_memory_bytes gauge");
+            let _ = writeln!(out, "process_resident_memory_bytes {}", usage.resident);
+            let _ = writeln!(out, "# TYPE process_open_fds gauge");
+            let _ = writeln!(out, "process_open_fds {}", usage.open_files);
+            let _ = writeln!(out, "# TYPE ais_forwarder_threads gauge");
+            for (state, count) in usage.threads.iter() {
+                let _ = writeln!(
+                    out,
+                    "ais_forwarder_threads{{state=\"{}\"}} {}",
+                    state_name(*state),
+                    count
+                );
+            }
+        }
+        let _ = writeln!(out, "# TYPE ais_forwarder_throttled_vessels gauge");
+        let _ = writeln!(
+            out,
+            "ais_forwarder_throttled_vessels {}",
+            self.throttled_vessels.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(out, "# TYPE ais_forwarder_throttle_evictions_total counter");
+        let _ = writeln!(
+            out,
+            "ais_forwarder_throttle_evictions_total {}",
+            self.throttle_evictions.load(Ordering::Relaxed)
+        );
+        if let Some((_, offset)) = *self.clock_offset.lock().unwrap() {
+            let _ = writeln!(out, "# TYPE ais_forwarder_clock_offset_seconds gauge");
+            let _ = writeln!(out, "ais_forwarder_clock_offset_seconds {}", offset);
+        }
+        let _ = writeln!(out, "# TYPE ais_forwarder_provider_idle_seconds gauge");
+        let _ = writeln!(
+            out,
+            "ais_forwarder_provider_idle_seconds {}",
+            self.provider_idle().as_secs()
+        );
+
+        let endpoints = self.endpoints.lock().unwrap();
+        let families: [MetricFamily; 10] = [
+            ("endpoint_messages_sent_total", "counter", |s| {
+                s.messages_sent.to_string()
+            }),
+            ("endpoint_bytes_sent_total", "counter", |s| {
+                s.bytes_sent.to_string()
+            }),
+            ("endpoint_errors_total", "counter", |s| s.errors.to_string()),
+            ("endpoint_consecutive_failures", "gauge", |s| {
+                s.consecutive_failures.to_string()
+            }),
+            ("endpoint_up", "gauge", |s| (s.healthy as u8).to_string()),
+            ("endpoint_send_latency_seconds_sum", "counter", |s| {
+                s.latency_total.as_secs_f64().to_string()
+            }),
+            ("endpoint_send_latency_seconds_max", "gauge", |s| {
+                s.latency_max.as_secs_f64().to_string()
+            }),
+            ("endpoint_data_age_seconds_count", "counter", |s| {
+                s.age_count.to_string()
+            }),
+            ("endpoint_data_age_seconds_sum", "counter", |s| {
+                s.age_total.as_secs_f64().to_string()
+            }),
+            ("endpoint_data_age_seconds_max", "gauge", |s| {
+                s.age_max.as_secs_f64().to_string()
+            }),
+        ];
+        let traffic = self.traffic.lock().unwrap();
+        let counters: [TrafficCounter; 2] = [
+            ("messages_received_total", |c| c.received),
+            ("messages_forwarded_total", |c| c.forwarded),
+        ];
+        for (name, value) in counters {
+            let _ = writeln!(out, "# TYPE ais_forwarder_{} counter", name);
+            for (message_type, counts) in traffic.by_type.iter() {
+                let _ = writeln!(
+                    out,
+                    "ais_forwarder_{}{{type=\"{}\"}} {}",
+                    name,
+                    escape_label(message_type),
+                    value(&counts.total)
+                );
+            }
+        }
+        let rates: [RateGauge; 2] = [
+            ("messages_received_per_minute", |rates| rates.0),
+            ("messages_forwarded_per_minute", |rates| rates.1),
+        ];
+        for (name, value) in rates {
+            let _ = writeln!(out, "# TYPE ais_forwarder_{} gauge", name);
+            for (message_type, counts) in traffic.by_type.iter() {
+                let _ = writeln!(
+                    out,
+                    "ais_forwarder_{}{{type=\"{}\"}} {}",
+                    name,
+                    escape_label(message_type),
+                    value(counts.rates())
+                );
+            }
+        }
+        drop(traffic);
+
+        let _ = writeln!(out, "# TYPE ais_forwarder_messages_dropped_total counter");
+        for ((endpoint, reason), count) in self.dropped.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "ais_forwarder_messages_dropped_total{{endpoint=\"{}\",reason=\"{}\"}} {}",
+                escape_label(endpoint),
+                reason.name(),
+                count
+            );
+        }
+
+        for (name, kind, value) in families {
+            let _ = writeln!(out, "# TYPE ais_forwarder_{} {}", name, kind);
+            for ((section, endpoint), stats) in endpoints.iter() {
+                let _ = writeln!(
+                    out,
+                    "ais_forwarder_{}{{section=\"{}\",endpoint=\"{}\"}} {}",
+                    name,
+                    escape_label(section),
+                    escape_label(endpoint),
+                    value(stats)
+                );
+            }
+        }
+        match self.station.get() {
+            Some(station) => with_label(&out, "station", station),
+            None => out,
+        }
+    }
+}
+
+// Add a label to every sample of an exposition.
+fn with_label(exposition: &str, name: &str, value: &str) -> String {
+    let label = format!("{}=\"{}\"", name, escape_label(value));
+    let mut out = String::with_capacity(exposition.len() * 2);
+    for line in exposition.lines() {
+        if line.starts_with('#') {
+            out.push_str(line);
+        } else if let Some((metric, labels)) = line.split_once('{') {
+            let _ = write!(out, "{}{{{},{}", metric, label, labels);
+        } else if let Some((metric, value)) = line.split_once(' ') {
+            let _ = write!(out, "{}{{{}}} {}", metric, label, value);
+        } else {
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    part as f64 / total as f64
+}
+
+fn average_ms(total: Duration, count: u64) -> f64 {
+    if count == 0 {
+        return 0.0;
+    }
+    total.as_secs_f64() * 1000.0 / count as f64
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 86400 {
+        format!("{}d {}h", secs / 86400, (secs % 86400) / 3600)
+    } else if secs >= 3600 {
+        format!("{}h {}m", secs / 3600, (secs % 3600) / 60)
+    } else {
+        format!("{}m {}s", secs / 60, secs % 60)
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,90 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// A device on a serial port that is talked to with AT commands, such as the
+// GSM modem of the SMS fallback (sms.rs) or a LoRa module (lora.rs). It is
+// put in raw mode with reads that return after a second without input, so
+// that a device that does not answer cannot hang the thread that uses it.
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+pub struct Modem {
+    device: String,
+    file: File,
+}
+
+impl Modem {
+    #[cfg(unix)]
+    pub fn open(device: &str) -> io::Result<Self> {
+        use std::os::fd::AsRawFd;
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(device)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", device, e)))?;
+        let fd = file.as_raw_fd();
+        // SAFETY: termios is plain data, filled in by tcgetattr before use
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut termios);
+            termios.c_cc[libc::VMIN] = 0;
+            termios.c_cc[libc::VTIME] = 10;
+            if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Modem {
+            device: device.to_string(),
+            file,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn open(device: &str) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{}: serial devices are only supported on Unix", device),
+        ))
+    }
+
+    // Send a command and wait for the reply. It ends with a carriage return
+    // only, as a line feed after AT+CMGS would be in the text.
+    pub fn command(&mut self, command: &str, reply: &str, timeout: Duration) -> io::Result<String> {
+        self.file.write_all(command.as_bytes())?;
+        self.file.write_all(b"\r")?;
+        self.expect(reply, timeout)
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.file.write_all(data)
+    }
+
+    // Read until the device answers with reply, or with an error, and return
+    // what it answered.
+    pub fn expect(&mut self, reply: &str, timeout: Duration) -> io::Result<String> {
+        let started = Instant::now();
+        let mut answer = String::new();
+        let mut buffer = [0u8; 256];
+        while started.elapsed() < timeout {
+            let n = self.file.read(&mut buffer)?;
+            answer.push_str(&String::from_utf8_lossy(&buffer[..n]));
+            if answer.contains(reply) {
+                return Ok(answer);
+            }
+            if answer.contains("ERROR") {
+                return Err(io::Error::other(format!(
+                    "{}: {}",
+                    self.device,
+                    answer.trim()
+                )));
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("{}: no {} in '{}'", self.device, reply, answer.trim()),
+        ))
+    }
+}
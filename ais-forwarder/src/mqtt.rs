@@ -0,0 +1,166 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Publish the forwarder's state to an MQTT broker. A retained "online" status
+// is published on connect and the broker publishes the retained "offline" Last
+// Will for us when the connection drops, so dashboards see a lost station
+// immediately.
+//
+// Only the handful of MQTT 3.1.1 packets that we need are implemented here.
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread::Builder;
+use std::time::Duration;
+
+use crate::metrics::Metrics;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+const ONLINE: &[u8] = b"online";
+const OFFLINE: &[u8] = b"offline";
+
+struct MqttConfig {
+    server: String,
+    client_id: String,
+    topic: String,
+    username: Option<String>,
+    password: Option<String>,
+    interval: Duration,
+}
+
+pub fn start(
+    section: &HashMap<String, String>,
+    mmsi: u32,
+    metrics: Arc<Metrics>,
+) -> io::Result<()> {
+    let server = section.get("server").ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Missing server in [mqtt] section",
+        )
+    })?;
+    let interval = match section.get("interval").map(|v| v.parse::<u64>()) {
+        None => 60,
+        Some(Ok(interval)) => interval,
+        Some(Err(e)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid interval in [mqtt] section: {}", e),
+            ));
+        }
+    };
+    let config = MqttConfig {
+        server: server.clone(),
+        client_id: section
+            .get("client_id")
+            .cloned()
+            .unwrap_or_else(|| format!("ais-forwarder-{}", mmsi)),
+        topic: section
+            .get("topic")
+            .cloned()
+            .unwrap_or_else(|| format!("ais-forwarder/{}", mmsi)),
+        username: section.get("username").cloned(),
+        password: section.get("password").cloned(),
+        interval: Duration::from_secs(interval.max(1)),
+    };
+
+    Builder::new().name("mqtt".to_string()).spawn(move || {
+        loop {
+            if let Err(e) = publish_loop(&config, &metrics) {
+                log::warn!("MQTT {}: {}", config.server, e);
+            }
+            std::thread::sleep(RECONNECT_DELAY);
+        }
+    })?;
+    Ok(())
+}
+
+fn publish_loop(config: &MqttConfig, metrics: &Metrics) -> io::Result<()> {
+    let status_topic = format!("{}/status", config.topic);
+    let state_topic = format!("{}/state", config.topic);
+    // Keep alive must exceed the publish interval, as the publications are
+    // the only packets we send.
+    let keep_alive = (config.interval.as_secs() * 3 / 2).clamp(30, u16::MAX as u64) as u16;
+
+    let mut stream = TcpStream::connect(&config.server)?;
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(30)))?;
+    stream.write_all(&connect_packet(config, &status_topic, keep_alive))?;
+
+    let mut connack = [0u8; 4];
+    stream.read_exact(&mut connack)?;
+    if connack[0] != 0x20 || connack[3] != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("connection refused, return code {}", connack[3]),
+        ));
+    }
+    log::info!("MQTT: Connected to {}", config.server);
+
+    stream.write_all(&publish_packet(&status_topic, ONLINE, true))?;
+    loop {
+        let state = metrics.summary_json().to_string();
+        stream.write_all(&publish_packet(&state_topic, state.as_bytes(), true))?;
+        log::debug!("MQTT: Published state to {}", state_topic);
+        std::thread::sleep(config.interval);
+    }
+}
+
+fn connect_packet(config: &MqttConfig, will_topic: &str, keep_alive: u16) -> Vec<u8> {
+    // Clean session, will flag, will QoS 1 and will retain
+    let mut flags = 0x02 | 0x04 | 0x08 | 0x20;
+    if config.username.is_some() {
+        flags |= 0x80;
+    }
+    if config.password.is_some() {
+        flags |= 0x40;
+    }
+
+    let mut body = Vec::new();
+    append_bytes(&mut body, b"MQTT");
+    body.push(4); // Protocol level 3.1.1
+    body.push(flags);
+    body.extend_from_slice(&keep_alive.to_be_bytes());
+    append_bytes(&mut body, config.client_id.as_bytes());
+    append_bytes(&mut body, will_topic.as_bytes());
+    append_bytes(&mut body, OFFLINE);
+    if let Some(username) = &config.username {
+        append_bytes(&mut body, username.as_bytes());
+    }
+    if let Some(password) = &config.password {
+        append_bytes(&mut body, password.as_bytes());
+    }
+    packet(0x10, &body)
+}
+
+// A QoS 0 publication, so there is no packet identifier and no PUBACK.
+fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    append_bytes(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload);
+    packet(if retain { 0x31 } else { 0x30 }, &body)
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(body.len() + 5);
+    packet.push(header);
+    // Remaining length, 7 bits at a time
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn append_bytes(body: &mut Vec<u8>, bytes: &[u8]) {
+    body.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    body.extend_from_slice(bytes);
+}
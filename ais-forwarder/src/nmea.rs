@@ -0,0 +1,685 @@
+// Helpers that look at the raw NMEA 0183 sentences, for the cases where we
+// need something that the parsed message does not tell us.
+use std::time::{Duration, SystemTime};
+
+// Source times above this are in milliseconds rather than seconds.
+const MILLISECOND_TIMESTAMPS: u64 = 100_000_000_000;
+
+// The lines in a chunk read from the provider, without line endings. They
+// are slices of the chunk, not copies. NMEA is plain ASCII; a line garbled
+// into invalid UTF-8 is cut short at the garbage, so that it still fails the
+// checksum and is counted.
+pub fn lines(chunk: &[u8]) -> impl Iterator<Item = &str> {
+    chunk
+        .split(|&byte| byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty())
+        .map(|line| match std::str::from_utf8(line) {
+            Ok(line) => line,
+            Err(e) => std::str::from_utf8(&line[..e.valid_up_to()]).unwrap_or_default(),
+        })
+}
+
+// The sentences of a message as they go out: in a single buffer, so that a
+// multi-sentence group is written with one call and never interleaved with
+// anything else, and each ended with CR LF as NMEA 0183 wants, so that the
+// receiver can tell them apart.
+pub fn group<S: AsRef<str>>(lines: &[S]) -> Vec<u8> {
+    let len = lines.iter().map(|line| line.as_ref().len() + 2).sum();
+    let mut data = Vec::with_capacity(len);
+    for line in lines {
+        data.extend_from_slice(line.as_ref().as_bytes());
+        data.extend_from_slice(b"\r\n");
+    }
+    data
+}
+
+// The sentence type for statistics: "AIS<n>" for AIS messages and the
+// sentence formatter (e.g. "RMC") for everything else.
+// Type 18 is told apart by the kind of Class B unit, "AIS18CS" or "AIS18SO".
+pub fn sentence_type(line: &str) -> String {
+    if let Some((message_type, _)) = ais_header(line) {
+        if message_type == 18
+            && let Some(unit) = class_b(line)
+        {
+            return format!("AIS18{}", unit.name());
+        }
+        return format!("AIS{}", message_type);
+    }
+    formatter(line).unwrap_or("unknown").to_string()
+}
+
+// The sentence formatter, the last three characters of the address, e.g.
+// "RMC" for $GPRMC,... or "VDM" for !AIVDM,...
+pub fn formatter(line: &str) -> Option<&str> {
+    let address = split_tag_block(line).1.split(',').next()?;
+    let address = address.strip_prefix(['$', '!'])?;
+    if address.len() < 5 {
+        return None;
+    }
+    // Not a slice, the last three bytes need not be characters
+    address.get(address.len() - 3..)
+}
+
+// The MMSI of an AIS message, decoded from the payload.
+pub fn mmsi(line: &str) -> Option<u32> {
+    ais_header(line).map(|(_, mmsi)| mmsi)
+}
+
+// Split off an IEC 61162-450 TAG block, \<fields>*hh\, in front of the sentence.
+pub fn split_tag_block(line: &str) -> (Option<&str>, &str) {
+    if let Some(rest) = line.strip_prefix('\\')
+        && let Some((tag_block, sentence)) = rest.split_once('\\')
+    {
+        return (Some(tag_block), sentence);
+    }
+    (None, line)
+}
+
+// The time the sentence was received at the source, from the c: field of its
+// TAG block.
+pub fn tag_timestamp(line: &str) -> Option<SystemTime> {
+    let tag_block = split_tag_block(line).0?;
+    let fields = tag_block.split('*').next()?;
+    let time = fields
+        .split(',')
+        .find_map(|field| field.strip_prefix("c:"))?;
+    let time = time.parse::<u64>().ok()?;
+    let millis = if time > MILLISECOND_TIMESTAMPS {
+        time
+    } else {
+        time * 1000
+    };
+    Some(SystemTime::UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+// Whether a station or source identifier fits a TAG block s: field: at most
+// 15 characters, none of which end the field or the block.
+pub fn valid_source(source: &str) -> bool {
+    !source.is_empty()
+        && source.len() <= 15
+        && source
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+// The sentences of a chunk with `source` as the s: field of their TAG
+// blocks, replacing the one they had; the other fields are kept.
+pub fn with_source(chunk: &[u8], source: &str) -> Vec<u8> {
+    let lines: Vec<String> = lines(chunk)
+        .map(|line| {
+            let (tag_block, sentence) = split_tag_block(line);
+            let mut fields: Vec<&str> = tag_block
+                .and_then(|tag_block| tag_block.split('*').next())
+                .map(|fields| {
+                    fields
+                        .split(',')
+                        .filter(|field| !field.is_empty() && !field.starts_with("s:"))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let source = format!("s:{}", source);
+            fields.push(&source);
+            let fields = fields.join(",");
+            let sum = fields.bytes().fold(0u8, |sum, b| sum ^ b);
+            format!("\\{}*{:02X}\\{}", fields, sum, sentence)
+        })
+        .collect();
+    group(&lines)
+}
+
+// The sentences of a multi-sentence AIS message with `sequence_id` as their
+// sequential message ID, None for a message of one sentence or anything else.
+// TAG blocks are kept.
+pub fn with_sequence_id(chunk: &[u8], sequence_id: u8) -> Option<Vec<u8>> {
+    let lines: Vec<&str> = lines(chunk).collect();
+    if fragment(lines.first()?)?.0 < 2 {
+        return None;
+    }
+    let sequence_id = sequence_id.to_string();
+    let lines = lines
+        .iter()
+        .map(|line| {
+            fragment(line)?;
+            let (tag_block, sentence) = split_tag_block(line);
+            let mut fields: Vec<&str> = sentence.split('*').next()?.split(',').collect();
+            *fields.get_mut(3)? = &sequence_id;
+            let sentence = with_checksum(&fields.join(","));
+            Some(match tag_block {
+                Some(tag_block) => format!("\\{}\\{}", tag_block, sentence),
+                None => sentence,
+            })
+        })
+        .collect::<Option<Vec<String>>>()?;
+    Some(group(&lines))
+}
+
+// The payload of a !xxVDM or !xxVDO sentence.
+pub fn ais_payload(line: &str) -> Option<&str> {
+    let mut fields = split_tag_block(line).1.split(',');
+    let address = fields.next()?;
+    if !address.starts_with('!') || !(address.ends_with("VDM") || address.ends_with("VDO")) {
+        return None;
+    }
+    fields.nth(4)
+}
+
+// Fragment count and fragment number of a !xxVDM or !xxVDO sentence.
+pub fn fragment(line: &str) -> Option<(u32, u32)> {
+    ais_payload(line)?;
+    let mut fields = split_tag_block(line).1.split(',').skip(1);
+    let total = fields.next()?.parse::<u32>().ok()?;
+    let number = fields.next()?.parse::<u32>().ok()?;
+    (number >= 1 && number <= total).then_some((total, number))
+}
+
+// Whether the sentence is a !xxVDO, a report of our own vessel.
+pub fn is_own_vessel(line: &str) -> bool {
+    split_tag_block(line)
+        .1
+        .split(',')
+        .next()
+        .is_some_and(|address| address.starts_with('!') && address.ends_with("VDO"))
+}
+
+// Whether the sentence has a valid checksum. Anything before the start
+// delimiter, such as a TAG block, is not part of the sentence.
+pub fn checksum_ok(line: &str) -> bool {
+    let Some(start) = line.find(['$', '!']) else {
+        return false;
+    };
+    let Some((body, checksum)) = line[start + 1..].trim_end().rsplit_once('*') else {
+        return false;
+    };
+    let sum = body.bytes().fold(0u8, |sum, b| sum ^ b);
+    u8::from_str_radix(checksum, 16) == Ok(sum)
+}
+
+// Navigational status of a position report, which AIS SARTs, MOB devices and
+// EPIRBs set to 15 when being tested, and to 14 when active.
+pub const NAV_STATUS_DISTRESS_TEST: u8 = 15;
+
+// Devices that transmit AIS when someone is in distress, told apart by the
+// first three digits of their MMSI.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DistressDevice {
+    Sart,
+    Mob,
+    Epirb,
+}
+
+impl DistressDevice {
+    pub fn from_mmsi(mmsi: u32) -> Option<Self> {
+        match mmsi / 1_000_000 {
+            970 => Some(DistressDevice::Sart),
+            972 => Some(DistressDevice::Mob),
+            974 => Some(DistressDevice::Epirb),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DistressDevice::Sart => "SART",
+            DistressDevice::Mob => "MOB",
+            DistressDevice::Epirb => "EPIRB",
+        }
+    }
+}
+
+// The kinds of Class B position reports. Carrier sense (CS) units send type 18
+// with the CS flag set, on a free slot every 30 seconds at most; self
+// organising (SO) units send type 18 without it, reserving slots like class A
+// and reporting as often. Type 19, the extended report, also carries the
+// name, type and dimensions of the vessel.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClassB {
+    CarrierSense,
+    SelfOrganising,
+    Extended,
+}
+
+impl ClassB {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClassB::CarrierSense => "CS",
+            ClassB::SelfOrganising => "SO",
+            ClassB::Extended => "extended",
+        }
+    }
+}
+
+// The kind of a Class B position report, types 18 and 19.
+pub fn class_b(line: &str) -> Option<ClassB> {
+    let bits = PayloadBits::new(ais_payload(line)?);
+    match bits.unsigned(0, 6)? {
+        18 => match bits.unsigned(141, 1)? {
+            1 => Some(ClassB::CarrierSense),
+            _ => Some(ClassB::SelfOrganising),
+        },
+        19 => Some(ClassB::Extended),
+        _ => None,
+    }
+}
+
+// Whether the sentence is a DSC call or its expansion, from a VHF radio.
+pub fn is_dsc(line: &str) -> bool {
+    split_tag_block(line).1.starts_with('$') && matches!(formatter(line), Some("DSC" | "DSE"))
+}
+
+// A DSC distress alert, or a relay or acknowledgement of one.
+pub struct DscDistress {
+    pub mmsi: Option<u32>,
+    pub position: Option<(f64, f64)>,
+}
+
+// The distress alert in a $--DSC sentence: format specifier 12, or category
+// 12 for a relay or acknowledgement, which name the vessel in distress in
+// field 8. MMSIs have a trailing 0 to make ten digits, the position is a
+// quadrant digit followed by ddmm latitude and dddmm longitude.
+pub fn dsc_distress(line: &str) -> Option<DscDistress> {
+    let sentence = split_tag_block(line).1;
+    let fields: Vec<&str> = sentence.split('*').next()?.split(',').collect();
+    if !fields.first()?.ends_with("DSC") || fields.len() < 9 {
+        return None;
+    }
+    let mmsi_field = match (fields[1], fields[3]) {
+        ("12", _) => fields[2],
+        (_, "12") => fields[8],
+        _ => return None,
+    };
+    let mmsi = mmsi_field
+        .parse::<u64>()
+        .ok()
+        .map(|mmsi| (mmsi / 10) as u32);
+    let position = dsc_position(fields[6]);
+    Some(DscDistress { mmsi, position })
+}
+
+fn dsc_position(field: &str) -> Option<(f64, f64)> {
+    if field.len() != 10 || !field.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| field[range].parse::<f64>().ok();
+    let latitude = number(1..3)? + number(3..5)? / 60.0;
+    let longitude = number(5..8)? + number(8..10)? / 60.0;
+    // 0 NE, 1 NW, 2 SE, 3 SW; 9 is no position
+    match &field[..1] {
+        "0" => Some((latitude, longitude)),
+        "1" => Some((latitude, -longitude)),
+        "2" => Some((-latitude, longitude)),
+        "3" => Some((-latitude, -longitude)),
+        _ => None,
+    }
+}
+
+// The navigational status of a class A position report, types 1 to 3.
+pub fn nav_status(line: &str) -> Option<u8> {
+    let bits = PayloadBits::new(ais_payload(line)?);
+    match bits.unsigned(0, 6)? {
+        1..=3 => bits.unsigned(38, 4).map(|status| status as u8),
+        _ => None,
+    }
+}
+
+// Message type and MMSI are in the first 38 bits of every AIS message.
+pub fn ais_header(line: &str) -> Option<(u8, u32)> {
+    let bits = PayloadBits::new(ais_payload(line)?);
+    let message_type = bits.unsigned(0, 6)? as u8;
+    let mmsi = bits.unsigned(8, 30)? as u32;
+    Some((message_type, mmsi))
+}
+
+// Random access to the bits of a six bit armoured AIS payload.
+pub struct PayloadBits {
+    sixbits: Vec<u8>,
+}
+
+impl PayloadBits {
+    pub fn new(payload: &str) -> Self {
+        let sixbits = payload
+            .bytes()
+            .map_while(|c| {
+                let v = c.checked_sub(48)?;
+                let v = if v > 40 { v - 8 } else { v };
+                if v < 64 { Some(v) } else { None }
+            })
+            .collect();
+        PayloadBits { sixbits }
+    }
+
+    pub fn len(&self) -> usize {
+        self.sixbits.len() * 6
+    }
+
+    pub fn unsigned(&self, start: usize, len: usize) -> Option<u64> {
+        if len > 64 || start + len > self.len() {
+            return None;
+        }
+        let mut value = 0u64;
+        for bit in start..start + len {
+            let sixbit = self.sixbits[bit / 6];
+            value = (value << 1) | ((sixbit >> (5 - bit % 6)) & 1) as u64;
+        }
+        Some(value)
+    }
+
+    // A two's complement value, as used for positions and temperatures.
+    pub fn signed(&self, start: usize, len: usize) -> Option<i64> {
+        let value = self.unsigned(start, len)?;
+        let shift = 64 - len as u32;
+        Some(((value << shift) as i64) >> shift)
+    }
+
+    // Six bit ASCII, without the '@' and spaces that pad it.
+    pub fn text(&self, start: usize, chars: usize) -> Option<String> {
+        let text: String = (0..chars)
+            .map(|i| {
+                let c = self.unsigned(start + i * 6, 6)? as u8;
+                Some(if c < 32 { c + 64 } else { c } as char)
+            })
+            .collect::<Option<_>>()?;
+        Some(
+            text.split('@')
+                .next()
+                .unwrap_or_default()
+                .trim_end()
+                .to_string(),
+        )
+    }
+}
+
+// Builds a six bit armoured AIS payload, the reverse of PayloadBits.
+#[derive(Default)]
+pub struct PayloadWriter {
+    bits: Vec<bool>,
+}
+
+impl PayloadWriter {
+    pub fn unsigned(&mut self, value: u64, len: usize) -> &mut Self {
+        self.bits
+            .extend((0..len).rev().map(|bit| (value >> bit) & 1 == 1));
+        self
+    }
+
+    pub fn signed(&mut self, value: i64, len: usize) -> &mut Self {
+        self.unsigned(value as u64, len)
+    }
+
+    // Six bit ASCII, upper case, padded with '@' to `chars` characters.
+    pub fn text(&mut self, text: &str, chars: usize) -> &mut Self {
+        let mut text = text.bytes().map(|c| c.to_ascii_uppercase());
+        for _ in 0..chars {
+            let c = match text.next() {
+                Some(c @ 64..=95) => c - 64,
+                Some(c @ 32..=63) => c,
+                Some(_) => 0,
+                None => 0,
+            };
+            self.unsigned(c as u64, 6);
+        }
+        self
+    }
+
+    // The armoured payload and the number of fill bits at its end.
+    pub fn finish(&self) -> (String, usize) {
+        let fill = (6 - self.bits.len() % 6) % 6;
+        let payload = self
+            .bits
+            .chunks(6)
+            .map(|chunk| {
+                let v = (0..6).fold(0u8, |v, i| {
+                    (v << 1) | chunk.get(i).copied().unwrap_or(false) as u8
+                });
+                (if v < 40 { v + 48 } else { v + 56 }) as char
+            })
+            .collect();
+        (payload, fill)
+    }
+}
+
+// The sentences that carry an AIS payload, split in pieces that keep them
+// within the 82 characters of NMEA 0183. The sequence id tells the pieces of
+// different messages apart.
+pub fn ais_sentences(talker: &str, payload: &str, fill: usize, sequence_id: u8) -> Vec<String> {
+    const MAX_PAYLOAD: usize = 60;
+    let pieces: Vec<&str> = payload
+        .as_bytes()
+        .chunks(MAX_PAYLOAD)
+        .map(|piece| std::str::from_utf8(piece).unwrap_or_default())
+        .collect();
+    let count = pieces.len();
+    let sequence = if count > 1 {
+        sequence_id.to_string()
+    } else {
+        String::new()
+    };
+    pieces
+        .iter()
+        .enumerate()
+        .map(|(i, piece)| {
+            let fill = if i + 1 == count { fill } else { 0 };
+            with_checksum(&format!(
+                "!{},{},{},{},A,{},{}",
+                talker,
+                count,
+                i + 1,
+                sequence,
+                piece,
+                fill
+            ))
+        })
+        .collect()
+}
+
+// Append the checksum to a sentence that starts with '$' or '!'.
+pub fn with_checksum(sentence: &str) -> String {
+    let sum = sentence.bytes().skip(1).fold(0u8, |sum, b| sum ^ b);
+    format!("{}*{:02X}", sentence, sum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITION_REPORT: &str = "!AIVDM,1,1,,B,177KQJ5000G?tO`K>RA1wUbN0TKH,0*5C";
+
+    #[test]
+    fn sentence_types() {
+        assert_eq!(sentence_type(POSITION_REPORT), "AIS1");
+        assert_eq!(sentence_type("$GPRMC,123519,A,4807.038,N*6A"), "RMC");
+        assert_eq!(
+            sentence_type("\\s:station,c:1700000000*5B\\$GPGGA,1*00"),
+            "GGA"
+        );
+        assert_eq!(sentence_type("$GP,1*00"), "unknown");
+        assert_eq!(sentence_type("garbage"), "unknown");
+    }
+
+    #[test]
+    fn formatter_is_not_sliced_inside_a_character() {
+        assert_eq!(formatter("$GPR\u{e9}MC,1"), None);
+        assert_eq!(formatter("$GP\u{e9}RMC,1"), Some("RMC"));
+    }
+
+    #[test]
+    fn tag_timestamp_in_seconds_or_milliseconds() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(tag_timestamp("\\s:a,c:1700000000*00\\!AIVDM,1"), Some(time));
+        assert_eq!(tag_timestamp("\\c:1700000000000*00\\!AIVDM,1"), Some(time));
+        assert_eq!(tag_timestamp("\\s:a*00\\!AIVDM,1"), None);
+        assert_eq!(tag_timestamp("!AIVDM,1"), None);
+    }
+
+    #[test]
+    fn dsc_position_in_each_quadrant() {
+        let close = |position: Option<(f64, f64)>, expected: (f64, f64)| {
+            let (latitude, longitude) = position.unwrap();
+            (latitude - expected.0).abs() < 1e-9 && (longitude - expected.1).abs() < 1e-9
+        };
+        assert!(close(
+            dsc_position("0530500525"),
+            (53.0 + 5.0 / 60.0, 5.0 + 25.0 / 60.0)
+        ));
+        assert!(close(
+            dsc_position("1530500525"),
+            (53.0 + 5.0 / 60.0, -(5.0 + 25.0 / 60.0))
+        ));
+        assert!(close(
+            dsc_position("2335115112"),
+            (-(33.0 + 51.0 / 60.0), 151.2)
+        ));
+        assert!(close(
+            dsc_position("3335115112"),
+            (-(33.0 + 51.0 / 60.0), -151.2)
+        ));
+        assert_eq!(dsc_position("9999999999"), None);
+        assert_eq!(dsc_position("053050052"), None);
+        assert_eq!(dsc_position("05305005x5"), None);
+    }
+
+    #[test]
+    fn dsc_distress_alert_and_relay() {
+        let alert = dsc_distress("$CDDSC,12,2470001230,,07,00,0530500525,1230,,,S,E*00").unwrap();
+        assert_eq!(alert.mmsi, Some(247000123));
+        assert!(alert.position.is_some());
+
+        let relay =
+            dsc_distress("$CDDSC,16,2442000000,12,12,00,3335115112,1230,2470001230,,S*00").unwrap();
+        assert_eq!(relay.mmsi, Some(247000123));
+        assert!(relay.position.is_some_and(|(latitude, _)| latitude < 0.0));
+
+        assert!(dsc_distress("$CDDSC,20,2442000000,00,21,00,,,,,S*00").is_none());
+        assert!(is_dsc("$CDDSE,1,1,A,2470001230,00,45894494*00"));
+        assert!(!is_dsc("!AIVDM,1,1,,A,DSC*00"));
+    }
+
+    #[test]
+    fn with_source_adds_or_replaces_the_station() {
+        let tag_block = |line: &str| {
+            let tag_block = split_tag_block(line).0.unwrap().to_string();
+            let (fields, sum) = tag_block.split_once('*').unwrap();
+            let expected = fields.bytes().fold(0u8, |sum, b| sum ^ b);
+            assert_eq!(sum, format!("{:02X}", expected));
+            fields.to_string()
+        };
+        let chunk = format!(
+            "{}\r\n\\s:old,c:1700000000*00\\{}\r\n",
+            POSITION_REPORT, POSITION_REPORT
+        );
+        let tagged = with_source(chunk.as_bytes(), "harlingen");
+        let tagged: Vec<&str> = lines(&tagged).collect();
+        assert_eq!(tagged.len(), 2);
+        assert_eq!(tag_block(tagged[0]), "s:harlingen");
+        assert_eq!(tag_block(tagged[1]), "c:1700000000,s:harlingen");
+        assert!(tagged.iter().all(|line| line.ends_with(POSITION_REPORT)));
+    }
+
+    #[test]
+    fn valid_sources() {
+        assert!(valid_source("harlingen-1"));
+        assert!(!valid_source(""));
+        assert!(!valid_source("a,b"));
+        assert!(!valid_source("sixteen-letters!"));
+        assert!(!valid_source("0123456789abcdef"));
+    }
+
+    #[test]
+    fn mmsi_from_the_payload() {
+        assert_eq!(mmsi(POSITION_REPORT), Some(477553000));
+        assert_eq!(mmsi("$GPRMC,123519,A*00"), None);
+    }
+
+    #[test]
+    fn payload_bits() {
+        let bits = PayloadBits::new("0w");
+        assert_eq!(bits.len(), 12);
+        assert_eq!(bits.unsigned(0, 6), Some(0));
+        assert_eq!(bits.unsigned(6, 6), Some(63));
+        assert_eq!(bits.unsigned(3, 6), Some(7));
+        assert_eq!(bits.unsigned(7, 6), None);
+        assert_eq!(bits.unsigned(0, 65), None);
+    }
+
+    #[test]
+    fn payload_bits_stop_at_an_invalid_character() {
+        assert_eq!(PayloadBits::new("0w x").len(), 12);
+    }
+
+    #[test]
+    fn payload_writer_is_read_back() {
+        let (payload, fill) = PayloadWriter::default()
+            .unsigned(5, 6)
+            .unsigned(247000123, 30)
+            .signed(-1234, 28)
+            .text("ab_1", 4)
+            .finish();
+        assert_eq!(fill, 2);
+        let bits = PayloadBits::new(&payload);
+        assert_eq!(bits.len(), 90);
+        assert_eq!(bits.unsigned(0, 6), Some(5));
+        assert_eq!(bits.unsigned(6, 30), Some(247000123));
+        assert_eq!(bits.signed(36, 28), Some(-1234));
+        // A, B, _ and 1 in six bit ASCII
+        assert_eq!(bits.unsigned(64, 6), Some(1));
+        assert_eq!(bits.unsigned(70, 6), Some(2));
+        assert_eq!(bits.unsigned(76, 6), Some(31));
+        assert_eq!(bits.unsigned(82, 6), Some(49));
+    }
+
+    #[test]
+    fn class_b_kinds() {
+        let report = |message_type: u64, cs: u64| {
+            let (payload, fill) = PayloadWriter::default()
+                .unsigned(message_type, 6)
+                .unsigned(0, 45)
+                .unsigned(0, 45)
+                .unsigned(0, 45)
+                .unsigned(cs, 1)
+                .unsigned(0, 26)
+                .finish();
+            ais_sentences("AIVDM", &payload, fill, 0).remove(0)
+        };
+        assert_eq!(class_b(&report(18, 1)), Some(ClassB::CarrierSense));
+        assert_eq!(class_b(&report(18, 0)), Some(ClassB::SelfOrganising));
+        assert_eq!(class_b(&report(19, 0)), Some(ClassB::Extended));
+        assert_eq!(class_b(&report(1, 1)), None);
+        assert_eq!(class_b("$GPRMC,123519,A*00"), None);
+    }
+
+    #[test]
+    fn ais_sentences_are_split() {
+        let single = ais_sentences("AIVDM", "15M67FC000G?ufbE`FepT@3n00Sa", 0, 3);
+        assert_eq!(single.len(), 1);
+        assert!(single[0].starts_with("!AIVDM,1,1,,A,15M67FC000G?ufbE`FepT@3n00Sa,0*"));
+
+        let payload = "0".repeat(100);
+        let split = ais_sentences("AIVDM", &payload, 4, 3);
+        assert_eq!(split.len(), 2);
+        assert!(split[0].starts_with(&format!("!AIVDM,2,1,3,A,{},0*", "0".repeat(60))));
+        assert!(split[1].starts_with(&format!("!AIVDM,2,2,3,A,{},4*", "0".repeat(40))));
+        assert!(split.iter().all(|sentence| checksum_ok(sentence)));
+        assert!(split.iter().all(|sentence| sentence.len() <= 82));
+    }
+
+    #[test]
+    fn with_sequence_id_renumbers_groups() {
+        let mut sentences = ais_sentences("AIVDM", &"0".repeat(100), 4, 3);
+        sentences[0] = format!("\\s:harlingen*00\\{}", sentences[0]);
+        let renumbered = with_sequence_id(&group(&sentences), 7).unwrap();
+        let lines: Vec<&str> = lines(&renumbered).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("\\s:harlingen*00\\!AIVDM,2,1,7,A,"));
+        assert!(lines[1].starts_with("!AIVDM,2,2,7,A,"));
+        assert!(checksum_ok(split_tag_block(lines[0]).1));
+        assert!(checksum_ok(lines[1]));
+    }
+
+    #[test]
+    fn with_sequence_id_leaves_single_sentences() {
+        let single = format!("{}\r\n", POSITION_REPORT);
+        assert_eq!(with_sequence_id(single.as_bytes(), 7), None);
+        let gga = "$GPGGA,120000,5310.000,N,00524.000,E,1,08,1.0,0.0,M,0.0,M,,*4A\r\n";
+        assert_eq!(with_sequence_id(gga.as_bytes(), 7), None);
+    }
+}
@@ -0,0 +1,680 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+use std::collections::HashMap;
+use std::io;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::Builder;
+use std::time::{Duration, Instant};
+
+use crate::email::{self, Mailer, Track};
+use crate::geo;
+use crate::metrics::Metrics;
+use crate::nmea::DistressDevice;
+
+const TELEGRAM_API: &str = "https://api.telegram.org";
+const PUSHOVER_API: &str = "https://api.pushover.net/1/messages.json";
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+const REPEAT_INTERVAL: Duration = Duration::from_secs(3600);
+const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+const ANCHOR_SETTLE_TIME: Duration = Duration::from_secs(600);
+const OWN_MOTION_TIMEOUT: Duration = Duration::from_secs(60);
+// Our position is sampled this often for the email digest
+const TRACK_INTERVAL: Duration = Duration::from_secs(60);
+pub const TCPA: Duration = Duration::from_secs(600);
+// Nautical miles around us that the traffic density is counted in
+pub const DENSITY_RANGE: f64 = 5.0;
+// Targets count as moving above this many knots, and as gone when not heard
+// for DENSITY_WINDOW
+const DENSITY_SOG: f64 = 1.0;
+const DENSITY_WINDOW: Duration = Duration::from_secs(600);
+
+pub enum Event {
+    ProviderDown {
+        minutes: u64,
+    },
+    ProviderUp,
+    EndpointFailing {
+        endpoint: String,
+        failures: u64,
+    },
+    EndpointRecovered {
+        endpoint: String,
+    },
+    AnchorDrift {
+        distance: f64,
+        latitude: f64,
+        longitude: f64,
+    },
+    Distress {
+        device: DistressDevice,
+        mmsi: u32,
+        latitude: f64,
+        longitude: f64,
+    },
+    DscDistress {
+        mmsi: Option<u32>,
+        position: Option<(f64, f64)>,
+    },
+    Proximity {
+        mmsi: u32,
+        cpa: f64,
+        tcpa: Duration,
+    },
+    Geofence {
+        name: String,
+        entered: bool,
+        latitude: f64,
+        longitude: f64,
+    },
+    TrafficDensity {
+        targets: usize,
+        range: f64,
+    },
+    // Asked for with the position command
+    Position {
+        name: Option<String>,
+        latitude: f64,
+        longitude: f64,
+    },
+}
+
+impl Event {
+    fn kind(&self) -> &'static str {
+        match self {
+            Event::ProviderDown { .. } => "provider_down",
+            Event::ProviderUp => "provider_up",
+            Event::EndpointFailing { .. } => "endpoint_failing",
+            Event::EndpointRecovered { .. } => "endpoint_recovered",
+            Event::AnchorDrift { .. } => "anchor_drift",
+            Event::Distress { device, .. } => match device {
+                DistressDevice::Sart => "sart",
+                DistressDevice::Mob => "mob",
+                DistressDevice::Epirb => "epirb",
+            },
+            Event::DscDistress { .. } => "dsc_distress",
+            Event::Proximity { .. } => "proximity",
+            Event::Geofence { entered: true, .. } => "geofence_enter",
+            Event::Geofence { entered: false, .. } => "geofence_exit",
+            Event::TrafficDensity { .. } => "traffic_density",
+            Event::Position { .. } => "position",
+        }
+    }
+
+    fn title(&self) -> String {
+        match self {
+            Event::ProviderDown { .. } => "AIS provider down".to_string(),
+            Event::ProviderUp => "AIS provider back up".to_string(),
+            Event::EndpointFailing { endpoint, .. } => format!("Endpoint {} failing", endpoint),
+            Event::EndpointRecovered { endpoint } => format!("Endpoint {} recovered", endpoint),
+            Event::AnchorDrift { .. } => "Anchor drift".to_string(),
+            Event::Distress { device, mmsi, .. } => {
+                format!("AIS {} {} detected", device.name(), mmsi)
+            }
+            Event::DscDistress { .. } => "DSC distress alert".to_string(),
+            Event::Proximity { mmsi, .. } => format!("Vessel {} approaching", mmsi),
+            Event::Geofence { name, entered, .. } => {
+                format!("{} {}", if *entered { "Entered" } else { "Left" }, name)
+            }
+            Event::TrafficDensity { .. } => "Busy waters".to_string(),
+            Event::Position { name, .. } => match name {
+                Some(name) => format!("Position of {}", name),
+                None => "Position".to_string(),
+            },
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            Event::ProviderDown { minutes } => {
+                format!("No data received from the provider for {} minutes", minutes)
+            }
+            Event::ProviderUp => "Receiving data from the provider again".to_string(),
+            Event::EndpointFailing { endpoint, failures } => {
+                format!("{} consecutive send failures to {}", failures, endpoint)
+            }
+            Event::EndpointRecovered { endpoint } => {
+                format!("Messages are being delivered to {} again", endpoint)
+            }
+            Event::AnchorDrift {
+                distance,
+                latitude,
+                longitude,
+            } => format!(
+                "Vessel is {:.0} m from its anchor position, now at {:.5}, {:.5}",
+                distance, latitude, longitude
+            ),
+            Event::Distress {
+                device,
+                mmsi,
+                latitude,
+                longitude,
+            } => format!(
+                "AIS {} {} transmitting at {:.5}, {:.5}",
+                device.name(),
+                mmsi,
+                latitude,
+                longitude
+            ),
+            Event::DscDistress { mmsi, position } => {
+                let mut message = match mmsi {
+                    Some(mmsi) => format!("DSC distress alert for MMSI {}", mmsi),
+                    None => "DSC distress alert".to_string(),
+                };
+                if let Some((latitude, longitude)) = position {
+                    message.push_str(&format!(" at {:.4}, {:.4}", latitude, longitude));
+                }
+                message
+            }
+            Event::Proximity { mmsi, cpa, tcpa } => format!(
+                "Vessel {} will pass at {:.2} NM in {} minutes",
+                mmsi,
+                cpa / 1852.0,
+                tcpa.as_secs().div_ceil(60)
+            ),
+            Event::Geofence {
+                name,
+                entered,
+                latitude,
+                longitude,
+            } => format!(
+                "Vessel {} {} at {:.5}, {:.5}",
+                if *entered { "entered" } else { "left" },
+                name,
+                latitude,
+                longitude
+            ),
+            Event::TrafficDensity { targets, range } => format!(
+                "{} moving vessels within {:.1} NM, approaching a shipping lane?",
+                targets, range
+            ),
+            Event::Position {
+                latitude,
+                longitude,
+                ..
+            } => format!(
+                "At {} at {}\n{}",
+                email::position_text(*latitude, *longitude),
+                chrono::Utc::now().format("%H:%M UTC"),
+                email::map_link(*latitude, *longitude)
+            ),
+        }
+    }
+
+    // Events that can repeat for every received message are only sent once an hour.
+    fn repeat_key(&self) -> Option<String> {
+        match self {
+            Event::AnchorDrift { .. } => Some("anchor_drift".to_string()),
+            Event::Distress { mmsi, .. } => Some(format!("distress-{}", mmsi)),
+            Event::DscDistress { mmsi, .. } => Some(format!("dsc-{}", mmsi.unwrap_or_default())),
+            Event::Proximity { mmsi, .. } => Some(format!("proximity-{}", mmsi)),
+            Event::TrafficDensity { .. } => Some("traffic_density".to_string()),
+            _ => None,
+        }
+    }
+
+    // Reports for people rather than alerts, only sent by email.
+    fn report(&self) -> bool {
+        matches!(self, Event::Position { .. })
+    }
+
+    fn urgent(&self) -> bool {
+        matches!(
+            self,
+            Event::AnchorDrift { .. }
+                | Event::Distress { .. }
+                | Event::DscDistress { .. }
+                | Event::Proximity { .. }
+        )
+    }
+}
+
+enum Backend {
+    Webhook(String),
+    Ntfy(String),
+    Discord(String),
+    Slack(String),
+    Telegram { token: String, chat_id: String },
+    Pushover { token: String, user: String },
+    Email(Box<Mailer>),
+}
+
+impl std::str::FromStr for Backend {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        let (kind, url) = s.split_once(':').ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid notification format, should be kind:url",
+            )
+        })?;
+        let url = url.to_string();
+        match kind {
+            "webhook" => Ok(Backend::Webhook(url)),
+            "ntfy" => Ok(Backend::Ntfy(url)),
+            "discord" => Ok(Backend::Discord(url)),
+            "slack" => Ok(Backend::Slack(url)),
+            // Bot tokens contain a colon themselves, so the chat id is split off the end
+            "telegram" => match url.rsplit_once(':') {
+                Some((token, chat_id)) => Ok(Backend::Telegram {
+                    token: token.to_string(),
+                    chat_id: chat_id.to_string(),
+                }),
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Invalid telegram format, should be telegram:<bot token>:<chat id>",
+                )),
+            },
+            "email" => Ok(Backend::Email(Box::new(url.parse()?))),
+            "pushover" => match url.split_once(':') {
+                Some((token, user)) => Ok(Backend::Pushover {
+                    token: token.to_string(),
+                    user: user.to_string(),
+                }),
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Invalid pushover format, should be pushover:<app token>:<user key>",
+                )),
+            },
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid notification kind '{}'", kind),
+            )),
+        }
+    }
+}
+
+impl Backend {
+    fn send(&self, event: &Event) -> io::Result<()> {
+        let title = event.title();
+        let message = event.message();
+        match self {
+            Backend::Webhook(url) => {
+                let body = serde_json::json!({
+                    "event": event.kind(),
+                    "title": title,
+                    "message": message,
+                    "timestamp": chrono::Utc::now().to_rfc3339(),
+                });
+                post_json(url, &body)
+            }
+            Backend::Ntfy(url) => {
+                let priority = if event.urgent() { "urgent" } else { "default" };
+                ureq::post(url)
+                    .timeout(HTTP_TIMEOUT)
+                    .set("Title", &title)
+                    .set("Priority", priority)
+                    .set("Tags", event.kind())
+                    .send_string(&message)
+                    .map(|_| ())
+                    .map_err(|e| io::Error::other(e.to_string()))
+            }
+            Backend::Discord(url) => {
+                let body = serde_json::json!({
+                    "content": format!("**{}**\n{}", title, message),
+                });
+                post_json(url, &body)
+            }
+            Backend::Slack(url) => {
+                let body = serde_json::json!({
+                    "text": format!("*{}*\n{}", title, message),
+                });
+                post_json(url, &body)
+            }
+            Backend::Telegram { token, chat_id } => {
+                let body = serde_json::json!({
+                    "chat_id": chat_id,
+                    "text": format!("{}\n{}", title, message),
+                    "disable_notification": !event.urgent(),
+                });
+                post_json(&format!("{}/bot{}/sendMessage", TELEGRAM_API, token), &body)
+            }
+            Backend::Pushover { token, user } => {
+                // High priority bypasses the user's quiet hours
+                let priority = if event.urgent() { "1" } else { "0" };
+                ureq::post(PUSHOVER_API)
+                    .timeout(HTTP_TIMEOUT)
+                    .send_form(&[
+                        ("token", token),
+                        ("user", user),
+                        ("title", &title),
+                        ("message", &message),
+                        ("priority", priority),
+                    ])
+                    .map(|_| ())
+                    .map_err(|e| io::Error::other(e.to_string()))
+            }
+            Backend::Email(mailer) => mailer.send(&title, &message),
+        }
+    }
+}
+
+fn post_json(url: &str, body: &serde_json::Value) -> io::Result<()> {
+    ureq::post(url)
+        .timeout(HTTP_TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(&body.to_string())
+        .map(|_| ())
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+// Cheap to clone handle used by the other threads to raise events. When no
+// notification backends are configured the events are only logged.
+#[derive(Clone)]
+pub struct Notifier {
+    tx: Option<Sender<Event>>,
+    last_sent: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl Notifier {
+    pub fn new(
+        section: Option<&HashMap<String, String>>,
+        metrics: Arc<Metrics>,
+    ) -> io::Result<Self> {
+        let mut notifier = Notifier {
+            tx: None,
+            last_sent: Arc::new(Mutex::new(HashMap::new())),
+        };
+        let section = match section {
+            Some(section) if !section.is_empty() => section,
+            _ => return Ok(notifier),
+        };
+        let mut backends = Vec::new();
+        for (name, value) in section {
+            let backend = value.parse::<Backend>().map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", name, e))
+            })?;
+            backends.push((name.clone(), backend));
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        Builder::new()
+            .name("notify".to_string())
+            .spawn(move || notify_loop(rx, backends, metrics))?;
+        notifier.tx = Some(tx);
+        Ok(notifier)
+    }
+
+    pub fn notify(&self, event: Event) {
+        if let Some(key) = event.repeat_key() {
+            let mut last_sent = self.last_sent.lock().unwrap();
+            if let Some(sent) = last_sent.get(&key)
+                && sent.elapsed() < REPEAT_INTERVAL
+            {
+                log::debug!("Not repeating {} notification", key);
+                return;
+            }
+            last_sent.insert(key, Instant::now());
+        }
+
+        if event.report() {
+            log::info!("{}: {}", event.title(), event.message());
+        } else {
+            log::warn!("{}: {}", event.title(), event.message());
+        }
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(event);
+        }
+    }
+}
+
+fn notify_loop(rx: Receiver<Event>, mut backends: Vec<(String, Backend)>, metrics: Arc<Metrics>) {
+    let mut track = Track::default();
+    let mut sampled = Instant::now();
+    loop {
+        match rx.recv_timeout(TRACK_INTERVAL) {
+            Ok(event) => {
+                for (name, backend) in backends.iter() {
+                    if event.report() && !matches!(backend, Backend::Email(_)) {
+                        continue;
+                    }
+                    match backend.send(&event) {
+                        Ok(()) => log::info!("Sent {} notification to {}", event.kind(), name),
+                        Err(e) => log::error!("Cannot send notification to {}: {}", name, e),
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        if sampled.elapsed() < TRACK_INTERVAL {
+            continue;
+        }
+        sampled = Instant::now();
+        let now = chrono::Utc::now();
+        track.sample(now, metrics.own_position());
+        for (name, backend) in backends.iter_mut() {
+            if let Backend::Email(mailer) = backend
+                && mailer.digest_due(now)
+            {
+                let station = metrics
+                    .own_name()
+                    .unwrap_or_else(|| "the station".to_string());
+                let (subject, body) = track.digest(&station, &metrics.voyage());
+                match mailer.send(&subject, &body) {
+                    Ok(()) => log::info!("Sent digest to {}", name),
+                    Err(e) => log::error!("Cannot send digest to {}: {}", name, e),
+                }
+            }
+        }
+    }
+}
+
+// Thresholds from the [alerts] section. Any that are not set are not checked.
+pub struct Alerts {
+    pub provider_down: Option<Duration>,
+    pub endpoint_failures: Option<u64>,
+    pub anchor_drift: Option<f64>,
+    pub cpa: Option<f64>,
+    pub tcpa: Duration,
+    pub density: Option<usize>,
+    pub density_range: f64,
+}
+
+// Watch the metrics for conditions that only show up as the absence of
+// something happening: a silent provider or an endpoint that keeps failing.
+pub fn start_watcher(alerts: &Alerts, metrics: Arc<Metrics>, notifier: Notifier) -> io::Result<()> {
+    let provider_down = alerts.provider_down;
+    let endpoint_failures = alerts.endpoint_failures;
+    if provider_down.is_none() && endpoint_failures.is_none() {
+        return Ok(());
+    }
+
+    Builder::new().name("alerts".to_string()).spawn(move || {
+        let mut provider_is_down = false;
+        let mut failing: HashMap<String, bool> = HashMap::new();
+        loop {
+            std::thread::sleep(WATCH_INTERVAL);
+
+            if let Some(provider_down) = provider_down {
+                let idle = metrics.provider_idle();
+                if !provider_is_down && idle >= provider_down {
+                    provider_is_down = true;
+                    notifier.notify(Event::ProviderDown {
+                        minutes: idle.as_secs() / 60,
+                    });
+                } else if provider_is_down && idle < provider_down {
+                    provider_is_down = false;
+                    notifier.notify(Event::ProviderUp);
+                }
+            }
+
+            if let Some(endpoint_failures) = endpoint_failures {
+                for (endpoint, failures) in metrics.consecutive_failures() {
+                    let alerted = failing.entry(endpoint.clone()).or_insert(false);
+                    if !*alerted && failures >= endpoint_failures {
+                        *alerted = true;
+                        notifier.notify(Event::EndpointFailing { endpoint, failures });
+                    } else if *alerted && failures == 0 {
+                        *alerted = false;
+                        notifier.notify(Event::EndpointRecovered { endpoint });
+                    }
+                }
+            }
+        }
+    })?;
+    Ok(())
+}
+
+// Detect anchor drift: once the own vessel has stayed within `radius` metres
+// of a position for a while we consider it anchored, and leaving that circle
+// raises an event.
+pub struct AnchorWatch {
+    radius: f64,
+    reference: Option<(f64, f64)>,
+    since: Instant,
+    anchored: Option<(f64, f64)>,
+}
+
+impl AnchorWatch {
+    pub fn new(radius: f64) -> Self {
+        AnchorWatch {
+            radius,
+            reference: None,
+            since: Instant::now(),
+            anchored: None,
+        }
+    }
+
+    pub fn update(&mut self, latitude: f64, longitude: f64) -> Option<Event> {
+        let now = Instant::now();
+        if let Some((anchor_lat, anchor_long)) = self.anchored {
+            let distance = geo::distance(anchor_lat, anchor_long, latitude, longitude);
+            if distance > self.radius {
+                self.anchored = None;
+                self.reference = Some((latitude, longitude));
+                self.since = now;
+                return Some(Event::AnchorDrift {
+                    distance,
+                    latitude,
+                    longitude,
+                });
+            }
+            return None;
+        }
+
+        match self.reference {
+            Some((ref_lat, ref_long))
+                if geo::distance(ref_lat, ref_long, latitude, longitude) <= self.radius =>
+            {
+                if now.duration_since(self.since) >= ANCHOR_SETTLE_TIME {
+                    log::info!("Anchored at {:.5}, {:.5}", ref_lat, ref_long);
+                    self.anchored = Some((ref_lat, ref_long));
+                }
+            }
+            _ => {
+                self.reference = Some((latitude, longitude));
+                self.since = now;
+            }
+        }
+        None
+    }
+}
+
+// Collision watch: every moving target is checked against our own last known
+// course and speed, and one whose closest point of approach is nearer than
+// `cpa` nautical miles within `tcpa` raises an event.
+pub struct CollisionWatch {
+    cpa: f64,
+    tcpa: Duration,
+    own: Option<(geo::Motion, Instant)>,
+}
+
+impl CollisionWatch {
+    pub fn new(cpa: f64, tcpa: Duration) -> Self {
+        CollisionWatch {
+            cpa: cpa * 1852.0,
+            tcpa,
+            own: None,
+        }
+    }
+
+    pub fn update_own(&mut self, own: geo::Motion) {
+        self.own = Some((own, Instant::now()));
+    }
+
+    pub fn check(&self, mmsi: u32, target: &geo::Motion) -> Option<Event> {
+        // Without a recent own position there is nothing to compare with
+        let (own, seen) = self.own.as_ref()?;
+        if seen.elapsed() > OWN_MOTION_TIMEOUT {
+            return None;
+        }
+        let (cpa, tcpa) = geo::cpa(own, target);
+        // Only targets still approaching; one lying still next to us is no news
+        if cpa < self.cpa && tcpa > 0.0 && tcpa <= self.tcpa.as_secs_f64() {
+            log::debug!(mmsi = mmsi; "CPA {:.0} m in {:.0} s", cpa, tcpa);
+            return Some(Event::Proximity {
+                mmsi,
+                cpa,
+                tcpa: Duration::from_secs_f64(tcpa),
+            });
+        }
+        None
+    }
+}
+
+// Traffic density: the distinct moving targets within `range` nautical miles
+// of us, heard in the last DENSITY_WINDOW. More than `threshold` raises an
+// event, a crude warning for single-handers that we are nearing a shipping
+// lane. It is raised again once the count has come down below the threshold
+// and gone over it again, at most once an hour.
+pub struct DensityWatch {
+    threshold: usize,
+    range: f64,
+    own: Option<(f64, f64, Instant)>,
+    targets: HashMap<u32, Instant>,
+    busy: bool,
+}
+
+impl DensityWatch {
+    pub fn new(threshold: usize, range: f64) -> Self {
+        DensityWatch {
+            threshold,
+            range,
+            own: None,
+            targets: HashMap::new(),
+            busy: false,
+        }
+    }
+
+    pub fn update_own(&mut self, latitude: f64, longitude: f64) {
+        self.own = Some((latitude, longitude, Instant::now()));
+    }
+
+    pub fn check(
+        &mut self,
+        mmsi: u32,
+        latitude: f64,
+        longitude: f64,
+        sog: Option<f64>,
+    ) -> Option<Event> {
+        let (own_latitude, own_longitude, seen) = self.own?;
+        if seen.elapsed() > OWN_MOTION_TIMEOUT {
+            return None;
+        }
+        let near =
+            geo::distance(own_latitude, own_longitude, latitude, longitude) <= self.range * 1852.0;
+        if near && sog.is_some_and(|sog| sog >= DENSITY_SOG) {
+            self.targets.insert(mmsi, Instant::now());
+        } else {
+            self.targets.remove(&mmsi);
+        }
+        self.targets
+            .retain(|_, heard| heard.elapsed() < DENSITY_WINDOW);
+
+        let targets = self.targets.len();
+        if targets <= self.threshold {
+            self.busy = false;
+            return None;
+        }
+        if self.busy {
+            return None;
+        }
+        self.busy = true;
+        log::info!("{} moving vessels within {} NM", targets, self.range);
+        Some(Event::TrafficDensity {
+            targets,
+            range: self.range,
+        })
+    }
+}
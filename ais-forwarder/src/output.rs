@@ -0,0 +1,721 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// The outputs that [ais] and [location] endpoints are sent to. Each is a Sink
+// created from its URL by the factory registered for the URL scheme; tcp://,
+// udp://, tcp-listen://, udp-listen://, http://, https://, lwe:// (IEC
+// 61162-450, see lwe.rs), lora:// and lorawan:// (see lora.rs) and
+// iridium:// (see iridium.rs) are built in, and parquet:// (see archive.rs)
+// with the parquet feature.
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::buffer::BufReaderDirectWriter;
+use common::sink::Sink;
+use common::{NetworkEndpoint, Protocol, send_message_tcp, send_message_udp};
+
+#[cfg(feature = "parquet")]
+use crate::archive::ArchiveSink;
+use crate::iridium::IridiumSink;
+use crate::lora::LoraSink;
+use crate::lwe::LweSink;
+use crate::nmea;
+use crate::schema::Report;
+
+// Messages for a TCP endpoint are collected and written together, up to about
+// a packet, unless the first has waited this long. The sender flushes as soon
+// as its queue is empty, so this only matters for a busy endpoint.
+const COALESCE_BYTES: usize = 1400;
+const COALESCE_LATENCY: Duration = Duration::from_millis(100);
+
+// Messages for an HTTP endpoint are posted together, up to this many, when the
+// sender has caught up. A failed post is tried again a few times when the
+// server or the link may recover, 5xx and timeouts, doubling the wait.
+const HTTP_BATCH: usize = 100;
+const HTTP_ATTEMPTS: u32 = 3;
+
+// Wait between attempts to connect to an endpoint that is down, doubling up
+// to the maximum, so that every queued message does not try again.
+const MIN_RETRY: Duration = Duration::from_secs(1);
+const MAX_RETRY: Duration = Duration::from_secs(60);
+
+// A client of a tcp-listen output that does not take what it is sent within
+// this is dropped, so that it cannot hold up the others.
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// A client of a udp-listen output is sent to until it has not been heard from
+// for this long, unless the URL has ?expiry=seconds. There is room for a
+// cabin full of tablets.
+const CLIENT_EXPIRY: Duration = Duration::from_secs(300);
+const MAX_CLIENTS: usize = 32;
+
+// Settings from [general] that apply to all outputs.
+#[derive(Clone)]
+pub struct Options {
+    pub connect_timeout: Duration,
+    // A peer that stops reading is given up on after this, so that its sender
+    // reconnects instead of hanging in a write that keepalive will not end.
+    pub write_timeout: Duration,
+    // When set, all UDP outputs send from this one socket instead of each
+    // from their own, so they share a source port.
+    pub udp_socket: Option<Arc<UdpSocket>>,
+    // How long the address of an endpoint is used before its name is looked
+    // up again.
+    pub dns_ttl: Duration,
+}
+
+// Creates a sink for the endpoint name and URL.
+pub type Factory = fn(name: &str, url: &str, options: &Options) -> io::Result<Box<dyn Sink>>;
+
+pub struct Registry {
+    factories: HashMap<String, Factory>,
+    options: Options,
+}
+
+impl Registry {
+    pub fn new(options: Options) -> Self {
+        let mut registry = Registry {
+            factories: HashMap::new(),
+            options,
+        };
+        registry.register("tcp", NetworkSink::create);
+        registry.register("udp", NetworkSink::create);
+        registry.register("tcp-listen", TcpListenSink::create);
+        registry.register("udp-listen", UdpListenSink::create);
+        registry.register("http", HttpSink::create);
+        registry.register("https", HttpSink::create);
+        registry.register("lwe", LweSink::create);
+        registry.register("lora", LoraSink::create);
+        registry.register("lorawan", LoraSink::create);
+        registry.register("iridium", IridiumSink::create);
+        #[cfg(feature = "parquet")]
+        registry.register("parquet", ArchiveSink::create);
+        registry
+    }
+
+    pub fn register(&mut self, scheme: &str, factory: Factory) {
+        self.factories.insert(scheme.to_string(), factory);
+    }
+
+    pub fn create(&self, name: &str, url: &str) -> io::Result<Box<dyn Sink>> {
+        let scheme = url.split_once("://").map_or("", |(scheme, _)| scheme);
+        match self.factories.get(scheme) {
+            Some(factory) => factory(name, url, &self.options),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported output '{}'", url),
+            )),
+        }
+    }
+}
+
+// An outgoing TCP connection or UDP socket.
+pub struct NetworkSink {
+    name: String,
+    endpoint: NetworkEndpoint,
+    options: Options,
+    healthy: bool,
+    // When the next connect may be tried, after a failed one
+    retry_at: Option<Instant>,
+    retry_delay: Duration,
+    // Where to send to from the shared UDP socket
+    udp_target: Option<SocketAddr>,
+    // Messages not yet written to the TCP stream
+    pending: Vec<u8>,
+    pending_since: Instant,
+}
+
+impl NetworkSink {
+    fn create(name: &str, url: &str, options: &Options) -> io::Result<Box<dyn Sink>> {
+        let mut endpoint: NetworkEndpoint = url.parse()?;
+        endpoint.dns_ttl = options.dns_ttl;
+        Ok(Box::new(NetworkSink {
+            name: name.to_string(),
+            endpoint,
+            options: options.clone(),
+            healthy: true,
+            retry_at: None,
+            retry_delay: MIN_RETRY,
+            udp_target: None,
+            pending: Vec::with_capacity(COALESCE_BYTES),
+            pending_since: Instant::now(),
+        }))
+    }
+
+    fn connect_tcp(&mut self) -> io::Result<std::net::TcpStream> {
+        let key = &self.name;
+        if let Some(retry_at) = self.retry_at
+            && let Some(wait) = retry_at.checked_duration_since(Instant::now())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                format!(
+                    "{}: not connected, retrying in {}s",
+                    key,
+                    wait.as_secs() + 1
+                ),
+            ));
+        }
+        let timeout = self.options.connect_timeout;
+        let result = self.endpoint.resolve().and_then(|addr| {
+            std::net::TcpStream::connect_timeout(&addr, timeout).map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::ConnectionRefused,
+                    format!("{} ({}): {}", key, addr, e),
+                )
+            })
+        });
+        match result {
+            Ok(_) => {
+                self.retry_at = None;
+                self.retry_delay = MIN_RETRY;
+            }
+            Err(_) => {
+                self.retry_at = Some(Instant::now() + self.retry_delay);
+                self.retry_delay = (self.retry_delay * 2).min(MAX_RETRY);
+            }
+        }
+        result
+    }
+
+    fn try_connect(&mut self) -> io::Result<()> {
+        let key = &self.name;
+        let address = &mut self.endpoint;
+        match address.protocol {
+            Protocol::TCP => {
+                address.tcp_stream.retain(|writer| {
+                    if writer.peer_addr().is_err() {
+                        log::warn!("Removing disconnected TCP stream");
+                        false
+                    } else {
+                        true
+                    }
+                });
+
+                if address.tcp_stream.is_empty() {
+                    let stream = self.connect_tcp()?;
+                    let key = &self.name;
+                    let address = &mut self.endpoint;
+
+                    // Set the stream to use keepalive
+                    let sock_ref = socket2::SockRef::from(&stream);
+                    let mut ka = socket2::TcpKeepalive::new();
+                    ka = ka.with_time(Duration::from_secs(30));
+                    ka = ka.with_interval(Duration::from_secs(30));
+                    sock_ref.set_tcp_keepalive(&ka)?;
+                    stream.set_write_timeout(Some(self.options.write_timeout))?;
+
+                    log::info!(endpoint = key.as_str(); "{}: Connected to {}", key, address);
+                    let writer = BufReaderDirectWriter::new(stream);
+                    address.tcp_stream.push(writer);
+                }
+            }
+            // UDP has no connection that fails when the address moves, so the
+            // name is looked up again every dns_ttl.
+            Protocol::UDP if self.options.udp_socket.is_some() => {
+                let addr = address.resolve()?;
+                if self.udp_target.is_none() {
+                    log::info!(endpoint = key.as_str(); "{}: Sending to {} from the shared socket", key, address);
+                }
+                self.udp_target = Some(addr);
+            }
+            Protocol::UDP => {
+                let addr = address.resolve()?;
+                if address.udp_socket.is_none() {
+                    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::ConnectionRefused,
+                            format!("{} ({}): {}", key, addr, e),
+                        )
+                    })?;
+                    address.udp_socket = Some(socket);
+                }
+                if let Some(socket) = &address.udp_socket
+                    && socket.peer_addr().ok() != Some(addr)
+                {
+                    socket.connect(addr)?;
+                    log::info!(endpoint = key.as_str(); "{}: Connected to {}", key, address);
+                }
+            }
+            // Not outputs of this sink, see TcpListenSink and UdpListenSink
+            Protocol::TCPListen | Protocol::UDPListen => {}
+        }
+        Ok(())
+    }
+
+    fn try_send(&mut self, nmea_message: &[u8]) -> io::Result<()> {
+        self.try_connect()?;
+        if !self.endpoint.tcp_stream.is_empty() {
+            if self.pending.is_empty() {
+                self.pending_since = Instant::now();
+            }
+            self.pending.extend_from_slice(nmea_message);
+            if self.pending.len() >= COALESCE_BYTES
+                || self.pending_since.elapsed() >= COALESCE_LATENCY
+            {
+                self.write_pending()?;
+            }
+        }
+        let key = &self.name;
+        let address = &mut self.endpoint;
+        if let Some(udp_socket) = address.udp_socket.as_mut() {
+            send_message_udp(udp_socket, nmea_message).map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::ConnectionRefused,
+                    format!("send_message udp {} ({}): {}", key, address, e),
+                )
+            })?;
+        }
+        if let Some(udp_socket) = &self.options.udp_socket
+            && let Some(addr) = self.udp_target
+        {
+            udp_socket.send_to(nmea_message, addr).map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::ConnectionRefused,
+                    format!("send_message udp {} ({}): {}", key, address, e),
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    // An endpoint that fails may have moved to another address.
+    fn set_healthy(&mut self, result: &io::Result<()>) {
+        self.healthy = result.is_ok();
+        if result.is_err() {
+            self.endpoint.forget_address();
+        }
+    }
+
+    // Write the collected messages in one go. When that fails they are lost,
+    // with the connection.
+    fn write_pending(&mut self) -> io::Result<()> {
+        let key = &self.name;
+        let address = &mut self.endpoint;
+        let pending = std::mem::take(&mut self.pending);
+        if let Some(tcp_stream) = address.tcp_stream.get_mut(0)
+            && !pending.is_empty()
+        {
+            // Any failure, a timeout too, may have left part of a message in
+            // the stream, so the connection is dropped and made again.
+            let result = send_message_tcp(tcp_stream, &pending).map_err(|e| {
+                address.tcp_stream.clear();
+                match e.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!(
+                            "send_message tcp {} ({}): peer is not reading, disconnected",
+                            key, address
+                        ),
+                    ),
+                    _ => io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        format!("send_message tcp {} ({}): {}", key, address, e),
+                    ),
+                }
+            });
+            // Keep the allocation
+            self.pending = pending;
+            self.pending.clear();
+            result?;
+            log::debug!(endpoint = key.as_str(); "{}: Sent message to {}", key, address);
+        }
+        Ok(())
+    }
+}
+
+impl Sink for NetworkSink {
+    fn connect(&mut self) -> io::Result<()> {
+        let result = self.try_connect();
+        self.set_healthy(&result);
+        result
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        let result = self.try_send(message);
+        self.set_healthy(&result);
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.write_pending();
+        self.set_healthy(&result);
+        result
+    }
+
+    fn close(&mut self) {
+        // Whatever was collected still goes out, if the connection takes it
+        if let Err(e) = self.write_pending() {
+            log::debug!(endpoint = self.name.as_str(); "{}", e);
+        }
+        self.endpoint.tcp_stream.clear();
+        self.endpoint.udp_socket = None;
+        self.udp_target = None;
+    }
+
+    fn healthy(&self) -> bool {
+        self.healthy
+    }
+}
+
+// A TCP port that plotters and apps connect to, each getting what is
+// forwarded for as long as they stay connected.
+pub struct TcpListenSink {
+    name: String,
+    endpoint: NetworkEndpoint,
+    clients: Vec<(TcpStream, SocketAddr)>,
+    healthy: bool,
+}
+
+impl TcpListenSink {
+    fn create(name: &str, url: &str, _options: &Options) -> io::Result<Box<dyn Sink>> {
+        Ok(Box::new(TcpListenSink {
+            name: name.to_string(),
+            endpoint: url.parse()?,
+            clients: Vec::new(),
+            healthy: true,
+        }))
+    }
+
+    fn accept_clients(&mut self) -> io::Result<()> {
+        self.endpoint.bind()?;
+        let Some(listener) = &self.endpoint.tcp_listener else {
+            return Ok(());
+        };
+        let key = self.name.as_str();
+        loop {
+            match listener.accept() {
+                Ok((stream, addr)) => {
+                    if self.clients.len() >= MAX_CLIENTS {
+                        log::warn!(endpoint = key; "{}: Too many clients, refusing {}", key, addr);
+                        continue;
+                    }
+                    // Accepted sockets may inherit the non-blocking listener
+                    stream.set_nonblocking(false)?;
+                    stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+                    stream.set_nodelay(true)?;
+                    log::info!(endpoint = key; "{}: Client {} connected", key, addr);
+                    self.clients.push((stream, addr));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn try_send(&mut self, message: &[u8]) -> io::Result<()> {
+        self.accept_clients()?;
+        let key = self.name.as_str();
+        self.clients
+            .retain_mut(|(stream, addr)| match stream.write_all(message) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::info!(endpoint = key; "{}: Client {} dropped: {}", key, addr, e);
+                    false
+                }
+            });
+        Ok(())
+    }
+}
+
+impl Sink for TcpListenSink {
+    fn connect(&mut self) -> io::Result<()> {
+        let result = self.accept_clients();
+        self.healthy = result.is_ok();
+        result
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        let result = self.try_send(message);
+        self.healthy = result.is_ok();
+        result
+    }
+
+    fn close(&mut self) {
+        self.clients.clear();
+        self.endpoint.tcp_listener = None;
+    }
+
+    fn healthy(&self) -> bool {
+        self.healthy
+    }
+}
+
+// A UDP port that clients register with by sending it a datagram, anything
+// will do, and then get what is forwarded for as long as they keep doing so
+// within the expiry. For apps on a phone or tablet that cannot be given an
+// address to be sent to, as the router gives them a new one every time.
+pub struct UdpListenSink {
+    name: String,
+    endpoint: NetworkEndpoint,
+    expiry: Duration,
+    // When each client was last heard from
+    clients: HashMap<SocketAddr, Instant>,
+    healthy: bool,
+}
+
+impl UdpListenSink {
+    fn create(name: &str, url: &str, _options: &Options) -> io::Result<Box<dyn Sink>> {
+        let (url, query) = url.split_once('?').unwrap_or((url, ""));
+        let mut expiry = CLIENT_EXPIRY;
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            match (key, value.parse::<u64>()) {
+                ("expiry", Ok(seconds)) if seconds > 0 => expiry = Duration::from_secs(seconds),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{}: invalid option '{}={}'", url, key, value),
+                    ));
+                }
+            }
+        }
+        Ok(Box::new(UdpListenSink {
+            name: name.to_string(),
+            endpoint: url.parse()?,
+            expiry,
+            clients: HashMap::new(),
+            healthy: true,
+        }))
+    }
+
+    fn try_connect(&mut self) -> io::Result<()> {
+        if self.endpoint.udp_socket.is_none() {
+            self.endpoint.bind()?;
+            if let Some(socket) = &self.endpoint.udp_socket {
+                // Registrations are picked up when there is something to send
+                socket.set_nonblocking(true)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Take in the clients that registered since the last time, and forget
+    // the ones that expired.
+    fn register_clients(&mut self) -> io::Result<()> {
+        let Some(socket) = &self.endpoint.udp_socket else {
+            return Ok(());
+        };
+        let key = self.name.as_str();
+        let mut buffer = [0u8; 64];
+        loop {
+            match socket.recv_from(&mut buffer) {
+                Ok((_, addr)) => {
+                    if self.clients.insert(addr, Instant::now()).is_none() {
+                        log::info!(endpoint = key; "{}: Client {} registered", key, addr);
+                        if self.clients.len() > MAX_CLIENTS
+                            && let Some(oldest) = self
+                                .clients
+                                .iter()
+                                .min_by_key(|(_, heard)| **heard)
+                                .map(|(addr, _)| *addr)
+                        {
+                            log::warn!(endpoint = key; "{}: Too many clients, dropping {}", key, oldest);
+                            self.clients.remove(&oldest);
+                        }
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                // Such as an ICMP port unreachable for an earlier send
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        let expiry = self.expiry;
+        self.clients.retain(|addr, heard| {
+            let keep = heard.elapsed() < expiry;
+            if !keep {
+                log::info!(endpoint = key; "{}: Client {} expired", key, addr);
+            }
+            keep
+        });
+        Ok(())
+    }
+
+    fn try_send(&mut self, message: &[u8]) -> io::Result<()> {
+        self.try_connect()?;
+        self.register_clients()?;
+        let Some(socket) = &self.endpoint.udp_socket else {
+            return Ok(());
+        };
+        let key = self.name.as_str();
+        // A client that is gone is no reason to fail the others
+        self.clients
+            .retain(|addr, _| match socket.send_to(message, addr) {
+                Ok(_) => true,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => true,
+                Err(e) => {
+                    log::info!(endpoint = key; "{}: Dropping client {}: {}", key, addr, e);
+                    false
+                }
+            });
+        Ok(())
+    }
+}
+
+impl Sink for UdpListenSink {
+    fn connect(&mut self) -> io::Result<()> {
+        let result = self.try_connect();
+        self.healthy = result.is_ok();
+        result
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        let result = self.try_send(message);
+        self.healthy = result.is_ok();
+        result
+    }
+
+    fn close(&mut self) {
+        self.endpoint.udp_socket = None;
+        self.clients.clear();
+    }
+
+    fn healthy(&self) -> bool {
+        self.healthy
+    }
+}
+
+// Messages posted to a web service, one sentence per line, or with
+// ?format=json as a JSON array of decoded reports (see schema.rs). The agent
+// keeps the connection, and its TLS session, open between posts.
+pub struct HttpSink {
+    name: String,
+    url: String,
+    agent: ureq::Agent,
+    healthy: bool,
+    json: bool,
+    batch: Vec<u8>,
+    reports: Vec<Report>,
+    messages: usize,
+}
+
+impl HttpSink {
+    fn create(name: &str, url: &str, options: &Options) -> io::Result<Box<dyn Sink>> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(options.connect_timeout)
+            .timeout_read(options.write_timeout)
+            .timeout_write(options.write_timeout)
+            .max_idle_connections_per_host(1)
+            .build();
+        // The format is ours, the rest of the query is for the server
+        let (address, query) = url.split_once('?').unwrap_or((url, ""));
+        let mut json = false;
+        let mut rest = Vec::new();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            match pair {
+                "format=json" => json = true,
+                "format=nmea" => json = false,
+                _ if pair.starts_with("format=") => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{}: format should be nmea or json", url),
+                    ));
+                }
+                _ => rest.push(pair),
+            }
+        }
+        let url = if rest.is_empty() {
+            address.to_string()
+        } else {
+            format!("{}?{}", address, rest.join("&"))
+        };
+        Ok(Box::new(HttpSink {
+            name: name.to_string(),
+            url,
+            agent,
+            healthy: true,
+            json,
+            batch: Vec::new(),
+            reports: Vec::new(),
+            messages: 0,
+        }))
+    }
+
+    fn post(&mut self) -> io::Result<()> {
+        if self.messages == 0 {
+            return Ok(());
+        }
+        let (content_type, body) = if self.json {
+            let body = serde_json::to_vec(&self.reports).map_err(io::Error::other)?;
+            ("application/json", body)
+        } else {
+            ("text/plain", std::mem::take(&mut self.batch))
+        };
+        let mut delay = MIN_RETRY;
+        let mut attempt = 1;
+        let result = loop {
+            let error = match self
+                .agent
+                .post(&self.url)
+                .set("Content-Type", content_type)
+                .send_bytes(&body)
+            {
+                Ok(_) => break Ok(()),
+                Err(e) => e,
+            };
+            let retry = match &error {
+                ureq::Error::Status(status, _) => *status >= 500,
+                ureq::Error::Transport(_) => true,
+            };
+            if !retry || attempt >= HTTP_ATTEMPTS {
+                break Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    format!("send_message http {} ({}): {}", self.name, self.url, error),
+                ));
+            }
+            log::debug!(endpoint = self.name.as_str(); "{}: {}, retrying in {}s", self.name, error, delay.as_secs());
+            std::thread::sleep(delay);
+            delay *= 2;
+            attempt += 1;
+        };
+        if result.is_ok() {
+            log::debug!(endpoint = self.name.as_str(); "{}: Posted {} messages to {}", self.name, self.messages, self.url);
+        }
+        // Lost when it failed, like a message to a TCP endpoint that is down
+        self.batch.clear();
+        self.reports.clear();
+        self.messages = 0;
+        result
+    }
+}
+
+impl Sink for HttpSink {
+    fn connect(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        if self.json {
+            let lines: Vec<&str> = nmea::lines(message).collect();
+            match Report::decode(&lines) {
+                Some(report) => self.reports.push(report),
+                None => return Ok(()),
+            }
+        } else {
+            self.batch.extend_from_slice(message);
+        }
+        self.messages += 1;
+        if self.messages >= HTTP_BATCH {
+            return self.flush();
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.post();
+        self.healthy = result.is_ok();
+        result
+    }
+
+    fn close(&mut self) {
+        self.batch.clear();
+        self.reports.clear();
+        self.messages = 0;
+    }
+
+    fn healthy(&self) -> bool {
+        self.healthy
+    }
+}
@@ -0,0 +1,587 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Transform pipelines for [ais] endpoints, from the [pipeline] section per
+// endpoint name or as default: the built-in transforms that a message goes
+// through, in order, before it is sent to the endpoint:
+//
+//     Research = filter:others | filter:types=1,2,3,18 | anonymize | tagblock:off | throttle:60
+//     Logger = tagblock:source=boat | format:json
+//
+// - filter:own and filter:others keep only our own vessel or the others,
+//   filter:types=1,2,3 only those AIS message types and filter:mmsi=... only
+//   those vessels; everything else, such as DSC, does not pass a filter;
+// - filter:provider=... keeps what those [providers] read, wherever it is in
+//   the pipeline, as the endpoint is not even sent the rest;
+// - anonymize[:salt] gives every vessel a made up MMSI, the same one for as
+//   long as the salt is, and blanks names, call signs and IMO numbers. Other
+//   messages of vessels, binary and safety messages and the like, are dropped,
+//   as they name vessels or carry free text. Base stations and aids to
+//   navigation are left as they are. Without a salt a random one is made at
+//   startup, so that the made up MMSIs cannot be traced back;
+// - tagblock:off drops TAG blocks, tagblock:source=<name> sets their s:
+//   field, as the station setting in [general] does for all endpoints;
+// - throttle:<seconds> sends each other vessel that often at most, our own
+//   vessel always, by the clock of [general] clock;
+// - format:json turns each message into a line of JSON in the schema of
+//   schema.rs, dropping what cannot be decoded. It has to be the last step.
+//
+// A [profile] applies after the pipeline.
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hash};
+use std::io;
+use std::time::{Duration, Instant};
+
+use common::sink::Sink;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::clock::{Clock, ClockSource};
+use crate::nmea::{self, PayloadBits, PayloadWriter};
+use crate::schema::Report;
+
+// Vessels remembered by throttle before forgetting the ones not sent lately
+const MAX_THROTTLED: usize = 10_000;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Filter {
+    Own,
+    Others,
+    Types(HashSet<u8>),
+    Mmsi(HashSet<u32>),
+    Provider(HashSet<String>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Transform {
+    Filter(Filter),
+    Anonymize(String),
+    TagBlockOff,
+    TagBlockSource(String),
+    Throttle(Duration),
+    Json,
+}
+
+// Numbers or names separated by commas.
+fn list<T>(values: &str) -> io::Result<HashSet<T>>
+where
+    T: std::str::FromStr + Hash + Eq,
+    T::Err: std::fmt::Display,
+{
+    values
+        .split(',')
+        .map(|value| value.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid list '{}': {}", values, e),
+            )
+        })
+}
+
+impl std::str::FromStr for Transform {
+    type Err = io::Error;
+
+    fn from_str(step: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let (kind, argument) = match step.split_once(':') {
+            Some((kind, argument)) => (kind.trim(), Some(argument.trim())),
+            None => (step.trim(), None),
+        };
+        match (kind, argument) {
+            ("filter", Some("own")) => Ok(Transform::Filter(Filter::Own)),
+            ("filter", Some("others")) => Ok(Transform::Filter(Filter::Others)),
+            ("filter", Some(argument)) => match argument.split_once('=') {
+                Some(("types", types)) => Ok(Transform::Filter(Filter::Types(list(types)?))),
+                Some(("mmsi", mmsi)) => Ok(Transform::Filter(Filter::Mmsi(list(mmsi)?))),
+                Some(("provider", names)) => Ok(Transform::Filter(Filter::Provider(list(names)?))),
+                _ => Err(invalid(format!(
+                    "Invalid filter '{}', should be own, others, types=..., mmsi=... or provider=...",
+                    argument
+                ))),
+            },
+            ("anonymize", Some(salt)) if !salt.is_empty() => {
+                Ok(Transform::Anonymize(salt.to_string()))
+            }
+            ("anonymize", _) => Ok(Transform::Anonymize(random_salt())),
+            ("tagblock", Some("off")) => Ok(Transform::TagBlockOff),
+            ("tagblock", Some(argument)) => match argument.split_once('=') {
+                Some(("source", source)) if nmea::valid_source(source) => {
+                    Ok(Transform::TagBlockSource(source.to_string()))
+                }
+                _ => Err(invalid(format!(
+                    "Invalid tagblock '{}', should be off or source=<name>",
+                    argument
+                ))),
+            },
+            ("throttle", Some(seconds)) => seconds
+                .parse()
+                .map(|seconds| Transform::Throttle(Duration::from_secs(seconds)))
+                .map_err(|e| invalid(format!("Invalid throttle '{}': {}", seconds, e))),
+            ("format", Some("json")) => Ok(Transform::Json),
+            _ => Err(invalid(format!(
+                "Invalid step '{}', should be filter, anonymize, tagblock, throttle or format",
+                step
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pipeline {
+    steps: Vec<Transform>,
+}
+
+impl std::str::FromStr for Pipeline {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        let steps = s
+            .split('|')
+            .filter(|step| !step.trim().is_empty())
+            .map(str::parse)
+            .collect::<io::Result<Vec<Transform>>>()?;
+        if let Some(json) = steps.iter().position(|step| *step == Transform::Json)
+            && json + 1 != steps.len()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "format:json has to be the last step",
+            ));
+        }
+        Ok(Pipeline { steps })
+    }
+}
+
+impl Pipeline {
+    // The providers that filter:provider steps let through, None for all.
+    pub fn providers(&self) -> Option<HashSet<String>> {
+        self.steps
+            .iter()
+            .filter_map(|step| match step {
+                Transform::Filter(Filter::Provider(providers)) => Some(providers.clone()),
+                _ => None,
+            })
+            .reduce(|a, b| a.intersection(&b).cloned().collect())
+    }
+
+    // The clock is that of the dispatcher, for throttle.
+    pub fn wrap(&self, name: &str, clock: ClockSource, sink: Box<dyn Sink>) -> Box<dyn Sink> {
+        if self.steps.is_empty() {
+            return sink;
+        }
+        Box::new(PipelineSink {
+            name: name.to_string(),
+            steps: self.steps.clone(),
+            clock: Clock::new(clock),
+            last_sent: HashMap::new(),
+            inner: sink,
+        })
+    }
+}
+
+struct PipelineSink {
+    name: String,
+    steps: Vec<Transform>,
+    clock: Clock,
+    // When each other vessel was last let through, per throttle step
+    last_sent: HashMap<(usize, u32), Instant>,
+    inner: Box<dyn Sink>,
+}
+
+impl PipelineSink {
+    // The message after all steps, None when one of them dropped it.
+    fn apply(&mut self, message: &[u8]) -> Option<Vec<u8>> {
+        if let Some(first) = nmea::lines(message).next() {
+            self.clock.observe_sentence(first);
+        }
+        let now = self.clock.now();
+        let mut message = message.to_vec();
+        for (index, step) in self.steps.iter().enumerate() {
+            let lines: Vec<&str> = nmea::lines(&message).collect();
+            let first = *lines.first()?;
+            let own = nmea::is_own_vessel(first);
+            message = match step {
+                Transform::Filter(filter) => {
+                    let header = nmea::ais_header(first);
+                    let keep = match filter {
+                        Filter::Own => own,
+                        Filter::Others => header.is_some() && !own,
+                        Filter::Types(types) => header.is_some_and(|(t, _)| types.contains(&t)),
+                        Filter::Mmsi(mmsi) => header.is_some_and(|(_, m)| mmsi.contains(&m)),
+                        // Done by the endpoint, see providers()
+                        Filter::Provider(_) => true,
+                    };
+                    if !keep {
+                        return None;
+                    }
+                    message
+                }
+                Transform::Anonymize(salt) => anonymize(&lines, salt)?,
+                Transform::TagBlockOff => {
+                    let lines: Vec<&str> = lines
+                        .iter()
+                        .map(|line| nmea::split_tag_block(line).1)
+                        .collect();
+                    nmea::group(&lines)
+                }
+                Transform::TagBlockSource(source) => nmea::with_source(&message, source),
+                Transform::Throttle(interval) => {
+                    if !own && let Some(mmsi) = nmea::mmsi(first) {
+                        if self
+                            .last_sent
+                            .get(&(index, mmsi))
+                            .is_some_and(|sent| now.duration_since(*sent) < *interval)
+                        {
+                            return None;
+                        }
+                        self.last_sent.insert((index, mmsi), now);
+                        if self.last_sent.len() > MAX_THROTTLED {
+                            self.last_sent
+                                .retain(|_, sent| now.duration_since(*sent) < *interval);
+                        }
+                    }
+                    message
+                }
+                Transform::Json => {
+                    let report = Report::decode(&lines)?;
+                    let mut json = serde_json::to_vec(&report).ok()?;
+                    json.extend_from_slice(b"\r\n");
+                    json
+                }
+            };
+        }
+        Some(message)
+    }
+}
+
+impl Sink for PipelineSink {
+    fn connect(&mut self) -> io::Result<()> {
+        self.inner.connect()
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        match self.apply(message) {
+            Some(message) => self.inner.send(&message),
+            None => {
+                log::trace!(endpoint = self.name.as_str(); "{}: Dropped by the pipeline", self.name);
+                Ok(())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn flush_due(&self) -> Option<Duration> {
+        self.inner.flush_due()
+    }
+
+    fn close(&mut self) {
+        self.inner.close();
+    }
+
+    fn healthy(&self) -> bool {
+        self.inner.healthy()
+    }
+}
+
+// The sentences of an AIS message of a vessel with a made up MMSI and
+// without name, call sign and IMO number; the message as it is when it is
+// not of a vessel, and None for messages of vessels that cannot be made
+// anonymous. The payload keeps its length, so each sentence keeps its piece
+// of it and its TAG block.
+fn anonymize(lines: &[&str], salt: &str) -> Option<Vec<u8>> {
+    if nmea::ais_payload(lines.first()?).is_none() {
+        return Some(nmea::group(lines));
+    }
+    let payload: String = lines
+        .iter()
+        .map(|line| nmea::ais_payload(line))
+        .collect::<Option<_>>()?;
+    let bits = PayloadBits::new(&payload);
+    let message_type = bits.unsigned(0, 6)?;
+    let mmsi = bits.unsigned(8, 30)? as u32;
+    // Base stations, 00MIDxxxx, and aids to navigation, 99MIDxxxx
+    if !(10_000_000..990_000_000).contains(&mmsi) || matches!(message_type, 4 | 11 | 17 | 20..=23) {
+        return Some(nmea::group(lines));
+    }
+    // What to blank, start and length in bits
+    let blanks: &[(usize, usize)] = match message_type {
+        1..=3 | 9 | 18 | 27 => &[],
+        // IMO number, call sign and name
+        5 => &[(40, 30), (70, 42), (112, 120)],
+        19 => &[(143, 120)],
+        24 => match bits.unsigned(38, 2)? {
+            0 => &[(40, 120)],
+            // An auxiliary craft, 98MIDxxxx, has the MMSI of its mother ship
+            // where others have their dimensions
+            _ if mmsi >= 980_000_000 => &[(90, 42), (132, 30)],
+            _ => &[(90, 42)],
+        },
+        _ => return None,
+    };
+
+    let copy = |writer: &mut PayloadWriter, from: usize, to: usize| -> Option<()> {
+        let mut at = from;
+        while at < to {
+            let len = (to - at).min(60);
+            writer.unsigned(bits.unsigned(at, len)?, len);
+            at += len;
+        }
+        Some(())
+    };
+    let mut writer = PayloadWriter::default();
+    copy(&mut writer, 0, 8)?;
+    writer.unsigned(pseudonym(mmsi, salt) as u64, 30);
+    let mut copied = 38;
+    for &(start, len) in blanks {
+        if start + len > bits.len() {
+            break;
+        }
+        copy(&mut writer, copied, start)?;
+        for _ in 0..len / 6 {
+            writer.unsigned(0, 6);
+        }
+        copied = start + len;
+    }
+    copy(&mut writer, copied, bits.len())?;
+    let (anonymous, _) = writer.finish();
+
+    let mut rest = anonymous.as_str();
+    let mut sentences = Vec::with_capacity(lines.len());
+    for line in lines {
+        let (tag_block, sentence) = nmea::split_tag_block(line);
+        let mut fields: Vec<&str> = sentence.split('*').next()?.split(',').collect();
+        let (piece, after) = rest.split_at_checked(fields.get(5)?.len())?;
+        fields[5] = piece;
+        rest = after;
+        let sentence = nmea::with_checksum(&fields.join(","));
+        sentences.push(match tag_block {
+            Some(tag_block) => format!("\\{}\\{}", tag_block, sentence),
+            None => sentence,
+        });
+    }
+    Some(nmea::group(&sentences))
+}
+
+// In the range of ship stations, the same for the same MMSI and salt. Keyed
+// with the salt, so that the MMSI cannot be found by trying them all without
+// knowing it, and the same on every build.
+fn pseudonym(mmsi: u32, salt: &str) -> u32 {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(salt.as_bytes()).expect("HMAC takes a key of any length");
+    mac.update(&mmsi.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let value = u64::from_be_bytes(hash[..8].try_into().unwrap());
+    200_000_000 + (value % 600_000_000) as u32
+}
+
+// A salt for anonymize without one, different on every start.
+fn random_salt() -> String {
+    let state = RandomState::new();
+    format!("{:016x}{:016x}", state.hash_one(1u8), state.hash_one(2u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITION_REPORT: &str = "!AIVDM,1,1,,B,177KQJ5000G?tO`K>RA1wUbN0TKH,0*5C";
+
+    struct Discard;
+
+    impl Sink for Discard {
+        fn connect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn send(&mut self, _message: &[u8]) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) {}
+
+        fn healthy(&self) -> bool {
+            true
+        }
+    }
+
+    fn pipeline_sink(steps: &str, clock: ClockSource) -> PipelineSink {
+        PipelineSink {
+            name: "test".to_string(),
+            steps: steps.parse::<Pipeline>().unwrap().steps,
+            clock: Clock::new(clock),
+            last_sent: HashMap::new(),
+            inner: Box::new(Discard),
+        }
+    }
+
+    fn sentences(writer: &PayloadWriter) -> Vec<String> {
+        let (payload, fill) = writer.finish();
+        nmea::ais_sentences("AIVDM", &payload, fill, 0)
+    }
+
+    fn static_data(mmsi: u32) -> Vec<String> {
+        let mut writer = PayloadWriter::default();
+        writer
+            .unsigned(5, 6)
+            .unsigned(0, 2)
+            .unsigned(mmsi as u64, 30)
+            .unsigned(0, 2)
+            .unsigned(9_123_456, 30)
+            .text("PD1234", 7)
+            .text("NOORDERLICHT", 20)
+            .unsigned(36, 8)
+            .unsigned(0, 30)
+            .unsigned(0, 4)
+            .unsigned(0, 20)
+            .unsigned(0, 8)
+            .text("HARLINGEN", 20)
+            .unsigned(0, 2);
+        sentences(&writer)
+    }
+
+    fn anonymized<S: AsRef<str>>(lines: &[S], salt: &str) -> Option<Vec<String>> {
+        let lines: Vec<&str> = lines.iter().map(AsRef::as_ref).collect();
+        let message = anonymize(&lines, salt)?;
+        Some(nmea::lines(&message).map(str::to_string).collect())
+    }
+
+    #[test]
+    fn steps_are_parsed() {
+        let pipeline: Pipeline =
+            "filter:others | anonymize:pepper | tagblock:source=relay | throttle:60 | format:json"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            pipeline.steps,
+            vec![
+                Transform::Filter(Filter::Others),
+                Transform::Anonymize("pepper".to_string()),
+                Transform::TagBlockSource("relay".to_string()),
+                Transform::Throttle(Duration::from_secs(60)),
+                Transform::Json,
+            ]
+        );
+        let pipeline: Pipeline = "filter:types=1,2,3|filter:mmsi=244000000".parse().unwrap();
+        assert_eq!(
+            pipeline.steps,
+            vec![
+                Transform::Filter(Filter::Types(HashSet::from([1, 2, 3]))),
+                Transform::Filter(Filter::Mmsi(HashSet::from([244_000_000]))),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_steps_are_refused() {
+        for steps in [
+            "format:json|throttle:60",
+            "compress",
+            "filter:nearby",
+            "filter:types=1,x",
+            "tagblock:source=a b",
+            "throttle:soon",
+        ] {
+            assert!(steps.parse::<Pipeline>().is_err(), "{}", steps);
+        }
+    }
+
+    #[test]
+    fn anonymize_without_salt_uses_a_random_one() {
+        let Ok(Transform::Anonymize(salt)) = "anonymize".parse() else {
+            panic!("not anonymize");
+        };
+        assert!(!salt.is_empty());
+        let Ok(Transform::Anonymize(other)) = "anonymize:".parse() else {
+            panic!("not anonymize");
+        };
+        assert_ne!(salt, other);
+    }
+
+    #[test]
+    fn anonymize_position_report() {
+        let tagged = format!("\\s:harlingen*00\\{}", POSITION_REPORT);
+        let line = anonymized(&[tagged], "pepper").unwrap().remove(0);
+        assert!(line.starts_with("\\s:harlingen*00\\!AIVDM,1,1,,B,"));
+        assert!(nmea::checksum_ok(nmea::split_tag_block(&line).1));
+        let mmsi = nmea::mmsi(&line).unwrap();
+        assert_ne!(mmsi, 477_553_000);
+        assert!((200_000_000..800_000_000).contains(&mmsi));
+        // Only the MMSI, in the characters up to bit 42, is changed
+        let original = nmea::ais_payload(POSITION_REPORT).unwrap();
+        let payload = nmea::ais_payload(&line).unwrap();
+        assert_eq!(payload[..1], original[..1]);
+        assert_eq!(payload[7..], original[7..]);
+
+        let pseudonym = |salt| anonymized(&[POSITION_REPORT], salt).map(|l| nmea::mmsi(&l[0]));
+        assert_eq!(pseudonym("pepper"), Some(Some(mmsi)));
+        assert_ne!(pseudonym("salt"), Some(Some(mmsi)));
+    }
+
+    #[test]
+    fn anonymize_static_data() {
+        let lines = anonymized(&static_data(244_000_000), "pepper").unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| nmea::checksum_ok(line)));
+        let payload: String = lines
+            .iter()
+            .map(|line| nmea::ais_payload(line).unwrap())
+            .collect();
+        let bits = PayloadBits::new(&payload);
+        assert_eq!(bits.unsigned(40, 30), Some(0));
+        assert_eq!(bits.text(70, 7).as_deref(), Some(""));
+        assert_eq!(bits.text(112, 20).as_deref(), Some(""));
+        assert_eq!(bits.unsigned(232, 8), Some(36));
+        assert_eq!(bits.text(302, 20).as_deref(), Some("HARLINGEN"));
+    }
+
+    #[test]
+    fn anonymize_leaves_stations_and_drops_other_vessel_messages() {
+        let mut writer = PayloadWriter::default();
+        writer
+            .unsigned(4, 6)
+            .unsigned(0, 2)
+            .unsigned(2_442_000, 30)
+            .unsigned(0, 60)
+            .unsigned(0, 60)
+            .unsigned(0, 10);
+        let base_station = sentences(&writer);
+        assert_eq!(anonymized(&base_station, "pepper"), Some(base_station));
+
+        let binary = |mmsi: u32| {
+            let mut writer = PayloadWriter::default();
+            writer
+                .unsigned(8, 6)
+                .unsigned(0, 2)
+                .unsigned(mmsi as u64, 30)
+                .unsigned(0, 18);
+            sentences(&writer)
+        };
+        assert_eq!(anonymized(&binary(244_000_000), "pepper"), None);
+        assert_eq!(
+            anonymized(&binary(2_442_000), "pepper"),
+            Some(binary(2_442_000))
+        );
+
+        let gga = ["$GPGGA,120000,5310.000,N,00524.000,E,1,08,1.0,0.0,M,0.0,M,,*4A"];
+        assert_eq!(anonymized(&gga, "pepper"), Some(vec![gga[0].to_string()]));
+    }
+
+    #[test]
+    fn throttle_follows_the_message_clock() {
+        let mut sink = pipeline_sink("throttle:60", ClockSource::Message);
+        let at = |seconds: u64| {
+            format!(
+                "\\c:{}*00\\{}\r\n",
+                1_700_000_000 + seconds,
+                POSITION_REPORT
+            )
+        };
+        assert!(sink.apply(at(0).as_bytes()).is_some());
+        assert!(sink.apply(at(30).as_bytes()).is_none());
+        assert!(sink.apply(at(61).as_bytes()).is_some());
+    }
+}
@@ -0,0 +1,334 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Ask the boat's router to forward the ports of tcp-listen:// outputs to us,
+// so that a client on shore can connect back to the station without anyone
+// setting up the router. NAT-PMP (RFC 6886) is tried first, as it is one
+// datagram to the default gateway, then UPnP IGD: the router is found with
+// SSDP and asked with SOAP.
+//
+// Mappings are leased and renewed at half the lease, so that they go away by
+// themselves when we stop. The external port is the same as the one that we
+// listen on.
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::str::FromStr;
+use std::thread::Builder;
+use std::time::Duration;
+
+const LEASE: Duration = Duration::from_secs(3600);
+// After a failure, try again this much later
+const RETRY_DELAY: Duration = Duration::from_secs(300);
+
+const NAT_PMP_PORT: u16 = 5351;
+// RFC 6886 section 3.1: start at 250 ms and double. Routers that answer do so
+// at once, so fewer tries than the RFC's nine.
+const NAT_PMP_TRIES: u32 = 4;
+const NAT_PMP_FIRST_TIMEOUT: Duration = Duration::from_millis(250);
+const NAT_PMP_MAP_TCP: u8 = 2;
+
+const SSDP_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SSDP_PORT: u16 = 1900;
+const SSDP_TIMEOUT: Duration = Duration::from_secs(3);
+const IGD: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+const WAN_SERVICES: [&str; 2] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Method {
+    #[default]
+    Off,
+    NatPmp,
+    Upnp,
+    // NAT-PMP, then UPnP
+    Auto,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Method::Off),
+            "nat-pmp" => Ok(Method::NatPmp),
+            "upnp" => Ok(Method::Upnp),
+            "auto" => Ok(Method::Auto),
+            _ => Err(format!("'{}' is not off, nat-pmp, upnp or auto", s)),
+        }
+    }
+}
+
+// A port to map, with the key of its output for the logs and the router.
+pub struct Mapping {
+    pub name: String,
+    pub port: u16,
+}
+
+// The IPv4 default gateway, from the kernel's routing table.
+fn default_gateway() -> io::Result<Ipv4Addr> {
+    let routes = std::fs::read_to_string("/proc/net/route")?;
+    routes
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(1);
+            match (fields.next(), fields.next()) {
+                (Some("00000000"), Some(gateway)) => u32::from_str_radix(gateway, 16).ok(),
+                _ => None,
+            }
+        })
+        .find(|&gateway| gateway != 0)
+        // The table has addresses in host order, which is little endian
+        .map(|gateway| Ipv4Addr::from(gateway.swap_bytes()))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no default gateway"))
+}
+
+// Our address on the way to addr; nothing is sent.
+fn local_address(addr: SocketAddr) -> io::Result<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(addr)?;
+    match socket.local_addr()? {
+        SocketAddr::V4(local) if !local.ip().is_unspecified() => Ok(*local.ip()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            format!("no IPv4 address towards {}", addr),
+        )),
+    }
+}
+
+// Send a request to the gateway and wait for the answer to it, an opcode
+// with 128 added.
+fn nat_pmp_request(gateway: Ipv4Addr, request: &[u8]) -> io::Result<Vec<u8>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect((gateway, NAT_PMP_PORT))?;
+    let mut timeout = NAT_PMP_FIRST_TIMEOUT;
+    let mut buffer = [0u8; 16];
+    for _ in 0..NAT_PMP_TRIES {
+        socket.send(request)?;
+        socket.set_read_timeout(Some(timeout))?;
+        match socket.recv(&mut buffer) {
+            Ok(len) if len >= 8 && buffer[0] == 0 && buffer[1] == request[1] + 128 => {
+                return match u16::from_be_bytes([buffer[2], buffer[3]]) {
+                    0 => Ok(buffer[..len].to_vec()),
+                    result => Err(io::Error::other(format!(
+                        "NAT-PMP gateway {} refused with result {}",
+                        gateway, result
+                    ))),
+                };
+            }
+            Ok(_) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                timeout *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("no NAT-PMP answer from {}", gateway),
+    ))
+}
+
+// Map the port, returning the external address and the lease that was given.
+fn nat_pmp_map(port: u16) -> io::Result<(SocketAddr, Duration)> {
+    let gateway = default_gateway()?;
+    let answer = nat_pmp_request(gateway, &[0, 0])?;
+    let external = Ipv4Addr::new(answer[8], answer[9], answer[10], answer[11]);
+
+    let mut request = vec![0, NAT_PMP_MAP_TCP, 0, 0];
+    request.extend_from_slice(&port.to_be_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    request.extend_from_slice(&(LEASE.as_secs() as u32).to_be_bytes());
+    let answer = nat_pmp_request(gateway, &request)?;
+    if answer.len() < 16 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "short NAT-PMP answer",
+        ));
+    }
+    let mapped = u16::from_be_bytes([answer[10], answer[11]]);
+    let lease = u32::from_be_bytes([answer[12], answer[13], answer[14], answer[15]]);
+    Ok((
+        SocketAddr::V4(SocketAddrV4::new(external, mapped)),
+        Duration::from_secs(lease as u64),
+    ))
+}
+
+// The text of the first element with this name, enough for the small and
+// regular documents that routers give.
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = xml[start..].find(&format!("</{}>", name))? + start;
+    Some(xml[start..end].trim())
+}
+
+// The router's WAN connection service: its control URL and service type.
+fn upnp_discover() -> io::Result<(String, &'static str)> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(SSDP_TIMEOUT))?;
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}:{}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+        SSDP_GROUP, SSDP_PORT, IGD
+    );
+    socket.send_to(search.as_bytes(), (SSDP_GROUP, SSDP_PORT))?;
+    let mut buffer = [0u8; 2048];
+    let (len, _) = socket.recv_from(&mut buffer)?;
+    let answer = String::from_utf8_lossy(&buffer[..len]);
+    let location = answer
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(header, _)| header.trim().eq_ignore_ascii_case("location"))
+        .map(|(_, value)| value.trim().to_string())
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "SSDP answer without LOCATION")
+        })?;
+
+    let description = ureq::get(&location)
+        .timeout(HTTP_TIMEOUT)
+        .call()
+        .map_err(|e| io::Error::other(format!("{}: {}", location, e)))?
+        .into_string()?;
+    for service_type in WAN_SERVICES {
+        let Some(at) = description.find(&format!("<serviceType>{}</serviceType>", service_type))
+        else {
+            continue;
+        };
+        // The control URL follows the type within the same <service>
+        let service = &description[at..];
+        let service = &service[..service.find("</service>").unwrap_or(service.len())];
+        if let Some(control) = element(service, "controlURL") {
+            let url = if control.starts_with("http") {
+                control.to_string()
+            } else {
+                // Relative to the host of the description
+                let host_end = location
+                    .find("://")
+                    .and_then(|scheme| location[scheme + 3..].find('/').map(|p| p + scheme + 3))
+                    .unwrap_or(location.len());
+                format!(
+                    "{}/{}",
+                    &location[..host_end],
+                    control.trim_start_matches('/')
+                )
+            };
+            return Ok((url, service_type));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{}: no WAN connection service", location),
+    ))
+}
+
+fn soap(
+    url: &str,
+    service_type: &str,
+    action: &str,
+    arguments: &[(&str, String)],
+) -> io::Result<String> {
+    let arguments: String = arguments
+        .iter()
+        .map(|(name, value)| format!("<{}>{}</{}>", name, value, name))
+        .collect();
+    let body = format!(
+        "<?xml version=\"1.0\"?>\r\n<s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+         s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body>\
+         <u:{action} xmlns:u=\"{service_type}\">{arguments}</u:{action}></s:Body></s:Envelope>"
+    );
+    ureq::post(url)
+        .timeout(HTTP_TIMEOUT)
+        .set("Content-Type", "text/xml; charset=\"utf-8\"")
+        .set("SOAPAction", &format!("\"{}#{}\"", service_type, action))
+        .send_string(&body)
+        .map_err(|e| io::Error::other(format!("UPnP {}: {}", action, e)))?
+        .into_string()
+}
+
+fn upnp_map(mapping: &Mapping) -> io::Result<(SocketAddr, Duration)> {
+    let (url, service_type) = upnp_discover()?;
+    // The router is asked to forward to the address that we reach it from
+    let host = url
+        .split("://")
+        .nth(1)
+        .and_then(|rest| rest.split('/').next())
+        .unwrap_or_default();
+    let router: SocketAddr = std::net::ToSocketAddrs::to_socket_addrs(host)?
+        .next()
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("cannot resolve {}", host))
+        })?;
+    let local = local_address(router)?;
+    soap(
+        &url,
+        service_type,
+        "AddPortMapping",
+        &[
+            ("NewRemoteHost", String::new()),
+            ("NewExternalPort", mapping.port.to_string()),
+            ("NewProtocol", "TCP".to_string()),
+            ("NewInternalPort", mapping.port.to_string()),
+            ("NewInternalClient", local.to_string()),
+            ("NewEnabled", "1".to_string()),
+            (
+                "NewPortMappingDescription",
+                format!("ais-forwarder {}", mapping.name),
+            ),
+            ("NewLeaseDuration", LEASE.as_secs().to_string()),
+        ],
+    )?;
+    let external = soap(&url, service_type, "GetExternalIPAddress", &[])
+        .ok()
+        .and_then(|answer| element(&answer, "NewExternalIPAddress").and_then(|ip| ip.parse().ok()))
+        .unwrap_or(Ipv4Addr::UNSPECIFIED);
+    Ok((
+        SocketAddr::V4(SocketAddrV4::new(external, mapping.port)),
+        LEASE,
+    ))
+}
+
+fn map(method: Method, mapping: &Mapping) -> io::Result<(SocketAddr, Duration)> {
+    match method {
+        Method::Off => Err(io::Error::other("port mapping is off")),
+        Method::NatPmp => nat_pmp_map(mapping.port),
+        Method::Upnp => upnp_map(mapping),
+        Method::Auto => nat_pmp_map(mapping.port).or_else(|e| {
+            log::debug!("{}: {}, trying UPnP", mapping.name, e);
+            upnp_map(mapping)
+        }),
+    }
+}
+
+pub fn start(method: Method, mappings: Vec<Mapping>) -> io::Result<()> {
+    if method == Method::Off || mappings.is_empty() {
+        return Ok(());
+    }
+    Builder::new()
+        .name("portmap".to_string())
+        .spawn(move || {
+            loop {
+                // Renew all at half the shortest lease
+                let mut next = LEASE / 2;
+                for mapping in &mappings {
+                    let key = mapping.name.as_str();
+                    match map(method, mapping) {
+                        Ok((external, lease)) => {
+                            log::info!(endpoint = key; "{}: Router forwards {} to port {}", key, external, mapping.port);
+                            next = next.min(lease / 2);
+                        }
+                        Err(e) => {
+                            log::warn!(endpoint = key; "{}: Cannot map port {}: {}", key, mapping.port, e);
+                            next = next.min(RETRY_DELAY);
+                        }
+                    }
+                }
+                std::thread::sleep(next.max(Duration::from_secs(60)));
+            }
+        })?;
+    Ok(())
+}
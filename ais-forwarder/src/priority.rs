@@ -0,0 +1,67 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Niceness of the forwarder, so that it makes way for the other services on a
+// small boat router, or goes first when it is the one that matters. `nice` is
+// set for the whole process at startup, `io_nice` for the threads that move
+// AIS data: the provider reader, the dispatcher and the AIS senders.
+//
+// Going below the niceness we started with takes root, CAP_SYS_NICE or a nice
+// limit (LimitNICE= in systemd). The I/O threads set theirs after privileges
+// have been dropped, so a negative io_nice with --user needs one of the last
+// two.
+use std::io;
+
+pub const MIN_NICE: i32 = -20;
+pub const MAX_NICE: i32 = 19;
+
+// Set the niceness of the process. On Linux this is the niceness of the
+// calling thread, which the threads it starts afterwards inherit, so this is
+// done before any are started.
+#[cfg(unix)]
+pub fn set_process(nice: i32) -> io::Result<()> {
+    // SAFETY: plain system call, who 0 is ourselves
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn set_process(_nice: i32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "nice is not supported on this platform",
+    ))
+}
+
+// Set the niceness of the calling thread only.
+#[cfg(target_os = "linux")]
+fn set_thread(nice: i32) -> io::Result<()> {
+    // SAFETY: gettid cannot fail, and Linux takes a thread id as PRIO_PROCESS
+    let result = unsafe {
+        let tid = libc::gettid();
+        libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice)
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_thread(_nice: i32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "io_nice is not supported on this platform",
+    ))
+}
+
+// Called by an I/O thread when it starts. Failing to change it is not a
+// reason to stop forwarding.
+pub fn io_thread(name: &str, nice: Option<i32>) {
+    if let Some(nice) = nice {
+        match set_thread(nice) {
+            Ok(()) => log::debug!("{} thread runs at nice {}", name, nice),
+            Err(e) => log::warn!("Cannot set io_nice {} for {} thread: {}", nice, name, e),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Switch to an unprivileged user once everything that needs root, such as
+// binding low ports, has been done.
+use std::ffi::CString;
+use std::io;
+
+// Resolve user and group names (or numeric ids) to ids. Without a group the
+// user's primary group is used.
+fn lookup(user: Option<&str>, group: Option<&str>) -> io::Result<(Option<u32>, Option<u32>)> {
+    let mut uid = None;
+    let mut gid = None;
+    if let Some(user) = user {
+        let name = CString::new(user)?;
+        // SAFETY: getpwnam returns NULL or a pointer to a static entry, which we
+        // read before making any other passwd call.
+        let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+        if passwd.is_null() {
+            uid = Some(user.parse::<u32>().map_err(|_| {
+                io::Error::new(io::ErrorKind::NotFound, format!("Unknown user '{}'", user))
+            })?);
+        } else {
+            // SAFETY: checked for NULL above
+            let passwd = unsafe { &*passwd };
+            uid = Some(passwd.pw_uid);
+            gid = Some(passwd.pw_gid);
+        }
+    }
+    if let Some(group) = group {
+        let name = CString::new(group)?;
+        // SAFETY: as for getpwnam
+        let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+        if entry.is_null() {
+            gid = Some(group.parse::<u32>().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Unknown group '{}'", group),
+                )
+            })?);
+        } else {
+            // SAFETY: checked for NULL above
+            let entry = unsafe { &*entry };
+            gid = Some(entry.gr_gid);
+        }
+    }
+    Ok((uid, gid))
+}
+
+// Drop to the given user and group. Paths in `chown` are handed over to the
+// new user first, so that it can keep writing to them.
+pub fn drop_privileges(user: Option<&str>, group: Option<&str>, chown: &[&str]) -> io::Result<()> {
+    if user.is_none() && group.is_none() {
+        return Ok(());
+    }
+    let (uid, gid) = lookup(user, group)?;
+    // SAFETY: geteuid and getegid cannot fail
+    let (euid, egid) = unsafe { (libc::geteuid(), libc::getegid()) };
+    // Already running as them, for instance after a reload
+    if uid.is_none_or(|uid| uid == euid) && gid.is_none_or(|gid| gid == egid) {
+        return Ok(());
+    }
+    if euid != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "--user and --group need ais-forwarder to be started as root",
+        ));
+    }
+
+    for path in chown {
+        std::os::unix::fs::chown(path, uid, gid)?;
+    }
+    // SAFETY: plain system calls, the results are checked. In a multi-threaded
+    // process libc applies these to every thread.
+    unsafe {
+        if let Some(gid) = gid
+            && (libc::setgroups(1, &gid) != 0 || libc::setgid(gid) != 0)
+        {
+            return Err(io::Error::last_os_error());
+        }
+        if let Some(uid) = uid
+            && libc::setuid(uid) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    log::info!(
+        "Dropped privileges to uid {} gid {}",
+        uid.map_or("unchanged".to_string(), |uid| uid.to_string()),
+        gid.map_or("unchanged".to_string(), |gid| gid.to_string())
+    );
+    Ok(())
+}
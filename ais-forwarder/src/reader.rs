@@ -0,0 +1,157 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// The provider is read from its own thread, which hands what it reads to the
+// dispatcher over a bounded channel. Parsing, throttling and queueing for the
+// endpoints never keep us from draining the socket, so a burst does not
+// overrun the kernel buffer of a UDP provider. When the dispatcher does fall
+// that far behind, what it cannot take is dropped and counted here.
+//
+// With [providers] there is a thread for each, all on the same channel, and
+// every chunk says which provider it came from.
+use std::io;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::thread::Builder;
+use std::time::Duration;
+
+use common::{NetworkEndpoint, Protocol};
+
+use crate::EXIT_FAILURE;
+use crate::metrics::{DropReason, Metrics};
+use crate::priority;
+
+// Chunks, which are lines for TCP and datagrams for UDP, unless [queues]
+// provider says otherwise.
+pub const QUEUE_SIZE: usize = 1000;
+
+// The name of the provider in [general].
+pub const DEFAULT_NAME: &str = "provider";
+
+// Wait between attempts to read from a failing provider, doubling up to the
+// maximum while it keeps failing without delivering anything.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// How often a listening provider without clients is polled.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub enum Input {
+    // What the provider with this index read
+    Chunk(usize, Vec<u8>),
+    // Reading failed, there will be nothing for a while
+    Failed(usize),
+}
+
+pub struct Reader {
+    pub input: Receiver<Input>,
+    // The names of the providers, by index
+    pub names: Vec<String>,
+    // Only UDP can silently lose data, TCP retransmits. Loss is estimated
+    // from the sequence of the messages, which several providers mix up.
+    pub lossy: bool,
+}
+
+impl Reader {
+    // For the modes that feed the dispatcher themselves, as one provider.
+    pub fn fed(input: Receiver<Input>) -> Self {
+        Reader {
+            input,
+            names: vec![DEFAULT_NAME.to_string()],
+            lossy: false,
+        }
+    }
+}
+
+pub fn start(
+    providers: Vec<(String, NetworkEndpoint)>,
+    capacity: usize,
+    io_nice: Option<i32>,
+    metrics: Arc<Metrics>,
+) -> io::Result<Reader> {
+    let (tx, rx) = std::sync::mpsc::sync_channel(capacity);
+    let lossy = match providers.as_slice() {
+        [(_, provider)] => matches!(provider.protocol, Protocol::UDP | Protocol::UDPListen),
+        _ => false,
+    };
+    let mut names = Vec::with_capacity(providers.len());
+    for (index, (name, mut provider)) in providers.into_iter().enumerate() {
+        let tx = tx.clone();
+        let metrics = metrics.clone();
+        names.push(name.clone());
+        Builder::new().name("provider".to_string()).spawn(move || {
+            priority::io_thread("provider", io_nice);
+            read(index, &name, &mut provider, &tx, &metrics)
+        })?;
+    }
+    Ok(Reader {
+        input: rx,
+        names,
+        lossy,
+    })
+}
+
+// Read until the dispatcher is gone, retrying a failing provider forever.
+fn read(
+    index: usize,
+    name: &str,
+    provider: &mut NetworkEndpoint,
+    tx: &SyncSender<Input>,
+    metrics: &Metrics,
+) {
+    let provider_name = provider.to_string();
+    let mut buffer = Vec::new();
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        metrics.record_progress("provider");
+        metrics.set_provider_state(name, format!("reading from {}", provider_name));
+        let mut delivered = false;
+        let e = loop {
+            match provider.read(&mut buffer) {
+                Ok(message) => {
+                    metrics.record_provider_message(name);
+                    delivered = true;
+                    match tx.try_send(Input::Chunk(index, message.to_vec())) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(_)) => {
+                            log::warn!(provider = name; "Dispatcher cannot keep up, dropping data from {}", name);
+                            metrics.record_dropped(None, DropReason::DispatcherBehind);
+                        }
+                        Err(TrySendError::Disconnected(_)) => return,
+                    }
+                }
+                Err(e) => break e,
+            }
+        };
+        if matches!(
+            e.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ) {
+            // A quiet provider, or a listening one without clients
+            std::thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+        if tx.send(Input::Failed(index)).is_err() {
+            return;
+        }
+        if is_fatal(&e) {
+            log::error!(provider = name; "Cannot read from {} {}: {}", name, provider_name, e);
+            std::process::exit(EXIT_FAILURE);
+        }
+        if delivered {
+            backoff = MIN_BACKOFF;
+        }
+        log::error!(provider = name; "{}: {}, retrying in {}s", name, e, backoff.as_secs());
+        metrics.set_provider_state(name, format!("failing {}: {}", provider_name, e));
+        std::thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+// Errors that retrying will not fix, such as a provider address we may not bind.
+fn is_fatal(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::PermissionDenied
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::InvalidInput
+            | io::ErrorKind::Unsupported
+    )
+}
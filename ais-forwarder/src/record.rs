@@ -0,0 +1,52 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Record mode: read the provider and write every sentence with the time it
+// was received to a file, without forwarding anything. The file is a capture
+// file, as --capture writes, but with a sentence per line instead of a chunk,
+// so it reads well in a bug report; soak and replay take it as it is.
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::NetworkEndpoint;
+
+use crate::capture::Capture;
+use crate::metrics::Metrics;
+use crate::nmea;
+use crate::reader::{self, Input};
+
+// How often the duration is checked while the provider is quiet.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn run(provider: NetworkEndpoint, path: &str, duration: Option<Duration>) -> io::Result<()> {
+    let capture = Capture::new(path, false)?;
+    let name = provider.to_string();
+    let reader = reader::start(
+        vec![(reader::DEFAULT_NAME.to_string(), provider)],
+        reader::QUEUE_SIZE,
+        None,
+        Arc::new(Metrics::new()),
+    )?;
+    match duration {
+        Some(duration) => log::info!("Recording {} for {}s", name, duration.as_secs()),
+        None => log::info!("Recording {}, stop with Ctrl-C", name),
+    }
+
+    let end = duration.map(|duration| Instant::now() + duration);
+    let mut sentences = 0;
+    while end.is_none_or(|end| Instant::now() < end) {
+        match reader.input.recv_timeout(POLL_INTERVAL) {
+            Ok(Input::Chunk(_, chunk)) => {
+                for line in nmea::lines(&chunk) {
+                    capture.received("provider", format!("{}\r\n", line).as_bytes());
+                    sentences += 1;
+                }
+            }
+            // The reader logs why and tries again
+            Ok(Input::Failed(_)) => {}
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    log::info!("Recorded {} sentences to {}", sentences, path);
+    Ok(())
+}
@@ -0,0 +1,153 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// GPS repeater: our own position as $GPRMC and $GPGGA sentences to the [ais]
+// endpoints in [general] gps_endpoints, for devices on the network that want
+// a position but not AIS, such as a tablet with a chart app or an autopilot.
+// The sentences are made from the position that we use, smoothed when
+// smooth_position is on, with the speed and course of our own report, at most
+// once every INTERVAL. Whether it came from the GPS or the transponder, they
+// look the same. While the GPS has no good fix, see gps.rs, they say so, RMC
+// with status V and GGA with fix quality 0, so that an autopilot does not
+// steer by it.
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::nmea;
+
+const INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct GpsRepeater {
+    endpoints: Vec<String>,
+    last_sent: Option<Instant>,
+}
+
+impl GpsRepeater {
+    pub fn new(endpoints: Vec<String>) -> Self {
+        GpsRepeater {
+            endpoints,
+            last_sent: None,
+        }
+    }
+
+    pub fn wants(&self, name: &str) -> bool {
+        self.endpoints.iter().any(|endpoint| endpoint == name)
+    }
+
+    // Whether it is time for the next sentences, at most once every
+    // INTERVAL.
+    pub fn due(&mut self, now: Instant) -> bool {
+        if self
+            .last_sent
+            .is_some_and(|sent| now.duration_since(sent) < INTERVAL)
+        {
+            return false;
+        }
+        self.last_sent = Some(now);
+        true
+    }
+}
+
+// The RMC and GGA for our position.
+pub fn sentences(
+    time: DateTime<Utc>,
+    fix: bool,
+    latitude: f64,
+    longitude: f64,
+    sog: Option<f64>,
+    cog: Option<f64>,
+) -> Vec<u8> {
+    let position = position(latitude, longitude);
+    let optional = |value: Option<f64>| value.map_or(String::new(), |v| format!("{:.1}", v));
+    // Status and mode: A for autonomous, V and N for not valid
+    let (status, mode) = if fix { ('A', 'A') } else { ('V', 'N') };
+    let rmc = nmea::with_checksum(&format!(
+        "$GPRMC,{},{},{},{},{},{},,,{}",
+        time.format("%H%M%S.00"),
+        status,
+        position,
+        optional(sog),
+        optional(cog),
+        time.format("%d%m%y"),
+        mode,
+    ));
+    // Fix quality 1 for a GPS fix, 0 for none; the rest we do not know
+    let gga = nmea::with_checksum(&format!(
+        "$GPGGA,{},{},{},,,,M,,M,,",
+        time.format("%H%M%S.00"),
+        position,
+        u8::from(fix),
+    ));
+    nmea::group(&[rmc, gga])
+}
+
+// ddmm.mmmm,N,dddmm.mmmm,E
+fn position(latitude: f64, longitude: f64) -> String {
+    let degrees_minutes = |value: f64| {
+        let minutes = (value.abs() * 60.0 * 10_000.0).round() / 10_000.0;
+        ((minutes / 60.0).trunc(), minutes % 60.0)
+    };
+    let (lat, lat_minutes) = degrees_minutes(latitude);
+    let (long, long_minutes) = degrees_minutes(longitude);
+    format!(
+        "{:02}{:07.4},{},{:03}{:07.4},{}",
+        lat,
+        lat_minutes,
+        if latitude >= 0.0 { 'N' } else { 'S' },
+        long,
+        long_minutes,
+        if longitude >= 0.0 { 'E' } else { 'W' },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn position_north_east() {
+        assert_eq!(position(53.175, 5.4175), "5310.5000,N,00525.0500,E");
+    }
+
+    #[test]
+    fn position_south_west() {
+        assert_eq!(position(-33.8568, -151.2153), "3351.4080,S,15112.9180,W");
+    }
+
+    #[test]
+    fn minutes_round_up_to_the_next_degree() {
+        assert_eq!(
+            position(52.999_999_999, -4.999_999_999),
+            "5300.0000,N,00500.0000,W"
+        );
+    }
+
+    #[test]
+    fn sentences_with_and_without_fix() {
+        let time = Utc.with_ymd_and_hms(2025, 6, 1, 12, 34, 56).unwrap();
+        let with_fix = sentences(time, true, 53.175, 5.4175, Some(5.2), Some(270.0));
+        let lines: Vec<&str> = nmea::lines(&with_fix).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| nmea::checksum_ok(line)));
+        assert!(
+            lines[0]
+                .starts_with("$GPRMC,123456.00,A,5310.5000,N,00525.0500,E,5.2,270.0,010625,,,A*")
+        );
+        assert!(lines[1].starts_with("$GPGGA,123456.00,5310.5000,N,00525.0500,E,1,"));
+
+        let without_fix = sentences(time, false, 53.175, 5.4175, None, None);
+        let lines: Vec<&str> = nmea::lines(&without_fix).collect();
+        assert!(lines[0].starts_with("$GPRMC,123456.00,V,"));
+        assert!(lines[0].contains(",,,N*"));
+        assert!(lines[1].starts_with("$GPGGA,123456.00,5310.5000,N,00525.0500,E,0,"));
+    }
+
+    #[test]
+    fn at_most_once_every_interval() {
+        let mut repeater = GpsRepeater::new(vec!["tablet".to_string()]);
+        let now = Instant::now();
+        assert!(repeater.due(now));
+        assert!(!repeater.due(now + INTERVAL / 2));
+        assert!(repeater.due(now + INTERVAL));
+    }
+}
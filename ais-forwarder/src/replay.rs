@@ -0,0 +1,47 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Replay mode: play a capture, from record or --capture, into the provider of
+// a forwarder with the timing it was received with, so that its throttling,
+// movement detection and location updates go as they did live. At a higher
+// speed the traffic comes faster but the forwarder's intervals do not shrink
+// with it, so fewer messages get through its throttling.
+use std::io;
+use std::time::Instant;
+
+use crate::capture;
+use crate::feed::Feed;
+use crate::nmea;
+use crate::output::Registry;
+
+pub fn run(path: &str, address: &str, speed: f64, outputs: &Registry) -> io::Result<()> {
+    let chunks = capture::load(path)?;
+    let sentences: usize = chunks
+        .iter()
+        .map(|(_, data)| nmea::lines(data).count())
+        .sum();
+    if sentences == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No sentences in {}", path),
+        ));
+    }
+    let mut feed = Feed::new(address, outputs)?;
+    feed.wait_for_provider();
+    log::info!(
+        "Replaying {} sentences from {} to {}",
+        sentences,
+        path,
+        address
+    );
+
+    let start = Instant::now();
+    for (offset, data) in chunks {
+        capture::pace(start, offset, speed);
+        feed.send(&[data]);
+    }
+    log::info!(
+        "Replayed {} sentences in {:.0}s",
+        sentences,
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
@@ -0,0 +1,103 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Our own CPU time, memory, open files and threads, sampled now and then so
+// that the status page can show whether the forwarder is what keeps a small
+// router busy. Only Linux tells us this through /proc.
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Arc;
+use std::thread::Builder;
+use std::time::{Duration, Instant};
+
+use crate::metrics::Metrics;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Default)]
+pub struct Usage {
+    pub cpu_seconds: f64,
+    // Of one core, over the last sample interval
+    pub cpu_percent: f64,
+    pub resident: u64,
+    pub open_files: u64,
+    // Thread count per state letter from /proc, R running, S sleeping, D disk wait...
+    pub threads: BTreeMap<char, u64>,
+}
+
+pub fn start(metrics: Arc<Metrics>) -> io::Result<()> {
+    if sample().is_none() {
+        log::debug!("No resource usage available on this platform");
+        return Ok(());
+    }
+    Builder::new()
+        .name("resources".to_string())
+        .spawn(move || {
+            let mut previous: Option<(Instant, f64)> = None;
+            loop {
+                if let Some(mut usage) = sample() {
+                    let now = Instant::now();
+                    if let Some((when, cpu_seconds)) = previous {
+                        usage.cpu_percent = (usage.cpu_seconds - cpu_seconds)
+                            / now.duration_since(when).as_secs_f64()
+                            * 100.0;
+                    }
+                    previous = Some((now, usage.cpu_seconds));
+                    metrics.record_resources(usage);
+                }
+                std::thread::sleep(SAMPLE_INTERVAL);
+            }
+        })?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn sample() -> Option<Usage> {
+    // SAFETY: sysconf has no preconditions
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    let ticks = ticks.max(1) as f64;
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    let fields = stat_fields(&stat)?;
+    // utime and stime are fields 14 and 15, counted from pid as 1
+    let utime = fields.get(11)?.parse::<u64>().ok()?;
+    let stime = fields.get(12)?.parse::<u64>().ok()?;
+
+    let mut threads = BTreeMap::new();
+    for task in std::fs::read_dir("/proc/self/task").ok()?.flatten() {
+        if let Ok(stat) = std::fs::read_to_string(task.path().join("stat"))
+            && let Some(state) =
+                stat_fields(&stat).and_then(|fields| fields.first().and_then(|f| f.chars().next()))
+        {
+            *threads.entry(state).or_default() += 1;
+        }
+    }
+    Some(Usage {
+        cpu_seconds: (utime + stime) as f64 / ticks,
+        cpu_percent: 0.0,
+        resident: crate::memory::resident().unwrap_or(0),
+        open_files: std::fs::read_dir("/proc/self/fd").ok()?.count() as u64,
+        threads,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sample() -> Option<Usage> {
+    None
+}
+
+pub fn state_name(state: char) -> String {
+    match state {
+        'R' => "running".to_string(),
+        'S' => "sleeping".to_string(),
+        'D' => "disk wait".to_string(),
+        'T' | 't' => "stopped".to_string(),
+        'Z' => "zombie".to_string(),
+        other => other.to_string(),
+    }
+}
+
+// The fields of a /proc stat line after the command name, which is in
+// parentheses and may itself contain spaces. The first one is the state.
+#[cfg(target_os = "linux")]
+fn stat_fields(stat: &str) -> Option<Vec<&str>> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    Some(rest.split_whitespace().collect())
+}
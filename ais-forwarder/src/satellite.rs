@@ -0,0 +1,217 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Transport profiles for [ais] endpoints, from the [profile] section per
+// endpoint name or as default. The satellite profile is for a link that is
+// paid by the byte and often down, such as Iridium or a metered Starlink plan.
+// It wraps the sink of the endpoint and:
+//
+// - sends each other vessel every VESSEL_INTERVAL at most, our own vessel
+//   always;
+// - drops TAG blocks, which are as long as the sentence they come with;
+// - collects what is sent and writes it out every BATCH_INTERVAL, in writes
+//   of up to BATCH_BYTES, so that the modem wakes up for one burst, also when
+//   no more messages come;
+// - keeps up to STORE_BYTES while the link is down, dropping the oldest, and
+//   sends it when the link is back;
+// - stops for the rest of the UTC day once DAILY_BUDGET bytes went out.
+//
+// AIS services take plain NMEA 0183, so the sentences themselves are not
+// compressed. The budget starts again when the forwarder is restarted.
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::time::{Duration, Instant};
+
+use chrono::{NaiveDate, Utc};
+use common::sink::Sink;
+
+use crate::nmea;
+
+const VESSEL_INTERVAL: Duration = Duration::from_secs(600);
+const BATCH_INTERVAL: Duration = Duration::from_secs(300);
+// One datagram for a udp:// endpoint
+const BATCH_BYTES: usize = 1400;
+const STORE_BYTES: usize = 256 * 1024;
+const DAILY_BUDGET: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Profile {
+    #[default]
+    Default,
+    Satellite,
+}
+
+impl std::str::FromStr for Profile {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        match s {
+            "default" => Ok(Profile::Default),
+            "satellite" => Ok(Profile::Satellite),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid profile '{}', should be default or satellite", s),
+            )),
+        }
+    }
+}
+
+impl Profile {
+    pub fn wrap(&self, name: &str, sink: Box<dyn Sink>) -> Box<dyn Sink> {
+        match self {
+            Profile::Default => sink,
+            Profile::Satellite => Box::new(SatelliteSink {
+                name: name.to_string(),
+                inner: sink,
+                last_sent: HashMap::new(),
+                store: VecDeque::new(),
+                stored: 0,
+                batch_started: None,
+                day: Utc::now().date_naive(),
+                spent: 0,
+                healthy: true,
+            }),
+        }
+    }
+}
+
+struct SatelliteSink {
+    name: String,
+    inner: Box<dyn Sink>,
+    // When each other vessel was last let through
+    last_sent: HashMap<u32, Instant>,
+    // Messages waiting for the next batch, and their size
+    store: VecDeque<Vec<u8>>,
+    stored: usize,
+    batch_started: Option<Instant>,
+    // Bytes sent on this UTC day
+    day: NaiveDate,
+    spent: usize,
+    healthy: bool,
+}
+
+impl SatelliteSink {
+    // Whether another vessel was let through too recently.
+    fn throttled(&mut self, message: &[u8]) -> bool {
+        let Some(first) = nmea::lines(message).next() else {
+            return true;
+        };
+        if nmea::is_own_vessel(first) {
+            return false;
+        }
+        let Some(mmsi) = nmea::mmsi(first) else {
+            return false;
+        };
+        let now = Instant::now();
+        if self
+            .last_sent
+            .get(&mmsi)
+            .is_some_and(|sent| now.duration_since(*sent) < VESSEL_INTERVAL)
+        {
+            return true;
+        }
+        self.last_sent.insert(mmsi, now);
+        if self.last_sent.len() > 10_000 {
+            self.last_sent
+                .retain(|_, sent| now.duration_since(*sent) < VESSEL_INTERVAL);
+        }
+        false
+    }
+
+    // The sentences without their TAG blocks.
+    fn compact(message: &[u8]) -> Vec<u8> {
+        let lines: Vec<&str> = nmea::lines(message)
+            .map(|line| nmea::split_tag_block(line).1)
+            .collect();
+        nmea::group(&lines)
+    }
+
+    // Write out the stored messages in bursts of up to BATCH_BYTES, keeping
+    // what could not be sent for the next time.
+    fn send_batch(&mut self) -> io::Result<()> {
+        let today = Utc::now().date_naive();
+        if today != self.day {
+            self.day = today;
+            self.spent = 0;
+        }
+        self.batch_started = None;
+        while !self.store.is_empty() {
+            let mut burst = Vec::new();
+            let mut count = 0;
+            for message in self.store.iter() {
+                if !burst.is_empty() && burst.len() + message.len() > BATCH_BYTES {
+                    break;
+                }
+                burst.extend_from_slice(message);
+                count += 1;
+            }
+            if self.spent + burst.len() > DAILY_BUDGET {
+                // Warned about once a day
+                if self.spent < DAILY_BUDGET {
+                    log::warn!(endpoint = self.name.as_str(); "{}: Daily budget of {} kB spent, dropping messages until tomorrow", self.name, DAILY_BUDGET / 1024);
+                    self.spent = DAILY_BUDGET;
+                }
+                self.store.clear();
+                self.stored = 0;
+                return Ok(());
+            }
+            self.inner.send(&burst)?;
+            self.spent += burst.len();
+            self.stored -= burst.len();
+            self.store.drain(..count);
+        }
+        self.inner.flush()
+    }
+}
+
+impl Sink for SatelliteSink {
+    fn connect(&mut self) -> io::Result<()> {
+        self.inner.connect()
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        if self.throttled(message) {
+            return Ok(());
+        }
+        let message = Self::compact(message);
+        self.stored += message.len();
+        self.store.push_back(message);
+        while self.stored > STORE_BYTES
+            && let Some(oldest) = self.store.pop_front()
+        {
+            self.stored -= oldest.len();
+        }
+        self.batch_started.get_or_insert_with(Instant::now);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self
+            .batch_started
+            .is_none_or(|started| started.elapsed() < BATCH_INTERVAL)
+        {
+            return Ok(());
+        }
+        let result = self.send_batch();
+        if result.is_err() {
+            // Tried again with the next batch
+            self.batch_started = Some(Instant::now());
+        }
+        self.healthy = result.is_ok();
+        result
+    }
+
+    fn flush_due(&self) -> Option<Duration> {
+        self.batch_started
+            .map(|started| BATCH_INTERVAL.saturating_sub(started.elapsed()))
+    }
+
+    fn close(&mut self) {
+        if let Err(e) = self.send_batch() {
+            log::warn!(endpoint = self.name.as_str(); "{}: {} messages not sent: {}", self.name, self.store.len(), e);
+        }
+        self.inner.close();
+    }
+
+    fn healthy(&self) -> bool {
+        self.healthy && self.inner.healthy()
+    }
+}
@@ -0,0 +1,254 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// The decoded AIS message that outputs emit as JSON, such as an http:// endpoint
+// with ?format=json. It is ours rather than the parser's, so that the field
+// names stay the same when the parser changes, and it is versioned: every
+// report has "schema": SCHEMA_VERSION. Fields may be added within a version;
+// a field that is renamed, removed or changes meaning makes a new version.
+//
+// Version 1, fields other than schema, time, type and mmsi only when the
+// message has them:
+//
+//   schema       1
+//   time         when received, from the TAG block or else by us, RFC 3339
+//   type         ITU-R M.1371 message type, 1 to 27
+//   mmsi         the station that sent it
+//   kind         "A", "B", "base station", "AtoN" or "SAR aircraft"
+//   latitude     degrees, north positive
+//   longitude    degrees, east positive
+//   sog          knots
+//   cog          degrees true
+//   heading      degrees true
+//   nav_status   0 to 14, as in the message
+//   name, call_sign, destination
+//   ship_type    as in the message
+//   length, beam metres
+//
+// The reports are decoded from the payload bits rather than by the parser,
+// so that they come with passthrough as well, when the dispatcher does not
+// parse what it forwards.
+use std::time::SystemTime;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::nmea::{self, PayloadBits};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Kind {
+    #[serde(rename = "A")]
+    ClassA,
+    #[serde(rename = "B")]
+    ClassB,
+    #[serde(rename = "base station")]
+    BaseStation,
+    #[serde(rename = "AtoN")]
+    AidToNavigation,
+    #[serde(rename = "SAR aircraft")]
+    SarAircraft,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::ClassA => "A",
+            Kind::ClassB => "B",
+            Kind::BaseStation => "base station",
+            Kind::AidToNavigation => "AtoN",
+            Kind::SarAircraft => "SAR aircraft",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub schema: u32,
+    pub time: String,
+    #[serde(rename = "type")]
+    pub message_type: u8,
+    pub mmsi: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<Kind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sog: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cog: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nav_status: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_sign: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ship_type: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beam: Option<u16>,
+}
+
+impl Report {
+    // The sentences of an AIS message, all fragments in order. Messages of
+    // types not decoded here only have the header fields.
+    pub fn decode<S: AsRef<str>>(lines: &[S]) -> Option<Report> {
+        let payload = lines
+            .iter()
+            .map(|line| nmea::ais_payload(line.as_ref()))
+            .collect::<Option<String>>()?;
+        let bits = PayloadBits::new(&payload);
+        let message_type = bits.unsigned(0, 6)? as u8;
+        let mmsi = bits.unsigned(8, 30)? as u32;
+        let time = lines
+            .first()
+            .and_then(|line| nmea::tag_timestamp(line.as_ref()))
+            .unwrap_or_else(SystemTime::now);
+        let mut report = Report {
+            schema: SCHEMA_VERSION,
+            time: DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true),
+            message_type,
+            mmsi,
+            kind: None,
+            latitude: None,
+            longitude: None,
+            sog: None,
+            cog: None,
+            heading: None,
+            nav_status: None,
+            name: None,
+            call_sign: None,
+            destination: None,
+            ship_type: None,
+            length: None,
+            beam: None,
+        };
+        match message_type {
+            1..=3 => {
+                report.kind = Some(Kind::ClassA);
+                report.nav_status = bits
+                    .unsigned(38, 4)
+                    .filter(|&status| status != 15)
+                    .map(|status| status as u8);
+                report.set_motion(&bits, 50, 116, 128);
+                report.set_position(&bits, 61);
+            }
+            4 => {
+                report.kind = Some(Kind::BaseStation);
+                report.set_position(&bits, 79);
+            }
+            5 => {
+                report.kind = Some(Kind::ClassA);
+                report.call_sign = text(&bits, 70, 7);
+                report.name = text(&bits, 112, 20);
+                report.set_ship(&bits, 232, 240);
+                report.destination = text(&bits, 302, 20);
+            }
+            9 => {
+                report.kind = Some(Kind::SarAircraft);
+                // Whole knots
+                report.sog = bits
+                    .unsigned(50, 10)
+                    .filter(|&sog| sog < 1023)
+                    .map(|sog| sog as f64);
+                report.cog = bits
+                    .unsigned(116, 12)
+                    .filter(|&cog| cog < 3600)
+                    .map(|cog| cog as f64 / 10.0);
+                report.set_position(&bits, 61);
+            }
+            18 | 19 => {
+                report.kind = Some(Kind::ClassB);
+                report.set_motion(&bits, 46, 112, 124);
+                report.set_position(&bits, 57);
+                if message_type == 19 {
+                    report.name = text(&bits, 143, 20);
+                    report.set_ship(&bits, 263, 271);
+                }
+            }
+            21 => {
+                report.kind = Some(Kind::AidToNavigation);
+                report.name = text(&bits, 43, 20);
+                report.set_position(&bits, 164);
+            }
+            24 => {
+                report.kind = Some(Kind::ClassB);
+                match bits.unsigned(38, 2) {
+                    Some(0) => report.name = text(&bits, 40, 20),
+                    Some(1) => {
+                        report.call_sign = text(&bits, 90, 7);
+                        report.set_ship(&bits, 40, 132);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Some(report)
+    }
+
+    pub fn position(&self) -> Option<(f64, f64)> {
+        self.latitude.zip(self.longitude)
+    }
+
+    // Whether the message has speed and course, so that these not being
+    // there means not available rather than not sent.
+    pub fn has_motion(&self) -> bool {
+        matches!(self.message_type, 1..=3 | 9 | 18 | 19)
+    }
+
+    // Longitude and latitude in 1/10000 minutes, starting at bit start.
+    fn set_position(&mut self, bits: &PayloadBits, start: usize) {
+        let longitude = bits.signed(start, 28).map(|l| l as f64 / 600_000.0);
+        let latitude = bits.signed(start + 28, 27).map(|l| l as f64 / 600_000.0);
+        if let (Some(latitude), Some(longitude)) = (latitude, longitude)
+            && latitude.abs() <= 90.0
+            && longitude.abs() <= 180.0
+            && (latitude != 0.0 || longitude != 0.0)
+        {
+            self.latitude = Some(latitude);
+            self.longitude = Some(longitude);
+        }
+    }
+
+    // Speed in tenths of knots, course in tenths of degrees and the true
+    // heading, as class A and B reports have them.
+    fn set_motion(&mut self, bits: &PayloadBits, sog: usize, cog: usize, heading: usize) {
+        self.sog = bits
+            .unsigned(sog, 10)
+            .filter(|&sog| sog < 1023)
+            .map(|sog| sog as f64 / 10.0);
+        self.cog = bits
+            .unsigned(cog, 12)
+            .filter(|&cog| cog < 3600)
+            .map(|cog| cog as f64 / 10.0);
+        self.heading = bits
+            .unsigned(heading, 9)
+            .filter(|&heading| heading < 360)
+            .map(|heading| heading as u16);
+    }
+
+    // Ship type and the dimensions to bow, stern, port and starboard.
+    fn set_ship(&mut self, bits: &PayloadBits, ship_type: usize, dimensions: usize) {
+        self.ship_type = bits
+            .unsigned(ship_type, 8)
+            .filter(|&t| t != 0)
+            .map(|t| t as u8);
+        let dimension = |start, len| bits.unsigned(start, len).unwrap_or(0) as u16;
+        let length = dimension(dimensions, 9) + dimension(dimensions + 9, 9);
+        let beam = dimension(dimensions + 18, 6) + dimension(dimensions + 24, 6);
+        self.length = (length > 0).then_some(length);
+        self.beam = (beam > 0).then_some(beam);
+    }
+}
+
+fn text(bits: &PayloadBits, start: usize, chars: usize) -> Option<String> {
+    bits.text(start, chars).filter(|text| !text.is_empty())
+}
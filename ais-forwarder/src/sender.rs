@@ -0,0 +1,355 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Every [ais] endpoint is sent to from its own thread, fed through a bounded
+// queue, so that an aggregator that is slow or unreachable only holds up its
+// own messages. What is dropped when its queue is full is decided by the drop
+// policy of the endpoint, and counted.
+//
+// Multi-sentence messages are given sequential message IDs of the endpoint's
+// own, 0 to 9 in turn, rather than the ones they came in with: after the
+// filtering and throttling, those of two groups in flight may be the same,
+// and some decoders drop a group that reuses an ID.
+//
+// On the way out, drain() closes every queue and waits a while for the
+// threads to send what is queued, flush and close their sinks.
+use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{Builder, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use common::sink::Sink;
+
+use crate::Shared;
+use crate::audit::AuditLog;
+use crate::capture::Capture;
+use crate::metrics::{DropReason, Metrics};
+use crate::{nmea, priority};
+
+// About a minute of a busy port, and at most a few hundred kB per endpoint,
+// unless [queues] endpoint says otherwise.
+pub const QUEUE_SIZE: usize = 1000;
+
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// The sender threads that may still be running, for drain()
+static SENDERS: Mutex<Vec<(Arc<Queue>, JoinHandle<()>)>> = Mutex::new(Vec::new());
+
+// Cloned for every endpoint; the message itself is shared, not copied.
+#[derive(Clone)]
+pub struct Outgoing {
+    pub data: Arc<[u8]>,
+    pub source_time: Option<SystemTime>,
+    // Our own position, for the audit log; None for other vessels
+    pub own_position: Option<Option<(f64, f64)>>,
+    pub static_data: bool,
+}
+
+// What to drop when the queue of an endpoint is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DropPolicy {
+    // The new message, keeping what was queued first
+    #[default]
+    Newest,
+    // The oldest queued message, to keep the data fresh
+    Oldest,
+    // Other vessels before our own, oldest first
+    OthersFirst,
+    // Static data before positions, oldest first
+    StaticFirst,
+}
+
+impl std::str::FromStr for DropPolicy {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        match s {
+            "newest" => Ok(DropPolicy::Newest),
+            "oldest" => Ok(DropPolicy::Oldest),
+            "others-first" => Ok(DropPolicy::OthersFirst),
+            "static-first" => Ok(DropPolicy::StaticFirst),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid drop policy '{}', should be newest, oldest, others-first or static-first",
+                    s
+                ),
+            )),
+        }
+    }
+}
+
+impl DropPolicy {
+    // Whether the policy would rather lose this message than others.
+    fn prefers_to_drop(&self, outgoing: &Outgoing) -> bool {
+        match self {
+            DropPolicy::Newest | DropPolicy::Oldest => true,
+            DropPolicy::OthersFirst => outgoing.own_position.is_none(),
+            DropPolicy::StaticFirst => outgoing.static_data,
+        }
+    }
+
+    // Add outgoing to a full queue by dropping one message, which may be
+    // outgoing itself.
+    fn make_room(&self, queue: &mut VecDeque<Outgoing>, outgoing: Outgoing) {
+        if *self == DropPolicy::Newest {
+            return;
+        }
+        match queue.iter().position(|queued| self.prefers_to_drop(queued)) {
+            Some(victim) => {
+                queue.remove(victim);
+            }
+            // Nothing queued that we prefer to lose, then the new one goes,
+            // unless it is one we want to keep as well.
+            None if self.prefers_to_drop(&outgoing) => return,
+            None => {
+                queue.pop_front();
+            }
+        }
+        queue.push_back(outgoing);
+    }
+}
+
+struct Pending {
+    messages: VecDeque<Outgoing>,
+    closed: bool,
+}
+
+// A bounded queue from the dispatcher to one sender thread.
+struct Queue {
+    pending: Mutex<Pending>,
+    available: Condvar,
+    capacity: usize,
+}
+
+enum Next {
+    Message(Outgoing),
+    // Nothing came within the timeout
+    Idle,
+    // The endpoint is dropped and everything queued is sent
+    Closed,
+}
+
+impl Queue {
+    // Wait for the next message, for at most timeout if there is one.
+    fn next(&self, timeout: Option<Duration>) -> Next {
+        let mut pending = self.pending.lock().unwrap();
+        loop {
+            if let Some(outgoing) = pending.messages.pop_front() {
+                return Next::Message(outgoing);
+            }
+            if pending.closed {
+                return Next::Closed;
+            }
+            pending = match timeout {
+                None => self.available.wait(pending).unwrap(),
+                Some(timeout) => {
+                    let (pending, result) = self.available.wait_timeout(pending, timeout).unwrap();
+                    if result.timed_out() {
+                        return Next::Idle;
+                    }
+                    pending
+                }
+            };
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending.lock().unwrap().messages.is_empty()
+    }
+
+    // The sender thread sends what is queued and stops.
+    fn close(&self) {
+        self.pending.lock().unwrap().closed = true;
+        self.available.notify_all();
+    }
+}
+
+pub struct Endpoint {
+    name: String,
+    queue: Arc<Queue>,
+    policy: DropPolicy,
+    metrics: Arc<Metrics>,
+    providers: Option<HashSet<String>>,
+}
+
+impl Endpoint {
+    // Start the sender thread. It stops when the Endpoint is dropped.
+    pub fn start(
+        name: &str,
+        sink: Box<dyn Sink>,
+        policy: DropPolicy,
+        shared: &Shared,
+    ) -> io::Result<Self> {
+        let capacity = shared.capacities.endpoint;
+        let queue = Arc::new(Queue {
+            pending: Mutex::new(Pending {
+                messages: VecDeque::with_capacity(capacity),
+                closed: false,
+            }),
+            available: Condvar::new(),
+            capacity,
+        });
+        let sender = Sender {
+            name: name.to_string(),
+            sink,
+            metrics: shared.metrics.clone(),
+            capture: shared.capture.clone(),
+            audit: shared.audit.clone(),
+            io_nice: shared.io_nice,
+            sequence_id: 0,
+        };
+        let thread_queue = queue.clone();
+        let thread = Builder::new()
+            .name(format!("ais {}", name))
+            .spawn(move || sender.run(&thread_queue))?;
+        let mut senders = SENDERS.lock().unwrap();
+        senders.retain(|(_, thread)| !thread.is_finished());
+        senders.push((queue.clone(), thread));
+        Ok(Endpoint {
+            name: name.to_string(),
+            queue,
+            policy,
+            metrics: shared.metrics.clone(),
+            providers: None,
+        })
+    }
+
+    // Only send what these providers read, from a filter:provider=... step
+    // in the [pipeline] of the endpoint.
+    pub fn only_from(mut self, providers: Option<HashSet<String>>) -> Self {
+        self.providers = providers;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn wants(&self, provider: &str) -> bool {
+        self.providers
+            .as_ref()
+            .is_none_or(|providers| providers.contains(provider))
+    }
+
+    // Queue a message without ever waiting for the endpoint.
+    pub fn send(&self, outgoing: Outgoing) {
+        let mut pending = self.queue.pending.lock().unwrap();
+        if pending.messages.len() < self.queue.capacity {
+            pending.messages.push_back(outgoing);
+        } else {
+            self.policy.make_room(&mut pending.messages, outgoing);
+            log::warn!(endpoint = self.name.as_str(); "{}: Queue is full, dropping messages", self.name);
+            self.metrics
+                .record_dropped(Some(&self.name), DropReason::QueueFull);
+        }
+        self.queue.available.notify_one();
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+// Close the queues of all endpoints and wait until timeout for their sender
+// threads to send what is queued. The dispatcher must not be sending any more.
+pub fn drain(timeout: Duration) {
+    let senders = std::mem::take(&mut *SENDERS.lock().unwrap());
+    for (queue, _) in &senders {
+        queue.close();
+    }
+    let deadline = Instant::now() + timeout;
+    for (_, thread) in senders {
+        while !thread.is_finished() {
+            if Instant::now() >= deadline {
+                log::warn!(
+                    "{} did not finish sending in time",
+                    thread.thread().name().unwrap_or("An endpoint")
+                );
+                break;
+            }
+            std::thread::sleep(DRAIN_POLL_INTERVAL);
+        }
+        if thread.is_finished() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Sender {
+    name: String,
+    sink: Box<dyn Sink>,
+    metrics: Arc<Metrics>,
+    capture: Option<Arc<Capture>>,
+    audit: Option<Arc<AuditLog>>,
+    io_nice: Option<i32>,
+    // For the next multi-sentence message
+    sequence_id: u8,
+}
+
+impl Sender {
+    fn run(mut self, queue: &Queue) {
+        priority::io_thread(&format!("ais {}", self.name), self.io_nice);
+        // Connect before the first message, not while it waits
+        if let Err(e) = self.sink.connect() {
+            log::warn!(endpoint = self.name.as_str(); "{}", e);
+        }
+        self.metrics
+            .record_health("ais", &self.name, self.sink.healthy());
+        loop {
+            let mut outgoing = match queue.next(self.sink.flush_due()) {
+                Next::Message(outgoing) => outgoing,
+                // Time for what the sink holds back to go out
+                Next::Idle => {
+                    if let Err(e) = self.sink.flush() {
+                        log::warn!(endpoint = self.name.as_str(); "{}", e);
+                    }
+                    self.metrics
+                        .record_health("ais", &self.name, self.sink.healthy());
+                    continue;
+                }
+                Next::Closed => break,
+            };
+            if let Some(data) = nmea::with_sequence_id(&outgoing.data, self.sequence_id) {
+                outgoing.data = data.into();
+                self.sequence_id = (self.sequence_id + 1) % 10;
+            }
+            let start = Instant::now();
+            let mut result = self.sink.send(&outgoing.data);
+            // Anything the sink buffers goes out once we have caught up
+            if result.is_ok() && queue.is_empty() {
+                result = self.sink.flush();
+            }
+            self.metrics.record_send(
+                "ais",
+                &self.name,
+                outgoing.data.len(),
+                start.elapsed(),
+                &result,
+            );
+            self.metrics
+                .record_health("ais", &self.name, self.sink.healthy());
+            if let Err(e) = result {
+                log::warn!(endpoint = self.name.as_str(); "{}", e);
+                continue;
+            }
+            if let Some(age) = outgoing.source_time.and_then(|time| time.elapsed().ok()) {
+                self.metrics.record_age("ais", &self.name, age);
+            }
+            if let Some(capture) = &self.capture {
+                capture.sent("ais", &self.name, &outgoing.data);
+            }
+            if let Some(audit) = &self.audit
+                && let Some(position) = outgoing.own_position
+            {
+                audit.record("ais", &self.name, position, &outgoing.data);
+            }
+        }
+        if let Err(e) = self.sink.flush() {
+            log::warn!(endpoint = self.name.as_str(); "{}", e);
+        }
+        self.sink.close();
+        log::debug!(endpoint = self.name.as_str(); "{}: Sender thread stopped", self.name);
+    }
+}
@@ -0,0 +1,102 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Handle SIGTERM and SIGINT (or a Windows service stop or Ctrl-C) without
+// being killed halfway a write. The dispatcher holds the busy lock while it
+// handles a chunk from the provider, so once we own it no more messages are
+// queued for the AIS endpoints. Their sender threads then get the chance to
+// send what is queued, and the location thread to store what it still has,
+// and we leave.
+use std::io;
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::location::LocationMessage;
+use crate::sender;
+
+const LOCATION_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+const ENDPOINT_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct State {
+    busy: Arc<Mutex<()>>,
+    location_tx: SyncSender<LocationMessage>,
+    remove_on_exit: Vec<String>,
+}
+
+static STATE: OnceLock<State> = OnceLock::new();
+
+pub fn start(
+    busy: Arc<Mutex<()>>,
+    location_tx: SyncSender<LocationMessage>,
+    remove_on_exit: Vec<String>,
+) -> io::Result<()> {
+    let _ = STATE.set(State {
+        busy,
+        location_tx,
+        remove_on_exit,
+    });
+
+    #[cfg(unix)]
+    {
+        use signal_hook::consts::{SIGINT, SIGTERM};
+        use signal_hook::iterator::Signals;
+
+        let mut signals = Signals::new([SIGTERM, SIGINT])?;
+        std::thread::Builder::new()
+            .name("shutdown".to_string())
+            .spawn(move || {
+                if let Some(signal) = signals.forever().next() {
+                    log::info!("Received signal {}, shutting down", signal);
+                    graceful();
+                    std::process::exit(0);
+                }
+            })?;
+    }
+    #[cfg(windows)]
+    crate::windows::handle_console_ctrl()?;
+    Ok(())
+}
+
+// Shut down and start again in this process, with the same arguments, so that
+// the configuration is read again. Only returns when that fails.
+#[cfg(unix)]
+pub fn reload() -> ! {
+    use std::os::unix::process::CommandExt;
+
+    log::info!("Reloading");
+    graceful();
+    // argv[0] rather than current_exe(), to pick up an upgraded binary
+    let mut args = std::env::args_os();
+    let program = args.next().unwrap_or_default();
+    let e = std::process::Command::new(&program).args(args).exec();
+    log::error!("Cannot restart {}: {}", program.to_string_lossy(), e);
+    log::logger().flush();
+    std::process::exit(crate::EXIT_FAILURE);
+}
+
+// Stop sending and store what is pending. The caller exits the process.
+pub fn graceful() {
+    let Some(state) = STATE.get() else {
+        return;
+    };
+    // A poisoned lock still means the dispatcher is not sending. The guard is
+    // kept, we are on our way out.
+    std::mem::forget(state.busy.lock());
+
+    sender::drain(ENDPOINT_DRAIN_TIMEOUT);
+
+    let (ack_tx, ack_rx) = mpsc::channel();
+    if state
+        .location_tx
+        .send(LocationMessage::Shutdown(ack_tx))
+        .is_ok()
+        && ack_rx.recv_timeout(LOCATION_FLUSH_TIMEOUT).is_err()
+    {
+        log::warn!("Location thread did not finish in time");
+    }
+
+    for path in &state.remove_on_exit {
+        let _ = std::fs::remove_file(path);
+    }
+    log::info!("Shutdown complete");
+    log::logger().flush();
+}
@@ -0,0 +1,340 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Simulate mode: generate a scene around a position, our own vessel sailing a
+// square around it and other vessels crossing it, as the sentences that a
+// receiver would give, so that the forwarder can be tried without one. Class
+// A vessels report every 10 seconds and class B every 30, both with their
+// static data every 6 minutes; our own GPS gives an RMC every second and the
+// transponder a VDO every 10.
+//
+// The address is the other end of the provider, see feed.rs.
+use std::io;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Timelike, Utc};
+
+use crate::feed::Feed;
+use crate::geo;
+use crate::nmea::{self, PayloadWriter};
+use crate::output::Registry;
+
+const TICK: Duration = Duration::from_secs(1);
+// In ticks
+const CLASS_A_INTERVAL: u64 = 10;
+const CLASS_B_INTERVAL: u64 = 30;
+const STATIC_INTERVAL: u64 = 360;
+const OWN_VDO_INTERVAL: u64 = 10;
+
+// Nautical miles from the centre: other vessels turn back beyond the scene,
+// ours sails a square with its corners this far north or south and east or
+// west
+const SCENE_RADIUS: f64 = 5.0;
+const ROUTE_RADIUS: f64 = 1.0;
+const OWN_SPEED: f64 = 6.0;
+// Metres from a waypoint at which we head for the next one
+const WAYPOINT_REACHED: f64 = 50.0;
+
+pub struct Options {
+    pub targets: usize,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub mmsi: u32,
+    pub seed: u64,
+}
+
+struct Vessel {
+    mmsi: u32,
+    name: String,
+    class_b: bool,
+    ship_type: u8,
+    length: u64,
+    beam: u64,
+    latitude: f64,
+    longitude: f64,
+    // Knots and degrees
+    sog: f64,
+    cog: f64,
+}
+
+impl Vessel {
+    fn sail(&mut self, seconds: f64) {
+        // A minute of latitude is a nautical mile
+        let distance = self.sog * seconds / 3600.0 / 60.0;
+        let course = self.cog.to_radians();
+        self.latitude += distance * course.cos();
+        self.longitude += distance * course.sin() / self.latitude.to_radians().cos();
+    }
+
+    fn call_sign(&self) -> String {
+        format!("SIM{}", self.mmsi % 10_000)
+    }
+
+    // Message 1 for class A and our own transponder, 18 for class B.
+    fn position_report(&self, second: u32) -> PayloadWriter {
+        let mut w = PayloadWriter::default();
+        let sog = (self.sog * 10.0).round() as u64;
+        let longitude = (self.longitude * 600_000.0).round() as i64;
+        let latitude = (self.latitude * 600_000.0).round() as i64;
+        let cog = (self.cog * 10.0).round() as u64 % 3600;
+        let heading = self.cog.round() as u64 % 360;
+        if self.class_b {
+            w.unsigned(18, 6)
+                .unsigned(0, 2)
+                .unsigned(self.mmsi as u64, 30)
+                .unsigned(0, 8)
+                .unsigned(sog, 10)
+                .unsigned(0, 1)
+                .signed(longitude, 28)
+                .signed(latitude, 27)
+                .unsigned(cog, 12)
+                .unsigned(heading, 9)
+                .unsigned(second as u64, 6)
+                .unsigned(0, 2)
+                // Carrier sense unit, no display or DSC, whole band
+                .unsigned(1, 1)
+                .unsigned(0, 2)
+                .unsigned(1, 1)
+                .unsigned(0, 3)
+                .unsigned(0, 20);
+        } else {
+            // Under way or at anchor
+            let nav_status = if self.sog < 0.1 { 1 } else { 0 };
+            w.unsigned(1, 6)
+                .unsigned(0, 2)
+                .unsigned(self.mmsi as u64, 30)
+                .unsigned(nav_status, 4)
+                // Rate of turn not available
+                .signed(-128, 8)
+                .unsigned(sog, 10)
+                .unsigned(0, 1)
+                .signed(longitude, 28)
+                .signed(latitude, 27)
+                .unsigned(cog, 12)
+                .unsigned(heading, 9)
+                .unsigned(second as u64, 6)
+                .unsigned(0, 6)
+                .unsigned(0, 19);
+        }
+        w
+    }
+
+    // Message 5 for class A, 24 parts A and B for class B.
+    fn static_reports(&self) -> Vec<PayloadWriter> {
+        let bow = self.length * 2 / 3;
+        let port = self.beam / 2;
+        if self.class_b {
+            let mut a = PayloadWriter::default();
+            a.unsigned(24, 6)
+                .unsigned(0, 2)
+                .unsigned(self.mmsi as u64, 30)
+                .unsigned(0, 2)
+                .text(&self.name, 20)
+                .unsigned(0, 8);
+            let mut b = PayloadWriter::default();
+            b.unsigned(24, 6)
+                .unsigned(0, 2)
+                .unsigned(self.mmsi as u64, 30)
+                .unsigned(1, 2)
+                .unsigned(self.ship_type as u64, 8)
+                .text("SIM", 3)
+                .unsigned(0, 24)
+                .text(&self.call_sign(), 7)
+                .unsigned(bow, 9)
+                .unsigned(self.length - bow, 9)
+                .unsigned(port, 6)
+                .unsigned(self.beam - port, 6)
+                .unsigned(0, 6);
+            vec![a, b]
+        } else {
+            let mut w = PayloadWriter::default();
+            w.unsigned(5, 6)
+                .unsigned(0, 2)
+                .unsigned(self.mmsi as u64, 30)
+                .unsigned(0, 2)
+                .unsigned(0, 30)
+                .text(&self.call_sign(), 7)
+                .text(&self.name, 20)
+                .unsigned(self.ship_type as u64, 8)
+                .unsigned(bow, 9)
+                .unsigned(self.length - bow, 9)
+                .unsigned(port, 6)
+                .unsigned(self.beam - port, 6)
+                // GPS, no ETA
+                .unsigned(1, 4)
+                .unsigned(0, 4)
+                .unsigned(0, 5)
+                .unsigned(24, 5)
+                .unsigned(60, 6)
+                .unsigned(self.length / 20 + 10, 8)
+                .text("HARLINGEN", 20)
+                .unsigned(0, 2);
+            vec![w]
+        }
+    }
+
+    fn rmc(&self, now: DateTime<Utc>) -> String {
+        let degrees_minutes = |value: f64| (value.abs().trunc(), value.abs().fract() * 60.0);
+        let (lat, lat_minutes) = degrees_minutes(self.latitude);
+        let (long, long_minutes) = degrees_minutes(self.longitude);
+        nmea::with_checksum(&format!(
+            "$GPRMC,{},A,{:02}{:07.4},{},{:03}{:07.4},{},{:.1},{:.1},{},,,A",
+            now.format("%H%M%S.00"),
+            lat,
+            lat_minutes,
+            if self.latitude >= 0.0 { 'N' } else { 'S' },
+            long,
+            long_minutes,
+            if self.longitude >= 0.0 { 'E' } else { 'W' },
+            self.sog,
+            self.cog,
+            now.format("%d%m%y"),
+        ))
+    }
+}
+
+// Xorshift, so that a seed always gives the same scene.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Random((seed ^ 0x9E37_79B9_7F4A_7C15).max(1))
+    }
+
+    // Uniform in [low, high)
+    fn range(&mut self, low: f64, high: f64) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        low + (high - low) * (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+pub fn run(address: &str, options: &Options, outputs: &Registry) -> io::Result<()> {
+    let mut feed = Feed::new(address, outputs)?;
+    let mut random = Random::new(options.seed);
+    let (centre_lat, centre_long) = (options.latitude, options.longitude);
+    // Degrees per nautical mile
+    let lat_mile = 1.0 / 60.0;
+    let long_mile = lat_mile / centre_lat.to_radians().cos();
+
+    let mut own = Vessel {
+        mmsi: options.mmsi,
+        name: "OWN VESSEL".to_string(),
+        class_b: false,
+        ship_type: 37,
+        length: 12,
+        beam: 4,
+        latitude: centre_lat - ROUTE_RADIUS * lat_mile,
+        longitude: centre_long - ROUTE_RADIUS * long_mile,
+        sog: OWN_SPEED,
+        cog: 0.0,
+    };
+    let route = [(1.0, -1.0), (1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0)].map(|(north, east)| {
+        (
+            centre_lat + north * ROUTE_RADIUS * lat_mile,
+            centre_long + east * ROUTE_RADIUS * long_mile,
+        )
+    });
+    let mut waypoint = 0;
+
+    let mut targets: Vec<Vessel> = (0..options.targets)
+        .map(|i| {
+            let class_b = random.range(0.0, 1.0) < 0.25;
+            let (ship_type, length, beam) = match (class_b, i % 3) {
+                (true, 0) => (37, 10, 3),
+                (true, _) => (36, 12, 4),
+                (false, 0) => (60, 80, 14),
+                (false, 1) => (70, 120, 18),
+                (false, _) => (80, 180, 30),
+            };
+            Vessel {
+                mmsi: 244_100_000 + i as u32,
+                name: format!("SIM TARGET {}", i + 1),
+                class_b,
+                ship_type,
+                length,
+                beam,
+                latitude: centre_lat + random.range(-SCENE_RADIUS, SCENE_RADIUS) * lat_mile,
+                longitude: centre_long + random.range(-SCENE_RADIUS, SCENE_RADIUS) * long_mile,
+                // Some lie at anchor
+                sog: if random.range(0.0, 1.0) < 0.2 {
+                    0.0
+                } else {
+                    random.range(2.0, 15.0)
+                },
+                cog: random.range(0.0, 360.0),
+            }
+        })
+        .collect();
+
+    log::info!(
+        "Simulating {} vessels around {:.4}, {:.4} to {}",
+        targets.len(),
+        centre_lat,
+        centre_long,
+        address
+    );
+    let start = Instant::now();
+    let mut sequence_id = 0;
+    let mut tick = 0u64;
+    loop {
+        let now = Utc::now();
+        let second = now.second();
+
+        let (wp_lat, wp_long) = route[waypoint];
+        if geo::distance(own.latitude, own.longitude, wp_lat, wp_long) < WAYPOINT_REACHED {
+            waypoint = (waypoint + 1) % route.len();
+        }
+        let (wp_lat, wp_long) = route[waypoint];
+        own.cog = ((wp_long - own.longitude) / long_mile)
+            .atan2((wp_lat - own.latitude) / lat_mile)
+            .to_degrees()
+            .rem_euclid(360.0);
+        own.sail(TICK.as_secs_f64());
+        for target in targets.iter_mut() {
+            target.sail(TICK.as_secs_f64());
+            let distance =
+                geo::distance(centre_lat, centre_long, target.latitude, target.longitude);
+            if distance > SCENE_RADIUS * 1852.0 {
+                // Head back to the centre, give or take
+                target.cog = ((centre_long - target.longitude) / long_mile)
+                    .atan2((centre_lat - target.latitude) / lat_mile)
+                    .to_degrees()
+                    .rem_euclid(360.0);
+            }
+        }
+
+        let mut messages = vec![nmea::group(&[own.rmc(now)])];
+        let mut add = |talker: &str, payload: &PayloadWriter| {
+            let (payload, fill) = payload.finish();
+            let sentences = nmea::ais_sentences(talker, &payload, fill, sequence_id);
+            if sentences.len() > 1 {
+                sequence_id = (sequence_id + 1) % 10;
+            }
+            messages.push(nmea::group(&sentences));
+        };
+        if tick.is_multiple_of(OWN_VDO_INTERVAL) {
+            add("AIVDO", &own.position_report(second));
+        }
+        for (i, target) in targets.iter().enumerate() {
+            let interval = if target.class_b {
+                CLASS_B_INTERVAL
+            } else {
+                CLASS_A_INTERVAL
+            };
+            // Spread over the interval, as the slots of a real channel
+            if tick % interval == i as u64 % interval {
+                add("AIVDM", &target.position_report(second));
+            }
+            if tick % STATIC_INTERVAL == i as u64 % STATIC_INTERVAL {
+                for report in target.static_reports() {
+                    add("AIVDM", &report);
+                }
+            }
+        }
+        feed.send(&messages);
+
+        let next = start + TICK * (tick + 1) as u32;
+        std::thread::sleep(next.saturating_duration_since(Instant::now()));
+        tick += 1;
+    }
+}
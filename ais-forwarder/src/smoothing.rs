@@ -0,0 +1,132 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Smoothing of our own position, with [general] smooth_position. In a marina
+// the GPS signal bounces off masts and buildings, and single positions jump
+// tens of metres away and back; we would seem to be moving, and the jumps add
+// up in the track and the logbook. Each position is compared with the median
+// of the last few; one that is further from it than MAX_JUMP plus what we can
+// sail in the meantime is left out. What is used instead of a position is
+// the median of the last few including it, which takes a few seconds to
+// follow when we really move.
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::geo;
+
+const WINDOW: usize = 5;
+// Metres
+const MAX_JUMP: f64 = 50.0;
+// Metres per second, some 30 knots
+const MAX_SPEED: f64 = 15.0;
+// This many left out in a row is where we are, such as after the GPS was off
+const MAX_REJECTED: usize = 5;
+
+#[derive(Default)]
+pub struct PositionFilter {
+    samples: VecDeque<(Instant, f64, f64)>,
+    rejected: usize,
+}
+
+impl PositionFilter {
+    // The smoothed position, None when this one is left out.
+    pub fn update(&mut self, now: Instant, latitude: f64, longitude: f64) -> Option<(f64, f64)> {
+        if let Some((median_latitude, median_longitude)) = self.median()
+            && let Some((last, _, _)) = self.samples.back()
+        {
+            let jump = geo::distance(median_latitude, median_longitude, latitude, longitude);
+            if jump > MAX_JUMP + MAX_SPEED * now.duration_since(*last).as_secs_f64() {
+                self.rejected += 1;
+                if self.rejected < MAX_REJECTED {
+                    log::debug!("Leaving out our position {:.0} m away", jump);
+                    return None;
+                }
+                log::info!(
+                    "Our position is {:.0} m from where it was, starting over",
+                    jump
+                );
+                self.samples.clear();
+            }
+        }
+        self.rejected = 0;
+        self.samples.push_back((now, latitude, longitude));
+        if self.samples.len() > WINDOW {
+            self.samples.pop_front();
+        }
+        self.median()
+    }
+
+    // Of the latitudes and of the longitudes, each on its own.
+    fn median(&self) -> Option<(f64, f64)> {
+        let median = |mut values: Vec<f64>| {
+            values.sort_by(f64::total_cmp);
+            values.get(values.len() / 2).copied()
+        };
+        Some((
+            median(
+                self.samples
+                    .iter()
+                    .map(|(_, latitude, _)| *latitude)
+                    .collect(),
+            )?,
+            median(
+                self.samples
+                    .iter()
+                    .map(|(_, _, longitude)| *longitude)
+                    .collect(),
+            )?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // Harlingen, and a thousandth of a degree is some 111 m north
+    const LATITUDE: f64 = 53.175;
+    const LONGITUDE: f64 = 5.41;
+
+    #[test]
+    fn median_of_the_last_few() {
+        let start = Instant::now();
+        let mut filter = PositionFilter::default();
+        let mut smoothed = None;
+        for (i, offset) in [0.0, 0.0001, -0.0001, 0.0002, 0.0].iter().enumerate() {
+            let now = start + Duration::from_secs(i as u64);
+            smoothed = filter.update(now, LATITUDE + offset, LONGITUDE);
+            assert!(smoothed.is_some());
+        }
+        assert_eq!(smoothed, Some((LATITUDE, LONGITUDE)));
+    }
+
+    #[test]
+    fn jump_is_left_out() {
+        let start = Instant::now();
+        let mut filter = PositionFilter::default();
+        for i in 0..3 {
+            filter.update(start + Duration::from_secs(i), LATITUDE, LONGITUDE);
+        }
+        let now = start + Duration::from_secs(3);
+        assert_eq!(filter.update(now, LATITUDE + 0.001, LONGITUDE), None);
+        // Sailing away at some 5 knots is not a jump
+        let now = start + Duration::from_secs(60);
+        assert!(filter.update(now, LATITUDE + 0.0015, LONGITUDE).is_some());
+    }
+
+    #[test]
+    fn starts_over_after_too_many_jumps() {
+        let start = Instant::now();
+        let mut filter = PositionFilter::default();
+        filter.update(start, LATITUDE, LONGITUDE);
+        let elsewhere = LATITUDE + 0.01;
+        for i in 1..MAX_REJECTED as u64 {
+            let now = start + Duration::from_secs(i);
+            assert_eq!(filter.update(now, elsewhere, LONGITUDE), None);
+        }
+        let now = start + Duration::from_secs(MAX_REJECTED as u64);
+        assert_eq!(
+            filter.update(now, elsewhere, LONGITUDE),
+            Some((elsewhere, LONGITUDE))
+        );
+    }
+}
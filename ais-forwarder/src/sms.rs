@@ -0,0 +1,182 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// SMS fallback: when the [location] endpoints have not been reachable for
+// `after` seconds, our position goes out as a text message every `interval`
+// seconds, so that the people ashore still know where we are when there is
+// no internet, only a mobile signal. From the [sms] section:
+//
+//     to = +31612345678
+//     gateway = http://192.168.1.1/cgi-bin/sms_send?number={to}&text={text}
+//     modem = /dev/ttyUSB2        instead of gateway, a GSM modem on a tty
+//     after = 1800                seconds, half an hour when not given
+//     interval = 3600             seconds, an hour when not given
+//
+// The gateway is an HTTP GET, as the SMS APIs of mobile routers are, with
+// {to} and {text} replaced. A modem is sent AT commands in text mode; it is
+// opened for each message, so it can be shared with the router's own
+// connection manager when it has a spare AT port.
+//
+// The text is plain ASCII, so that it fits in one SMS:
+//
+//     HOLLANDIA 244123456 53.17500N 5.41700E 6.2kn 245T 12:34Z
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::modem::Modem;
+
+const DEFAULT_AFTER: u64 = 1800;
+const DEFAULT_INTERVAL: u64 = 3600;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+// Sending a message over the air takes the modem a while
+const SEND_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+enum Via {
+    Gateway(String),
+    Modem(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Sms {
+    to: String,
+    via: Via,
+    after: Duration,
+    interval: Duration,
+    // Since when the location endpoints fail, and when we last sent a text
+    down_since: Option<Instant>,
+    last_sent: Option<Instant>,
+}
+
+impl Sms {
+    pub fn from_config(section: &HashMap<String, String>) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let seconds = |key: &str, default: u64| -> io::Result<Duration> {
+            match section.get(key).map(|value| value.trim().parse::<u64>()) {
+                None => Ok(Duration::from_secs(default)),
+                Some(Ok(seconds)) => Ok(Duration::from_secs(seconds)),
+                Some(Err(e)) => Err(invalid(format!("Invalid {}: {}", key, e))),
+            }
+        };
+        let to = section
+            .get("to")
+            .map(|to| to.trim().to_string())
+            .filter(|to| !to.is_empty())
+            .ok_or_else(|| invalid("Missing to".to_string()))?;
+        let via = match (section.get("gateway"), section.get("modem")) {
+            (Some(url), None) => Via::Gateway(url.trim().to_string()),
+            (None, Some(device)) => Via::Modem(device.trim().to_string()),
+            _ => return Err(invalid("Set either gateway or modem".to_string())),
+        };
+        if let Via::Gateway(url) = &via
+            && !url.contains("{text}")
+        {
+            return Err(invalid(format!("Gateway '{}' has no {{text}}", url)));
+        }
+        Ok(Sms {
+            to,
+            via,
+            after: seconds("after", DEFAULT_AFTER)?,
+            interval: seconds("interval", DEFAULT_INTERVAL)?,
+            down_since: None,
+            last_sent: None,
+        })
+    }
+
+    // Called with each own position and whether the location endpoints took
+    // it, true when a text is due.
+    pub fn due(&mut self, connection_ok: bool) -> bool {
+        let now = Instant::now();
+        if connection_ok {
+            if self.down_since.take().is_some() && self.last_sent.take().is_some() {
+                log::info!("Location endpoints reachable again, no more SMS");
+            }
+            return false;
+        }
+        let down_since = *self.down_since.get_or_insert(now);
+        now.duration_since(down_since) >= self.after
+            && self
+                .last_sent
+                .is_none_or(|sent| now.duration_since(sent) >= self.interval)
+    }
+
+    // Not tried again before the interval is over, also when it failed.
+    pub fn send(&mut self, text: &str) {
+        self.last_sent = Some(Instant::now());
+        let result = match &self.via {
+            Via::Gateway(url) => send_gateway(url, &self.to, text),
+            Via::Modem(device) => send_modem(device, &self.to, text),
+        };
+        match &result {
+            Ok(()) => log::info!("Sent position by SMS to {}: {}", self.to, text),
+            Err(e) => log::warn!("Cannot send position by SMS to {}: {}", self.to, e),
+        }
+    }
+}
+
+// The text for a position, `who` being our name and MMSI.
+pub fn text(
+    who: &str,
+    latitude: f64,
+    longitude: f64,
+    sog: Option<f64>,
+    cog: Option<f64>,
+    time: DateTime<Utc>,
+) -> String {
+    let mut text = format!(
+        "{} {:.5}{} {:.5}{}",
+        who,
+        latitude.abs(),
+        if latitude >= 0.0 { "N" } else { "S" },
+        longitude.abs(),
+        if longitude >= 0.0 { "E" } else { "W" },
+    );
+    if let Some(sog) = sog {
+        text.push_str(&format!(" {:.1}kn", sog));
+    }
+    if let Some(cog) = cog {
+        text.push_str(&format!(" {:.0}T", cog));
+    }
+    text.push_str(&time.format(" %H:%MZ").to_string());
+    text
+}
+
+fn send_gateway(url: &str, to: &str, text: &str) -> io::Result<()> {
+    let url = url
+        .replace("{to}", &encode(to))
+        .replace("{text}", &encode(text));
+    ureq::get(&url)
+        .timeout(HTTP_TIMEOUT)
+        .call()
+        .map(|_| ())
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+// Percent encoding for a query value.
+fn encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn send_modem(device: &str, to: &str, text: &str) -> io::Result<()> {
+    let mut modem = Modem::open(device)?;
+    modem.command("AT", "OK", COMMAND_TIMEOUT)?;
+    modem.command("AT+CMGF=1", "OK", COMMAND_TIMEOUT)?;
+    modem.command(&format!("AT+CMGS=\"{}\"", to), ">", COMMAND_TIMEOUT)?;
+    // The text ends with Ctrl-Z, after which the modem answers with the
+    // number of the message, rather than OK that could be in the echoed text
+    modem.write(text.as_bytes())?;
+    modem.write(&[0x1a])?;
+    modem.expect("+CMGS:", SEND_TIMEOUT)?;
+    Ok(())
+}
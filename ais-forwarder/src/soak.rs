@@ -0,0 +1,131 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Soak mode: replay a capture of a busy port through the dispatcher, as fast
+// as possible or at a multiple of the recorded speed, into AIS endpoints that
+// throw away what they get, and report how many sentences per second were
+// handled. A Pi-class device should sustain at least TARGET.
+//
+// The capture is one made with --capture or record; lines that are not
+// capture records are taken to be plain NMEA sentences, paced by their TAG
+// block timestamps if they have them.
+use std::io;
+use std::thread::Builder;
+use std::time::{Duration, Instant};
+
+use common::sink::Sink;
+
+use crate::capture;
+use crate::clock::ClockSource;
+use crate::location::{self, LocationMessage};
+use crate::nmea;
+use crate::reader::{self, Input, Reader};
+use crate::sender::{DropPolicy, Endpoint};
+use crate::{Dispatcher, Shared};
+
+// Sentences per second
+pub const TARGET: f64 = 10_000.0;
+
+pub struct Options {
+    pub speed: f64,
+    pub endpoints: usize,
+    pub interval: u64,
+    pub passthrough: bool,
+    pub clock: ClockSource,
+}
+
+// An AIS endpoint that is always up and infinitely fast.
+struct NullSink;
+
+impl Sink for NullSink {
+    fn connect(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn send(&mut self, _message: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn close(&mut self) {}
+
+    fn healthy(&self) -> bool {
+        true
+    }
+}
+
+pub fn run(path: &str, options: &Options) -> io::Result<()> {
+    let chunks = capture::load(path)?;
+    let sentences: usize = chunks
+        .iter()
+        .map(|(_, data)| nmea::lines(data).count())
+        .sum();
+    if sentences == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No sentences in {}", path),
+        ));
+    }
+    log::info!(
+        "Replaying {} sentences from {} into {} endpoints",
+        sentences,
+        path,
+        options.endpoints
+    );
+
+    // We take the place of the provider reader, and the end of the feed ends
+    // the work of the dispatcher.
+    let mut shared = Shared::standalone(options.passthrough)?;
+    shared.clock = options.clock;
+    let metrics = shared.metrics.clone();
+    let (feed, input) = std::sync::mpsc::sync_channel(reader::QUEUE_SIZE);
+    let ais = (0..options.endpoints)
+        .map(|i| {
+            Endpoint::start(
+                &format!("null{}", i),
+                Box::new(NullSink),
+                DropPolicy::default(),
+                &shared,
+            )
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    // Our own position goes nowhere
+    let (tx, rx) = std::sync::mpsc::sync_channel::<LocationMessage>(location::QUEUE_SIZE);
+    Builder::new()
+        .name("soak location".to_string())
+        .spawn(move || for _ in rx {})?;
+    let reader = Reader::fed(input);
+    let mut dispatcher = Dispatcher::new(reader, ais, tx, options.interval, 600, 86400, &shared);
+
+    let speed = options.speed;
+    let feed_metrics = metrics.clone();
+    Builder::new()
+        .name("soak feed".to_string())
+        .spawn(move || {
+            let start = Instant::now();
+            for (offset, data) in chunks {
+                capture::pace(start, offset, speed);
+                feed_metrics.record_provider_message(reader::DEFAULT_NAME);
+                if feed.send(Input::Chunk(0, data)).is_err() {
+                    return;
+                }
+            }
+        })?;
+
+    let start = Instant::now();
+    let _ = dispatcher.work();
+    let elapsed = start.elapsed();
+    // Let the senders catch up before their counts are shown
+    drop(dispatcher);
+    std::thread::sleep(Duration::from_millis(100));
+
+    let rate = sentences as f64 / elapsed.as_secs_f64();
+    print!("{}", metrics.render_status());
+    println!(
+        "\n{} sentences in {:.2}s, {:.0} sentences per second",
+        sentences,
+        elapsed.as_secs_f64(),
+        rate
+    );
+    if speed == 0.0 && rate < TARGET {
+        log::warn!("Below the target of {:.0} sentences per second", TARGET);
+    }
+    Ok(())
+}
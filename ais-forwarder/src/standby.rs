@@ -0,0 +1,158 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Hot standby for a pair of stations that watch the same provider. Both run
+// all the time and tell each other over UDP, every second, their priority and
+// whether their provider is delivering data. Only the active one forwards:
+//
+// - without word from the peer for `timeout` seconds we are active,
+// - otherwise a station whose provider works wins from one whose doesn't,
+// - and when both are equally healthy the higher priority wins.
+//
+// Equal priorities make both active; sending twice is better than not at all.
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::Builder;
+use std::time::{Duration, Instant};
+
+use crate::metrics::Metrics;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+const MAGIC: &str = "ais-forwarder-standby";
+
+pub struct Standby {
+    active: AtomicBool,
+}
+
+impl Standby {
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+}
+
+struct Peer {
+    priority: u32,
+    healthy: bool,
+    seen: Instant,
+}
+
+// Start the heartbeat thread when the [standby] section has a peer.
+pub fn start(
+    section: &HashMap<String, String>,
+    metrics: Arc<Metrics>,
+) -> io::Result<Option<Arc<Standby>>> {
+    let Some(peer_address) = section.get("peer").cloned() else {
+        return Ok(None);
+    };
+    let listen = match section.get("listen").map(|v| v.parse::<SocketAddr>()) {
+        Some(Ok(listen)) => listen,
+        Some(Err(e)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid listen: {}", e),
+            ));
+        }
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Missing listen address",
+            ));
+        }
+    };
+    let priority = parse(section, "priority", 100)?;
+    let timeout = Duration::from_secs(u64::from(parse(section, "timeout", 10)?.max(3)));
+
+    let socket = UdpSocket::bind(listen)?;
+    socket.set_read_timeout(Some(HEARTBEAT_INTERVAL))?;
+    log::info!(
+        "Standby with priority {}, peer {}, listening on {}",
+        priority,
+        peer_address,
+        listen
+    );
+
+    let standby = Arc::new(Standby {
+        active: AtomicBool::new(false),
+    });
+    let thread_standby = standby.clone();
+    metrics.set_standby_state(Some("standby, waiting for peer".to_string()));
+    Builder::new().name("standby".to_string()).spawn(move || {
+        let started = Instant::now();
+        let mut peer: Option<Peer> = None;
+        let mut last_sent = Instant::now() - HEARTBEAT_INTERVAL;
+        let mut buffer = [0u8; 128];
+        loop {
+            let healthy = metrics.provider_idle() < timeout;
+            if last_sent.elapsed() >= HEARTBEAT_INTERVAL {
+                last_sent = Instant::now();
+                let heartbeat = format!("{} {} {}\n", MAGIC, priority, healthy as u8);
+                // Resolved every time, the peer may come and go from DNS
+                if let Err(e) = peer_address
+                    .to_socket_addrs()
+                    .and_then(|mut addrs| {
+                        addrs.next().ok_or_else(|| {
+                            io::Error::new(io::ErrorKind::NotFound, "No address found")
+                        })
+                    })
+                    .and_then(|addr| socket.send_to(heartbeat.as_bytes(), addr))
+                {
+                    log::debug!("Cannot send heartbeat to {}: {}", peer_address, e);
+                }
+            }
+            if let Ok((len, _)) = socket.recv_from(&mut buffer)
+                && let Some((peer_priority, peer_healthy)) =
+                    parse_heartbeat(&String::from_utf8_lossy(&buffer[..len]))
+            {
+                peer = Some(Peer {
+                    priority: peer_priority,
+                    healthy: peer_healthy,
+                    seen: Instant::now(),
+                });
+            }
+
+            let (active, state) = match &peer {
+                Some(peer) if peer.seen.elapsed() < timeout => {
+                    let active = match (healthy, peer.healthy) {
+                        (true, false) => true,
+                        (false, true) => false,
+                        _ => priority >= peer.priority,
+                    };
+                    let role = if active { "active" } else { "standby" };
+                    (active, format!("{}, peer priority {}", role, peer.priority))
+                }
+                // Give the peer a chance to speak up before taking over at startup
+                None if started.elapsed() < timeout => {
+                    (false, "standby, waiting for peer".to_string())
+                }
+                _ => (true, "active, peer is silent".to_string()),
+            };
+            if active != thread_standby.active.swap(active, Ordering::Relaxed) {
+                log::warn!("Standby: now {}", state);
+            }
+            metrics.set_standby_state(Some(state));
+        }
+    })?;
+    Ok(Some(standby))
+}
+
+fn parse(section: &HashMap<String, String>, key: &str, default: u32) -> io::Result<u32> {
+    match section.get(key).map(|v| v.parse::<u32>()) {
+        None => Ok(default),
+        Some(Ok(value)) => Ok(value),
+        Some(Err(e)) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid {}: {}", key, e),
+        )),
+    }
+}
+
+fn parse_heartbeat(heartbeat: &str) -> Option<(u32, bool)> {
+    let mut fields = heartbeat.split_whitespace();
+    if fields.next()? != MAGIC {
+        return None;
+    }
+    let priority = fields.next()?.parse().ok()?;
+    let healthy = fields.next()? == "1";
+    Some((priority, healthy))
+}
@@ -0,0 +1,95 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Talk to systemd when we run as a Type=notify service: report that we are
+// ready, keep the status line current and keep the watchdog fed for as long
+// as data keeps coming in from the provider. When the provider read loop
+// hangs the watchdog expires and systemd restarts us.
+use std::io;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::sync::Arc;
+use std::thread::Builder;
+use std::time::{Duration, Instant};
+
+use crate::metrics::Metrics;
+
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+}
+
+impl Notifier {
+    fn from_env() -> io::Result<Option<Self>> {
+        let path = match std::env::var("NOTIFY_SOCKET") {
+            Ok(path) => path,
+            Err(_) => return Ok(None),
+        };
+        let addr = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(&path)?,
+        };
+        Ok(Some(Notifier {
+            socket: UnixDatagram::unbound()?,
+            addr,
+        }))
+    }
+
+    fn send(&self, state: &str) {
+        if let Err(e) = self.socket.send_to_addr(state.as_bytes(), &self.addr) {
+            log::debug!("Cannot notify systemd: {}", e);
+        }
+    }
+}
+
+// Half the watchdog timeout, as systemd recommends.
+fn watchdog_interval() -> Option<Duration> {
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+    Some(Duration::from_micros(usec / 2))
+}
+
+pub fn start(metrics: Arc<Metrics>) -> io::Result<()> {
+    let notifier = match Notifier::from_env()? {
+        Some(notifier) => notifier,
+        None => return Ok(()),
+    };
+    notifier.send("READY=1");
+    let watchdog = watchdog_interval();
+    log::info!(
+        "Notifying systemd, watchdog {}",
+        watchdog.map_or("off".to_string(), |w| format!("every {}s", w.as_secs()))
+    );
+
+    let interval = watchdog.map_or(STATUS_INTERVAL, |w| w.min(STATUS_INTERVAL));
+    Builder::new().name("systemd".to_string()).spawn(move || {
+        let mut last_count = metrics.provider_messages();
+        let mut last_time = Instant::now();
+        loop {
+            std::thread::sleep(interval);
+            let count = metrics.provider_messages();
+            let rate = (count - last_count) as f64 / last_time.elapsed().as_secs_f64();
+            last_count = count;
+            last_time = Instant::now();
+
+            let mut state = format!(
+                "STATUS=Provider {}, {:.1} messages/s",
+                metrics.provider_state(),
+                rate
+            );
+            // Stop feeding the watchdog once the provider has been silent for
+            // a whole watchdog period.
+            if let Some(watchdog) = watchdog
+                && metrics.provider_idle() < watchdog * 2
+            {
+                state.push_str("\nWATCHDOG=1");
+            }
+            notifier.send(&state);
+        }
+    })?;
+    Ok(())
+}
@@ -0,0 +1,425 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// The vessels, aids to navigation and base stations around us, as last heard,
+// for the [http] status server to show as GeoJSON on /targets.geojson, which
+// Leaflet, OpenLayers and the like put on a map as they are, and as a table
+// on /vessels for a phone on the boat's WiFi. Where each has been over the
+// last day is kept as well, for /track/<mmsi>.
+//
+// The reports are decoded by schema.rs from the payload bits rather than taken
+// from the parser, so that targets show with passthrough as well.
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde_json::{Map, Value, json};
+
+use crate::geo;
+use crate::metrics::format_duration;
+use crate::schema::Report;
+
+// A target that has not been heard from for this long is gone; plotters call
+// it lost after about as long.
+const TARGET_EXPIRY: Duration = Duration::from_secs(600);
+// A coastal station with a good antenna hears a few thousand.
+const MAX_TARGETS: usize = 5000;
+// Tracks have a point every TRACK_INTERVAL at most, and only when the target
+// moved, going back TRACK_HISTORY. A day of a vessel under way is 17 kB.
+const TRACK_INTERVAL: u32 = 60;
+const TRACK_HISTORY: u32 = 24 * 3600;
+// Seconds between reloads of the vessel page
+const PAGE_REFRESH: u64 = 30;
+
+// A target on the vessel page, with its range in metres and bearing in
+// degrees from us.
+type Row<'a> = (u32, &'a Target, Option<(f64, f64)>);
+
+struct Target {
+    kind: &'static str,
+    // Degrees, knots and degrees
+    position: Option<(f64, f64)>,
+    sog: Option<f64>,
+    cog: Option<f64>,
+    heading: Option<u16>,
+    nav_status: Option<u8>,
+    name: Option<String>,
+    call_sign: Option<String>,
+    ship_type: Option<u8>,
+    destination: Option<String>,
+    // Metres
+    length: Option<u16>,
+    beam: Option<u16>,
+    heard: Instant,
+}
+
+impl Target {
+    fn new() -> Self {
+        Target {
+            kind: "",
+            position: None,
+            sog: None,
+            cog: None,
+            heading: None,
+            nav_status: None,
+            name: None,
+            call_sign: None,
+            ship_type: None,
+            destination: None,
+            length: None,
+            beam: None,
+            heard: Instant::now(),
+        }
+    }
+
+    // Take in what a report has, keeping what was heard before that it does
+    // not have.
+    fn merge(&mut self, report: Report) {
+        if let Some(kind) = report.kind {
+            self.kind = kind.name();
+        }
+        if let Some(position) = report.position() {
+            self.position = Some(position);
+        }
+        if report.has_motion() {
+            self.sog = report.sog;
+            self.cog = report.cog;
+            self.heading = report.heading;
+        }
+        if matches!(report.message_type, 1..=3) {
+            self.nav_status = report.nav_status;
+        }
+        for (field, value) in [
+            (&mut self.name, report.name),
+            (&mut self.call_sign, report.call_sign),
+            (&mut self.destination, report.destination),
+        ] {
+            if value.is_some() {
+                *field = value;
+            }
+        }
+        if report.ship_type.is_some() {
+            self.ship_type = report.ship_type;
+        }
+        if report.length.is_some() || report.beam.is_some() {
+            self.length = report.length;
+            self.beam = report.beam;
+        }
+    }
+
+    // The ship type as text, from the first digit mostly (ITU-R M.1371
+    // table 53), or what kind of station it is.
+    fn type_name(&self) -> &'static str {
+        match self.ship_type {
+            Some(20..=29) => "Wing in ground",
+            Some(30) => "Fishing",
+            Some(31 | 32) => "Towing",
+            Some(33) => "Dredging",
+            Some(34) => "Diving",
+            Some(35) => "Military",
+            Some(36) => "Sailing",
+            Some(37) => "Pleasure craft",
+            Some(40..=49) => "High speed craft",
+            Some(50) => "Pilot",
+            Some(51) => "Search and rescue",
+            Some(52) => "Tug",
+            Some(53) => "Port tender",
+            Some(54) => "Anti-pollution",
+            Some(55) => "Law enforcement",
+            Some(58) => "Medical transport",
+            Some(60..=69) => "Passenger",
+            Some(70..=79) => "Cargo",
+            Some(80..=89) => "Tanker",
+            _ => match self.kind {
+                "AtoN" => "Aid to navigation",
+                "base station" => "Base station",
+                "SAR aircraft" => "SAR aircraft",
+                _ => "Vessel",
+            },
+        }
+    }
+
+    fn feature(&self, mmsi: u32) -> Option<Value> {
+        let (latitude, longitude) = self.position?;
+        let mut properties = Map::new();
+        properties.insert("mmsi".to_string(), json!(mmsi));
+        properties.insert("kind".to_string(), json!(self.kind));
+        let mut add = |name: &str, value: Value| {
+            if !value.is_null() {
+                properties.insert(name.to_string(), value);
+            }
+        };
+        add("name", json!(self.name));
+        add("call_sign", json!(self.call_sign));
+        add("ship_type", json!(self.ship_type));
+        add("destination", json!(self.destination));
+        add("length", json!(self.length));
+        add("beam", json!(self.beam));
+        add("sog", json!(self.sog));
+        add("cog", json!(self.cog));
+        add("heading", json!(self.heading));
+        add("nav_status", json!(self.nav_status));
+        add("age", json!(self.heard.elapsed().as_secs()));
+        Some(json!({
+            "type": "Feature",
+            "id": mmsi,
+            "geometry": {
+                "type": "Point",
+                "coordinates": [longitude, latitude],
+            },
+            "properties": properties,
+        }))
+    }
+}
+
+// A point of a track, in seconds since 1970 and degrees. Single precision is
+// a metre or so, and halves the memory.
+struct TrackPoint {
+    time: u32,
+    latitude: f32,
+    longitude: f32,
+}
+
+#[derive(Default)]
+struct Track {
+    points: VecDeque<TrackPoint>,
+}
+
+impl Track {
+    fn add(&mut self, time: u32, (latitude, longitude): (f64, f64)) {
+        if self
+            .points
+            .back()
+            .is_some_and(|last| time < last.time + TRACK_INTERVAL)
+        {
+            return;
+        }
+        self.points.push_back(TrackPoint {
+            time,
+            latitude: latitude as f32,
+            longitude: longitude as f32,
+        });
+        self.prune(time);
+    }
+
+    fn prune(&mut self, now: u32) {
+        while self
+            .points
+            .front()
+            .is_some_and(|first| first.time + TRACK_HISTORY < now)
+        {
+            self.points.pop_front();
+        }
+    }
+}
+
+fn unix_time() -> u32 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as u32
+}
+
+pub struct Targets {
+    targets: Mutex<HashMap<u32, Target>>,
+    tracks: Mutex<HashMap<u32, Track>>,
+}
+
+impl Targets {
+    pub fn new() -> Self {
+        Targets {
+            targets: Mutex::new(HashMap::new()),
+            tracks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Take in the sentences of an AIS message from another vessel.
+    pub fn update<S: AsRef<str>>(&self, lines: &[S]) {
+        let Some(report) = Report::decode(lines).filter(|report| report.kind.is_some()) else {
+            return;
+        };
+        let mmsi = report.mmsi;
+        let mut targets = self.targets.lock().unwrap();
+        if targets.len() >= MAX_TARGETS && !targets.contains_key(&mmsi) {
+            targets.retain(|_, target| target.heard.elapsed() < TARGET_EXPIRY);
+            if targets.len() >= MAX_TARGETS {
+                return;
+            }
+        }
+        let target = targets.entry(mmsi).or_insert_with(Target::new);
+        target.heard = Instant::now();
+        let before = target.position;
+        target.merge(report);
+        if let Some(position) = target.position
+            && target.position != before
+        {
+            drop(targets);
+            self.add_to_track(mmsi, position);
+        }
+    }
+
+    fn add_to_track(&self, mmsi: u32, position: (f64, f64)) {
+        let now = unix_time();
+        let mut tracks = self.tracks.lock().unwrap();
+        if tracks.len() >= MAX_TARGETS && !tracks.contains_key(&mmsi) {
+            tracks.retain(|_, track| {
+                track.prune(now);
+                !track.points.is_empty()
+            });
+            if tracks.len() >= MAX_TARGETS {
+                return;
+            }
+        }
+        tracks.entry(mmsi).or_default().add(now, position);
+    }
+
+    // Forget them all when memory is short; they are heard again soon enough.
+    pub fn clear(&self) {
+        *self.targets.lock().unwrap() = HashMap::new();
+        *self.tracks.lock().unwrap() = HashMap::new();
+    }
+
+    // Where a target has been in the last hours, as a GeoJSON LineString
+    // Feature with the time of each point in coordTimes, or None when it was
+    // not seen moving.
+    pub fn track(&self, mmsi: u32, hours: u32) -> Option<Value> {
+        let since = unix_time().saturating_sub(hours.saturating_mul(3600));
+        let tracks = self.tracks.lock().unwrap();
+        let points: Vec<&TrackPoint> = tracks
+            .get(&mmsi)?
+            .points
+            .iter()
+            .filter(|point| point.time >= since)
+            .collect();
+        if points.is_empty() {
+            return None;
+        }
+        let coordinates: Vec<Value> = points
+            .iter()
+            // To the metre, rather than all the digits of the f32
+            .map(|point| {
+                let degrees = |value: f32| (value as f64 * 1e5).round() / 1e5;
+                json!([degrees(point.longitude), degrees(point.latitude)])
+            })
+            .collect();
+        let times: Vec<String> = points
+            .iter()
+            .filter_map(|point| chrono::DateTime::from_timestamp(point.time as i64, 0))
+            .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+            .collect();
+        drop(tracks);
+        // A line needs two points
+        let geometry = match coordinates.len() {
+            1 => json!({ "type": "Point", "coordinates": coordinates[0] }),
+            _ => json!({ "type": "LineString", "coordinates": coordinates }),
+        };
+        let name = self
+            .targets
+            .lock()
+            .unwrap()
+            .get(&mmsi)
+            .and_then(|target| target.name.clone());
+        Some(json!({
+            "type": "Feature",
+            "id": mmsi,
+            "geometry": geometry,
+            "properties": {
+                "mmsi": mmsi,
+                "name": name,
+                "coordTimes": times,
+            },
+        }))
+    }
+
+    // A table of the targets, the nearest first when we know where we are.
+    pub fn html(&self, own_position: Option<(f64, f64)>, voyage: &[(&str, String)]) -> String {
+        let mut targets = self.targets.lock().unwrap();
+        targets.retain(|_, target| target.heard.elapsed() < TARGET_EXPIRY);
+        let mut rows: Vec<Row> = targets
+            .iter()
+            .map(|(mmsi, target)| {
+                let relative =
+                    own_position
+                        .zip(target.position)
+                        .map(|((own_lat, own_lon), (lat, lon))| {
+                            (
+                                geo::distance(own_lat, own_lon, lat, lon),
+                                geo::bearing(own_lat, own_lon, lat, lon),
+                            )
+                        });
+                (*mmsi, target, relative)
+            })
+            .collect();
+        rows.sort_by(|a, b| {
+            let range = |row: &Row| row.2.map(|(range, _)| range);
+            range(a)
+                .unwrap_or(f64::MAX)
+                .total_cmp(&range(b).unwrap_or(f64::MAX))
+                .then(a.1.heard.cmp(&b.1.heard).reverse())
+        });
+
+        let mut page = String::new();
+        let _ = write!(
+            page,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+             <meta http-equiv=\"refresh\" content=\"{}\"><title>Vessels</title>\
+             <style>body{{font-family:sans-serif;margin:0.5em}}\
+             table{{border-collapse:collapse;width:100%}}\
+             th,td{{padding:0.3em;border-bottom:1px solid #ccc;text-align:left}}\
+             td.n{{text-align:right}}</style></head><body>\n",
+            PAGE_REFRESH,
+        );
+        for (day, stats) in voyage {
+            let _ = writeln!(page, "<p>{}: {}</p>", day, escape_html(stats));
+        }
+        let _ = write!(
+            page,
+            "<h1>Vessels ({})</h1>\n<table>\n<tr><th>Name</th><th>MMSI</th><th>Type</th>\
+             <th>Range nm</th><th>Bearing</th><th>Age</th></tr>\n",
+            rows.len()
+        );
+        for (mmsi, target, relative) in rows {
+            let (range, bearing) = match relative {
+                Some((range, bearing)) => (
+                    format!("{:.1}", range / 1852.0),
+                    format!("{:03.0}°", bearing.round() % 360.0),
+                ),
+                None => (String::new(), String::new()),
+            };
+            let _ = writeln!(
+                page,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"n\">{}</td>\
+                 <td class=\"n\">{}</td><td class=\"n\">{}</td></tr>",
+                escape_html(target.name.as_deref().unwrap_or("")),
+                mmsi,
+                target.type_name(),
+                range,
+                bearing,
+                format_duration(target.heard.elapsed())
+            );
+        }
+        page.push_str("</table>\n</body></html>\n");
+        page
+    }
+
+    // The targets with a position as a GeoJSON FeatureCollection of points.
+    pub fn geojson(&self) -> Value {
+        let mut targets = self.targets.lock().unwrap();
+        targets.retain(|_, target| target.heard.elapsed() < TARGET_EXPIRY);
+        let features: Vec<Value> = targets
+            .iter()
+            .filter_map(|(mmsi, target)| target.feature(*mmsi))
+            .collect();
+        json!({
+            "type": "FeatureCollection",
+            "features": features,
+        })
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,143 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Engine and tank telemetry: the RPM and XDR sentences that the NMEA 0183
+// network carries besides AIS are sampled into the status outputs, so that
+// the [heartbeat] and [mqtt] state that a monitoring service gets has them
+// as well, for basic remote monitoring of the boat. From the [telemetry]
+// section:
+//
+//     sentences = RPM, XDR             the ones to sample
+//     transducers = FUEL, FRESHWATER   XDR transducers to keep, all when not given
+//
+// Readings are kept by name with their unit, the last one of each: "engine 1"
+// or "shaft 1" in rpm, and the transducer name for XDR, such as a tank level
+// in percent. The sentences are not forwarded.
+use std::collections::HashMap;
+use std::io;
+
+use crate::nmea;
+
+const SENTENCES: [&str; 2] = ["RPM", "XDR"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reading {
+    pub name: String,
+    pub value: f64,
+    pub unit: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Telemetry {
+    sentences: Vec<String>,
+    transducers: Vec<String>,
+}
+
+impl Telemetry {
+    pub fn from_config(section: &HashMap<String, String>) -> io::Result<Self> {
+        let list = |key: &str| -> Vec<String> {
+            section
+                .get(key)
+                .into_iter()
+                .flat_map(|names| names.split(','))
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_uppercase)
+                .collect()
+        };
+        let sentences = list("sentences");
+        if sentences.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Missing sentences",
+            ));
+        }
+        if let Some(sentence) = sentences
+            .iter()
+            .find(|sentence| !SENTENCES.contains(&sentence.as_str()))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Unknown sentence '{}', should be one of {}",
+                    sentence,
+                    SENTENCES.join(", ")
+                ),
+            ));
+        }
+        Ok(Telemetry {
+            sentences,
+            transducers: list("transducers"),
+        })
+    }
+
+    // The readings in a sentence, None when it is not one that is sampled.
+    pub fn readings(&self, line: &str) -> Option<Vec<Reading>> {
+        let sentence = nmea::split_tag_block(line).1;
+        if !sentence.starts_with('$') {
+            return None;
+        }
+        let formatter = nmea::formatter(sentence)?;
+        if !self.sentences.iter().any(|sentence| sentence == formatter) {
+            return None;
+        }
+        let fields: Vec<&str> = sentence.split('*').next()?.split(',').collect();
+        match formatter {
+            "RPM" => Some(rpm(&fields).into_iter().collect()),
+            "XDR" => Some(
+                xdr(&fields)
+                    .filter(|reading| {
+                        self.transducers.is_empty()
+                            || self.transducers.contains(&reading.name.to_uppercase())
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+// $--RPM,<S shaft or E engine>,<number>,<rpm>,<pitch %>,<A valid>
+fn rpm(fields: &[&str]) -> Option<Reading> {
+    let source = match *fields.get(1)? {
+        "E" => "engine",
+        "S" => "shaft",
+        _ => return None,
+    };
+    if *fields.get(5)? != "A" {
+        return None;
+    }
+    Some(Reading {
+        name: format!("{} {}", source, fields.get(2)?),
+        value: fields.get(3)?.parse().ok()?,
+        unit: "rpm".to_string(),
+    })
+}
+
+// $--XDR, followed by any number of <type>,<value>,<unit>,<name>
+fn xdr<'a>(fields: &'a [&str]) -> impl Iterator<Item = Reading> + 'a {
+    fields[1..].chunks_exact(4).filter_map(|quad| {
+        let (kind, value, unit, name) = (quad[0], quad[1], quad[2], quad[3]);
+        if name.is_empty() {
+            return None;
+        }
+        Some(Reading {
+            name: name.to_string(),
+            value: value.parse().ok()?,
+            unit: unit_name(kind, unit).to_string(),
+        })
+    })
+}
+
+// The unit letters of XDR depend on the type of transducer.
+fn unit_name<'a>(kind: &str, unit: &'a str) -> &'a str {
+    match (kind, unit) {
+        ("P", "P") => "Pa",
+        ("P", "B") => "bar",
+        // Tank levels, humidity and the like
+        (_, "P") => "%",
+        ("V", "M") => "m3",
+        ("T", "R") => "rpm",
+        ("A", "D") => "deg",
+        ("F", "H") => "Hz",
+        _ => unit,
+    }
+}
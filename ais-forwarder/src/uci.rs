@@ -0,0 +1,114 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Read the configuration from an OpenWrt UCI file, /etc/config/ais-forwarder,
+// instead of config.ini. Every `config <type> ['<name>']` block becomes a
+// section named after its name, or its type when it has none, and every
+// `option` a setting in it:
+//
+//   config general
+//       option mmsi '244000000'
+//       option provider 'tcp://127.0.0.1:2599'
+//
+//   config ais
+//       option MarineTraffic 'udp://5.9.207.224:5321'
+//
+// Repeated `list` entries are joined with commas.
+use std::collections::HashMap;
+use std::io;
+
+pub fn load(path: &str) -> io::Result<HashMap<String, HashMap<String, String>>> {
+    parse(&std::fs::read_to_string(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+}
+
+fn parse(text: &str) -> Result<HashMap<String, HashMap<String, String>>, String> {
+    let mut settings: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut section: Option<String> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let words = split_words(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        match words.as_slice() {
+            [] => {}
+            [keyword, kind] if keyword == "config" => {
+                section = Some(kind.clone());
+                settings.entry(kind.clone()).or_default();
+            }
+            [keyword, _kind, name] if keyword == "config" => {
+                section = Some(name.clone());
+                settings.entry(name.clone()).or_default();
+            }
+            [keyword, key, value] if keyword == "option" || keyword == "list" => {
+                let Some(section) = &section else {
+                    return Err(format!(
+                        "line {}: {} outside a config block",
+                        number + 1,
+                        keyword
+                    ));
+                };
+                let options = settings.entry(section.clone()).or_default();
+                match options.get_mut(key) {
+                    Some(existing) if keyword == "list" => {
+                        existing.push(',');
+                        existing.push_str(value);
+                    }
+                    _ => {
+                        options.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            // `package` only matters when several packages share one file
+            [keyword, _] if keyword == "package" => {}
+            _ => {
+                return Err(format!(
+                    "line {}: cannot parse '{}'",
+                    number + 1,
+                    line.trim()
+                ));
+            }
+        }
+    }
+    Ok(settings)
+}
+
+// Split a line into words the way UCI does: separated by whitespace, quoted
+// with single or double quotes, and ending at a # outside quotes.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            return Ok(words);
+        };
+        if first == '#' {
+            return Ok(words);
+        }
+        let mut word = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            chars.next();
+            match c {
+                '\'' | '"' => loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some('\\') if c == '"' => {
+                            if let Some(escaped) = chars.next() {
+                                word.push(escaped);
+                            }
+                        }
+                        Some(other) => word.push(other),
+                        None => return Err("unterminated quote".to_string()),
+                    }
+                },
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        word.push(escaped);
+                    }
+                }
+                _ => word.push(c),
+            }
+        }
+        words.push(word);
+    }
+}
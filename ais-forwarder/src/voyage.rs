@@ -0,0 +1,139 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Statistics of each UTC day from our own track: distance sailed, the highest
+// and average speed, and how long we were under way and at anchor. They are
+// in the status, on the /vessels page and in the email digest; today's so far
+// and yesterday's, as they start again at midnight and when restarted.
+//
+// Our position comes with every own report, seconds apart, when the GPS
+// jitter is as large as what we sail in between. So the track is taken every
+// SAMPLE_INTERVAL: we are under way between two samples when the reported
+// speed says so, or without one when we moved more than NOISE.
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::geo;
+use crate::metrics::format_duration;
+
+const SAMPLE_INTERVAL: chrono::Duration = chrono::Duration::seconds(60);
+// Samples further apart than this are a gap in the track; the distance counts
+// but the time is neither under way nor at anchor.
+const MAX_GAP: chrono::Duration = chrono::Duration::minutes(15);
+const NOISE: f64 = 20.0;
+// Knots, drifting at anchor with the tide stays below this
+const UNDER_WAY_SOG: f64 = 0.5;
+// AIS reports 102.2 knots or more as "not available" or out of range
+const MAX_SOG: f64 = 102.2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    pub day: NaiveDate,
+    // Metres
+    pub distance: f64,
+    // Knots
+    pub max_sog: Option<f64>,
+    pub under_way: Duration,
+    pub at_anchor: Duration,
+    // Metres sailed while under way, for the average speed
+    under_way_distance: f64,
+}
+
+impl Stats {
+    fn new(day: NaiveDate) -> Self {
+        Stats {
+            day,
+            distance: 0.0,
+            max_sog: None,
+            under_way: Duration::ZERO,
+            at_anchor: Duration::ZERO,
+            under_way_distance: 0.0,
+        }
+    }
+
+    // Knots over the time under way.
+    pub fn average_sog(&self) -> Option<f64> {
+        let hours = self.under_way.as_secs_f64() / 3600.0;
+        (hours > 0.0).then(|| self.under_way_distance / 1852.0 / hours)
+    }
+
+    // Such as "36.2 nm, 6h 2m under way at 6.0 kn on average, max 7.4 kn,
+    // 17h 58m at anchor".
+    pub fn describe(&self) -> String {
+        let mut text = format!("{:.1} nm", self.distance / 1852.0);
+        if let Some(average) = self.average_sog() {
+            text.push_str(&format!(
+                ", {} under way at {:.1} kn on average",
+                format_duration(self.under_way),
+                average
+            ));
+        }
+        if let Some(max_sog) = self.max_sog {
+            text.push_str(&format!(", max {:.1} kn", max_sog));
+        }
+        if !self.at_anchor.is_zero() {
+            text.push_str(&format!(", {} at anchor", format_duration(self.at_anchor)));
+        }
+        text
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Voyage {
+    today: Option<Stats>,
+    yesterday: Option<Stats>,
+    // The last sample, and whether the reported speed since said under way
+    sample: Option<(DateTime<Utc>, f64, f64)>,
+    sog_under_way: Option<bool>,
+}
+
+impl Voyage {
+    // Called with each own position and the speed that came with it.
+    pub fn update(&mut self, now: DateTime<Utc>, latitude: f64, longitude: f64, sog: Option<f64>) {
+        let day = now.date_naive();
+        if self.today.as_ref().is_none_or(|today| today.day != day) {
+            let today = self.today.replace(Stats::new(day));
+            // Kept as yesterday only when it was
+            self.yesterday = today.filter(|today| today.day.succ_opt() == Some(day));
+        }
+        let Some(today) = self.today.as_mut() else {
+            return;
+        };
+        if let Some(sog) = sog.filter(|sog| *sog < MAX_SOG) {
+            today.max_sog = Some(today.max_sog.map_or(sog, |max| max.max(sog)));
+            let under_way = sog >= UNDER_WAY_SOG;
+            self.sog_under_way = Some(self.sog_under_way.unwrap_or(false) || under_way);
+        }
+
+        let Some((time, last_latitude, last_longitude)) = self.sample else {
+            self.sample = Some((now, latitude, longitude));
+            return;
+        };
+        let elapsed = now - time;
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+        let step = geo::distance(last_latitude, last_longitude, latitude, longitude);
+        let under_way = self.sog_under_way.take().unwrap_or(step > NOISE);
+        if under_way {
+            today.distance += step;
+        }
+        if elapsed <= MAX_GAP {
+            let elapsed = elapsed.to_std().unwrap_or_default();
+            if under_way {
+                today.under_way += elapsed;
+                today.under_way_distance += step;
+            } else {
+                today.at_anchor += elapsed;
+            }
+        }
+        self.sample = Some((now, latitude, longitude));
+    }
+
+    pub fn today(&self) -> Option<&Stats> {
+        self.today.as_ref()
+    }
+
+    pub fn yesterday(&self) -> Option<&Stats> {
+        self.yesterday.as_ref()
+    }
+}
@@ -0,0 +1,49 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Restart when one of our own threads stops making progress, for instance a
+// read that blocks forever or a deadlock. The provider loop and the location
+// thread report progress in the metrics; when either has been silent for too
+// long we shut down as cleanly as we can and exit with a failure, so that
+// systemd, procd or the service manager starts us again.
+use std::io;
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread::Builder;
+use std::time::Duration;
+
+use crate::metrics::Metrics;
+
+// The location thread wakes up at least every 6 minutes, even when idle.
+pub const MIN_TIMEOUT: Duration = Duration::from_secs(420);
+// How long the graceful shutdown may take before we exit anyway; it may be
+// waiting for a lock held by the thread that is stuck.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(15);
+
+pub fn start(timeout: Duration, metrics: Arc<Metrics>) -> io::Result<()> {
+    Builder::new().name("watchdog".to_string()).spawn(move || {
+        loop {
+            std::thread::sleep(timeout / 4);
+            if let Some((thread, idle)) = metrics.stalled(timeout) {
+                log::error!(
+                    "The {} thread made no progress for {}s, restarting",
+                    thread,
+                    idle.as_secs()
+                );
+                restart();
+            }
+        }
+    })?;
+    Ok(())
+}
+
+fn restart() -> ! {
+    let (done_tx, done_rx) = mpsc::channel();
+    let _ = Builder::new().name("shutdown".to_string()).spawn(move || {
+        crate::shutdown::graceful();
+        let _ = done_tx.send(());
+    });
+    if done_rx.recv_timeout(SHUTDOWN_TIMEOUT).is_err() {
+        log::warn!("Shutdown did not finish in time");
+        log::logger().flush();
+    }
+    std::process::exit(crate::EXIT_FAILURE);
+}
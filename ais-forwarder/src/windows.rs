@@ -0,0 +1,202 @@
+/// (C) 2025 by Kees Verruijt, Harlingen, Netherlands
+// Windows support: running as a service, logging to the Event Log and
+// stopping cleanly on Ctrl-C when running in a console.
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Parser;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+use windows_sys::Win32::Foundation::{FALSE, TRUE};
+use windows_sys::Win32::System::Console::SetConsoleCtrlHandler;
+use windows_sys::Win32::System::EventLog::{
+    EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE, RegisterEventSourceW,
+    ReportEventW,
+};
+
+use crate::Cli;
+use crate::logging::APP_NAME;
+
+#[derive(clap::Subcommand, Clone, Debug)]
+pub enum ServiceAction {
+    /// Register the service, with the options given before `service`
+    Install,
+    /// Remove the service
+    Uninstall,
+    /// Run as a service, this is what the service manager starts
+    Run,
+}
+
+// Where configuration and cache live, the Windows equivalent of /etc and /var.
+pub fn program_data() -> PathBuf {
+    std::env::var_os("ProgramData")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+        .join(APP_NAME)
+}
+
+pub fn service(action: &ServiceAction) -> io::Result<()> {
+    match action {
+        ServiceAction::Install => install(),
+        ServiceAction::Uninstall => uninstall(),
+        ServiceAction::Run => {
+            service_dispatcher::start(APP_NAME, ffi_service_main).map_err(io::Error::other)
+        }
+    }
+}
+
+fn install() -> io::Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .map_err(io::Error::other)?;
+
+    // The service gets the same options, there is no stderr for a service
+    let mut arguments: Vec<OsString> = std::env::args_os()
+        .skip(1)
+        .filter(|arg| arg != "service" && arg != "install")
+        .collect();
+    if !arguments.iter().any(|arg| arg == "--log-target") {
+        arguments.extend(["--log-target".into(), "eventlog".into()]);
+    }
+    arguments.extend(["service".into(), "run".into()]);
+
+    let info = ServiceInfo {
+        name: APP_NAME.into(),
+        display_name: "AIS forwarder".into(),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: arguments,
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .map_err(io::Error::other)?;
+    service
+        .set_description("Forwards AIS and own position data to online services")
+        .map_err(io::Error::other)?;
+    println!("Service {} installed", APP_NAME);
+    Ok(())
+}
+
+fn uninstall() -> io::Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .map_err(io::Error::other)?;
+    let service = manager
+        .open_service(APP_NAME, ServiceAccess::DELETE)
+        .map_err(io::Error::other)?;
+    service.delete().map_err(io::Error::other)?;
+    println!("Service {} removed", APP_NAME);
+    Ok(())
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        log::error!("Service failed: {}", e);
+    }
+}
+
+fn run_service() -> windows_service::Result<()> {
+    let status_handle = service_control_handler::register(APP_NAME, |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            // Stopping takes a moment, don't keep the service manager waiting
+            std::thread::spawn(|| {
+                crate::shutdown::graceful();
+                std::process::exit(0);
+            });
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+    status_handle.set_service_status(ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: ServiceState::Running,
+        controls_accepted: ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        exit_code: ServiceExitCode::Win32(0),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    })?;
+
+    // Logging was set up by main(), the options are on our command line
+    crate::run(Cli::parse());
+}
+
+unsafe extern "system" fn console_ctrl(_ctrl_type: u32) -> i32 {
+    log::info!("Interrupted, shutting down");
+    crate::shutdown::graceful();
+    std::process::exit(0);
+}
+
+pub fn handle_console_ctrl() -> io::Result<()> {
+    // SAFETY: console_ctrl is a valid handler for the lifetime of the process
+    if unsafe { SetConsoleCtrlHandler(Some(console_ctrl), TRUE) } == FALSE {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Event source handle. Without a registered message file the Event Viewer
+// prefixes our text with a note, but the message itself is shown in full.
+pub struct EventLog(isize);
+
+impl EventLog {
+    pub fn open(source: &str) -> io::Result<Self> {
+        let source = wide(OsStr::new(source));
+        // SAFETY: source is a NUL terminated wide string
+        let handle = unsafe { RegisterEventSourceW(std::ptr::null(), source.as_ptr()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(EventLog(handle as isize))
+    }
+
+    pub fn report(&self, level: log::Level, message: &str) -> io::Result<()> {
+        let kind = match level {
+            log::Level::Error => EVENTLOG_ERROR_TYPE,
+            log::Level::Warn => EVENTLOG_WARNING_TYPE,
+            _ => EVENTLOG_INFORMATION_TYPE,
+        };
+        let message = wide(OsStr::new(message));
+        let strings = [message.as_ptr()];
+        // SAFETY: the handle came from RegisterEventSourceW and strings holds one
+        // NUL terminated wide string that outlives the call.
+        let ok = unsafe {
+            ReportEventW(
+                self.0 as _,
+                kind,
+                0,
+                0,
+                std::ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                std::ptr::null(),
+            )
+        };
+        if ok == FALSE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+fn wide(s: &OsStr) -> Vec<u16> {
+    s.encode_wide().chain(std::iter::once(0)).collect()
+}
@@ -1,10 +1,16 @@
 use std::io::{self, BufRead, Write};
 use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub mod buffer;
+pub mod sink;
 use buffer::BufReaderDirectWriter;
 
+// How long a resolved address is used before the name is looked up again.
+// The system resolver does not tell us the TTL of the record, so this is an
+// upper bound on how long we keep sending to an address that has moved.
+pub const DNS_TTL: Duration = Duration::from_secs(300);
+
 pub enum Protocol {
     TCP,
     UDP,
@@ -49,7 +55,12 @@ impl std::fmt::Debug for Protocol {
 
 pub struct NetworkEndpoint {
     pub protocol: Protocol,
-    pub addr: SocketAddr,
+    // host:port as configured, resolved into addr when first needed and again
+    // once it is dns_ttl old or the endpoint failed
+    pub host: String,
+    pub addr: Option<SocketAddr>,
+    pub resolved_at: Option<Instant>,
+    pub dns_ttl: Duration,
     pub tcp_listener: Option<std::net::TcpListener>,
     pub tcp_stream: Vec<BufReaderDirectWriter<std::net::TcpStream>>, // List of connected incoming TCP streams or single outgoing stream
     pub udp_socket: Option<std::net::UdpSocket>,
@@ -69,18 +80,26 @@ impl std::str::FromStr for NetworkEndpoint {
         let protocol = parts[0]
             .parse::<Protocol>()
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
-        let mut addr = parts[1].to_socket_addrs().map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                format!("{}: {}", parts[1], e),
-            )
-        })?;
-        let addr = addr.next().ok_or_else(|| {
-            std::io::Error::new(std::io::ErrorKind::InvalidInput, "No address found")
-        })?;
+        // The name is resolved when the endpoint is used, DNS may not be up yet
+        // at boot.
+        match parts[1]
+            .rsplit_once(':')
+            .map(|(host, port)| (host, port.parse::<u16>()))
+        {
+            Some((host, Ok(_))) if !host.is_empty() => {}
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{}: address should be host:port", parts[1]),
+                ));
+            }
+        }
         Ok(NetworkEndpoint {
             protocol,
-            addr,
+            host: parts[1].to_string(),
+            addr: None,
+            resolved_at: None,
+            dns_ttl: DNS_TTL,
             tcp_listener: None,
             tcp_stream: Vec::new(),
             udp_socket: None,
@@ -89,17 +108,12 @@ impl std::str::FromStr for NetworkEndpoint {
 }
 impl std::fmt::Display for NetworkEndpoint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}://{}", self.protocol, self.addr)
+        write!(f, "{}://{}", self.protocol, self.host)
     }
 }
 impl std::fmt::Debug for NetworkEndpoint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}://{}", self.protocol, self.addr)
-    }
-}
-impl std::convert::From<NetworkEndpoint> for SocketAddr {
-    fn from(addr: NetworkEndpoint) -> Self {
-        addr.addr
+        write!(f, "{}://{}", self.protocol, self.host)
     }
 }
 
@@ -108,12 +122,20 @@ pub fn send_message_udp(stream: &mut std::net::UdpSocket, message: &[u8]) -> std
     Ok(())
 }
 
-pub fn read_message_udp(stream: &mut std::net::UdpSocket) -> std::io::Result<String> {
-    let mut buffer = vec![0; 1024];
-    let (bytes_read, _) = stream.recv_from(&mut buffer)?;
-    buffer.truncate(bytes_read);
-    let buffer = String::from_utf8_lossy(&buffer).to_string();
-    Ok(buffer)
+// The largest UDP payload, so that a datagram full of sentences is never cut short.
+const MAX_DATAGRAM: usize = 65536;
+
+// Receive one datagram into buffer, which is sized once and then reused.
+// Returns the length of the datagram.
+pub fn read_message_udp(
+    stream: &mut std::net::UdpSocket,
+    buffer: &mut Vec<u8>,
+) -> std::io::Result<usize> {
+    if buffer.len() < MAX_DATAGRAM {
+        buffer.resize(MAX_DATAGRAM, 0);
+    }
+    let (bytes_read, _) = stream.recv_from(buffer)?;
+    Ok(bytes_read)
 }
 
 pub fn send_message_tcp(
@@ -125,33 +147,129 @@ pub fn send_message_tcp(
     Ok(())
 }
 
-pub fn read_message_tcp(stream: &mut BufReaderDirectWriter<TcpStream>) -> io::Result<String> {
-    let mut buffer = String::with_capacity(72);
-    let bytes_read = stream.read_line(&mut buffer)?;
-    buffer.truncate(bytes_read);
-    Ok(buffer)
+// Read one line into buffer, reusing its allocation. Returns the length of
+// the line, 0 at the end of the stream.
+pub fn read_message_tcp(
+    stream: &mut BufReaderDirectWriter<TcpStream>,
+    buffer: &mut Vec<u8>,
+) -> io::Result<usize> {
+    buffer.clear();
+    stream.read_until(b'\n', buffer)
 }
 
 impl NetworkEndpoint {
-    pub fn read_to_string(&mut self) -> io::Result<String> {
+    // The socket address, resolving the host name when it is first needed
+    // and again when the last lookup is older than dns_ttl. When the name
+    // cannot be resolved now, the address we had is better than nothing.
+    pub fn resolve(&mut self) -> io::Result<SocketAddr> {
+        if let Some(addr) = self.addr
+            && self
+                .resolved_at
+                .is_some_and(|at| at.elapsed() < self.dns_ttl)
+        {
+            return Ok(addr);
+        }
+        let result = self
+            .host
+            .to_socket_addrs()
+            .and_then(|mut addrs| {
+                addrs
+                    .next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No address found"))
+            })
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::HostUnreachable,
+                    format!("Cannot resolve {}: {}", self.host, e),
+                )
+            });
+        // Not looked up again until dns_ttl has passed, failed or not
+        self.resolved_at = Some(Instant::now());
+        match (result, self.addr) {
+            (Ok(addr), old) => {
+                if let Some(old) = old
+                    && old != addr
+                {
+                    log::info!("{} moved from {} to {}", self.host, old, addr);
+                }
+                self.addr = Some(addr);
+                Ok(addr)
+            }
+            (Err(e), Some(old)) => {
+                log::warn!("{}, still using {}", e, old);
+                Ok(old)
+            }
+            (Err(e), None) => {
+                self.resolved_at = None;
+                Err(e)
+            }
+        }
+    }
+
+    // Look the name up again the next time, the address may have moved.
+    pub fn forget_address(&mut self) {
+        self.resolved_at = None;
+    }
+
+    // Open the listening socket of a listening or UDP endpoint, if that has not
+    // been done yet. This can be done up front, before privileges are dropped.
+    pub fn bind(&mut self) -> io::Result<()> {
+        match self.protocol {
+            Protocol::TCP => {}
+            Protocol::TCPListen => {
+                if self.tcp_listener.is_none() {
+                    let addr = self.resolve()?;
+                    let listener = TcpListener::bind(addr).map_err(|e| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::AddrInUse,
+                            format!("provider {}: {}", addr, e),
+                        )
+                    })?;
+                    listener.set_nonblocking(true)?;
+                    log::info!("Listening on: {}", self);
+                    self.tcp_listener = Some(listener);
+                }
+            }
+            Protocol::UDP | Protocol::UDPListen => {
+                if self.udp_socket.is_none() {
+                    let socket = std::net::UdpSocket::bind(self.resolve()?)?;
+                    // Return now and then when the provider is quiet, see read
+                    socket.set_read_timeout(Some(Duration::from_secs(30)))?;
+                    log::info!("Listening on: {}", self);
+                    self.udp_socket = Some(socket);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Read the next chunk of data, a line from TCP or a datagram from UDP. It
+    // is returned as a slice of buffer, which the caller keeps and passes in
+    // again, so that a busy provider does not cost an allocation per read.
+    pub fn read<'a>(&mut self, buffer: &'a mut Vec<u8>) -> io::Result<&'a [u8]> {
         match self.protocol {
             Protocol::TCP => {
-                if self.tcp_stream.len() == 0 {
-                    let stream = std::net::TcpStream::connect(self.addr).map_err(|e| {
+                if self.tcp_stream.is_empty() {
+                    let addr = self.resolve()?;
+                    let stream = std::net::TcpStream::connect(addr).map_err(|e| {
                         std::io::Error::new(
                             std::io::ErrorKind::ConnectionRefused,
-                            format!("provider {}: {}", self.addr, e),
+                            format!("provider {}: {}", addr, e),
                         )
-                    })?;
+                    });
+                    if stream.is_err() {
+                        self.forget_address();
+                    }
+                    let stream = stream?;
                     log::info!("Connected to {}", self);
                     stream.set_read_timeout(Some(Duration::from_secs(30)))?;
                     let reader = BufReaderDirectWriter::new(stream);
                     self.tcp_stream.push(reader);
                 }
-                match read_message_tcp(&mut self.tcp_stream[0]) {
-                    Ok(message) => {
-                        if message.len() > 0 {
-                            return Ok(message);
+                match read_message_tcp(&mut self.tcp_stream[0], buffer) {
+                    Ok(bytes_read) => {
+                        if bytes_read > 0 {
+                            return Ok(&buffer[..bytes_read]);
                         }
                         self.tcp_stream.clear();
                         return Err(io::Error::new(
@@ -167,17 +285,7 @@ impl NetworkEndpoint {
                 }
             }
             Protocol::TCPListen => {
-                if self.tcp_listener.is_none() {
-                    let listener = TcpListener::bind(self.addr).map_err(|e| {
-                        std::io::Error::new(
-                            std::io::ErrorKind::AddrInUse,
-                            format!("provider {}: {}", self.addr, e),
-                        )
-                    })?;
-                    listener.set_nonblocking(true)?;
-                    log::info!("Listening on: {}", self);
-                    self.tcp_listener = Some(listener);
-                }
+                self.bind()?;
                 if let Some(tcp_listener) = self.tcp_listener.as_mut() {
                     loop {
                         match tcp_listener.accept() {
@@ -210,10 +318,10 @@ impl NetworkEndpoint {
 
                 let mut i = 0;
                 while i < self.tcp_stream.len() {
-                    match read_message_tcp(&mut self.tcp_stream[i]) {
-                        Ok(message) => {
-                            if message.len() > 0 {
-                                return Ok(message);
+                    match read_message_tcp(&mut self.tcp_stream[i], buffer) {
+                        Ok(bytes_read) => {
+                            if bytes_read > 0 {
+                                return Ok(&buffer[..bytes_read]);
                             }
                             // Drop stream on empty read
                             self.tcp_stream.remove(i);
@@ -239,19 +347,17 @@ impl NetworkEndpoint {
             }
 
             Protocol::UDP | Protocol::UDPListen => {
-                if self.udp_socket.is_none() {
-                    let socket = std::net::UdpSocket::bind(self.addr)?;
-                    log::info!("Listening on: {}", self);
-                    self.udp_socket = Some(socket);
-                }
+                self.bind()?;
                 if let Some(udp_socket) = self.udp_socket.as_mut() {
-                    return read_message_udp(udp_socket);
+                    let bytes_read = read_message_udp(udp_socket, buffer)?;
+                    return Ok(&buffer[..bytes_read]);
                 }
             }
         }
+        // Only reached by a listening TCP endpoint without data from any client
         Err(io::Error::new(
-            io::ErrorKind::Other,
-            "Failed to read message from network endpoint",
+            io::ErrorKind::WouldBlock,
+            "No data available from network endpoint",
         ))
     }
 }
@@ -0,0 +1,32 @@
+use std::io;
+use std::time::Duration;
+
+// A destination that messages are sent to. The forwarder creates one per
+// configured output, from its URL, and only talks to it through this trait,
+// so that new kinds of destinations can live in their own module or crate.
+pub trait Sink: Send {
+    // Make sure there is a connection, if the destination needs one. Called
+    // by send as well, so an explicit connect is only needed to connect early.
+    fn connect(&mut self) -> io::Result<()>;
+
+    // Send one message, which may be a group of NMEA sentences.
+    fn send(&mut self, message: &[u8]) -> io::Result<()>;
+
+    // Push out anything that send buffered.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    // For a sink that holds messages back for a while, how long until flush
+    // should be called even when no more messages come; None when there is
+    // nothing held back.
+    fn flush_due(&self) -> Option<Duration> {
+        None
+    }
+
+    // Drop the connection; the next send connects again.
+    fn close(&mut self);
+
+    // Whether the last attempt to connect or send worked.
+    fn healthy(&self) -> bool;
+}
@@ -14,7 +14,7 @@ fn main() {
     log::info!("location-receiver starting up");
 
     let db_path = Path::new("/var/db");
-    std::fs::create_dir_all(&db_path).expect("Cannot create /var/db directory");
+    std::fs::create_dir_all(db_path).expect("Cannot create /var/db directory");
 
     let listener = TcpListener::bind("10.67.0.1:11328").expect("Cannot bind to port 11328");
 
@@ -32,7 +32,7 @@ fn main() {
                         // Process the message here
                         for line in buffer.lines() {
                             if !line.is_empty() {
-                                process_message(line, &db_path);
+                                process_message(line, db_path);
                             }
                         }
                         buffer.clear();